@@ -0,0 +1,317 @@
+//! Chunked LZ4 compression of asset files. Chunks are compressed
+//! independently so they are decompressed in parallel while the file is
+//! streamed.
+//!
+//! Layout, all integers little endian:
+//! - Header: MAGIC, uncompressed size (u64), chunk count (u32), chunk size (u32).
+//! - For each chunk: compressed size (u32), uncompressed size (u32) and the
+//!   raw LZ4 block. Every chunk but the last has chunk size bytes once
+//!   decompressed.
+
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::thread::JoinHandle;
+
+use crate::AssetError;
+
+pub const MAGIC: [u8; 8] = *b"GRAYLZ4\0";
+pub const HEADER_SIZE: usize = 24;
+pub const CHUNK_HEADER_SIZE: usize = 8;
+pub const DEFAULT_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+/// Largest chunk accepted by LZ4 with some margin.
+pub const MAX_CHUNK_SIZE: usize = 1024 * 1024 * 1024;
+
+fn read_u32(b: &[u8]) -> u32 {
+    u32::from_le_bytes(b[..4].try_into().unwrap())
+}
+
+fn read_u64(b: &[u8]) -> u64 {
+    u64::from_le_bytes(b[..8].try_into().unwrap())
+}
+
+/// Worst case compressed size of a chunk, LZ4_COMPRESSBOUND.
+fn compress_bound(size: usize) -> usize {
+    size + size / 255 + 16
+}
+
+/// A byte of LZ4 block decompresses to at most 255 bytes, so chunk sizes
+/// are bounded by the input before anything is allocated.
+const MAX_RATIO: usize = 255;
+
+struct Header {
+    uncompressed_size: u64,
+    chunk_count: u32,
+    chunk_size: u32,
+}
+
+impl Header {
+    fn parse(b: &[u8]) -> Result<Header, AssetError> {
+        if b.len() < HEADER_SIZE || b[..8] != MAGIC {
+            return Err(AssetError::Compression);
+        }
+        let header = Header {
+            uncompressed_size: read_u64(&b[8..]),
+            chunk_count: read_u32(&b[16..]),
+            chunk_size: read_u32(&b[20..]),
+        };
+        let chunk_size = header.chunk_size as usize;
+        if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE
+            || header.uncompressed_size.div_ceil(chunk_size as u64) != header.chunk_count as u64 {
+            return Err(AssetError::Compression);
+        }
+        Ok(header)
+    }
+
+    /// Uncompressed and compressed sizes from a chunk header.
+    fn chunk(&self, b: &[u8]) -> Result<(usize, usize), AssetError> {
+        let c_size = read_u32(b) as usize;
+        let u_size = read_u32(&b[4..]) as usize;
+        if u_size > self.chunk_size as usize || u_size > c_size.saturating_mul(MAX_RATIO)
+            || c_size > compress_bound(self.chunk_size as usize) {
+            return Err(AssetError::Compression);
+        }
+        Ok((u_size, c_size))
+    }
+}
+
+pub fn compress(data: &[u8], chunk_size: usize) -> Vec<u8> {
+    assert!(chunk_size > 0 && chunk_size <= MAX_CHUNK_SIZE);
+
+    let chunk_count = data.len().div_ceil(chunk_size);
+    let mut buf = Vec::with_capacity(HEADER_SIZE + data.len() / 2);
+    buf.extend_from_slice(&MAGIC);
+    buf.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    buf.extend_from_slice(&(chunk_count as u32).to_le_bytes());
+    buf.extend_from_slice(&(chunk_size as u32).to_le_bytes());
+
+    for c in data.chunks(chunk_size) {
+        let compressed = lz4::block::compress(c, None, false).expect("Failed to compress chunk");
+        buf.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&(c.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&compressed);
+    }
+    buf
+}
+
+fn invalid_data(e: AssetError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// Decompresses a chunked file while it is read. The compressed chunks are
+/// read in order and decompressed ahead on worker threads, one per available
+/// core, so at most that many chunks are in memory.
+pub struct ChunkReader<R: Read> {
+    inner: R,
+    header: Header,
+    /// Chunks not read from inner yet.
+    unread_chunks: u32,
+    /// Bytes of the chunks read so far, checked against the header at the end.
+    total_size: u64,
+    /// Chunks being decompressed, in order.
+    pending: VecDeque<JoinHandle<Result<Vec<u8>, AssetError>>>,
+    max_pending: usize,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(mut inner: R) -> io::Result<ChunkReader<R>> {
+        let mut b = [0; HEADER_SIZE];
+        inner.read_exact(&mut b)?;
+        let header = Header::parse(&b).map_err(invalid_data)?;
+        Ok(ChunkReader {
+            inner,
+            unread_chunks: header.chunk_count,
+            total_size: 0,
+            header,
+            pending: VecDeque::new(),
+            max_pending: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            chunk: Vec::new(),
+            pos: 0,
+        })
+    }
+
+    /// Size of the decompressed data.
+    pub fn uncompressed_size(&self) -> u64 {
+        self.header.uncompressed_size
+    }
+
+    /// Reads compressed chunks until every worker has one.
+    fn read_ahead(&mut self) -> io::Result<()> {
+        while self.pending.len() < self.max_pending && self.unread_chunks > 0 {
+            let mut b = [0; CHUNK_HEADER_SIZE];
+            self.inner.read_exact(&mut b)?;
+            let (u_size, c_size) = self.header.chunk(&b).map_err(invalid_data)?;
+            if self.unread_chunks > 1 && u_size != self.header.chunk_size as usize {
+                return Err(invalid_data(AssetError::Compression));
+            }
+
+            // Read through take so a corrupted size fails at the end of the
+            // input instead of allocating it upfront
+            let mut compressed = Vec::new();
+            (&mut self.inner).take(c_size as u64).read_to_end(&mut compressed)?;
+            if compressed.len() != c_size {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.unread_chunks -= 1;
+            self.total_size += u_size as u64;
+
+            self.pending.push_back(std::thread::spawn(move || {
+                let mut chunk = vec![0; u_size];
+                match lz4::block::decompress_to_buffer(&compressed, Some(u_size as i32), &mut chunk) {
+                    Ok(size) if size == u_size => Ok(chunk),
+                    _ => Err(AssetError::Compression),
+                }
+            }));
+        }
+        Ok(())
+    }
+
+    fn next_chunk(&mut self) -> io::Result<()> {
+        self.read_ahead()?;
+        let chunk = self.pending.pop_front().expect("No chunk left to decompress");
+        self.chunk = chunk.join().unwrap().map_err(invalid_data)?;
+        self.pos = 0;
+        // Keep the workers busy while this chunk is read
+        self.read_ahead()
+    }
+}
+
+impl<R: Read> Read for ChunkReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            if self.unread_chunks == 0 && self.pending.is_empty() {
+                if self.total_size != self.header.uncompressed_size {
+                    return Err(invalid_data(AssetError::Compression));
+                }
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_data(len: usize) -> Vec<u8> {
+        // Compressible but not trivially
+        (0..len).map(|i| ((i * 7) ^ (i >> 5)) as u8).collect()
+    }
+
+    fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        ChunkReader::new(data)?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn round_trip() {
+        for (len, chunk_size) in [(0, 16), (1, 16), (16, 16), (1000, 16), (1000, 1000), (100_000, 999)] {
+            let data = test_data(len);
+            let compressed = compress(&data, chunk_size);
+            assert_eq!(read_u32(&compressed[16..]) as usize, len.div_ceil(chunk_size));
+
+            assert_eq!(ChunkReader::new(&compressed[..]).unwrap().uncompressed_size(), len as u64);
+            assert_eq!(decompress(&compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn small_reads() {
+        let data = test_data(10_000);
+        let compressed = compress(&data, 256);
+        let mut reader = ChunkReader::new(&compressed[..]).unwrap();
+        let mut out = Vec::new();
+        let mut b = [0; 7];
+        loop {
+            let n = reader.read(&mut b).unwrap();
+            if n == 0 {
+                break;
+            }
+            out.extend_from_slice(&b[..n]);
+        }
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn corrupted() {
+        let data = test_data(5000);
+        let compressed = compress(&data, 1024);
+
+        for len in [0, 10, HEADER_SIZE, HEADER_SIZE + 4, compressed.len() - 1] {
+            assert!(decompress(&compressed[..len]).is_err());
+        }
+
+        // Total size disagreeing with the chunks
+        let mut b = compressed.clone();
+        b[8..16].copy_from_slice(&4000u64.to_le_bytes());
+        assert!(decompress(&b).is_err());
+
+        // Chunk larger than the chunk size
+        let mut b = compressed.clone();
+        b[HEADER_SIZE + 4..HEADER_SIZE + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decompress(&b).is_err());
+
+        // Chunk count that can't fit in the data
+        let mut b = compressed[..HEADER_SIZE].to_vec();
+        b[8..16].copy_from_slice(&(u32::MAX as u64).to_le_bytes());
+        b[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        b[20..24].copy_from_slice(&1u32.to_le_bytes());
+        assert!(decompress(&b).is_err());
+
+        // Chunks claiming more than their block can hold
+        let mut b = compress(&[], 1 << 20);
+        b[8..16].copy_from_slice(&(4u64 << 20).to_le_bytes());
+        b[16..20].copy_from_slice(&4u32.to_le_bytes());
+        for _ in 0..4 {
+            b.extend_from_slice(&0u32.to_le_bytes());
+            b.extend_from_slice(&(1u32 << 20).to_le_bytes());
+        }
+        assert!(decompress(&b).is_err());
+
+        // Short first chunk, the streamed size no longer matches the header
+        let short = lz4::block::compress(&data[..10], None, false).unwrap();
+        let first = CHUNK_HEADER_SIZE + read_u32(&compressed[HEADER_SIZE..]) as usize;
+        let mut b = compressed[..HEADER_SIZE].to_vec();
+        b.extend_from_slice(&(short.len() as u32).to_le_bytes());
+        b.extend_from_slice(&10u32.to_le_bytes());
+        b.extend_from_slice(&short);
+        b.extend_from_slice(&compressed[HEADER_SIZE + first..]);
+        assert_eq!(decompress(&b).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // Files written before the chunk header
+        let mut old = Vec::new();
+        let c = lz4::block::compress(&data, None, true).unwrap();
+        old.extend_from_slice(&(c.len() as u32).to_le_bytes());
+        old.extend_from_slice(&c);
+        assert!(decompress(&old).is_err());
+
+        // Garbage in the blocks
+        let mut b = compressed;
+        for i in (HEADER_SIZE + CHUNK_HEADER_SIZE..b.len()).step_by(13) {
+            b[i] ^= 0x5a;
+        }
+        assert!(decompress(&b).is_err());
+    }
+
+    #[test]
+    fn read_ahead() {
+        // More chunks than workers, each decompressed ahead
+        let data = test_data(100_000);
+        let compressed = compress(&data, 100);
+        let mut reader = ChunkReader::new(&compressed[..]).unwrap();
+        let mut b = [0; 10];
+        reader.read_exact(&mut b).unwrap();
+        assert_eq!(reader.pending.len(), reader.max_pending.min(999));
+        let mut out = b.to_vec();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+    }
+}
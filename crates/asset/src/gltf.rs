@@ -6,95 +6,227 @@ use gltf::{Gltf};
 use std::path::{Path, PathBuf};
 use std::io::{BufReader, Cursor};
 use std::fs::File;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use scene::{Mesh, Scene, Image, Format, Material, MaterialParameter};
+use scene::{Mesh, Node, Primitive, Scene, Image, Format, Material, AlphaMode, OrmChannels, Clearcoat, Sheen, Light,
+            CameraDesc, Projection};
+use asset::mips::mipmapped_image;
 use math::{
     vec::{Vec2, Vec3, Vec4},
     mat::Mat4,
     quat::Quat,
+    transform::Transform,
+    color::srgb_to_linear,
 };
 
-#[allow(unused)]
-pub fn import_node(blob: &Vec<u8>, scene: &mut Scene, node: gltf::Node,
-    parent: Mat4) -> Option<()> {
+/// Reads the geometry of a primitive, the material is an index in
+/// Scene::materials.
+fn import_primitive(blob: &[u8], primitive: &gltf::Primitive, material: u32) -> Primitive {
+    if let Some(acc) = primitive.indices() {
+        assert!(acc.count() % 3 == 0);
+    } else {
+        panic!();
+    }
 
-    let local_transform = match node.transform() {
-        gltf::scene::Transform::Matrix { matrix } => Mat4 { e: matrix },
-        gltf::scene::Transform::Decomposed { translation, rotation, scale } =>
-            Mat4::translation(Vec3::from_slice(&translation)) *
-            Quat::from_slice(&rotation).to_mat4() *
-            Mat4::scale3(Vec3::from_slice(&scale).into()),
+    let reader = primitive.reader(|_buf| Some(blob));
+    let positions = reader.read_positions().unwrap()
+        .map(|x| Vec3::from_slice(&x)).collect();
+    let normals = reader.read_normals().unwrap()
+        .map(|x| Vec3::from_slice(&x)).collect();
+    let uvs = reader.read_tex_coords(0).unwrap().into_f32()
+        .map(|x| Vec2::from_slice(&x)).collect();
+    let tangents = reader.read_tangents().unwrap()
+        .map(|x| {
+            Vec3::from_slice(x[..3].try_into().unwrap()) * x[3]
+        }).collect();
+
+    use gltf::mesh::util::ReadIndices;
+    let indices = match reader.read_indices().unwrap() {
+        ReadIndices::U8(it) => it.map(|x| x as u32).collect(),
+        ReadIndices::U16(it) => it.map(|x| x as u32).collect(),
+        ReadIndices::U32(it) => it.collect(),
     };
 
-    let transform = parent * local_transform;
-
-    if let Some(mesh) = node.mesh() {
-        for primitive in mesh.primitives() {
-
-            if let Some(acc) = primitive.indices() {
-                assert!(acc.count() % 3 == 0);
-            } else {
-                panic!();
-            }
+    Primitive {
+        positions,
+        normals,
+        tangents,
+        uvs,
+        indices,
+        material,
+    }
+}
 
-            let reader = primitive.reader(|_buf| Some(&blob[..]));
-            let positions = reader.read_positions().unwrap()
-                .map(|x| Vec3::from_slice(&x)).collect();
-            let normals = reader.read_normals().unwrap()
-                .map(|x| Vec3::from_slice(&x)).collect();
-            let uvs = reader.read_tex_coords(0).unwrap().into_f32()
-                .map(|x| Vec2::from_slice(&x)).collect();
-            let tangents = reader.read_tangents().unwrap()
-                .map(|x| {
-                    Vec3::from_slice((&x[..3]).try_into().unwrap()) * x[3]
-                }).collect();
-
-            use gltf::mesh::util::ReadIndices;
-            let indices = match reader.read_indices().unwrap() {
-                ReadIndices::U8(it) => it.map(|x| x as u32).collect(),
-                ReadIndices::U16(it) => it.map(|x| x as u32).collect(),
-                ReadIndices::U32(it) => it.collect(),
-            };
-
-            let mat = primitive.material();
-
-            let material = Material {
-                base_color: MaterialParameter::Texture(mat.pbr_metallic_roughness()
-                    .base_color_texture().unwrap()
-                    .texture().source().index() as u32),
-                normal: MaterialParameter::None,
-                specular: MaterialParameter::None,
-                emissive: MaterialParameter::None,
-            };
-
-            scene.meshes.push(Mesh {
-                positions,
-                normals,
-                tangents,
-                uvs,
-                indices,
-                transform: transform,
-                material,
-            });
-        }
+/// Matrices that can't be decomposed, like the zero scale used to hide
+/// nodes, keep their translation with a zero scale.
+fn local_transform(node: &gltf::Node) -> Transform {
+    match node.transform() {
+        gltf::scene::Transform::Matrix { matrix } => Mat4 { e: matrix }.decompose()
+            .unwrap_or_else(|| Transform::new(
+                Vec3::new(matrix[3][0], matrix[3][1], matrix[3][2]),
+                Quat::identity(),
+                Vec3::from_scalar(0.0),
+            )),
+        gltf::scene::Transform::Decomposed { translation, rotation, scale } =>
+            Transform::new(
+                Vec3::from_slice(&translation),
+                Quat::from_slice(&rotation),
+                Vec3::from_slice(&scale),
+            ),
     }
+}
 
+/// KHR_lights_punctual light, glTF lights have no size.
+fn import_light(light: &gltf::khr_lights_punctual::Light) -> Light {
     use gltf::khr_lights_punctual::Kind;
-    if let Some(light) = node.light() {
-        match light.kind() {
-            Kind::Directional => println!("Directional"),
-            Kind::Point => println!("Point"),
-            Kind::Spot { inner_cone_angle, outer_cone_angle } =>
-                println!("Spot {inner_cone_angle}, {outer_cone_angle}"),
-        }
+    let color = Vec3::from_slice(&light.color());
+    let intensity = light.intensity();
+    let range = light.range().unwrap_or(f32::INFINITY);
+    match light.kind() {
+        Kind::Directional => Light::Directional { color, intensity, angular_radius: 0.0 },
+        Kind::Point => Light::Point { color, intensity, radius: 0.0, range },
+        Kind::Spot { inner_cone_angle, outer_cone_angle } => Light::Spot {
+            color,
+            intensity,
+            radius: 0.0,
+            range,
+            inner_cone_angle,
+            outer_cone_angle,
+        },
+    }
+}
+
+fn import_camera(camera: &gltf::Camera) -> CameraDesc {
+    let projection = match camera.projection() {
+        gltf::camera::Projection::Perspective(p) => Projection::Perspective {
+            yfov: p.yfov(),
+            aspect_ratio: p.aspect_ratio(),
+            near: p.znear(),
+            far: p.zfar().unwrap_or(f32::INFINITY),
+        },
+        gltf::camera::Projection::Orthographic(o) => Projection::Orthographic {
+            xmag: o.xmag(),
+            ymag: o.ymag(),
+            near: o.znear(),
+            far: o.zfar(),
+        },
+    };
+    CameraDesc {
+        name: camera.name().map_or_else(|| format!("camera{}", camera.index()), str::to_string),
+        projection,
+        lens: None,
     }
+}
+
+/// Adds the node and its children, scene.meshes, scene.lights and
+/// scene.cameras must be indexed like the glTF ones.
+fn import_node(scene: &mut Scene, node: gltf::Node, parent: Option<u32>) {
+    let index = scene.add_node(Node {
+        name: node.name().map_or_else(|| format!("node{}", node.index()), str::to_string),
+        parent,
+        transform: local_transform(&node),
+        mesh: node.mesh().map(|m| m.index() as u32),
+        light: node.light().map(|l| l.index() as u32),
+        camera: node.camera().map(|c| c.index() as u32),
+        ..Default::default()
+    });
 
     for n in node.children() {
-        import_node(blob, scene, n, transform)?;
+        import_node(scene, n, Some(index));
     }
+}
 
-    Some(())
+fn import_nodes(gltf: &Gltf, scene: &mut Scene) {
+    if let Some(lights) = gltf.lights() {
+        scene.lights.extend(lights.map(|l| import_light(&l)));
+    }
+    scene.cameras.extend(gltf.cameras().map(|c| import_camera(&c)));
+    for s in gltf.scenes() {
+        for n in s.nodes() {
+            import_node(scene, n, None);
+        }
+    }
+}
+
+/// Image of a texture reference.
+fn image_index(info: gltf::texture::Info) -> u32 {
+    info.texture().source().index() as u32
+}
+
+/// Image of a texture in an extension object, {"index": n} under key.
+fn extension_texture(gltf: &Gltf, ext: &gltf::json::Value, key: &str) -> Option<u32> {
+    let index = ext.get(key)?.get("index")?.as_u64()?;
+    gltf.textures().nth(index as usize).map(|t| t.source().index() as u32)
+}
+
+fn extension_factor(ext: &gltf::json::Value, key: &str, default: f32) -> f32 {
+    ext.get(key).and_then(|v| v.as_f64()).map_or(default, |v| v as f32)
+}
+
+/// Core metallic-roughness material with the ior, transmission, emissive
+/// strength, clearcoat and sheen extensions.
+fn import_material(gltf: &Gltf, mat: &gltf::Material) -> Material {
+    let pbr = mat.pbr_metallic_roughness();
+    let orm_texture = pbr.metallic_roughness_texture().map(image_index);
+    let occlusion = mat.occlusion_texture();
+    let occlusion_texture = occlusion.as_ref().map(|o| o.texture().source().index() as u32);
+    // Occlusion is often packed in R of the metallic-roughness texture
+    let packed = occlusion_texture.is_some() && occlusion_texture == orm_texture;
+    let normal = mat.normal_texture();
+    let transmission = mat.transmission();
+
+    let mut material = Material {
+        base_color_factor: Vec4::from_slice(&pbr.base_color_factor()),
+        base_color_texture: pbr.base_color_texture().map(image_index),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        orm_texture,
+        orm_channels: OrmChannels { occlusion: packed.then_some(0), ..OrmChannels::GLTF },
+        occlusion_texture: occlusion_texture.filter(|_| !packed),
+        occlusion_strength: occlusion.as_ref().map_or(1.0, |o| o.strength()),
+        normal_texture: normal.as_ref().map(|n| n.texture().source().index() as u32),
+        normal_scale: normal.as_ref().map_or(1.0, |n| n.scale()),
+        emissive_factor: Vec3::from_slice(&mat.emissive_factor()),
+        emissive_strength: mat.emissive_strength().unwrap_or(1.0),
+        emissive_texture: mat.emissive_texture().map(image_index),
+        alpha_mode: match mat.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        alpha_cutoff: mat.alpha_cutoff().unwrap_or(0.5),
+        double_sided: mat.double_sided(),
+        ior: mat.ior().unwrap_or(1.5),
+        transmission_factor: transmission.as_ref().map_or(0.0, |t| t.transmission_factor()),
+        transmission_texture: transmission.and_then(|t| t.transmission_texture()).map(image_index),
+        ..Default::default()
+    };
+
+    if let Some(ext) = mat.extension_value("KHR_materials_clearcoat") {
+        material.clearcoat = Clearcoat {
+            factor: extension_factor(ext, "clearcoatFactor", 0.0),
+            texture: extension_texture(gltf, ext, "clearcoatTexture"),
+            roughness_factor: extension_factor(ext, "clearcoatRoughnessFactor", 0.0),
+            roughness_texture: extension_texture(gltf, ext, "clearcoatRoughnessTexture"),
+            normal_texture: extension_texture(gltf, ext, "clearcoatNormalTexture"),
+        };
+    }
+    if let Some(ext) = mat.extension_value("KHR_materials_sheen") {
+        let color = ext.get("sheenColorFactor").and_then(|v| v.as_array())
+            .filter(|v| v.len() == 3)
+            .map(|v| Vec3::new(
+                v[0].as_f64().unwrap_or(0.0) as f32,
+                v[1].as_f64().unwrap_or(0.0) as f32,
+                v[2].as_f64().unwrap_or(0.0) as f32,
+            ));
+        material.sheen = Sheen {
+            color_factor: color.unwrap_or(Vec3::from_scalar(0.0)),
+            color_texture: extension_texture(gltf, ext, "sheenColorTexture"),
+            roughness_factor: extension_factor(ext, "sheenRoughnessFactor", 0.0),
+            roughness_texture: extension_texture(gltf, ext, "sheenRoughnessTexture"),
+        };
+    }
+    material
 }
 
 #[allow(unused)]
@@ -106,11 +238,30 @@ pub fn import_file(path: &Path) -> Option<Scene> {
     let mut gltf = Gltf::from_reader_without_validation(reader).ok()?;
     let blob = gltf.blob.take().unwrap();
 
-    for s in gltf.scenes() {
-        for n in s.nodes() {
-            import_node(&blob, &mut scene, n, Mat4::identity())?;
-        }
+    for mat in gltf.materials() {
+        scene.materials.push(import_material(&gltf, &mat));
     }
+    // Color textures are decoded as sRGB and normal maps only keep X and Y
+    let srgb: HashSet<u32> = scene.materials.iter()
+        .flat_map(|m| [m.base_color_texture, m.emissive_texture, m.sheen.color_texture])
+        .flatten()
+        .collect();
+    let normal: HashSet<u32> = scene.materials.iter()
+        .flat_map(|m| [m.normal_texture, m.clearcoat.normal_texture])
+        .flatten()
+        .collect();
+
+    for mesh in gltf.meshes() {
+        let primitives = mesh.primitives()
+            .map(|p| {
+                let material = p.material().index().expect("Primitive without material") as u32;
+                import_primitive(&blob, &p, material)
+            })
+            .collect();
+        scene.meshes.push(Mesh { primitives });
+    }
+
+    import_nodes(&gltf, &mut scene);
 
     for (i, img) in gltf.images().enumerate() {
         match img.source() {
@@ -122,23 +273,35 @@ pub fn import_file(path: &Path) -> Option<Scene> {
                     .with_guessed_format().unwrap();
                 let img = enc.decode().unwrap();
                 let img = img.to_rgba8();
-                scene.images.push(Image {
-                    width: img.width(),
-                    height: img.height(),
-                    data: img.into_raw(),
-                    format: Format::RGBA8,
-                });
+                let i = i as u32;
+                let format = if srgb.contains(&i) {
+                    Format::SRGBA8
+                } else if normal.contains(&i) {
+                    Format::RG8
+                } else {
+                    Format::RGBA8
+                };
+                scene.images.push(mipmapped_image(img.width(), img.height(), &img, format));
             },
             _ => panic!(),
         }
     }
 
-    return Some(scene);
+    Some(scene)
+}
+
+/// Bistro texture, 1x1 images are replaced by their value.
+#[derive(Debug, Clone, Copy)]
+enum BistroTexture {
+    None,
+    Image(u32),
+    Constant(Vec4),
 }
 
 pub struct BistroImporter {
     scene: Scene,
-    textures_map: HashMap<PathBuf, MaterialParameter>,
+    textures_map: HashMap<PathBuf, BistroTexture>,
+    materials_map: HashMap<usize, u32>,
     current_texture: u32,
     texture_directory: PathBuf,
 }
@@ -148,6 +311,7 @@ impl BistroImporter {
         let mut importer = BistroImporter {
             scene: Scene::new(),
             textures_map: HashMap::new(),
+            materials_map: HashMap::new(),
             current_texture: 0,
             texture_directory: texture_directory.to_path_buf(),
         };
@@ -156,87 +320,27 @@ impl BistroImporter {
         let mut gltf = Gltf::from_reader_without_validation(reader).ok()?;
         let blob = gltf.blob.take().unwrap();
 
-        for s in gltf.scenes() {
-            for n in s.nodes() {
-                importer.import_node(&blob, n, Mat4::identity())?;
-            }
+        for mesh in gltf.meshes() {
+            let primitives = mesh.primitives()
+                .map(|p| {
+                    let material = importer.material(&p.material());
+                    import_primitive(&blob, &p, material)
+                })
+                .collect();
+            importer.scene.meshes.push(Mesh { primitives });
         }
 
+        import_nodes(&gltf, &mut importer.scene);
+
         Some(importer.scene)
     }
 
-    fn import_node(&mut self, blob: &Vec<u8>, node: gltf::Node, parent: Mat4)
-        -> Option<()> {
-
-        let local_transform = match node.transform() {
-            gltf::scene::Transform::Matrix { matrix } => Mat4 { e: matrix },
-            gltf::scene::Transform::Decomposed { translation, rotation, scale } =>
-                Mat4::translation(Vec3::from_slice(&translation)) *
-                Quat::from_slice(&rotation).to_mat4() *
-                Mat4::scale3(Vec3::from_slice(&scale).into()),
-        };
-
-        let transform = parent * local_transform;
-
-        if let Some(mesh) = node.mesh() {
-            for primitive in mesh.primitives() {
-                if let Some(acc) = primitive.indices() {
-                    assert!(acc.count() % 3 == 0);
-                } else {
-                    panic!();
-                }
-
-                let reader = primitive.reader(|_buf| Some(&blob[..]));
-                let positions = reader.read_positions().unwrap()
-                    .map(|x| Vec3::from_slice(&x)).collect();
-                let normals = reader.read_normals().unwrap()
-                    .map(|x| Vec3::from_slice(&x)).collect();
-                let uvs = reader.read_tex_coords(0).unwrap().into_f32()
-                    .map(|x| Vec2::from_slice(&x)).collect();
-                let tangents = reader.read_tangents().unwrap()
-                    .map(|x| {
-                        Vec3::from_slice((&x[..3]).try_into().unwrap()) * x[3]
-                    }).collect();
-
-                use gltf::mesh::util::ReadIndices;
-                let indices = match reader.read_indices().unwrap() {
-                    ReadIndices::U8(it) => it.map(|x| x as u32).collect(),
-                    ReadIndices::U16(it) => it.map(|x| x as u32).collect(),
-                    ReadIndices::U32(it) => it.collect(),
-                };
-
-                let material = self.material(&primitive.material());
-
-                self.scene.meshes.push(Mesh {
-                    positions,
-                    normals,
-                    uvs,
-                    tangents,
-                    indices,
-                    transform: transform,
-                    material,
-                });
-            }
-        }
-
-        use gltf::khr_lights_punctual::Kind;
-        if let Some(light) = node.light() {
-            match light.kind() {
-                Kind::Directional => println!("Directional"),
-                Kind::Point => println!("Point"),
-                Kind::Spot { inner_cone_angle, outer_cone_angle } =>
-                    println!("Spot {inner_cone_angle}, {outer_cone_angle}"),
-            }
-        }
-
-        for n in node.children() {
-            self.import_node(blob, n, transform)?;
+    fn material(&mut self, mat: &gltf::Material) -> u32 {
+        let key = mat.index().unwrap_or(usize::MAX);
+        if let Some(&index) = self.materials_map.get(&key) {
+            return index;
         }
 
-        Some(())
-    }
-
-    fn material(&mut self, mat: &gltf::Material) -> Material {
         let base_color = mat.pbr_metallic_roughness()
             .base_color_texture().unwrap()
             .texture().source().name().unwrap();
@@ -248,24 +352,53 @@ impl BistroImporter {
         let specular   = format!("{}Specular.png",  name);
         let emissive   = format!("{}Emissive.png",  name);
 
-        let base_color = self.texture(&base_color, Format::SRGBA8);
-        let normal     = self.texture(&normal,     Format::RGBA8);
-        let specular   = self.texture(&specular,   Format::RGBA8);
-        let emissive   = self.texture(&emissive,   Format::RGBA8);
+        // Without a specular texture the surface is rough and dielectric
+        let mut material = Material { metallic_factor: 0.0, ..Default::default() };
 
-        Material {
-            base_color,
-            normal,
-            specular,
-            emissive,
+        match self.texture(&base_color, Format::SRGBA8) {
+            BistroTexture::None => {},
+            BistroTexture::Image(i) => material.base_color_texture = Some(i),
+            BistroTexture::Constant(v) =>
+                material.base_color_factor = srgb_to_linear(v.xyz()).extend(v.w),
+        }
+        // Flat normal maps are left out
+        if let BistroTexture::Image(i) = self.texture(&normal, Format::RG8) {
+            material.normal_texture = Some(i);
+        }
+        // Roughness in G and metallic in B, R is unused
+        match self.texture(&specular, Format::RGBA8) {
+            BistroTexture::None => {},
+            BistroTexture::Image(i) => {
+                material.orm_texture = Some(i);
+                material.orm_channels = OrmChannels { occlusion: None, ..OrmChannels::GLTF };
+                material.metallic_factor = 1.0;
+            },
+            BistroTexture::Constant(v) => {
+                material.roughness_factor = v.y;
+                material.metallic_factor = v.z;
+            },
         }
+        // Colors like the base color
+        match self.texture(&emissive, Format::SRGBA8) {
+            BistroTexture::None => {},
+            BistroTexture::Image(i) => {
+                material.emissive_texture = Some(i);
+                material.emissive_factor = Vec3::from_scalar(1.0);
+            },
+            BistroTexture::Constant(v) => material.emissive_factor = srgb_to_linear(v.xyz()),
+        }
+
+        self.scene.materials.push(material);
+        let index = self.scene.materials.len() as u32 - 1;
+        self.materials_map.insert(key, index);
+        index
     }
 
-    fn texture(&mut self, path: &str, format: Format) -> MaterialParameter {
+    fn texture(&mut self, path: &str, format: Format) -> BistroTexture {
         let path = self.texture_directory.join(path);
 
         if !path.exists() {
-            return MaterialParameter::None;
+            return BistroTexture::None;
         }
 
         use std::collections::hash_map::Entry;
@@ -274,36 +407,183 @@ impl BistroImporter {
             Entry::Vacant(v) => {
                 use image::DynamicImage;
 
-                let img = ImageReader::open(&v.key()).unwrap().decode().unwrap();
-                let param = match format {
-                    Format::RGBA8 | Format::SRGBA8 => {
-                        let img = img.into_rgba8();
-                        if img.width() == 1 && img.height() == 1 {
-                            let rgb = img.get_pixel(0, 0).0;
-                            let v = Vec4::new(
-                                rgb[0] as f32 / 255.0,
-                                rgb[1] as f32 / 255.0,
-                                rgb[2] as f32 / 255.0,
-                                rgb[3] as f32 / 255.0
-                            );
-                            MaterialParameter::Vec4(v)
-                        } else {
-                            self.scene.images.push(Image {
-                                width: img.width(),
-                                height: img.height(),
-                                data: img.into_raw(),
-                                format,
-                            });
-                            let param = MaterialParameter::Texture(
-                                self.current_texture);
-                            self.current_texture += 1;
-                            param
-                        }
-                    }
+                let img = ImageReader::open(v.key()).unwrap().decode().unwrap();
+                let img = img.into_rgba8();
+                let param = if img.width() == 1 && img.height() == 1 {
+                    let rgb = img.get_pixel(0, 0).0;
+                    let v = Vec4::new(
+                        rgb[0] as f32 / 255.0,
+                        rgb[1] as f32 / 255.0,
+                        rgb[2] as f32 / 255.0,
+                        rgb[3] as f32 / 255.0
+                    );
+                    BistroTexture::Constant(v)
+                } else {
+                    self.scene.images.push(mipmapped_image(img.width(), img.height(), &img, format));
+                    let param = BistroTexture::Image(self.current_texture);
+                    self.current_texture += 1;
+                    param
                 };
                 v.insert(param);
                 param
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn material() {
+        let json = br#"{
+            "asset": { "version": "2.0" },
+            "images": [{ "uri": "a.png" }, { "uri": "b.png" }, { "uri": "c.png" }],
+            "textures": [{ "source": 2 }, { "source": 0 }, { "source": 1 }],
+            "materials": [{
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": [0.5, 0.5, 1.0, 0.25],
+                    "baseColorTexture": { "index": 0 },
+                    "metallicFactor": 0.0,
+                    "metallicRoughnessTexture": { "index": 1 }
+                },
+                "occlusionTexture": { "index": 1, "strength": 0.5 },
+                "normalTexture": { "index": 2, "scale": 2.0 },
+                "emissiveFactor": [1.0, 0.0, 0.0],
+                "alphaMode": "MASK",
+                "alphaCutoff": 0.25,
+                "doubleSided": true,
+                "extensions": {
+                    "KHR_materials_emissive_strength": { "emissiveStrength": 4.0 },
+                    "KHR_materials_ior": { "ior": 1.4 },
+                    "KHR_materials_transmission": { "transmissionFactor": 0.5 },
+                    "KHR_materials_clearcoat": { "clearcoatFactor": 1.0, "clearcoatNormalTexture": { "index": 2 } },
+                    "KHR_materials_sheen": { "sheenColorFactor": [0.5, 0.5, 0.5], "sheenRoughnessFactor": 0.5 }
+                }
+            }, {
+                "occlusionTexture": { "index": 2 }
+            }]
+        }"#;
+        let gltf = Gltf::from_slice_without_validation(json).unwrap();
+
+        let m = import_material(&gltf, &gltf.materials().next().unwrap());
+        assert_eq!(m.base_color_factor.to_slice(), [0.5, 0.5, 1.0, 0.25]);
+        assert_eq!(m.base_color_texture, Some(2));
+        assert_eq!((m.metallic_factor, m.roughness_factor), (0.0, 1.0));
+        // Occlusion packed in the metallic-roughness texture
+        assert_eq!(m.orm_texture, Some(0));
+        assert_eq!(m.orm_channels, OrmChannels::GLTF);
+        assert_eq!((m.occlusion_texture, m.occlusion_strength), (None, 0.5));
+        assert_eq!((m.normal_texture, m.normal_scale), (Some(1), 2.0));
+        assert_eq!(m.emission().to_slice(), [4.0, 0.0, 0.0]);
+        assert_eq!((m.alpha_mode, m.alpha_cutoff, m.double_sided), (AlphaMode::Mask, 0.25, true));
+        assert_eq!((m.ior, m.transmission_factor), (1.4, 0.5));
+        assert_eq!((m.clearcoat.factor, m.clearcoat.normal_texture), (1.0, Some(1)));
+        assert_eq!((m.sheen.color_factor.to_slice(), m.sheen.roughness_factor), ([0.5; 3], 0.5));
+
+        let m = import_material(&gltf, &gltf.materials().nth(1).unwrap());
+        assert_eq!((m.orm_texture, m.orm_channels.occlusion), (None, None));
+        assert_eq!(m.occlusion_texture, Some(1));
+        assert_eq!((m.metallic_factor, m.ior, m.clearcoat.factor), (1.0, 1.5, 0.0));
+    }
+
+    #[test]
+    fn lights() {
+        let json = br#"{
+            "asset": { "version": "2.0" },
+            "extensionsUsed": ["KHR_lights_punctual"],
+            "extensions": {
+                "KHR_lights_punctual": { "lights": [
+                    { "type": "directional", "color": [1.0, 0.5, 0.5], "intensity": 3.0 },
+                    { "type": "spot", "range": 10.0,
+                      "spot": { "innerConeAngle": 0.25, "outerConeAngle": 0.5 } }
+                ] }
+            },
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [
+                { "name": "sun", "children": [1],
+                  "extensions": { "KHR_lights_punctual": { "light": 0 } } },
+                { "translation": [0.0, 2.0, 0.0],
+                  "extensions": { "KHR_lights_punctual": { "light": 1 } } }
+            ]
+        }"#;
+        let gltf = Gltf::from_slice_without_validation(json).unwrap();
+        let mut scene = Scene::new();
+        import_nodes(&gltf, &mut scene);
+
+        assert!(matches!(scene.lights[0], Light::Directional { color, intensity: 3.0, .. }
+                         if color.to_slice() == [1.0, 0.5, 0.5]));
+        assert!(matches!(scene.lights[1], Light::Spot {
+            intensity: 1.0, range: 10.0, inner_cone_angle: 0.25, outer_cone_angle: 0.5, ..
+        }));
+        let instances = scene.light_instances();
+        assert_eq!(instances.iter().map(|i| (i.node, i.light)).collect::<Vec<_>>(), [(0, 0), (1, 1)]);
+        assert_eq!(instances[1].transform.transform_point(Vec3::from_scalar(0.0)).to_slice(), [0.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn hidden_node() {
+        let json = br#"{
+            "asset": { "version": "2.0" },
+            "scenes": [{ "nodes": [0, 1] }],
+            "nodes": [
+                { "matrix": [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 1] },
+                { "scale": [0.0, 0.0, 0.0] }
+            ]
+        }"#;
+        let gltf = Gltf::from_slice_without_validation(json).unwrap();
+        let mut scene = Scene::new();
+        import_nodes(&gltf, &mut scene);
+
+        let t = scene.nodes[0].transform;
+        assert_eq!((t.translation.to_slice(), t.scale.to_slice()), ([1.0, 2.0, 3.0], [0.0; 3]));
+        assert_eq!(scene.nodes[1].transform.scale.to_slice(), [0.0; 3]);
+    }
+
+    #[test]
+    fn cameras() {
+        let json = br#"{
+            "asset": { "version": "2.0" },
+            "cameras": [
+                { "type": "perspective", "perspective": { "yfov": 0.5, "znear": 0.1 } },
+                { "name": "top", "type": "orthographic",
+                  "orthographic": { "xmag": 2.0, "ymag": 1.0, "znear": 0.0, "zfar": 10.0 } }
+            ],
+            "scenes": [{ "nodes": [0, 2, 3] }],
+            "nodes": [
+                { "translation": [1.0, 0.0, 0.0], "scale": [3.0, 1.0, 1.0], "children": [1] },
+                { "translation": [0.0, 0.0, 5.0], "rotation": [0.0, 0.38268343, 0.0, 0.92387953], "camera": 0 },
+                { "camera": 0 },
+                { "camera": 1 }
+            ]
+        }"#;
+        let gltf = Gltf::from_slice_without_validation(json).unwrap();
+        let mut scene = Scene::new();
+        import_nodes(&gltf, &mut scene);
+
+        // Cameras are shared by the nodes like lights
+        assert_eq!(scene.cameras.len(), 2);
+        assert_eq!(scene.nodes.iter().map(|n| n.camera).collect::<Vec<_>>(), [None, Some(0), Some(0), Some(1)]);
+        assert_eq!(scene.cameras[0].name, "camera0");
+        assert_eq!(scene.cameras[1].name, "top");
+        assert!(matches!(scene.cameras[1].projection, Projection::Orthographic { xmag: 2.0, ymag: 1.0, .. }));
+        assert!(matches!(scene.cameras[0].projection, Projection::Perspective {
+            yfov: 0.5, aspect_ratio: None, near: 0.1, far
+        } if far == f32::INFINITY));
+
+        let instances = scene.camera_instances();
+        assert_eq!(instances.iter().map(|i| (i.node, i.camera)).collect::<Vec<_>>(), [(1, 0), (2, 0), (3, 1)]);
+
+        // Rotated child of a non-uniformly scaled parent, the world
+        // transform is sheared
+        let world = instances[0].transform;
+        assert!(world.decompose().is_none());
+        let camera = scene.cameras[0].to_camera(&world, Vec3::new(0.0, 1.0, 0.0), 2.0).unwrap();
+        math::assert_approx_eq!(camera.position, Vec3::new(1.0, 0.0, 5.0));
+        math::assert_approx_eq!(camera.forward, Vec3::new(-3.0, 0.0, -1.0).normalized(), epsilon = 1e-6);
+        assert!(camera.projection().e.iter().flatten().all(|x| x.is_finite()));
+
+        let camera = scene.cameras[0].to_camera(&instances[1].transform, Vec3::new(0.0, 1.0, 0.0), 2.0).unwrap();
+        assert_eq!(camera.forward.to_slice(), [0.0, 0.0, -1.0]);
+    }
+}
@@ -1,51 +1,208 @@
 #![feature(allocator_api)]
 
-use std::{path::Path, alloc::{Allocator, Global}};
-use scene::{Scene, Deserialize};
-use std::io::Read;
-
-fn load_data_from_disk(path: &Path) -> Option<Vec<u8>> {
-    let mut file = std::fs::File::open(path).ok()?;
-    let mut data: Vec<u8> = Vec::new();
-    file.read_to_end(&mut data).ok()?;
-
-    let mut view = &data[..];
-    let mut total_size = 0;
-    let mut buf: Vec<u8> = vec![0; 4 * 1024 * 1024 * 1024];
-    loop {
-        if view.len() == 0 {
-            break;
+use std::{path::Path, alloc::Allocator, io::{BufRead, BufReader, Read}};
+use scene::{Scene, SceneView, ContainerError, SectionKind};
+use scene::container::{self, SectionHeader};
+
+pub mod chunks;
+pub mod mips;
+
+#[derive(Debug)]
+pub enum AssetError {
+    Io(std::io::Error),
+    /// A chunk header or compressed block is malformed, see chunks.rs.
+    Compression,
+    /// Only uncompressed files can be mapped.
+    Compressed,
+    Container(ContainerError),
+}
+
+impl std::fmt::Display for AssetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssetError::Io(e) => write!(f, "{e}"),
+            AssetError::Compression => write!(f, "corrupted compressed asset file"),
+            AssetError::Compressed =>
+                write!(f, "compressed asset file can't be mapped, rebuild it with asset_builder --uncompressed"),
+            AssetError::Container(e) => write!(f, "{e}"),
         }
-        let c_size = u32::from_le_bytes((&view[..4]).try_into().unwrap()) as usize;
-        let u_size = u32::from_le_bytes((&view[4..8]).try_into().unwrap()) as usize;
+    }
+}
 
-        lz4::block::decompress_to_buffer(&view[4..4 + c_size], None, &mut buf[total_size..]).unwrap();
+impl std::error::Error for AssetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AssetError::Io(e) => Some(e),
+            AssetError::Compression | AssetError::Compressed => None,
+            AssetError::Container(e) => Some(e),
+        }
+    }
+}
 
-        view = &view[4 + c_size..];
-        total_size += u_size;
+impl From<std::io::Error> for AssetError {
+    fn from(e: std::io::Error) -> Self {
+        AssetError::Io(e)
     }
+}
 
-    buf.truncate(total_size);
-    Some(buf)
+impl From<ContainerError> for AssetError {
+    fn from(e: ContainerError) -> Self {
+        AssetError::Container(e)
+    }
 }
 
-pub fn load_scene_from_asset_file(path: &Path) -> Option<Scene> {
-    let buf = load_data_from_disk(path)?;
+/// Reads a scene container one section at a time, each section is
+/// deserialized before the next one is read, so with a ChunkReader only one
+/// section and the chunks decompressed ahead are in memory.
+pub fn read_scene_in<A: Allocator + Copy>(mut r: impl Read, a: A) -> Result<Scene<A>, AssetError> {
+    let header_size = core::mem::size_of::<container::Header>();
+    let mut b = Vec::new();
+    (&mut r).take(header_size as u64).read_to_end(&mut b)?;
+    let header = container::Header::parse(&b)?;
 
-    let mut buf = &buf[..];
-    let scene = Scene::<Global>::deserialize(&mut buf);
-    assert!(buf.len() == 0);
+    // Sizes are read through take so corrupted ones fail at the end of the
+    // input instead of allocating
+    let table_size = header.table_size().ok_or(ContainerError::Truncated)?;
+    b.clear();
+    (&mut r).take(table_size as u64).read_to_end(&mut b)?;
+    if b.len() != table_size {
+        return Err(ContainerError::Truncated.into());
+    }
+    let mut sections: Vec<SectionHeader> = container::parse_table(&b)?;
+    sections.sort_by_key(|s| s.offset);
 
-    Some(scene)
+    let mut scene = Scene::new_in(a);
+    let mut pos = (header_size + table_size) as u64;
+    for s in sections {
+        // Padding between the sections
+        let padding = s.offset.checked_sub(pos).ok_or(ContainerError::Overlap { kind: s.kind })?;
+        if std::io::copy(&mut (&mut r).take(padding), &mut std::io::sink())? != padding {
+            return Err(ContainerError::Truncated.into());
+        }
+
+        b.clear();
+        (&mut r).take(s.size).read_to_end(&mut b)?;
+        if b.len() as u64 != s.size {
+            return Err(ContainerError::Truncated.into());
+        }
+        s.check(&b)?;
+        // Unknown kinds are skipped like in Container::parse
+        if let Ok(kind) = SectionKind::try_from(s.kind) {
+            scene.read_section(kind, Some(&b))
+                .map_err(|e| container::section_error(kind, s.offset, e))?;
+        }
+        pos = s.offset + s.size;
+    }
+    scene.validate()?;
+    Ok(scene)
 }
 
-pub fn load_scene_from_asset_file_with_allocator<A: Allocator + Copy>(path: &Path, a: A) -> Option<Box<Scene<A>, A>> {
-    let buf = load_data_from_disk(path)?;
+/// Asset files are either a scene container or a chunked LZ4 compressed
+/// one, which is decompressed on worker threads while the scene is read.
+pub fn load_scene_from_asset_file_with_allocator<A: Allocator + Copy>(path: &Path, a: A)
+    -> Result<Box<Scene<A>, A>, AssetError> {
+    let mut file = BufReader::new(std::fs::File::open(path)?);
+    let scene = if file.fill_buf()?.starts_with(&chunks::MAGIC) {
+        read_scene_in(chunks::ChunkReader::new(file)?, a)?
+    } else {
+        // read_scene_in rejects anything else, including the files written
+        // before the chunk header
+        read_scene_in(file, a)?
+    };
+    Ok(Box::new_in(scene, a))
+}
 
-    let mut buf = &buf[..];
-    let mut scene = Box::new_in(Scene::<A>::new_in(a), a);
-    *scene = Scene::deserialize_in(&mut buf, a);
-    assert!(buf.len() == 0);
+pub fn load_scene_from_asset_file(path: &Path) -> Result<Scene, AssetError> {
+    load_scene_from_asset_file_with_allocator(path, std::alloc::Global).map(|s| *s)
+}
 
-    Some(scene)
+/// Uncompressed asset file mapped in memory, the vectors of its view are
+/// borrowed from the mapping.
+pub struct MappedScene {
+    mmap: memmap2::Mmap,
+}
+
+impl MappedScene {
+    pub fn open(path: &Path) -> Result<MappedScene, AssetError> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: asset files are not modified while the renderer runs
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        if !mmap.starts_with(&scene::container::MAGIC) {
+            return Err(AssetError::Compressed);
+        }
+        Ok(MappedScene { mmap })
+    }
+
+    pub fn view(&self) -> Result<SceneView<'_>, AssetError> {
+        Ok(SceneView::parse(&self.mmap)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scene::Serialize;
+
+    #[test]
+    fn mapped_scene() {
+        let mut scene = Scene::new();
+        scene.images.push(scene::Image {
+            width: 1,
+            height: 1,
+            format: scene::Format::RGBA8,
+            mip_levels: 1,
+            array_layers: 1,
+            data: vec![1, 2, 3, 4],
+        });
+        let path = std::env::temp_dir().join(format!("mapped_scene_{}.bin", std::process::id()));
+        std::fs::write(&path, scene.serialize()).unwrap();
+
+        let mapped = MappedScene::open(&path).unwrap();
+        let view = mapped.view().unwrap();
+        assert_eq!(view.images[0].data, [1, 2, 3, 4]);
+        assert!(load_scene_from_asset_file(&path).is_ok());
+        drop(mapped);
+
+        std::fs::write(&path, chunks::compress(&scene.serialize(), 16)).unwrap();
+        assert!(matches!(MappedScene::open(&path), Err(AssetError::Compressed)));
+        assert_eq!(load_scene_from_asset_file(&path).unwrap().images[0].data, [1, 2, 3, 4]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn streamed_scene() {
+        let mut scene = Scene::new();
+        scene.materials.push(scene::Material::default());
+        scene.lights.push(scene::Light::Environment { image: 0, intensity: 2.0 });
+        scene.images.push(scene::Image {
+            width: 2,
+            height: 1,
+            format: scene::Format::RGBA8,
+            mip_levels: 1,
+            array_layers: 1,
+            data: (0..8).collect(),
+        });
+        let buf = scene.serialize();
+
+        let streamed = read_scene_in(chunks::ChunkReader::new(&chunks::compress(&buf, 7)[..]).unwrap(),
+                                     std::alloc::Global).unwrap();
+        assert_eq!(streamed.images[0].data, scene.images[0].data);
+        assert_eq!(streamed.materials.len(), 1);
+        assert_eq!(streamed.lights.len(), 1);
+
+        // Same errors as Scene::from_container
+        for len in [0, 10, 20, buf.len() - 1] {
+            assert!(read_scene_in(&buf[..len], std::alloc::Global).is_err());
+        }
+        let mut b = buf.clone();
+        *b.last_mut().unwrap() ^= 1;
+        assert_eq!(read_scene_in(&b[..], std::alloc::Global).unwrap_err().to_string(),
+                   Scene::from_container(&b).unwrap_err().to_string());
+
+        // Cross-section references are checked once every section is read
+        scene.images.clear();
+        let b = scene.serialize();
+        assert_eq!(read_scene_in(&b[..], std::alloc::Global).unwrap_err().to_string(),
+                   Scene::from_container(&b).unwrap_err().to_string());
+    }
 }
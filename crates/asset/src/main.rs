@@ -10,61 +10,40 @@ use scene::{Scene, Serialize, Deserialize};
 const IMPORT_BISTRO: bool = true;
 
 fn print_scene_stats(scene: &Scene) {
-    let mut base_none = 0;
-    let mut spec_none = 0;
-    let mut emis_none = 0;
-    let mut norm_none = 0;
-
-    let mut base_text = 0;
-    let mut spec_text = 0;
-    let mut emis_text = 0;
-    let mut norm_text = 0;
-
-    let mut base_const = 0;
-    let mut spec_const = 0;
-    let mut emis_const = 0;
-    let mut norm_const = 0;
-
-    use scene::MaterialParameter;
-
-    for m in scene.meshes.iter() {
-        match m.material.base_color {
-            MaterialParameter::None       => base_none  += 1,
-            MaterialParameter::Texture(_) => base_text  += 1,
-            MaterialParameter::Vec4(_)    => base_const += 1,
-            _ => unreachable!(),
-        }
-
-        match m.material.specular {
-            MaterialParameter::None       => spec_none  += 1,
-            MaterialParameter::Texture(_) => spec_text  += 1,
-            MaterialParameter::Vec4(_)    => spec_const += 1,
-            _ => unreachable!(),
-        }
-
-        match m.material.normal {
-            MaterialParameter::None       => norm_none  += 1,
-            MaterialParameter::Texture(_) => norm_text  += 1,
-            MaterialParameter::Vec4(_)    => norm_const += 1,
-            _ => unreachable!(),
-        }
-
-        match m.material.emissive {
-            MaterialParameter::None       => emis_none  += 1,
-            MaterialParameter::Texture(_) => emis_text  += 1,
-            MaterialParameter::Vec4(_)    => emis_const += 1,
-            _ => unreachable!(),
-        }
-    }
-    println!("Scene: {} meshes and {} images", scene.meshes.len(), scene.images.len());
-    println!("Base: {base_none:4} / {base_const:4} / {base_text:4}");
-    println!("Spec: {spec_none:4} / {spec_const:4} / {spec_text:4}");
-    println!("Norm: {norm_none:4} / {norm_const:4} / {norm_text:4}");
-    println!("Emis: {emis_none:4} / {emis_const:4} / {emis_text:4}");
+    use scene::{AlphaMode, Material};
+
+    let count = |f: fn(&Material) -> bool| scene.materials.iter().filter(|m| f(m)).count();
+
+    println!("Scene: {} meshes, {} materials and {} images",
+             scene.meshes.len(), scene.materials.len(), scene.images.len());
+    println!("Nodes: {} with {} mesh instances", scene.nodes.len(), scene.flatten().len());
+    println!("Lights: {} with {} instances", scene.lights.len(), scene.light_instances().len());
+    println!("Cameras: {}", scene.cameras.len());
+    println!("Textured: {:4} base / {:4} ORM / {:4} normal / {:4} emissive",
+             count(|m| m.base_color_texture.is_some()), count(|m| m.orm_texture.is_some()),
+             count(|m| m.normal_texture.is_some()), count(|m| m.emissive_texture.is_some()));
+    println!("Emissive: {:4}", count(|m| m.emission().max_element() > 0.0));
+    println!("Alpha:    {:4} masked / {:4} blended",
+             count(|m| m.alpha_mode == AlphaMode::Mask), count(|m| m.alpha_mode == AlphaMode::Blend));
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    // Uncompressed files can be mapped with asset::MappedScene
+    let uncompressed = std::env::args().any(|a| a == "--uncompressed");
+    // In MiB
+    let chunk_size = std::env::args()
+        .find_map(|a| a.strip_prefix("--chunk-size=").map(|s| {
+            let max = asset::chunks::MAX_CHUNK_SIZE / (1024 * 1024);
+            s.parse::<usize>().ok()
+                .filter(|mib| (1..=max).contains(mib))
+                .and_then(|mib| mib.checked_mul(1024 * 1024))
+                .unwrap_or_else(|| {
+                    eprintln!("Invalid chunk size {s}, expected --chunk-size=<MiB> between 1 and {max}");
+                    std::process::exit(1)
+                })
+        }))
+        .unwrap_or(asset::chunks::DEFAULT_CHUNK_SIZE);
+    let args: Vec<String> = std::env::args().filter(|a| !a.starts_with("--")).collect();
     let input_path = Path::new(&args[1]);
     let textures_directory = Path::new(&args[2]);
     let output_path = Path::new(&args[3]);
@@ -78,11 +57,10 @@ fn main() {
 
     print_scene_stats(&scene);
 
-    let vec = scene.serialize();
-    let mut file = std::fs::File::create(output_path).expect("Failed to create output file");
-    for c in vec.chunks(1024 * 1024 * 1024) {
-        let compressed = lz4::block::compress(&c, None, true).expect("Failed to compress scene");
-        file.write_all(&(compressed.len() as u32).to_le_bytes()).expect("Failed to write file");
-        file.write_all(&compressed).expect("Failed to write file")
+    let mut vec = scene.serialize();
+    if !uncompressed {
+        vec = asset::chunks::compress(&vec, chunk_size);
     }
+    let mut file = std::fs::File::create(output_path).expect("Failed to create output file");
+    file.write_all(&vec).expect("Failed to write file");
 }
@@ -0,0 +1,95 @@
+//! Mip chains of 8 bit images, box filtered in linear space for the sRGB
+//! formats.
+
+use math::color::{srgb_eotf, srgb_oetf};
+use scene::{image::mip_extent, image::max_mip_levels, Format, Image};
+
+/// Image with a full mip chain from RGBA8 pixels, keeping the channels of
+/// format.
+pub fn mipmapped_image(width: u32, height: u32, rgba: &[u8], format: Format) -> Image {
+    let channels = match format {
+        Format::R8 => 1,
+        Format::RG8 => 2,
+        Format::RGBA8 | Format::SRGBA8 => 4,
+        _ => panic!("Mips of {format:?} images are not generated"),
+    };
+    assert_eq!(rgba.len(), width as usize * height as usize * 4);
+
+    // Alpha is linear
+    let srgb = |c: usize| format.is_srgb() && c < 3;
+    let decode = |v: u8, c: usize| {
+        let v = v as f32 / 255.0;
+        if srgb(c) { srgb_eotf(v) } else { v }
+    };
+    let encode = |v: f32, c: usize| {
+        let v = if srgb(c) { srgb_oetf(v) } else { v };
+        (v.clamp(0.0, 1.0) * 255.0).round() as u8
+    };
+
+    let mip_levels = max_mip_levels(width, height);
+    let mut data = Vec::with_capacity(format.image_size(width, height, mip_levels, 1).unwrap());
+    let mut level: Vec<f32> = rgba.chunks_exact(4)
+        .flat_map(|p| (0..channels).map(|c| decode(p[c], c)))
+        .collect();
+    let (mut w, mut h) = (width as usize, height as usize);
+
+    for l in 0..mip_levels {
+        data.extend(level.iter().enumerate().map(|(i, &v)| encode(v, i % channels)));
+        if l + 1 == mip_levels {
+            break;
+        }
+
+        // Average of 2x2 pixels. Sizes are rounded down, so the last row or
+        // column of an odd size is dropped, and a size of 1 is repeated
+        let (nw, nh) = mip_extent(width, height, l + 1);
+        let (nw, nh) = (nw as usize, nh as usize);
+        let mut next = vec![0.0; nw * nh * channels];
+        for y in 0..nh {
+            for x in 0..nw {
+                for (sx, sy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let src = ((2 * y + sy).min(h - 1) * w + (2 * x + sx).min(w - 1)) * channels;
+                    for c in 0..channels {
+                        next[(y * nw + x) * channels + c] += level[src + c] * 0.25;
+                    }
+                }
+            }
+        }
+        (level, w, h) = (next, nw, nh);
+    }
+
+    Image {
+        width,
+        height,
+        format,
+        mip_levels,
+        array_layers: 1,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_chain() {
+        let rgba: Vec<u8> = (0..5 * 3 * 4).map(|i| i as u8 * 4).collect();
+        let image = mipmapped_image(5, 3, &rgba, Format::RGBA8);
+        assert_eq!(image.mip_levels, 3);
+        assert_eq!(image.data.len(), Format::RGBA8.image_size(5, 3, 3, 1).unwrap());
+        assert_eq!(image.subresource(0, 0), &rgba[..]);
+        assert_eq!(image.mip_extent(1), (2, 1));
+        assert_eq!(image.subresource(0, 2).len(), 4);
+
+        let image = mipmapped_image(5, 3, &rgba, Format::RG8);
+        assert_eq!(image.data.len(), Format::RG8.image_size(5, 3, 3, 1).unwrap());
+        assert_eq!(image.subresource(0, 0)[..4], [0, 4, 16, 20]);
+
+        // Black and white checkerboard averages to linear grey, alpha stays linear
+        let rgba = [[0, 0, 0, 0], [255; 4], [255; 4], [0, 0, 0, 0]].concat();
+        let image = mipmapped_image(2, 2, &rgba, Format::SRGBA8);
+        assert_eq!(image.subresource(0, 1), [188, 188, 188, 128]);
+        let image = mipmapped_image(2, 2, &rgba, Format::RGBA8);
+        assert_eq!(image.subresource(0, 1), [128; 4]);
+    }
+}
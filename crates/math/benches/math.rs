@@ -0,0 +1,53 @@
+//! Compare the scalar and simd paths with:
+//!
+//! cargo bench
+//! cargo bench --features simd
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use math::vec::{Vec3, Vec4};
+use math::mat::Mat4;
+use math::quat::Quat;
+
+fn transform() -> Mat4 {
+    Mat4::translation(Vec3::new(1., 2., 3.)) *
+        Quat::rotate(Vec3::new(0., 0.6, 0.8), 0.7).to_mat4() *
+        Mat4::scale3(Vec3::new(2., 3., 4.))
+}
+
+fn vectors(c: &mut Criterion) {
+    let a = Vec4::new(1., 2., 3., 4.);
+    let b = Vec4::new(5., 6., 7., 8.);
+    c.bench_function("vec4 dot", |bench| {
+        bench.iter(|| black_box(a).dot(black_box(b)))
+    });
+
+    let a = Vec3::new(1., 2., 3.);
+    let b = Vec3::new(4., 5., 6.);
+    c.bench_function("vec3 cross", |bench| {
+        bench.iter(|| black_box(a).cross(black_box(b)))
+    });
+}
+
+fn matrices(c: &mut Criterion) {
+    let a = transform();
+    let b = a.inverse();
+    c.bench_function("mat4 * mat4", |bench| {
+        bench.iter(|| black_box(a) * black_box(b))
+    });
+
+    let v = Vec4::new(1., 2., 3., 1.);
+    c.bench_function("mat4 * vec4", |bench| {
+        bench.iter(|| black_box(a) * black_box(v))
+    });
+
+    let mut points: Vec<Vec3> = (0..100_000)
+        .map(|i| Vec3::new(i as f32, (i * 2) as f32, (i * 3) as f32))
+        .collect();
+    c.bench_function("mat4 transform_points 100k", |bench| {
+        bench.iter(|| black_box(a).transform_points(black_box(&mut points)))
+    });
+}
+
+criterion_group!(benches, vectors, matrices);
+criterion_main!(benches);
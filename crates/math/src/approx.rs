@@ -0,0 +1,251 @@
+//! Approximate equality of scalars and math types, compared component-wise.
+//!
+//! Two values are equal if the absolute difference is within epsilon, which
+//! handles values close to zero, or if they pass the relative or ULPs test.
+
+use crate::vec::*;
+use crate::mat::*;
+use crate::quat::{Quat, Quatd};
+use crate::transform::{Transform, Transformd};
+
+pub trait ApproxEq {
+    type Scalar: Copy;
+
+    /// Epsilon and max relative difference used by approx_eq.
+    fn default_epsilon() -> Self::Scalar;
+
+    /// |a - b| <= epsilon
+    fn abs_diff_eq(&self, other: &Self, epsilon: Self::Scalar) -> bool;
+
+    /// |a - b| <= epsilon or |a - b| <= max_relative * max(|a|, |b|)
+    fn relative_eq(&self, other: &Self, epsilon: Self::Scalar,
+                   max_relative: Self::Scalar) -> bool;
+
+    /// |a - b| <= epsilon or a and b are at most max_ulps representable
+    /// values apart.
+    fn ulps_eq(&self, other: &Self, epsilon: Self::Scalar, max_ulps: u32) -> bool;
+
+    #[inline]
+    fn approx_eq(&self, other: &Self) -> bool {
+        self.relative_eq(other, Self::default_epsilon(), Self::default_epsilon())
+    }
+}
+
+macro_rules! approx_float_impl {
+    ($t: ident, $bits: ident) => {
+        impl ApproxEq for $t {
+            type Scalar = $t;
+
+            #[inline]
+            fn default_epsilon() -> $t {
+                $t::EPSILON
+            }
+
+            #[inline]
+            fn abs_diff_eq(&self, other: &$t, epsilon: $t) -> bool {
+                (self - other).abs() <= epsilon
+            }
+
+            #[inline]
+            fn relative_eq(&self, other: &$t, epsilon: $t, max_relative: $t) -> bool {
+                // Handles infinities
+                if self == other {
+                    return true;
+                }
+                let diff = (self - other).abs();
+                diff <= epsilon || diff <= self.abs().max(other.abs()) * max_relative
+            }
+
+            #[inline]
+            fn ulps_eq(&self, other: &$t, epsilon: $t, max_ulps: u32) -> bool {
+                if self.abs_diff_eq(other, epsilon) || self == other {
+                    return true;
+                }
+                if self.is_nan() || other.is_nan() ||
+                    self.is_sign_positive() != other.is_sign_positive() {
+                    return false;
+                }
+                let a = self.to_bits() as $bits;
+                let b = other.to_bits() as $bits;
+                (a - b).unsigned_abs() <= max_ulps as _
+            }
+        }
+    }
+}
+
+approx_float_impl!(f32, i32);
+approx_float_impl!(f64, i64);
+
+macro_rules! approx_int_impl {
+    ($t: ident) => {
+        /// Integers are exact, every test is on the absolute difference.
+        impl ApproxEq for $t {
+            type Scalar = $t;
+
+            #[inline]
+            fn default_epsilon() -> $t {
+                0
+            }
+
+            #[inline]
+            fn abs_diff_eq(&self, other: &$t, epsilon: $t) -> bool {
+                (*self as i64 - *other as i64).abs() <= epsilon as i64
+            }
+
+            #[inline]
+            fn relative_eq(&self, other: &$t, epsilon: $t, _max_relative: $t) -> bool {
+                self.abs_diff_eq(other, epsilon)
+            }
+
+            #[inline]
+            fn ulps_eq(&self, other: &$t, epsilon: $t, max_ulps: u32) -> bool {
+                self.abs_diff_eq(other, epsilon) ||
+                    (*self as i64 - *other as i64).unsigned_abs() <= max_ulps as u64
+            }
+        }
+    }
+}
+
+approx_int_impl!(i32);
+approx_int_impl!(u32);
+
+/// Component-wise implementation over the scalars returned by $get.
+macro_rules! approx_impl {
+    ($name: ident, $t: ident, $get: expr) => {
+        impl ApproxEq for $name {
+            type Scalar = $t;
+
+            #[inline]
+            fn default_epsilon() -> $t {
+                <$t as ApproxEq>::default_epsilon()
+            }
+
+            fn abs_diff_eq(&self, other: &$name, epsilon: $t) -> bool {
+                let (a, b) = ($get(self), $get(other));
+                a.iter().zip(b.iter()).all(|(a, b)| a.abs_diff_eq(b, epsilon))
+            }
+
+            fn relative_eq(&self, other: &$name, epsilon: $t, max_relative: $t) -> bool {
+                let (a, b) = ($get(self), $get(other));
+                a.iter().zip(b.iter()).all(|(a, b)| a.relative_eq(b, epsilon, max_relative))
+            }
+
+            fn ulps_eq(&self, other: &$name, epsilon: $t, max_ulps: u32) -> bool {
+                let (a, b) = ($get(self), $get(other));
+                a.iter().zip(b.iter()).all(|(a, b)| a.ulps_eq(b, epsilon, max_ulps))
+            }
+        }
+    }
+}
+
+macro_rules! approx_vec_impl {
+    ($($v: ident: $t: ident),*) => {
+        $( approx_impl!($v, $t, |v: &$v| v.to_slice()); )*
+    }
+}
+
+approx_vec_impl!(Vec2i: i32, Vec3i: i32, Vec4i: i32,
+                 Vec2u: u32, Vec3u: u32, Vec4u: u32,
+                 Vec2: f32, Vec3: f32, Vec4: f32,
+                 Vec2d: f64, Vec3d: f64, Vec4d: f64);
+
+approx_impl!(Mat2, f32, |m: &Mat2| bytemuck::cast::<Mat2, [f32; 4]>(*m));
+approx_impl!(Mat3, f32, |m: &Mat3| bytemuck::cast::<Mat3, [f32; 9]>(*m));
+approx_impl!(Mat4, f32, |m: &Mat4| bytemuck::cast::<Mat4, [f32; 16]>(*m));
+approx_impl!(Mat2d, f64, |m: &Mat2d| bytemuck::cast::<Mat2d, [f64; 4]>(*m));
+approx_impl!(Mat3d, f64, |m: &Mat3d| bytemuck::cast::<Mat3d, [f64; 9]>(*m));
+approx_impl!(Mat4d, f64, |m: &Mat4d| bytemuck::cast::<Mat4d, [f64; 16]>(*m));
+
+approx_impl!(Quat, f32, |q: &Quat| [q.x, q.y, q.z, q.w]);
+approx_impl!(Quatd, f64, |q: &Quatd| [q.x, q.y, q.z, q.w]);
+
+approx_impl!(Transform, f32, |t: &Transform| bytemuck::cast::<Transform, [f32; 10]>(*t));
+approx_impl!(Transformd, f64, |t: &Transformd| bytemuck::cast::<Transformd, [f64; 10]>(*t));
+
+/// Asserts that two values are approximately equal, with the relative test
+/// and default tolerances or with the given ones:
+///
+/// assert_approx_eq!(a, b);
+/// assert_approx_eq!(a, b, epsilon = 1e-6);
+/// assert_approx_eq!(a, b, epsilon = 1e-6, max_relative = 1e-4);
+/// assert_approx_eq!(a, b, epsilon = 1e-6, ulps = 4);
+#[macro_export]
+macro_rules! assert_approx_eq {
+    ($a: expr, $b: expr) => {
+        $crate::assert_approx_eq!(@check $a, $b, |a, b| $crate::approx::ApproxEq::approx_eq(a, b))
+    };
+    ($a: expr, $b: expr, epsilon = $eps: expr) => {
+        $crate::assert_approx_eq!(@check $a, $b,
+            |a, b| $crate::approx::ApproxEq::abs_diff_eq(a, b, $eps))
+    };
+    ($a: expr, $b: expr, epsilon = $eps: expr, max_relative = $rel: expr) => {
+        $crate::assert_approx_eq!(@check $a, $b,
+            |a, b| $crate::approx::ApproxEq::relative_eq(a, b, $eps, $rel))
+    };
+    ($a: expr, $b: expr, epsilon = $eps: expr, ulps = $ulps: expr) => {
+        $crate::assert_approx_eq!(@check $a, $b,
+            |a, b| $crate::approx::ApproxEq::ulps_eq(a, b, $eps, $ulps))
+    };
+    (@check $a: expr, $b: expr, $eq: expr) => {
+        match (&$a, &$b) {
+            (a, b) => {
+                if !$eq(a, b) {
+                    panic!("assertion failed: {} approx equals {}\n  left: {:?}\n right: {:?}",
+                           stringify!($a), stringify!($b), a, b);
+                }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scalars() {
+        assert!(1.0f32.abs_diff_eq(&1.05, 0.1));
+        assert!(!1.0f32.abs_diff_eq(&1.2, 0.1));
+
+        assert!(1e6f32.relative_eq(&(1e6 + 1.0), 0.0, 1e-5));
+        assert!(!1e-6f32.relative_eq(&2e-6, 0.0, 1e-5));
+        assert!(f32::INFINITY.relative_eq(&f32::INFINITY, 0.0, 0.0));
+        assert!(!f32::NAN.approx_eq(&f32::NAN));
+
+        let next = f32::from_bits(1.0f32.to_bits() + 3);
+        assert!(1.0f32.ulps_eq(&next, 0.0, 3));
+        assert!(!1.0f32.ulps_eq(&next, 0.0, 2));
+        assert!((-0.0f64).ulps_eq(&0.0, 0.0, 0));
+        assert!(!(-1e-300f64).ulps_eq(&1e-300, 0.0, 100));
+
+        assert!(3i32.abs_diff_eq(&5, 2));
+        assert!(!u32::MAX.approx_eq(&0));
+    }
+
+    #[test]
+    fn types() {
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        assert_approx_eq!(a, a * 3.0 / 3.0);
+        assert_approx_eq!(a, a + 1e-4, epsilon = 1e-3);
+        assert_approx_eq!(a * 1e6, a * 1e6 + 1.0, epsilon = 0.0, max_relative = 1e-6);
+        assert_approx_eq!(a, Vec3::new(1.0, 2.0, f32::from_bits(3.0f32.to_bits() + 1)),
+                          epsilon = 0.0, ulps = 1);
+        assert!(!a.approx_eq(&Vec3::new(1.0, 2.0, 3.001)));
+
+        let q = Quat::rotate(Vec3::new(0., 0., 1.), 1.0);
+        assert_approx_eq!(q.to_mat4().inverse(), q.inverse().to_mat4(), epsilon = 1e-6);
+        assert_approx_eq!(q * q.inverse(), Quat::identity(), epsilon = 1e-6);
+        assert!(!Mat3::identity().approx_eq(&Mat3::scale_uniform(2.0)));
+
+        let t = Transform::new(a, q, Vec3::from_scalar(2.0));
+        assert_approx_eq!(t.to_mat4().decompose().unwrap(), t, epsilon = 1e-5);
+
+        assert_approx_eq!(Vec4u::new(1, 2, 3, 4), Vec4u::new(1, 2, 3, 5), epsilon = 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "approx equals")]
+    fn assert_fails() {
+        assert_approx_eq!(Vec2::new(0.0, 1.0), Vec2::new(0.0, 1.1), epsilon = 1e-3);
+    }
+}
@@ -0,0 +1,503 @@
+//! Color spaces, exposure and tonemapping. Colors are linear unless the name
+//! says otherwise, the formulas match the references cited on each function
+//! so CPU tools and shaders produce the same values.
+
+// Constants are kept with the digits of their references
+#![allow(clippy::excessive_precision)]
+
+use crate::vec::Vec3;
+use crate::mat::{Mat3, Mat3d};
+use crate::vec::Vec3d;
+
+#[inline]
+fn map(c: Vec3, f: impl Fn(f32) -> f32) -> Vec3 {
+    Vec3::new(f(c.x), f(c.y), f(c.z))
+}
+
+const fn from_rows(r: [[f32; 3]; 3]) -> Mat3 {
+    Mat3 {
+        e: [
+            [r[0][0], r[1][0], r[2][0]],
+            [r[0][1], r[1][1], r[2][1]],
+            [r[0][2], r[1][2], r[2][2]],
+        ],
+    }
+}
+
+//
+// Transfer functions
+//
+
+/// sRGB OETF (IEC 61966-2-1), linear to encoded, clamps to [0, 1] like
+/// linearToSRGB in the shaders.
+#[inline]
+pub fn srgb_oetf(v: f32) -> f32 {
+    let v = v.clamp(0.0, 1.0);
+    if v > 0.0031308 {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    } else {
+        v * 12.92
+    }
+}
+
+/// sRGB EOTF (IEC 61966-2-1), encoded to linear.
+#[inline]
+pub fn srgb_eotf(v: f32) -> f32 {
+    if v > 0.04045 {
+        ((v + 0.055) / 1.055).powf(2.4)
+    } else {
+        v / 12.92
+    }
+}
+
+#[inline]
+pub fn linear_to_srgb(c: Vec3) -> Vec3 {
+    map(c, srgb_oetf)
+}
+
+#[inline]
+pub fn srgb_to_linear(c: Vec3) -> Vec3 {
+    map(c, srgb_eotf)
+}
+
+//
+// Color spaces
+//
+
+/// CIE 1931 xy chromaticities.
+pub const D65: [f64; 2] = [0.3127, 0.3290];
+/// ACES white point, approximately D60.
+pub const D60: [f64; 2] = [0.32168, 0.33767];
+
+pub const REC709_PRIMARIES: [[f64; 2]; 3] = [[0.64, 0.33], [0.30, 0.60], [0.15, 0.06]];
+pub const AP1_PRIMARIES: [[f64; 2]; 3] = [[0.713, 0.293], [0.165, 0.830], [0.128, 0.044]];
+
+fn xy_to_xyz(xy: [f64; 2]) -> Vec3d {
+    Vec3d::new(xy[0] / xy[1], 1.0, (1.0 - xy[0] - xy[1]) / xy[1])
+}
+
+/// RGB to XYZ matrix of the space with the given primaries and white point,
+/// the white (1, 1, 1) maps to Y = 1.
+pub fn rgb_to_xyz_matrix(primaries: [[f64; 2]; 3], white: [f64; 2]) -> Mat3d {
+    let p = Mat3d::from_columns(&[
+        xy_to_xyz(primaries[0]),
+        xy_to_xyz(primaries[1]),
+        xy_to_xyz(primaries[2]),
+    ]);
+    let s = p.inverse() * xy_to_xyz(white);
+    p * Mat3d::scale(s)
+}
+
+/// Bradford chromatic adaptation from the src to the dst white point.
+pub fn chromatic_adaptation_matrix(src: [f64; 2], dst: [f64; 2]) -> Mat3d {
+    let b = Mat3d::from_columns(&[
+        Vec3d::new(0.8951, -0.7502, 0.0389),
+        Vec3d::new(0.2664, 1.7135, -0.0685),
+        Vec3d::new(-0.1614, 0.0367, 1.0296),
+    ]);
+    let s = b * xy_to_xyz(src);
+    let d = b * xy_to_xyz(dst);
+    b.inverse() * Mat3d::scale(Vec3d::new(d.x / s.x, d.y / s.y, d.z / s.z)) * b
+}
+
+// Matrices below are rgb_to_xyz_matrix and chromatic_adaptation_matrix
+// evaluated in f64, the tests check that they stay in sync.
+
+/// Linear Rec.709 (sRGB primaries, D65) to XYZ.
+pub const REC709_TO_XYZ: Mat3 = from_rows([
+    [0.4123907993, 0.3575843394, 0.1804807884],
+    [0.2126390059, 0.7151686788, 0.0721923154],
+    [0.0193308187, 0.1191947798, 0.9505321522],
+]);
+
+pub const XYZ_TO_REC709: Mat3 = from_rows([
+    [3.2409699419, -1.5373831776, -0.4986107603],
+    [-0.9692436363, 1.8759675015, 0.0415550574],
+    [0.0556300797, -0.2039769589, 1.0569715142],
+]);
+
+/// ACEScg (AP1 primaries, ACES white) to XYZ, without adaptation.
+pub const ACESCG_TO_XYZ: Mat3 = from_rows([
+    [0.6624541811, 0.1340042065, 0.1561876870],
+    [0.2722287168, 0.6740817658, 0.0536895174],
+    [-0.0055746495, 0.0040607335, 1.0103391003],
+]);
+
+pub const XYZ_TO_ACESCG: Mat3 = from_rows([
+    [1.6410233797, -0.3248032942, -0.2364246952],
+    [-0.6636628587, 1.6153315917, 0.0167563477],
+    [0.0117218943, -0.0082844420, 0.9883948585],
+]);
+
+/// Bradford adaptation of XYZ from D65 to the ACES white.
+pub const D65_TO_D60: Mat3 = from_rows([
+    [1.0130349146, 0.0061052578, -0.0149709436],
+    [0.0076982301, 0.9981633521, -0.0050320385],
+    [-0.0028413174, 0.0046851567, 0.9245061375],
+]);
+
+pub const D60_TO_D65: Mat3 = from_rows([
+    [0.9872240087, -0.0061132286, 0.0159532883],
+    [-0.0075983718, 1.0018614847, 0.0053300358],
+    [0.0030725771, -0.0050959615, 1.0816806031],
+]);
+
+/// XYZ_TO_ACESCG * D65_TO_D60 * REC709_TO_XYZ
+pub const REC709_TO_ACESCG: Mat3 = from_rows([
+    [0.6130974024, 0.3395231462, 0.0473794514],
+    [0.0701937225, 0.9163538791, 0.0134523985],
+    [0.0206155929, 0.1095697729, 0.8698146342],
+]);
+
+pub const ACESCG_TO_REC709: Mat3 = from_rows([
+    [1.7050509927, -0.6217921207, -0.0832588720],
+    [-0.1302564175, 1.1408047366, -0.0105483191],
+    [-0.0240033568, -0.1289689761, 1.1529723329],
+]);
+
+/// Linear RGB working spaces.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorSpace {
+    Rec709,
+    AcesCg,
+}
+
+impl ColorSpace {
+    /// To XYZ with the native white point of the space.
+    pub fn to_xyz(self) -> Mat3 {
+        match self {
+            ColorSpace::Rec709 => REC709_TO_XYZ,
+            ColorSpace::AcesCg => ACESCG_TO_XYZ,
+        }
+    }
+
+    pub fn from_xyz(self) -> Mat3 {
+        match self {
+            ColorSpace::Rec709 => XYZ_TO_REC709,
+            ColorSpace::AcesCg => XYZ_TO_ACESCG,
+        }
+    }
+
+    /// Matrix converting colors from self to dst, white points are adapted so
+    /// that white stays white.
+    pub fn conversion_to(self, dst: ColorSpace) -> Mat3 {
+        match (self, dst) {
+            (ColorSpace::Rec709, ColorSpace::AcesCg) => REC709_TO_ACESCG,
+            (ColorSpace::AcesCg, ColorSpace::Rec709) => ACESCG_TO_REC709,
+            _ => Mat3::identity(),
+        }
+    }
+
+    /// Weights of the Y row of to_xyz.
+    pub fn luminance_weights(self) -> Vec3 {
+        self.to_xyz().to_rows()[1]
+    }
+
+    #[inline]
+    pub fn luminance(self, c: Vec3) -> f32 {
+        c.dot(self.luminance_weights())
+    }
+}
+
+//
+// Exposure
+//
+
+/// Scale of an exposure compensation in stops.
+#[inline]
+pub fn exposure_from_ev(ev: f32) -> f32 {
+    ev.exp2()
+}
+
+/// Exposure value at ISO 100 of a camera with the given f-number, shutter
+/// time in seconds and ISO sensitivity.
+#[inline]
+pub fn ev100(aperture: f32, shutter_time: f32, iso: f32) -> f32 {
+    (aperture * aperture / shutter_time * 100.0 / iso).log2()
+}
+
+/// Scale that maps the luminance saturating the sensor at the given EV100 to
+/// 1, from "Moving Frostbite to Physically Based Rendering" (Lagarde, de
+/// Rousiers).
+#[inline]
+pub fn exposure_from_ev100(ev100: f32) -> f32 {
+    1.0 / (1.2 * ev100.exp2())
+}
+
+//
+// Tonemapping, all operators take and return linear Rec.709 colors, apply
+// linear_to_srgb for display.
+//
+
+/// c / (1 + c), what the postprocess shader does.
+#[inline]
+pub fn tonemap_reinhard(c: Vec3) -> Vec3 {
+    map(c, |x| x / (1.0 + x))
+}
+
+/// Reinhard with a white point, values at white map to 1.
+#[inline]
+pub fn tonemap_reinhard_extended(c: Vec3, white: f32) -> Vec3 {
+    let w2 = white * white;
+    map(c, |x| x * (1.0 + x / w2) / (1.0 + x))
+}
+
+/// Reinhard extended on luminance, preserves hue.
+#[inline]
+pub fn tonemap_reinhard_extended_luminance(c: Vec3, white: f32) -> Vec3 {
+    let l = ColorSpace::Rec709.luminance(c);
+    if l <= 0.0 {
+        return Vec3::from_scalar(0.0);
+    }
+    let w2 = white * white;
+    c * ((1.0 + l / w2) / (1.0 + l))
+}
+
+/// ACES RRT + ODT fit from BakingLab (Stephen Hill).
+pub fn tonemap_aces_fitted(c: Vec3) -> Vec3 {
+    const INPUT: Mat3 = from_rows([
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ]);
+    const OUTPUT: Mat3 = from_rows([
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ]);
+
+    let v = map(INPUT * c, |v| {
+        let a = v * (v + 0.0245786) - 0.000090537;
+        let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+        a / b
+    });
+    map(OUTPUT * v, |x| x.clamp(0.0, 1.0))
+}
+
+/// AgX with the default look, polynomial fit of the contrast curve by
+/// Benjamin Wrensch. The curve outputs display encoded values, they are
+/// decoded with a 2.2 gamma to return linear values like the others.
+pub fn tonemap_agx(c: Vec3) -> Vec3 {
+    const INSET: Mat3 = Mat3 {
+        e: [
+            [0.842479062253094, 0.0423282422610123, 0.0423756549057051],
+            [0.0784335999999992, 0.878468636469772, 0.0784336],
+            [0.0792237451477643, 0.0791661274605434, 0.879142973793104],
+        ],
+    };
+    const OUTSET: Mat3 = Mat3 {
+        e: [
+            [1.19687900512017, -0.0528968517574562, -0.0529716355144438],
+            [-0.0980208811401368, 1.15190312990417, -0.0980434501171241],
+            [-0.0990297440797205, -0.0989611768448433, 1.15107367264116],
+        ],
+    };
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let v = map(INSET * c, |x| {
+        let x = (x.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x +
+            0.4298 * x2 + 0.1191 * x - 0.00232
+    });
+    map(OUTSET * v, |x| x.clamp(0.0, 1.0).powf(2.2))
+}
+
+/// Gran Turismo tonemapper, from "HDR Theory and Practice" (Uchimura 2017).
+#[derive(Debug, Copy, Clone)]
+pub struct Uchimura {
+    pub max_brightness: f32,
+    pub contrast: f32,
+    pub linear_start: f32,
+    pub linear_length: f32,
+    pub black_tightness: f32,
+    pub pedestal: f32,
+}
+
+impl Default for Uchimura {
+    fn default() -> Self {
+        Uchimura {
+            max_brightness: 1.0,
+            contrast: 1.0,
+            linear_start: 0.22,
+            linear_length: 0.4,
+            black_tightness: 1.33,
+            pedestal: 0.0,
+        }
+    }
+}
+
+impl Uchimura {
+    pub fn tonemap_scalar(&self, x: f32) -> f32 {
+        let p = self.max_brightness;
+        let a = self.contrast;
+        let m = self.linear_start;
+        let c = self.black_tightness;
+
+        let l0 = (p - m) * self.linear_length / a;
+        let s0 = m + l0;
+        let s1 = m + a * l0;
+        let c2 = a * p / (p - s1);
+        let cp = -c2 / p;
+
+        let t = (x / m).clamp(0.0, 1.0);
+        let w0 = 1.0 - t * t * (3.0 - 2.0 * t);
+        let w2 = if x >= m + l0 { 1.0 } else { 0.0 };
+        let w1 = 1.0 - w0 - w2;
+
+        let toe = m * (x / m).powf(c) + self.pedestal;
+        let shoulder = p - (p - s1) * (cp * (x - s0)).exp();
+        let linear = m + a * (x - m);
+
+        toe * w0 + linear * w1 + shoulder * w2
+    }
+
+    pub fn tonemap(&self, c: Vec3) -> Vec3 {
+        map(c, |x| self.tonemap_scalar(x))
+    }
+}
+
+/// Tonemapping operator selection.
+#[derive(Debug, Copy, Clone)]
+pub enum Tonemapper {
+    None,
+    Reinhard,
+    ReinhardExtended { white: f32 },
+    AcesFitted,
+    AgX,
+    Uchimura(Uchimura),
+}
+
+impl Tonemapper {
+    pub fn apply(&self, c: Vec3) -> Vec3 {
+        match self {
+            Tonemapper::None => c,
+            Tonemapper::Reinhard => tonemap_reinhard(c),
+            Tonemapper::ReinhardExtended { white } => tonemap_reinhard_extended(c, *white),
+            Tonemapper::AcesFitted => tonemap_aces_fitted(c),
+            Tonemapper::AgX => tonemap_agx(c),
+            Tonemapper::Uchimura(u) => u.tonemap(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_vec_eq(a: Vec3, b: Vec3, eps: f32) {
+        assert!((a - b).length() < eps, "{:?} != {:?}", a, b);
+    }
+
+    fn assert_mat_eq(a: Mat3, b: Mat3d, eps: f64) {
+        for j in 0..3 {
+            for i in 0..3 {
+                assert!((a.e[j][i] as f64 - b.e[j][i]).abs() < eps, "{:?}\n{:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn srgb() {
+        // Reference values from the exact IEC 61966-2-1 formulas in f64
+        assert!((srgb_oetf(0.18) - 0.4613561295).abs() < 1e-6);
+        assert!((srgb_oetf(0.5) - 0.7353569831).abs() < 1e-6);
+        assert!((srgb_eotf(0.5) - 0.2140411405).abs() < 1e-6);
+        assert!((srgb_oetf(0.002) - 0.02584).abs() < 1e-7);
+        assert_eq!(srgb_oetf(-1.0), 0.0);
+        assert!((srgb_oetf(2.0) - 1.0).abs() < 1e-6);
+
+        for i in 0..=255 {
+            let v = i as f32 / 255.0;
+            assert!((srgb_oetf(srgb_eotf(v)) - v).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn matrices() {
+        let rec709 = rgb_to_xyz_matrix(REC709_PRIMARIES, D65);
+        let ap1 = rgb_to_xyz_matrix(AP1_PRIMARIES, D60);
+        let adapt = chromatic_adaptation_matrix(D65, D60);
+        let to_acescg = ap1.inverse() * adapt * rec709;
+
+        assert_mat_eq(REC709_TO_XYZ, rec709, 1e-7);
+        assert_mat_eq(XYZ_TO_REC709, rec709.inverse(), 1e-6);
+        assert_mat_eq(ACESCG_TO_XYZ, ap1, 1e-7);
+        assert_mat_eq(XYZ_TO_ACESCG, ap1.inverse(), 1e-6);
+        assert_mat_eq(D65_TO_D60, adapt, 1e-7);
+        assert_mat_eq(D60_TO_D65, adapt.inverse(), 1e-6);
+        assert_mat_eq(REC709_TO_ACESCG, to_acescg, 1e-7);
+        assert_mat_eq(ACESCG_TO_REC709, to_acescg.inverse(), 1e-6);
+
+        // Published Rec.709 to ACEScg matrix (colour-science)
+        let r = REC709_TO_ACESCG.to_rows();
+        assert!((r[0].x - 0.6130974024).abs() < 1e-7);
+        assert!((r[1].y - 0.9163538791).abs() < 1e-7);
+        assert!((r[2].z - 0.8698146342).abs() < 1e-7);
+
+        // White is preserved and has luminance 1
+        let white = Vec3::from_scalar(1.0);
+        assert_vec_eq(REC709_TO_ACESCG * white, white, 1e-6);
+        assert_vec_eq(ACESCG_TO_REC709 * white, white, 1e-6);
+        assert!((ColorSpace::Rec709.luminance(white) - 1.0).abs() < 1e-6);
+        assert!((ColorSpace::AcesCg.luminance(white) - 1.0).abs() < 1e-6);
+
+        let c = Vec3::new(0.18, 0.5, 0.9);
+        let acescg = ColorSpace::Rec709.conversion_to(ColorSpace::AcesCg) * c;
+        assert_vec_eq(ColorSpace::AcesCg.conversion_to(ColorSpace::Rec709) * acescg, c, 1e-6);
+        assert!((c.luminance() - ColorSpace::Rec709.luminance(c)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn exposure() {
+        assert_eq!(exposure_from_ev(-2.0), 0.25);
+        assert_eq!(ev100(1.0, 1.0, 100.0), 0.0);
+        // Sunny 16 rule
+        assert!((ev100(16.0, 1.0 / 100.0, 100.0) - 14.643856).abs() < 1e-5);
+        assert!((exposure_from_ev100(0.0) - 1.0 / 1.2).abs() < 1e-7);
+    }
+
+    #[test]
+    fn tonemappers() {
+        // Reference values from f64 evaluations of the original code
+        let grey = Vec3::from_scalar(0.18);
+        let c = Vec3::new(1.0, 0.5, 0.1);
+        let bright = Vec3::new(10.0, 2.0, 0.0);
+
+        assert_vec_eq(tonemap_reinhard(c), Vec3::new(0.5, 1.0 / 3.0, 1.0 / 11.0), 1e-6);
+        assert_vec_eq(tonemap_reinhard_extended(Vec3::new(1.0, 0.5, 4.0), 4.0),
+                      Vec3::new(0.53125, 0.34375, 1.0), 1e-6);
+        let l = tonemap_reinhard_extended_luminance(Vec3::from_scalar(4.0), 4.0);
+        assert_vec_eq(l, Vec3::from_scalar(1.0), 1e-5);
+
+        assert_vec_eq(tonemap_aces_fitted(grey), Vec3::new(0.1055912, 0.1055912, 0.1055902), 1e-6);
+        assert_vec_eq(tonemap_aces_fitted(c), Vec3::new(0.6388707, 0.3838670, 0.0822001), 1e-6);
+        assert_vec_eq(tonemap_aces_fitted(bright), Vec3::new(1.0, 0.8422640, 0.3755316), 1e-6);
+
+        assert_vec_eq(tonemap_agx(grey), Vec3::new(0.2144674, 0.2145327, 0.2145367), 1e-5);
+        assert_vec_eq(tonemap_agx(c), Vec3::new(0.6241500, 0.4390840, 0.1746965), 1e-5);
+        assert_vec_eq(tonemap_agx(bright), Vec3::new(1.0, 0.7851967, 0.4155438), 1e-5);
+
+        let u = Uchimura::default();
+        let expected = [(0.1, 0.0869875), (0.18, 0.1789948), (0.5, 0.5),
+                        (1.0, 0.8278324), (10.0, 1.0)];
+        for &(x, y) in expected.iter() {
+            assert!((u.tonemap_scalar(x) - y).abs() < 1e-6, "{} {}", x, u.tonemap_scalar(x));
+        }
+        assert_eq!(u.tonemap_scalar(0.0), 0.0);
+
+        // Operators are monotonic and bounded below the white point
+        let ops = [Tonemapper::Reinhard, Tonemapper::ReinhardExtended { white: 8.0 },
+                   Tonemapper::AcesFitted, Tonemapper::AgX, Tonemapper::Uchimura(u)];
+        for op in ops.iter() {
+            let mut prev = -1.0;
+            for i in 0..800 {
+                let v = op.apply(Vec3::from_scalar(i as f32 * 0.01)).y;
+                assert!(v >= prev && v <= 1.0 + 1e-6, "{:?} at {}", op, i);
+                prev = v;
+            }
+        }
+    }
+}
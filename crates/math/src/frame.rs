@@ -0,0 +1,180 @@
+//! Orthonormal shading frame, same as Frame in shaders/utils.hlsl. Local
+//! coordinates have the normal along +Z, the tangent along +X and the
+//! bitangent along +Y.
+
+use crate::vec::Vec3;
+use crate::mat::Mat3;
+
+use bytemuck::{Pod, Zeroable};
+
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct Frame {
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    pub normal: Vec3,
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Frame::new(Vec3::new(1., 0., 0.), Vec3::new(0., 1., 0.), Vec3::new(0., 0., 1.))
+    }
+}
+
+impl Frame {
+    /// The vectors must already be orthonormal, like makeFrame.
+    #[inline]
+    pub fn new(tangent: Vec3, bitangent: Vec3, normal: Vec3) -> Frame {
+        Frame { tangent, bitangent, normal }
+    }
+
+    /// Right handed frame around a unit normal, branchless construction from
+    /// "Building an Orthonormal Basis, Revisited" (Duff et al. 2017).
+    #[inline]
+    pub fn from_normal(n: Vec3) -> Frame {
+        let sign = if n.z >= 0.0 { 1.0 } else { -1.0 };
+        let a = -1.0 / (sign + n.z);
+        let b = n.x * n.y * a;
+        Frame {
+            tangent: Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+            bitangent: Vec3::new(b, sign + n.y * n.y * a, -n.y),
+            normal: n,
+        }
+    }
+
+    /// Gram-Schmidt orthonormalization of an interpolated or normal mapped
+    /// tangent space. The tangent is made perpendicular to the normal and the
+    /// bitangent only gives the handedness, so mirrored UVs keep a left handed
+    /// frame. Falls back to from_normal if the tangent is parallel to the normal.
+    pub fn from_tbn(tangent: Vec3, bitangent: Vec3, normal: Vec3) -> Frame {
+        let n = normal.normalized();
+        let t = tangent - n * n.dot(tangent);
+        let len = t.norm();
+        if len.is_nan() || len <= 1e-6 * tangent.norm() {
+            return Frame::from_normal(n);
+        }
+
+        let t = t / len;
+        let b = n.cross(t);
+        let b = if b.dot(bitangent) < 0.0 { -b } else { b };
+        Frame { tangent: t, bitangent: b, normal: n }
+    }
+
+    #[inline]
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        self.tangent * v.x + self.bitangent * v.y + self.normal * v.z
+    }
+
+    #[inline]
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(self.tangent.dot(v), self.bitangent.dot(v), self.normal.dot(v))
+    }
+
+    /// world_from_local, the vectors are the columns.
+    #[inline]
+    pub fn to_mat3(&self) -> Mat3 {
+        Mat3::from_columns(&[self.tangent, self.bitangent, self.normal])
+    }
+
+    /// +1 for right handed frames and -1 for mirrored ones, like the w of
+    /// glTF tangents.
+    #[inline]
+    pub fn handedness(&self) -> f32 {
+        1.0f32.copysign(self.tangent.cross(self.bitangent).dot(self.normal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Rng;
+    use crate::assert_approx_eq;
+
+    fn random_unit(rng: &mut Rng) -> Vec3 {
+        loop {
+            let v = Vec3::new(rng.range(-1., 1.), rng.range(-1., 1.), rng.range(-1., 1.));
+            if v.norm() > 0.1 && v.norm() <= 1.0 {
+                return v.normalized();
+            }
+        }
+    }
+
+    fn assert_orthonormal(f: &Frame) {
+        for v in [f.tangent, f.bitangent, f.normal] {
+            assert!((v.norm() - 1.0).abs() < 1e-5, "{:?}", f);
+        }
+        assert!(f.tangent.dot(f.bitangent).abs() < 1e-5, "{:?}", f);
+        assert!(f.tangent.dot(f.normal).abs() < 1e-5, "{:?}", f);
+        assert!(f.bitangent.dot(f.normal).abs() < 1e-5, "{:?}", f);
+    }
+
+    #[test]
+    fn from_normal() {
+        let mut rng = Rng(21);
+        let mut normals: Vec<Vec3> = (0..10000).map(|_| random_unit(&mut rng)).collect();
+        // Poles and the -Z singularity of the original Frisvad construction
+        normals.extend_from_slice(&[
+            Vec3::new(0., 0., 1.), Vec3::new(0., 0., -1.), Vec3::new(1., 0., 0.),
+            Vec3::new(0., -1., 0.), Vec3::new(0., 1e-4, -1.).normalized(),
+            Vec3::new(-0., -0., -1.),
+        ]);
+
+        for n in normals {
+            let f = Frame::from_normal(n);
+            assert_orthonormal(&f);
+            assert_eq!(f.handedness(), 1.0);
+            assert_approx_eq!(f.tangent.cross(f.bitangent), n, epsilon = 1e-5);
+        }
+
+        let f = Frame::from_normal(Vec3::new(0., 0., 1.));
+        assert_approx_eq!(f.to_mat3(), Mat3::identity());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut rng = Rng(22);
+        for _ in 0..1000 {
+            let f = Frame::from_normal(random_unit(&mut rng));
+            let v = Vec3::new(rng.range(-2., 2.), rng.range(-2., 2.), rng.range(-2., 2.));
+
+            assert_approx_eq!(f.to_world(f.to_local(v)), v, epsilon = 1e-5);
+            assert_approx_eq!(f.to_local(f.to_world(v)), v, epsilon = 1e-5);
+            assert_approx_eq!(f.to_mat3() * v, f.to_world(v), epsilon = 1e-5);
+            assert!((f.to_local(f.normal).z - 1.0).abs() < 1e-6);
+            assert!((f.to_local(v).norm() - v.norm()).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn from_tbn() {
+        let mut rng = Rng(23);
+        for _ in 0..1000 {
+            let n = random_unit(&mut rng);
+            let t = random_unit(&mut rng);
+            let b = random_unit(&mut rng);
+            let f = Frame::from_tbn(t * 3.0, b, n * 0.5);
+
+            assert_orthonormal(&f);
+            assert_approx_eq!(f.normal, n, epsilon = 1e-5);
+            // Tangent is the input tangent projected on the normal plane
+            assert_approx_eq!(f.tangent, (t - n * n.dot(t)).normalized(), epsilon = 1e-4);
+            assert_eq!(f.handedness(), 1.0f32.copysign(n.cross(t).dot(b)));
+        }
+
+        // Already orthonormal frames are unchanged, mirrored ones stay mirrored
+        let f = Frame::from_normal(Vec3::new(1., 2., 3.).normalized());
+        let g = Frame::from_tbn(f.tangent, f.bitangent, f.normal);
+        assert_approx_eq!(g.tangent, f.tangent, epsilon = 1e-6);
+        assert_approx_eq!(g.bitangent, f.bitangent, epsilon = 1e-6);
+        let m = Frame::from_tbn(f.tangent, -f.bitangent, f.normal);
+        assert_eq!(m.handedness(), -1.0);
+        assert_approx_eq!(m.to_world(m.to_local(Vec3::new(1., 2., 3.))), Vec3::new(1., 2., 3.),
+                          epsilon = 1e-5);
+
+        // Degenerate tangent
+        let f = Frame::from_tbn(Vec3::new(0., 0., 2.), Vec3::new(0., 1., 0.), Vec3::new(0., 0., 1.));
+        assert_orthonormal(&f);
+        let f = Frame::from_tbn(Vec3::from_scalar(0.), Vec3::new(0., 1., 0.), Vec3::new(0., 0., 1.));
+        assert_orthonormal(&f);
+    }
+}
@@ -0,0 +1,655 @@
+use crate::vec::{Vec3, Vec4};
+use crate::mat::Mat4;
+
+use bytemuck::{Pod, Zeroable};
+
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+/// Barycentric coordinates follow the DXR convention, u is the weight of
+/// the second vertex and v the weight of the third.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct TriangleHit {
+    pub t: f32,
+    pub u: f32,
+    pub v: f32,
+}
+
+impl Ray {
+    #[inline]
+    pub fn new(origin: Vec3, direction: Vec3) -> Ray {
+        Ray { origin, direction }
+    }
+
+    #[inline]
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    /// Möller-Trumbore, hits both front and back faces. Fast, but rays
+    /// through a shared edge can miss both triangles.
+    pub fn intersect_triangle(&self, p0: Vec3, p1: Vec3, p2: Vec3,
+                              t_min: f32, t_max: f32) -> Option<TriangleHit> {
+        let e1 = p1 - p0;
+        let e2 = p2 - p0;
+
+        let p = self.direction.cross(e2);
+        let det = e1.dot(p);
+        if det.abs() < f32::EPSILON * e1.length() * e2.length() {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let s = self.origin - p0;
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(e1);
+        let v = self.direction.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = e2.dot(q) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        Some(TriangleHit { t, u, v })
+    }
+
+    /// Watertight ray-triangle intersection from:
+    /// Woop, Benthin, Wald - Watertight Ray/Triangle Intersection (2013)
+    ///
+    /// A ray through an edge or vertex shared by two triangles always hits
+    /// at least one of them.
+    pub fn intersect_triangle_watertight(&self, p0: Vec3, p1: Vec3, p2: Vec3,
+                                         t_min: f32, t_max: f32)
+        -> Option<TriangleHit> {
+        let d = self.direction.to_slice();
+
+        // Permute so that z is the largest direction component
+        let mut kz = 0;
+        for i in 1..3 {
+            if d[i].abs() > d[kz].abs() {
+                kz = i;
+            }
+        }
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        if d[kz] < 0.0 {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+
+        // Shear constants
+        let sx = d[kx] / d[kz];
+        let sy = d[ky] / d[kz];
+        let sz = 1.0 / d[kz];
+
+        let a = (p0 - self.origin).to_slice();
+        let b = (p1 - self.origin).to_slice();
+        let c = (p2 - self.origin).to_slice();
+
+        let ax = a[kx] - sx * a[kz];
+        let ay = a[ky] - sy * a[kz];
+        let bx = b[kx] - sx * b[kz];
+        let by = b[ky] - sy * b[kz];
+        let cx = c[kx] - sx * c[kz];
+        let cy = c[ky] - sy * c[kz];
+
+        let mut u = cx * by - cy * bx;
+        let mut v = ax * cy - ay * cx;
+        let mut w = bx * ay - by * ax;
+
+        // Recompute in double precision on edges
+        if u == 0.0 || v == 0.0 || w == 0.0 {
+            u = (cx as f64 * by as f64 - cy as f64 * bx as f64) as f32;
+            v = (ax as f64 * cy as f64 - ay as f64 * cx as f64) as f32;
+            w = (bx as f64 * ay as f64 - by as f64 * ax as f64) as f32;
+        }
+
+        if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+            return None;
+        }
+
+        let det = u + v + w;
+        if det == 0.0 {
+            return None;
+        }
+
+        let az = sz * a[kz];
+        let bz = sz * b[kz];
+        let cz = sz * c[kz];
+        let t = (u * az + v * bz + w * cz) / det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        Some(TriangleHit { t, u: v / det, v: w / det })
+    }
+
+    /// Slab test, returns the entry and exit distances clipped to
+    /// [t_min, t_max]. Rays parallel to a slab and lying on its boundary
+    /// are considered inside.
+    pub fn intersect_aabb(&self, aabb: &Aabb, t_min: f32, t_max: f32)
+        -> Option<(f32, f32)> {
+        let o = self.origin.to_slice();
+        let d = self.direction.to_slice();
+        let min = aabb.min.to_slice();
+        let max = aabb.max.to_slice();
+
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+        for i in 0..3 {
+            // Avoid the NaN produced by 0 * inf when the origin lies on a
+            // slab the ray is parallel to.
+            if d[i] == 0.0 {
+                if o[i] < min[i] || o[i] > max[i] {
+                    return None;
+                }
+                continue;
+            }
+
+            let inv = 1.0 / d[i];
+            let mut near = (min[i] - o[i]) * inv;
+            let mut far = (max[i] - o[i]) * inv;
+            if near > far {
+                std::mem::swap(&mut near, &mut far);
+            }
+
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+            if t0 > t1 {
+                return None;
+            }
+        }
+
+        Some((t0, t1))
+    }
+
+    /// Returns the closest distance in [t_min, t_max], if any.
+    pub fn intersect_sphere(&self, sphere: &Sphere, t_min: f32, t_max: f32)
+        -> Option<f32> {
+        let oc = self.origin - sphere.center;
+        let a = self.direction.length2();
+        let half_b = oc.dot(self.direction);
+        let c = oc.length2() - sphere.radius * sphere.radius;
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrt_d = discriminant.sqrt();
+        let t = (-half_b - sqrt_d) / a;
+        if t >= t_min && t <= t_max {
+            return Some(t);
+        }
+
+        let t = (-half_b + sqrt_d) / a;
+        if t >= t_min && t <= t_max {
+            return Some(t);
+        }
+
+        None
+    }
+
+    pub fn intersect_plane(&self, plane: &Plane, t_min: f32, t_max: f32)
+        -> Option<f32> {
+        let denom = plane.normal.dot(self.direction);
+        if denom == 0.0 {
+            return None;
+        }
+
+        let t = -plane.signed_distance(self.origin) / denom;
+        if t >= t_min && t <= t_max {
+            Some(t)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl Aabb {
+    #[inline]
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// Inverted box that acts as the identity for union.
+    #[inline]
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vec3::from_scalar(f32::INFINITY),
+            max: Vec3::from_scalar(f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Aabb {
+        points.iter().fold(Aabb::empty(), |b, p| b.grow(*p))
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y ||
+            self.min.z > self.max.z
+    }
+
+    #[inline]
+    pub fn grow(&self, p: Vec3) -> Aabb {
+        Aabb {
+            min: Vec3::min(self.min, p),
+            max: Vec3::max(self.max, p),
+        }
+    }
+
+    #[inline]
+    pub fn union(&self, b: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::min(self.min, b.min),
+            max: Vec3::max(self.max, b.max),
+        }
+    }
+
+    #[inline]
+    pub fn intersection(&self, b: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::max(self.min, b.min),
+            max: Vec3::min(self.max, b.max),
+        }
+    }
+
+    #[inline]
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    #[inline]
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let e = self.extent();
+        2.0 * (e.x * e.y + e.y * e.z + e.z * e.x)
+    }
+
+    #[inline]
+    pub fn contains(&self, p: Vec3) -> bool {
+        p.x >= self.min.x && p.y >= self.min.y && p.z >= self.min.z &&
+            p.x <= self.max.x && p.y <= self.max.y && p.z <= self.max.z
+    }
+
+    #[inline]
+    pub fn overlaps(&self, b: &Aabb) -> bool {
+        !self.intersection(b).is_empty()
+    }
+
+    /// Bounding box of the transformed box, from:
+    /// Arvo - Transforming Axis-Aligned Bounding Boxes (Graphics Gems 1990)
+    pub fn transform(&self, m: &Mat4) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+
+        let min = self.min.to_slice();
+        let max = self.max.to_slice();
+
+        let mut out_min = [m.e[3][0], m.e[3][1], m.e[3][2]];
+        let mut out_max = out_min;
+
+        for j in 0..3 {
+            for i in 0..3 {
+                let a = m.e[j][i] * min[j];
+                let b = m.e[j][i] * max[j];
+                out_min[i] += a.min(b);
+                out_max[i] += a.max(b);
+            }
+        }
+
+        Aabb {
+            min: Vec3::from_slice(&out_min),
+            max: Vec3::from_slice(&out_max),
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Sphere {
+    #[inline]
+    pub fn new(center: Vec3, radius: f32) -> Sphere {
+        Sphere { center, radius }
+    }
+
+    #[inline]
+    pub fn to_aabb(&self) -> Aabb {
+        Aabb {
+            min: self.center - self.radius,
+            max: self.center + self.radius,
+        }
+    }
+}
+
+/// Points p on the plane satisfy dot(normal, p) + d = 0, the normal points
+/// towards the positive half space.
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub d: f32,
+}
+
+impl Plane {
+    #[inline]
+    pub fn new(normal: Vec3, d: f32) -> Plane {
+        Plane { normal, d }
+    }
+
+    #[inline]
+    pub fn from_point_normal(p: Vec3, normal: Vec3) -> Plane {
+        let normal = normal.normalized();
+        Plane { normal, d: -normal.dot(p) }
+    }
+
+    /// Plane through 3 points, counter clockwise when seen from the
+    /// positive side in a right-handed frame.
+    pub fn from_points(p0: Vec3, p1: Vec3, p2: Vec3) -> Plane {
+        Plane::from_point_normal(p0, (p1 - p0).cross(p2 - p0))
+    }
+
+    #[inline]
+    pub fn from_vec4(v: Vec4) -> Plane {
+        Plane { normal: v.xyz(), d: v.w }
+    }
+
+    #[inline]
+    pub fn normalized(&self) -> Plane {
+        let i = 1.0 / self.normal.length();
+        Plane { normal: self.normal * i, d: self.d * i }
+    }
+
+    #[inline]
+    pub fn signed_distance(&self, p: Vec3) -> f32 {
+        self.normal.dot(p) + self.d
+    }
+}
+
+/// Planes point inwards, in order: left, right, bottom, top, near, far.
+#[derive(Debug, Default, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a view-projection matrix with [0, 1] depth
+    /// (see mat::lh::zo and mat::rh::zo), from:
+    /// Gribb, Hartmann - Fast Extraction of Viewing Frustum Planes from the
+    /// World-View-Projection Matrix (2001)
+    ///
+    /// [-1, 1] depth (mat::*::no) is not supported. With reverse z the near
+    /// and far planes are swapped, and a far plane at infinity has no normal
+    /// so it always passes.
+    pub fn from_view_projection(m: Mat4) -> Frustum {
+        let r = m.to_rows();
+
+        let planes = [
+            r[3] + r[0],
+            r[3] - r[0],
+            r[3] + r[1],
+            r[3] - r[1],
+            r[2],
+            r[3] - r[2],
+        ];
+
+        Frustum {
+            planes: planes.map(|p| {
+                let plane = Plane::from_vec4(p);
+                if plane.normal.length() > 0.0 {
+                    plane.normalized()
+                } else {
+                    Plane { normal: Vec3::from_scalar(0.0), d: f32::MAX }
+                }
+            }),
+        }
+    }
+
+    pub fn contains(&self, p: Vec3) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(p) >= 0.0)
+    }
+
+    /// Conservative test, boxes close to the frustum corners may be
+    /// reported as intersecting even if they are outside.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // Corner furthest along the plane normal
+            let p = Vec3::new(
+                if plane.normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.normal.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            plane.signed_distance(p) >= 0.0
+        })
+    }
+
+    /// Conservative, like intersects_aabb.
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes.iter()
+            .all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Rng;
+
+    const P0: Vec3 = Vec3 { x: 0., y: 0., z: 0. };
+    const P1: Vec3 = Vec3 { x: 1., y: 0., z: 0. };
+    const P2: Vec3 = Vec3 { x: 0., y: 1., z: 0. };
+    const P3: Vec3 = Vec3 { x: 1., y: 1., z: 0. };
+
+    fn down(x: f32, y: f32) -> Ray {
+        Ray::new(Vec3::new(x, y, 5.), Vec3::new(0., 0., -1.))
+    }
+
+    #[test]
+    fn triangle_hit() {
+        let r = down(0.25, 0.5);
+        let a = r.intersect_triangle(P0, P1, P2, 0., f32::MAX).unwrap();
+        let b = r.intersect_triangle_watertight(P0, P1, P2, 0., f32::MAX).unwrap();
+
+        for h in [a, b] {
+            assert!((h.t - 5.).abs() < 1e-6);
+            assert!((h.u - 0.25).abs() < 1e-6);
+            assert!((h.v - 0.5).abs() < 1e-6);
+        }
+
+        // Back face, miss and t range
+        let r = Ray::new(Vec3::new(0.25, 0.25, -1.), Vec3::new(0., 0., 1.));
+        assert!(r.intersect_triangle(P0, P1, P2, 0., f32::MAX).is_some());
+        assert!(r.intersect_triangle_watertight(P0, P1, P2, 0., f32::MAX).is_some());
+        assert!(r.intersect_triangle(P0, P1, P2, 0., 0.5).is_none());
+        assert!(r.intersect_triangle_watertight(P0, P1, P2, 0., 0.5).is_none());
+        assert!(down(0.75, 0.75).intersect_triangle(P0, P1, P2, 0., f32::MAX).is_none());
+        assert!(down(0.75, 0.75).intersect_triangle_watertight(P0, P1, P2, 0., f32::MAX).is_none());
+
+        // Parallel to the triangle plane
+        let r = Ray::new(Vec3::new(-1., 0.25, 0.), Vec3::new(1., 0., 0.));
+        assert!(r.intersect_triangle(P0, P1, P2, 0., f32::MAX).is_none());
+        assert!(r.intersect_triangle_watertight(P0, P1, P2, 0., f32::MAX).is_none());
+    }
+
+    #[test]
+    fn shared_edges_are_watertight() {
+        // Quad split along the P1-P2 diagonal, rays through the diagonal and
+        // the shared vertices must hit at least one of the two triangles.
+        let mut rng = Rng(11);
+        for i in 0..10000 {
+            let s = if i == 0 { 0.0 } else if i == 1 { 1.0 } else { rng.range(0., 1.) };
+            let p = P1 * (1. - s) + P2 * s;
+            let origin = Vec3::new(rng.range(-3., 3.), rng.range(-3., 3.), 5.);
+            let r = Ray::new(origin, p - origin);
+
+            let a = r.intersect_triangle_watertight(P0, P1, P2, 0., f32::MAX);
+            let b = r.intersect_triangle_watertight(P1, P3, P2, 0., f32::MAX);
+            assert!(a.is_some() || b.is_some(), "{} {}", r.origin, p);
+        }
+
+        // Exactly on an outer edge of a single triangle
+        let h = down(0.5, 0.0).intersect_triangle_watertight(P0, P1, P2, 0., f32::MAX);
+        assert!(h.is_some());
+        let h = down(0.0, 0.0).intersect_triangle_watertight(P0, P1, P2, 0., f32::MAX);
+        assert!(h.is_some());
+    }
+
+    #[test]
+    fn aabb_slabs() {
+        let b = Aabb::new(Vec3::new(-1., -1., -1.), Vec3::new(1., 1., 1.));
+
+        let r = Ray::new(Vec3::new(-5., 0., 0.), Vec3::new(1., 0., 0.));
+        assert_eq!(r.intersect_aabb(&b, 0., f32::MAX), Some((4., 6.)));
+        assert_eq!(r.intersect_aabb(&b, 0., 3.), None);
+
+        // Origin inside
+        let r = Ray::new(Vec3::new(0., 0., 0.), Vec3::new(0., 0., 1.));
+        assert_eq!(r.intersect_aabb(&b, 0., f32::MAX), Some((0., 1.)));
+
+        // Parallel to slabs, inside, outside and exactly on the boundary
+        for dir in [Vec3::new(1., 0., 0.), Vec3::new(1., -0., 0.)] {
+            let r = Ray::new(Vec3::new(-5., 0.5, 0.), dir);
+            assert!(r.intersect_aabb(&b, 0., f32::MAX).is_some());
+
+            let r = Ray::new(Vec3::new(-5., 1.5, 0.), dir);
+            assert!(r.intersect_aabb(&b, 0., f32::MAX).is_none());
+
+            let r = Ray::new(Vec3::new(-5., 1., -1.), dir);
+            assert_eq!(r.intersect_aabb(&b, 0., f32::MAX), Some((4., 6.)));
+        }
+
+        // Negative direction and a diagonal miss
+        let r = Ray::new(Vec3::new(0., 5., 0.), Vec3::new(0., -1., 0.));
+        assert_eq!(r.intersect_aabb(&b, 0., f32::MAX), Some((4., 6.)));
+        let r = Ray::new(Vec3::new(3., 0., 0.), Vec3::new(0., 1., 1.));
+        assert!(r.intersect_aabb(&b, 0., f32::MAX).is_none());
+    }
+
+    #[test]
+    fn aabb_ops() {
+        let a = Aabb::new(Vec3::new(0., 0., 0.), Vec3::new(1., 2., 3.));
+        assert_eq!(a.surface_area(), 22.);
+        assert_eq!(Aabb::empty().surface_area(), 0.);
+        assert!(Aabb::empty().is_empty());
+
+        let b = Aabb::from_points(&[Vec3::new(-1., 0., 0.), Vec3::new(0., 5., 1.)]);
+        let u = a.union(&b);
+        assert_eq!(u.min.to_slice(), [-1., 0., 0.]);
+        assert_eq!(u.max.to_slice(), [1., 5., 3.]);
+        assert!(a.overlaps(&b));
+        assert!(u.contains(Vec3::new(0.5, 4., 2.)));
+        assert_eq!(Aabb::empty().union(&a).max.to_slice(), a.max.to_slice());
+
+        // Transformed box bounds every transformed corner tightly
+        let m = Mat4::translation(Vec3::new(1., 2., 3.)) *
+            Mat4::rotation(Vec3::new(0., 0., 1.), 0.7) *
+            Mat4::scale3(Vec3::new(2., -1., 0.5));
+        let t = a.transform(&m);
+        let mut corners = Aabb::empty();
+        for i in 0..8 {
+            let p = Vec3::new(
+                if i & 1 == 0 { a.min.x } else { a.max.x },
+                if i & 2 == 0 { a.min.y } else { a.max.y },
+                if i & 4 == 0 { a.min.z } else { a.max.z },
+            );
+            corners = corners.grow((m * p.extend(1.)).xyz());
+        }
+        assert!((t.min - corners.min).length() < 1e-5);
+        assert!((t.max - corners.max).length() < 1e-5);
+    }
+
+    #[test]
+    fn sphere_and_plane() {
+        let s = Sphere::new(Vec3::new(0., 0., 10.), 2.);
+        let r = Ray::new(Vec3::new(0., 0., 0.), Vec3::new(0., 0., 1.));
+        assert_eq!(r.intersect_sphere(&s, 0., f32::MAX), Some(8.));
+        assert_eq!(r.intersect_sphere(&s, 9., f32::MAX), Some(12.));
+        let r = Ray::new(Vec3::new(0., 3., 0.), Vec3::new(0., 0., 1.));
+        assert_eq!(r.intersect_sphere(&s, 0., f32::MAX), None);
+
+        let p = Plane::from_points(P0, P1, P2);
+        assert_eq!(p.normal.to_slice(), [0., 0., 1.]);
+        assert_eq!(p.signed_distance(Vec3::new(3., 3., -2.)), -2.);
+        assert_eq!(down(3., 3.).intersect_plane(&p, 0., f32::MAX), Some(5.));
+        let r = Ray::new(Vec3::new(0., 0., 1.), Vec3::new(1., 0., 0.));
+        assert_eq!(r.intersect_plane(&p, 0., f32::MAX), None);
+    }
+
+    #[test]
+    fn frustum() {
+        let view = crate::mat::lh::look_at(Vec3::new(0., 0., 0.),
+            Vec3::new(0., 0., 1.), Vec3::new(0., 1., 0.));
+        let proj = crate::mat::lh::zo::perspective(
+            core::f32::consts::FRAC_PI_2, 1., 100., 1.);
+        let f = Frustum::from_view_projection(proj * view);
+
+        assert!(f.contains(Vec3::new(0., 0., 50.)));
+        assert!(!f.contains(Vec3::new(0., 0., 0.5)));
+        assert!(!f.contains(Vec3::new(0., 0., 101.)));
+        assert!(!f.contains(Vec3::new(20., 0., 10.)));
+
+        let inside = Aabb::new(Vec3::new(-1., -1., 10.), Vec3::new(1., 1., 12.));
+        let straddling = Aabb::new(Vec3::new(5., -1., 8.), Vec3::new(15., 1., 9.));
+        let behind = Aabb::new(Vec3::new(-1., -1., -5.), Vec3::new(1., 1., -2.));
+        let side = Aabb::new(Vec3::new(30., -1., 10.), Vec3::new(31., 1., 12.));
+        let beyond = Aabb::new(Vec3::new(-1., -1., 200.), Vec3::new(1., 1., 201.));
+
+        assert!(f.intersects_aabb(&inside));
+        assert!(f.intersects_aabb(&straddling));
+        assert!(!f.intersects_aabb(&behind));
+        assert!(!f.intersects_aabb(&side));
+        assert!(!f.intersects_aabb(&beyond));
+
+        assert!(f.intersects_sphere(&Sphere::new(Vec3::new(11., 0., 10.), 1.5)));
+        assert!(!f.intersects_sphere(&Sphere::new(Vec3::new(13., 0., 10.), 1.5)));
+
+        // Infinite far plane
+        let proj = crate::mat::lh::zo::perspective_infinite_reverse(core::f32::consts::FRAC_PI_2, 1., 1.);
+        let f = Frustum::from_view_projection(proj * view);
+        assert!(f.planes.iter().all(|p| p.normal.x.is_finite() && !p.d.is_nan()));
+        assert!(f.contains(Vec3::new(0., 0., 1e6)));
+        assert!(!f.contains(Vec3::new(0., 0., 0.5)));
+        assert!(!f.contains(Vec3::new(20., 0., 10.)));
+        assert!(f.intersects_aabb(&beyond));
+        assert!(!f.intersects_aabb(&behind));
+        assert!(f.intersects_sphere(&Sphere::new(Vec3::new(0., 0., 1e6), 1.)));
+    }
+}
@@ -0,0 +1,142 @@
+//! Optional serde and mint support, both going through the array conversions
+//! of each type. Vectors are serialized as [x, y, ..], matrices as arrays of
+//! columns and quaternions as [x, y, z, w].
+
+#[cfg(any(feature = "serde", feature = "mint"))]
+use crate::{vec::*, mat::*, quat::*};
+
+#[cfg(feature = "serde")]
+macro_rules! serde_array_impl {
+    ($($name: ident: $a: ty),*) => {
+        $(
+            impl serde::Serialize for $name {
+                fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    <$a>::from(*self).serialize(serializer)
+                }
+            }
+
+            impl<'de> serde::Deserialize<'de> for $name {
+                fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    <$a>::deserialize(deserializer).map($name::from)
+                }
+            }
+        )*
+    }
+}
+
+#[cfg(feature = "serde")]
+serde_array_impl!(Vec2i: [i32; 2], Vec3i: [i32; 3], Vec4i: [i32; 4],
+                  Vec2u: [u32; 2], Vec3u: [u32; 3], Vec4u: [u32; 4],
+                  Vec2: [f32; 2], Vec3: [f32; 3], Vec4: [f32; 4],
+                  Vec2d: [f64; 2], Vec3d: [f64; 3], Vec4d: [f64; 4],
+                  Mat2: [[f32; 2]; 2], Mat3: [[f32; 3]; 3], Mat4: [[f32; 4]; 4],
+                  Mat2d: [[f64; 2]; 2], Mat3d: [[f64; 3]; 3], Mat4d: [[f64; 4]; 4],
+                  Quat: [f32; 4], Quatd: [f64; 4]);
+
+#[cfg(feature = "mint")]
+macro_rules! mint_impl {
+    ($($name: ident: $mint: ty, $a: ty),*) => {
+        $(
+            impl From<$mint> for $name {
+                #[inline]
+                fn from(m: $mint) -> $name {
+                    $name::from(<$a>::from(m))
+                }
+            }
+
+            impl From<$name> for $mint {
+                #[inline]
+                fn from(v: $name) -> $mint {
+                    <$mint>::from(<$a>::from(v))
+                }
+            }
+
+            impl mint::IntoMint for $name {
+                type MintType = $mint;
+            }
+        )*
+    }
+}
+
+#[cfg(feature = "mint")]
+mint_impl!(Vec2i: mint::Vector2<i32>, [i32; 2],
+           Vec3i: mint::Vector3<i32>, [i32; 3],
+           Vec4i: mint::Vector4<i32>, [i32; 4],
+           Vec2u: mint::Vector2<u32>, [u32; 2],
+           Vec3u: mint::Vector3<u32>, [u32; 3],
+           Vec4u: mint::Vector4<u32>, [u32; 4],
+           Vec2: mint::Vector2<f32>, [f32; 2],
+           Vec3: mint::Vector3<f32>, [f32; 3],
+           Vec4: mint::Vector4<f32>, [f32; 4],
+           Vec2d: mint::Vector2<f64>, [f64; 2],
+           Vec3d: mint::Vector3<f64>, [f64; 3],
+           Vec4d: mint::Vector4<f64>, [f64; 4],
+           Mat2: mint::ColumnMatrix2<f32>, [[f32; 2]; 2],
+           Mat3: mint::ColumnMatrix3<f32>, [[f32; 3]; 3],
+           Mat4: mint::ColumnMatrix4<f32>, [[f32; 4]; 4],
+           Mat2d: mint::ColumnMatrix2<f64>, [[f64; 2]; 2],
+           Mat3d: mint::ColumnMatrix3<f64>, [[f64; 3]; 3],
+           Mat4d: mint::ColumnMatrix4<f64>, [[f64; 4]; 4],
+           Quat: mint::Quaternion<f32>, [f32; 4],
+           Quatd: mint::Quaternion<f64>, [f64; 4]);
+
+#[cfg(test)]
+mod tests {
+    use crate::vec::*;
+    use crate::mat::*;
+    use crate::quat::*;
+    use crate::assert_approx_eq;
+
+    #[test]
+    fn arrays() {
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        assert_eq!(<[f32; 3]>::from(v), [1.0, 2.0, 3.0]);
+        assert_approx_eq!(Vec3::from([1.0, 2.0, 3.0]), v, epsilon = 0.0);
+        assert_eq!(<[u32; 4]>::from(Vec4u::from([1, 2, 3, 4])), [1, 2, 3, 4]);
+
+        // Arrays of columns, the translation is in the last one.
+        let m = Mat4::from([[1., 0., 0., 0.], [0., 1., 0., 0.], [0., 0., 1., 0.], [5., 6., 7., 1.]]);
+        assert_approx_eq!(m.transform_point(Vec3::from_scalar(0.0)), Vec3::new(5.0, 6.0, 7.0));
+        assert_eq!(<[[f32; 4]; 4]>::from(m)[3], [5., 6., 7., 1.]);
+
+        let q = Quat::from([0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(q.re(), 0.0);
+        assert_eq!(<[f32; 4]>::from(q), [0.0, 0.0, 1.0, 0.0]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        use crate::transform::Transform;
+
+        let v = Vec3::new(1.0, -2.5, 3.0);
+        let json = serde_json::to_string(&v).unwrap();
+        assert_eq!(json, "[1.0,-2.5,3.0]");
+        assert_approx_eq!(serde_json::from_str::<Vec3>(&json).unwrap(), v);
+        assert!(serde_json::from_str::<Vec3>("[1.0,2.0]").is_err());
+
+        let m = Mat3d::scale(Vec3d::new(1.0, 2.0, 3.0));
+        let json = serde_json::to_string(&m).unwrap();
+        assert_eq!(json, "[[1.0,0.0,0.0],[0.0,2.0,0.0],[0.0,0.0,3.0]]");
+        assert_approx_eq!(serde_json::from_str::<Mat3d>(&json).unwrap(), m);
+
+        let t = Transform::new(v, Quat::rotate(Vec3::new(0.0, 1.0, 0.0), 0.5), Vec3::from_scalar(2.0));
+        let t2: Transform = serde_json::from_str(&serde_json::to_string(&t).unwrap()).unwrap();
+        assert_approx_eq!(t, t2);
+    }
+
+    #[cfg(feature = "mint")]
+    #[test]
+    fn mint() {
+        let v: mint::Vector3<f32> = Vec3::new(1.0, 2.0, 3.0).into();
+        assert_eq!((v.x, v.y, v.z), (1.0, 2.0, 3.0));
+        assert_approx_eq!(Vec3::from(v), Vec3::new(1.0, 2.0, 3.0));
+
+        let m: mint::ColumnMatrix4<f32> = Mat4::translation(Vec3::new(5.0, 6.0, 7.0)).into();
+        assert_eq!((m.w.x, m.w.y, m.w.z, m.w.w), (5.0, 6.0, 7.0, 1.0));
+
+        let q: mint::Quaternion<f64> = Quatd::new(1.0, 2.0, 3.0, 4.0).into();
+        assert_eq!((q.v.x, q.v.y, q.v.z, q.s), (1.0, 2.0, 3.0, 4.0));
+        assert_eq!(Quatd::from(q).w, 4.0);
+    }
+}
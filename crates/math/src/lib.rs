@@ -1,9 +1,40 @@
 pub mod vec;
 pub mod mat;
 pub mod quat;
+pub mod transform;
+pub mod geometry;
+pub mod sampling;
+pub mod color;
+pub mod sh;
+pub mod morton;
+pub mod frame;
+pub mod pack;
+pub mod approx;
+mod interop;
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd;
 
 #[cfg(test)]
 mod tests {
+    /// Xorshift generator for randomized tests, avoids pulling in a
+    /// dependency just for tests.
+    pub struct Rng(pub u64);
+
+    impl Rng {
+        pub fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        /// Uniform float in [lo, hi)
+        pub fn range(&mut self, lo: f32, hi: f32) -> f32 {
+            let u = (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+            lo + (hi - lo) * u
+        }
+    }
 
     #[test]
     fn test() {
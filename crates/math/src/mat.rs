@@ -2,12 +2,6 @@ use crate::vec::*;
 
 use bytemuck::{Pod, Zeroable};
 
-// #[cfg(not(any(feature = "depth_zero_one", feature = "depth_negative_one_one")))]
-// compile_error!("no math depth configuration");
-//
-// #[cfg(all(feature = "depth_zero_one", feature = "depth_negative_one_one"))]
-// compile_error!("more than one math depth configuration");
-
 
 macro_rules! mat_impl {
     ($m: ident, $t: ident, $v: ident, $n: literal) => {
@@ -82,8 +76,120 @@ macro_rules! mat_impl {
                 self.transpose().to_columns()
             }
 
+            // The elimination below works directly on the column-major
+            // storage, which is the transpose of the matrix. This is fine
+            // since det(A^T) = det(A) and inverse(A^T) = inverse(A)^T, so
+            // the result is already laid out in column-major order.
+
+            pub fn determinant(&self) -> $t {
+                let mut a = self.e;
+                let mut det = 1.0;
+
+                for c in 0..$n {
+                    let mut p = c;
+                    for r in c + 1..$n {
+                        if a[r][c].abs() > a[p][c].abs() {
+                            p = r;
+                        }
+                    }
+
+                    if a[p][c] == 0.0 {
+                        return 0.0;
+                    }
+
+                    if p != c {
+                        a.swap(p, c);
+                        det = -det;
+                    }
+
+                    det *= a[c][c];
+
+                    for r in c + 1..$n {
+                        let f = a[r][c] / a[c][c];
+                        for k in c..$n {
+                            a[r][k] -= f * a[c][k];
+                        }
+                    }
+                }
+
+                det
+            }
+
+            /// Gauss-Jordan elimination with partial pivoting, returns None
+            /// if a pivot is negligible compared to the largest element of
+            /// its column in the matrix, so that a large translation doesn't
+            /// make a small scale look singular.
+            pub fn try_inverse(&self) -> Option<$m> {
+                let mut a = self.e;
+                let mut inv = $m::identity().e;
+
+                // Columns are eliminated as rows, the scales follow the swaps
+                let mut scale = a.map(|r| r.iter().fold(0.0, |s: $t, x| s.max(x.abs())));
+
+                for c in 0..$n {
+                    let mut p = c;
+                    for r in c + 1..$n {
+                        if a[r][c].abs() > a[p][c].abs() {
+                            p = r;
+                        }
+                    }
+
+                    if a[p][c].is_nan() || a[p][c].abs() <= scale[p] * $t::EPSILON {
+                        return None;
+                    }
+
+                    a.swap(p, c);
+                    inv.swap(p, c);
+                    scale.swap(p, c);
+
+                    let d = 1.0 / a[c][c];
+                    for k in 0..$n {
+                        a[c][k] *= d;
+                        inv[c][k] *= d;
+                    }
+
+                    for r in 0..$n {
+                        if r == c {
+                            continue;
+                        }
+
+                        let f = a[r][c];
+                        for k in 0..$n {
+                            a[r][k] -= f * a[c][k];
+                            inv[r][k] -= f * inv[c][k];
+                        }
+                    }
+                }
+
+                Some($m { e: inv })
+            }
+
+            /// Panics if the matrix is singular, see try_inverse.
+            #[inline]
+            pub fn inverse(&self) -> $m {
+                self.try_inverse().expect("Matrix is singular")
+            }
         }
 
+        /// Arrays of columns.
+        impl From<[[$t; $n]; $n]> for $m {
+            #[inline]
+            fn from(e: [[$t; $n]; $n]) -> $m {
+                $m { e }
+            }
+        }
+
+        impl From<$m> for [[$t; $n]; $n] {
+            #[inline]
+            fn from(m: $m) -> [[$t; $n]; $n] {
+                m.e
+            }
+        }
+    }
+}
+
+macro_rules! mat_mul_impl {
+    ($m: ident, $v: ident, $n: literal) => {
         impl std::ops::Mul<$m> for $m {
             type Output = $m;
 
@@ -118,8 +224,6 @@ macro_rules! mat_impl {
                 $v::from_slice(&v)
             }
         }
-
-
     }
 }
 
@@ -131,6 +235,94 @@ mat_impl!(Mat4d, f64, Vec4d, 4);
 mat_impl!(Mat3d, f64, Vec3d, 3);
 mat_impl!(Mat2d, f64, Vec2d, 2);
 
+// With the simd feature Mat4 multiplication is implemented in simd.rs
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+mat_mul_impl!(Mat4, Vec4, 4);
+mat_mul_impl!(Mat3, Vec3, 3);
+mat_mul_impl!(Mat2, Vec2, 2);
+
+mat_mul_impl!(Mat4d, Vec4d, 4);
+mat_mul_impl!(Mat3d, Vec3d, 3);
+mat_mul_impl!(Mat2d, Vec2d, 2);
+
+macro_rules! mat_normal_impl {
+    ($m: ident) => {
+        impl $m {
+            /// Inverse-transpose of the matrix. Singular matrices use the
+            /// cofactor matrix instead, equal to the inverse-transpose times
+            /// the determinant, so flattened geometry keeps its normals.
+            pub fn to_normal_matrix(&self) -> $m {
+                match self.try_inverse() {
+                    Some(m) => m.transpose(),
+                    None => self.cofactor(),
+                }
+            }
+        }
+    }
+}
+
+macro_rules! mat_cofactor_impl {
+    ($m3: ident, $m2: ident, $v2: ident) => {
+        impl $m3 {
+            /// Matrix of the cofactors, the transpose of the adjugate.
+            pub fn cofactor(&self) -> $m3 {
+                let [a, b, c] = self.to_columns();
+                $m3::from_columns(&[b.cross(c), c.cross(a), a.cross(b)])
+            }
+        }
+
+        impl $m2 {
+            /// Matrix of the cofactors, the transpose of the adjugate.
+            pub fn cofactor(&self) -> $m2 {
+                let [a, b] = self.to_columns();
+                $m2::from_columns(&[$v2::new(b.y, -b.x), $v2::new(-a.y, a.x)])
+            }
+        }
+    }
+}
+
+mat_cofactor_impl!(Mat3, Mat2, Vec2);
+mat_cofactor_impl!(Mat3d, Mat2d, Vec2d);
+
+macro_rules! mat4_utils_impl {
+    ($m4: ident, $m3: ident) => {
+        impl $m4 {
+            #[inline]
+            pub fn from_mat3(m: $m3) -> $m4 {
+                let mut r = $m4::identity();
+                for i in 0..3 {
+                    r.e[i][0..3].copy_from_slice(&m.e[i]);
+                }
+                r
+            }
+
+            /// Upper left 3x3 block of the matrix.
+            #[inline]
+            pub fn to_mat3(&self) -> $m3 {
+                let mut r = $m3::new();
+                for i in 0..3 {
+                    r.e[i].copy_from_slice(&self.e[i][0..3]);
+                }
+                r
+            }
+
+            /// Inverse-transpose of the upper 3x3 block, translation and
+            /// projective terms are dropped.
+            pub fn to_normal_matrix(&self) -> $m4 {
+                $m4::from_mat3(self.to_mat3().to_normal_matrix())
+            }
+        }
+    }
+}
+
+mat_normal_impl!(Mat3);
+mat_normal_impl!(Mat2);
+mat_normal_impl!(Mat3d);
+mat_normal_impl!(Mat2d);
+
+mat4_utils_impl!(Mat4, Mat3);
+mat4_utils_impl!(Mat4d, Mat3d);
+
 impl Mat3 {
     pub fn rotation(axis: Vec3, angle: f32) -> Self {
         let a = axis.x;
@@ -194,25 +386,34 @@ impl Mat4 {
     }
 
     pub fn scale3(v: Vec3) -> Self {
-        let vv = v.to_slice();
-
         let mut m = Mat4::identity();
-        for i in 0..3 {
-            m.e[i][i] = vv[i];
+        for (i, s) in v.to_slice().iter().enumerate() {
+            m.e[i][i] = *s;
         }
 
         m
     }
 
-    // TODO: do this properly after implementing inverse (std::simd?)
-    pub fn to_normal_matrix(&self) -> Mat4 {
-        let mut m = *self;
-        m.e[3][0] = 0.;
-        m.e[3][1] = 0.;
-        m.e[3][2] = 0.;
-        m.e[3][3] = 1.;
+    /// Transforms a point with w = 1, the w of the result is ignored.
+    #[inline]
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        (*self * p.extend(1.0)).xyz()
+    }
 
-        m
+    /// Transforms a vector with w = 0.
+    #[inline]
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        (*self * v.extend(0.0)).xyz()
+    }
+}
+
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+impl Mat4 {
+    /// Same as transform_point on every element, in place.
+    pub fn transform_points(&self, points: &mut [Vec3]) {
+        for p in points.iter_mut() {
+            *p = self.transform_point(*p);
+        }
     }
 }
 
@@ -287,6 +488,38 @@ pub mod lh {
             m
         }
 
+        /// Reverse z with the far plane at infinity, near maps to 1 and
+        /// infinity to 0.
+        pub fn perspective_infinite_reverse(hfov: f32, near: f32,
+                                            aspect_ratio: f32) -> Mat4 {
+            let mut m = Mat4::new();
+
+            let t = (hfov / 2.).tan();
+
+            m.e[0][0] = 1.0 / t;
+            m.e[1][1] = 1.0 / (aspect_ratio * t);
+            m.e[2][3] = 1.0;
+            m.e[3][2] = near;
+
+            m
+        }
+
+        /// Limit of perspective when far goes to infinity, near maps to 0
+        /// and infinity to 1.
+        pub fn perspective_infinite(hfov: f32, near: f32, aspect_ratio: f32)
+            -> Mat4 {
+            let mut m = Mat4::new();
+
+            let t = (hfov / 2.).tan();
+
+            m.e[0][0] = 1.0 / t;
+            m.e[1][1] = 1.0 / (aspect_ratio * t);
+            m.e[2][2] = 1.0;
+            m.e[2][3] = 1.0;
+            m.e[3][2] = -near;
+
+            m
+        }
     }
 
     // Negative one to one z
@@ -305,8 +538,429 @@ pub mod lh {
 
             m
         }
+
+        pub fn perspective(hfov: f32, near: f32, far: f32, aspect_ratio: f32)
+            -> Mat4 {
+
+            let mut m = Mat4::new();
+
+            let t = (hfov / 2.).tan();
+
+            m.e[0][0] = 1.0 / t;
+            m.e[1][1] = 1.0 / (aspect_ratio * t);
+            m.e[2][2] = (far + near) / (far - near);
+            m.e[2][3] = 1.0;
+            m.e[3][2] = -(2.0 * near * far) / (far - near);
+
+            m
+        }
+
+        /// Reverse z with the far plane at infinity, near maps to 1 and
+        /// infinity to -1.
+        pub fn perspective_infinite_reverse(hfov: f32, near: f32,
+                                            aspect_ratio: f32) -> Mat4 {
+            let mut m = Mat4::new();
+
+            let t = (hfov / 2.).tan();
+
+            m.e[0][0] = 1.0 / t;
+            m.e[1][1] = 1.0 / (aspect_ratio * t);
+            m.e[2][2] = -1.0;
+            m.e[2][3] = 1.0;
+            m.e[3][2] = 2.0 * near;
+
+            m
+        }
+    }
+}
+
+/// Right-handed matrices, the camera looks down the negative z axis
+pub mod rh {
+    use super::Mat4;
+    use super::Vec3;
+
+    pub fn look_at(from: Vec3, to: Vec3, up: Vec3) -> Mat4 {
+
+        let f = (to - from).normalized();
+        let r = f.cross(up).normalized();
+        let u = r.cross(f);
+
+        let mut m = Mat4::new();
+        m.e[0][0] = r.x;
+        m.e[1][0] = r.y;
+        m.e[2][0] = r.z;
+
+        m.e[0][1] = u.x;
+        m.e[1][1] = u.y;
+        m.e[2][1] = u.z;
+
+        m.e[0][2] = -f.x;
+        m.e[1][2] = -f.y;
+        m.e[2][2] = -f.z;
+
+        m.e[0][3] = 0.;
+        m.e[1][3] = 0.;
+        m.e[2][3] = 0.;
+
+        m.e[3][0] = -Vec3::dot(r, from);
+        m.e[3][1] = -Vec3::dot(u, from);
+        m.e[3][2] = Vec3::dot(f, from);
+        m.e[3][3] = 1.0;
+
+        m
+    }
+
+    // Zero to one z
+    pub mod zo {
+        use super::super::Mat4;
+
+        pub fn orthographic(left: f32, right: f32, bottom: f32,
+                            top: f32, far: f32, near: f32) -> Mat4 {
+            let mut m = Mat4::identity();
+            m.e[0][0] = 2.0 / (right - left);
+            m.e[1][1] = 2.0 / (top - bottom);
+            m.e[2][2] = -1.0 / (far - near);
+            m.e[3][0] = - (right + left) / (right - left);
+            m.e[3][1] = - (top + bottom) / (top - bottom);
+            m.e[3][2] = - near / (far - near);
+
+            m
+        }
+
+        pub fn perspective(hfov: f32, near: f32, far: f32, aspect_ratio: f32)
+            -> Mat4 {
+
+            let mut m = Mat4::new();
+
+            let t = (hfov / 2.).tan();
+
+            m.e[0][0] = 1.0 / t;
+            m.e[1][1] = 1.0 / (aspect_ratio * t);
+            m.e[2][2] = -far / (far - near);
+            m.e[2][3] = -1.0;
+            m.e[3][2] = -(near * far) / (far - near);
+
+            m
+        }
+
+        /// Reverse z with the far plane at infinity, near maps to 1 and
+        /// infinity to 0.
+        pub fn perspective_infinite_reverse(hfov: f32, near: f32,
+                                            aspect_ratio: f32) -> Mat4 {
+            let mut m = Mat4::new();
+
+            let t = (hfov / 2.).tan();
+
+            m.e[0][0] = 1.0 / t;
+            m.e[1][1] = 1.0 / (aspect_ratio * t);
+            m.e[2][3] = -1.0;
+            m.e[3][2] = near;
+
+            m
+        }
+    }
+
+    // Negative one to one z
+    pub mod no {
+        use super::super::Mat4;
+
+        pub fn orthographic(left: f32, right: f32, bottom: f32,
+                            top: f32, far: f32, near: f32) -> Mat4 {
+            let mut m = Mat4::identity();
+            m.e[0][0] = 2.0 / (right - left);
+            m.e[1][1] = 2.0 / (top - bottom);
+            m.e[2][2] = -2.0 / (far - near);
+            m.e[3][0] = - (right + left) / (right - left);
+            m.e[3][1] = - (top + bottom) / (top - bottom);
+            m.e[3][2] = - (far + near) / (far - near);
+
+            m
+        }
+
+        pub fn perspective(hfov: f32, near: f32, far: f32, aspect_ratio: f32)
+            -> Mat4 {
+
+            let mut m = Mat4::new();
+
+            let t = (hfov / 2.).tan();
+
+            m.e[0][0] = 1.0 / t;
+            m.e[1][1] = 1.0 / (aspect_ratio * t);
+            m.e[2][2] = -(far + near) / (far - near);
+            m.e[2][3] = -1.0;
+            m.e[3][2] = -(2.0 * near * far) / (far - near);
+
+            m
+        }
+
+        /// Reverse z with the far plane at infinity, near maps to 1 and
+        /// infinity to -1.
+        pub fn perspective_infinite_reverse(hfov: f32, near: f32,
+                                            aspect_ratio: f32) -> Mat4 {
+            let mut m = Mat4::new();
+
+            let t = (hfov / 2.).tan();
+
+            m.e[0][0] = 1.0 / t;
+            m.e[1][1] = 1.0 / (aspect_ratio * t);
+            m.e[2][2] = 1.0;
+            m.e[2][3] = -1.0;
+            m.e[3][2] = 2.0 * near;
+
+            m
+        }
     }
 }
 
 
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quat::Quat;
+    use crate::tests::Rng;
+
+    fn assert_identity(m: Mat4, eps: f32) {
+        let id = Mat4::identity();
+        for j in 0..4 {
+            for i in 0..4 {
+                assert!((m.e[j][i] - id.e[j][i]).abs() < eps, "{:?}", m);
+            }
+        }
+    }
+
+    fn random_trs(rng: &mut Rng) -> Mat4 {
+        let t = Vec3::new(rng.range(-100., 100.), rng.range(-100., 100.),
+                          rng.range(-100., 100.));
+        let axis = Vec3::new(rng.range(-1., 1.), rng.range(-1., 1.),
+                             rng.range(-1., 1.)).normalized();
+        let angle = rng.range(-core::f32::consts::PI, core::f32::consts::PI);
+        let mut s = Vec3::new(rng.range(0.1, 10.), rng.range(0.1, 10.),
+                              rng.range(0.1, 10.));
+        if rng.next_u64() & 1 == 1 {
+            s.x = -s.x;
+        }
+
+        Mat4::translation(t) * Quat::rotate(axis, angle).to_mat4() *
+            Mat4::scale3(s)
+    }
+
+    #[test]
+    fn inverse_random_trs() {
+        let mut rng = Rng(0x9E3779B97F4A7C15);
+        for _ in 0..1000 {
+            let m = random_trs(&mut rng);
+            let inv = m.inverse();
+            assert_identity(m * inv, 1e-4);
+            assert_identity(inv * m, 1e-4);
+        }
+    }
+
+    #[test]
+    fn determinant() {
+        assert_eq!(Mat4::identity().determinant(), 1.0);
+        assert_eq!(Mat3::scale(Vec3::new(2., 3., 4.)).determinant(), 24.0);
+        assert_eq!(Mat2d::from_columns(&[Vec2d::new(0., 1.), Vec2d::new(1., 0.)])
+                   .determinant(), -1.0);
+
+        let t = Mat4::translation(Vec3::new(5., 6., 7.)) *
+            Mat4::scale3(Vec3::new(-2., 1., 3.));
+        assert!((t.determinant() + 6.0).abs() < 1e-6);
+
+        let r = Mat3::rotation(Vec3::new(0., 0., 1.), 0.7);
+        assert!((r.determinant() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn singular() {
+        let m = Mat3::from_columns(&[
+            Vec3::new(1., 2., 3.),
+            Vec3::new(2., 4., 6.),
+            Vec3::new(0., 1., 0.),
+        ]);
+        assert_eq!(m.determinant(), 0.0);
+        assert!(m.try_inverse().is_none());
+        assert!(Mat4::scale3(Vec3::new(1., 0., 1.)).try_inverse().is_none());
+        assert!(Mat2d::new().try_inverse().is_none());
+    }
+
+    #[test]
+    fn inverse_large_translation_small_scale() {
+        let m = Mat4::translation(Vec3::new(5000., 0., 0.)) * Mat4::scale3(Vec3::from_scalar(0.01));
+        let inv = m.try_inverse().expect("TRS matrices are invertible");
+        assert_identity(m * inv, 1e-4);
+        assert_identity(inv * m, 1e-4);
+        crate::assert_approx_eq!(inv.transform_point(Vec3::new(5000., 0., 0.)), Vec3::from_scalar(0.), epsilon = 1e-3);
+    }
+
+    #[test]
+    fn normal_matrix() {
+        let mut rng = Rng(12345);
+        for _ in 0..100 {
+            let m = random_trs(&mut rng);
+            let n = m.to_normal_matrix();
+
+            // Translation is dropped
+            assert_eq!(n.e[3], [0., 0., 0., 1.]);
+
+            // Normals stay perpendicular to transformed tangents
+            let t = Vec3::new(rng.range(-1., 1.), rng.range(-1., 1.),
+                              rng.range(-1., 1.));
+            let b = Vec3::new(rng.range(-1., 1.), rng.range(-1., 1.),
+                              rng.range(-1., 1.));
+            let normal = t.cross(b);
+
+            let tw = m * Vec4::new(t.x, t.y, t.z, 0.);
+            let nw = n * Vec4::new(normal.x, normal.y, normal.z, 0.);
+            let cos = tw.dot(nw) / (tw.length() * nw.length());
+            assert!(cos.abs() < 1e-4);
+        }
+
+        // Non uniform scale
+        let n = Mat3::scale(Vec3::new(2., 4., 8.)).to_normal_matrix();
+        assert_eq!(n.e[0][0], 0.5);
+        assert_eq!(n.e[1][1], 0.25);
+        assert_eq!(n.e[2][2], 0.125);
+
+        // Cofactors are the inverse-transpose times the determinant
+        let mut rng = Rng(7);
+        let m = random_trs(&mut rng).to_mat3();
+        crate::assert_approx_eq!(m.cofactor(), m.to_normal_matrix() * Mat3::scale_uniform(m.determinant()),
+                                 epsilon = 1e-3);
+        let m2 = Mat2::from_columns(&[Vec2::new(1., 2.), Vec2::new(3., 5.)]);
+        crate::assert_approx_eq!(m2.cofactor(), m2.to_normal_matrix() * Mat2::scale_uniform(-1.), epsilon = 1e-5);
+
+        // Flattened on z, normals of the plane still point along z
+        let n = Mat4::scale3(Vec3::new(2., 3., 0.)).to_normal_matrix();
+        let normal = n * Vec4::new(0., 0., 1., 0.);
+        assert_eq!(normal.to_slice(), [0., 0., 6., 0.]);
+        let n = Mat3::scale(Vec3::new(2., 0., 0.)).to_normal_matrix();
+        assert_eq!(n.e, [[0.; 3]; 3]);
+    }
+
+    fn project(m: Mat4, p: Vec3) -> Vec3 {
+        let c = m * Vec4::new(p.x, p.y, p.z, 1.);
+        Vec3::new(c.x / c.w, c.y / c.w, c.z / c.w)
+    }
+
+    fn assert_ndc(m: Mat4, p: Vec3, expected: Vec3) {
+        let ndc = project(m, p);
+        assert!((ndc - expected).length() < 1e-4, "{} {}", ndc, expected);
+    }
+
+    #[test]
+    fn look_at() {
+        let from = Vec3::new(1., 2., 3.);
+        let to = Vec3::new(1., 2., 8.);
+        let up = Vec3::new(0., 1., 0.);
+
+        // Forward is +z in left-handed and -z in right-handed view space
+        let v = lh::look_at(from, to, up);
+        assert_ndc(v, to, Vec3::new(0., 0., 5.));
+        assert_ndc(v, from + up, Vec3::new(0., 1., 0.));
+        assert_ndc(v, from + Vec3::new(1., 0., 0.), Vec3::new(1., 0., 0.));
+
+        let v = rh::look_at(from, to, up);
+        assert_ndc(v, to, Vec3::new(0., 0., -5.));
+        assert_ndc(v, from + up, Vec3::new(0., 1., 0.));
+        assert_ndc(v, from + Vec3::new(-1., 0., 0.), Vec3::new(1., 0., 0.));
+    }
+
+    #[test]
+    fn perspective() {
+        let (near, far) = (0.5, 100.);
+        let hfov = core::f32::consts::FRAC_PI_2;
+        let aspect = 0.5;
+
+        // With a 90 degree hfov the right edge is at x = z and the top edge
+        // at y = z * aspect.
+        let lh_p = |z: f32| Vec3::new(z, z * aspect, z);
+        let rh_p = |z: f32| Vec3::new(z, z * aspect, -z);
+
+        let m = lh::zo::perspective(hfov, near, far, aspect);
+        assert_ndc(m, lh_p(near), Vec3::new(1., 1., 0.));
+        assert_ndc(m, lh_p(far), Vec3::new(1., 1., 1.));
+
+        let m = lh::no::perspective(hfov, near, far, aspect);
+        assert_ndc(m, lh_p(near), Vec3::new(1., 1., -1.));
+        assert_ndc(m, lh_p(far), Vec3::new(1., 1., 1.));
+
+        let m = rh::zo::perspective(hfov, near, far, aspect);
+        assert_ndc(m, rh_p(near), Vec3::new(1., 1., 0.));
+        assert_ndc(m, rh_p(far), Vec3::new(1., 1., 1.));
+
+        let m = rh::no::perspective(hfov, near, far, aspect);
+        assert_ndc(m, rh_p(near), Vec3::new(1., 1., -1.));
+        assert_ndc(m, rh_p(far), Vec3::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn perspective_infinite_reverse() {
+        let near = 0.5;
+        let hfov = core::f32::consts::FRAC_PI_2;
+        let aspect = 0.5;
+
+        let lh_p = |z: f32| Vec3::new(-z, z * aspect, z);
+        let rh_p = |z: f32| Vec3::new(-z, z * aspect, -z);
+
+        let m = lh::zo::perspective_infinite_reverse(hfov, near, aspect);
+        assert_ndc(m, lh_p(near), Vec3::new(-1., 1., 1.));
+        assert_ndc(m, lh_p(1e7), Vec3::new(-1., 1., 0.));
+
+        let m = lh::no::perspective_infinite_reverse(hfov, near, aspect);
+        assert_ndc(m, lh_p(near), Vec3::new(-1., 1., 1.));
+        assert_ndc(m, lh_p(1e7), Vec3::new(-1., 1., -1.));
+
+        let m = rh::zo::perspective_infinite_reverse(hfov, near, aspect);
+        assert_ndc(m, rh_p(near), Vec3::new(-1., 1., 1.));
+        assert_ndc(m, rh_p(1e7), Vec3::new(-1., 1., 0.));
+
+        let m = rh::no::perspective_infinite_reverse(hfov, near, aspect);
+        assert_ndc(m, rh_p(near), Vec3::new(-1., 1., 1.));
+        assert_ndc(m, rh_p(1e7), Vec3::new(-1., 1., -1.));
+
+        // Depth decreases monotonically with distance
+        let m = rh::zo::perspective_infinite_reverse(hfov, near, aspect);
+        assert!(project(m, rh_p(2.)).z > project(m, rh_p(3.)).z);
+    }
+
+    #[test]
+    fn perspective_infinite() {
+        let near = 0.5;
+        let hfov = core::f32::consts::FRAC_PI_2;
+        let aspect = 0.5;
+        let lh_p = |z: f32| Vec3::new(-z, z * aspect, z);
+
+        let m = lh::zo::perspective_infinite(hfov, near, aspect);
+        assert_ndc(m, lh_p(near), Vec3::new(-1., 1., 0.));
+        assert_ndc(m, lh_p(1e7), Vec3::new(-1., 1., 1.));
+        assert!(project(m, lh_p(2.)).z < project(m, lh_p(3.)).z);
+
+        // Same as a far plane far away
+        let finite = lh::zo::perspective(hfov, near, 1e9, aspect);
+        for (a, b) in m.e.iter().flatten().zip(finite.e.iter().flatten()) {
+            crate::assert_approx_eq!(*a, *b, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn orthographic() {
+        let (l, r, b, t, n, f) = (-2., 4., -1., 3., 1., 11.);
+
+        let m = lh::zo::orthographic(l, r, b, t, f, n);
+        assert_ndc(m, Vec3::new(l, b, n), Vec3::new(-1., -1., 0.));
+        assert_ndc(m, Vec3::new(r, t, f), Vec3::new(1., 1., 1.));
+
+        let m = lh::no::orthographic(l, r, b, t, f, n);
+        assert_ndc(m, Vec3::new(l, b, n), Vec3::new(-1., -1., -1.));
+        assert_ndc(m, Vec3::new(r, t, f), Vec3::new(1., 1., 1.));
+
+        let m = rh::zo::orthographic(l, r, b, t, f, n);
+        assert_ndc(m, Vec3::new(l, b, -n), Vec3::new(-1., -1., 0.));
+        assert_ndc(m, Vec3::new(r, t, -f), Vec3::new(1., 1., 1.));
+
+        let m = rh::no::orthographic(l, r, b, t, f, n);
+        assert_ndc(m, Vec3::new(l, b, -n), Vec3::new(-1., -1., -1.));
+        assert_ndc(m, Vec3::new(r, t, -f), Vec3::new(1., 1., 1.));
+    }
+}
@@ -0,0 +1,420 @@
+//! Morton (Z-order) and Hilbert space filling curves, used to order primitives
+//! for LBVH construction and tiles for cache friendly traversal, and an LSD
+//! radix sort for the resulting keys.
+
+use crate::vec::{Vec2u, Vec3, Vec3u};
+use crate::geometry::Aabb;
+
+/// Spreads the low 16 bits of x to the even bits.
+#[inline]
+pub fn part1by1(x: u32) -> u32 {
+    let mut x = x & 0x0000ffff;
+    x = (x | (x << 8)) & 0x00ff00ff;
+    x = (x | (x << 4)) & 0x0f0f0f0f;
+    x = (x | (x << 2)) & 0x33333333;
+    x = (x | (x << 1)) & 0x55555555;
+    x
+}
+
+/// Inverse of part1by1.
+#[inline]
+pub fn compact1by1(x: u32) -> u32 {
+    let mut x = x & 0x55555555;
+    x = (x | (x >> 1)) & 0x33333333;
+    x = (x | (x >> 2)) & 0x0f0f0f0f;
+    x = (x | (x >> 4)) & 0x00ff00ff;
+    x = (x | (x >> 8)) & 0x0000ffff;
+    x
+}
+
+/// Spreads the low 10 bits of x to every third bit.
+#[inline]
+pub fn part1by2(x: u32) -> u32 {
+    let mut x = x & 0x000003ff;
+    x = (x | (x << 16)) & 0x030000ff;
+    x = (x | (x << 8)) & 0x0300f00f;
+    x = (x | (x << 4)) & 0x030c30c3;
+    x = (x | (x << 2)) & 0x09249249;
+    x
+}
+
+/// Inverse of part1by2.
+#[inline]
+pub fn compact1by2(x: u32) -> u32 {
+    let mut x = x & 0x09249249;
+    x = (x | (x >> 2)) & 0x030c30c3;
+    x = (x | (x >> 4)) & 0x0300f00f;
+    x = (x | (x >> 8)) & 0x030000ff;
+    x = (x | (x >> 16)) & 0x000003ff;
+    x
+}
+
+/// Spreads the low 21 bits of x to every third bit.
+#[inline]
+pub fn part1by2_64(x: u64) -> u64 {
+    let mut x = x & 0x1fffff;
+    x = (x | (x << 32)) & 0x001f00000000ffff;
+    x = (x | (x << 16)) & 0x001f0000ff0000ff;
+    x = (x | (x << 8)) & 0x100f00f00f00f00f;
+    x = (x | (x << 4)) & 0x10c30c30c30c30c3;
+    x = (x | (x << 2)) & 0x1249249249249249;
+    x
+}
+
+/// Inverse of part1by2_64.
+#[inline]
+pub fn compact1by2_64(x: u64) -> u64 {
+    let mut x = x & 0x1249249249249249;
+    x = (x | (x >> 2)) & 0x10c30c30c30c30c3;
+    x = (x | (x >> 4)) & 0x100f00f00f00f00f;
+    x = (x | (x >> 8)) & 0x001f0000ff0000ff;
+    x = (x | (x >> 16)) & 0x001f00000000ffff;
+    x = (x | (x >> 32)) & 0x1fffff;
+    x
+}
+
+/// 32 bit code of the low 16 bits of each coordinate, x in the lowest bit.
+#[inline]
+pub fn morton2_encode(p: Vec2u) -> u32 {
+    part1by1(p.x) | (part1by1(p.y) << 1)
+}
+
+#[inline]
+pub fn morton2_decode(code: u32) -> Vec2u {
+    Vec2u::new(compact1by1(code), compact1by1(code >> 1))
+}
+
+/// 30 bit code of the low 10 bits of each coordinate, x in the lowest bit.
+#[inline]
+pub fn morton3_encode(p: Vec3u) -> u32 {
+    part1by2(p.x) | (part1by2(p.y) << 1) | (part1by2(p.z) << 2)
+}
+
+#[inline]
+pub fn morton3_decode(code: u32) -> Vec3u {
+    Vec3u::new(compact1by2(code), compact1by2(code >> 1), compact1by2(code >> 2))
+}
+
+/// 63 bit code of the low 21 bits of each coordinate, x in the lowest bit.
+#[inline]
+pub fn morton3_encode_64(p: Vec3u) -> u64 {
+    part1by2_64(p.x as u64) | (part1by2_64(p.y as u64) << 1) | (part1by2_64(p.z as u64) << 2)
+}
+
+#[inline]
+pub fn morton3_decode_64(code: u64) -> Vec3u {
+    Vec3u::new(compact1by2_64(code) as u32,
+               compact1by2_64(code >> 1) as u32,
+               compact1by2_64(code >> 2) as u32)
+}
+
+/// Cell of p in a grid of 2^bits cells per axis covering bounds, points
+/// outside are clamped to the border cells and flat axes map to cell 0.
+#[inline]
+pub fn quantize(p: Vec3, bounds: &Aabb, bits: u32) -> Vec3u {
+    let cells = (1u32 << bits) as f32;
+    let e = bounds.extent();
+    let d = p - bounds.min;
+    let q = |d: f32, e: f32| {
+        if e > 0.0 {
+            (d / e * cells).clamp(0.0, cells - 1.0) as u32
+        } else {
+            0
+        }
+    };
+    Vec3u::new(q(d.x, e.x), q(d.y, e.y), q(d.z, e.z))
+}
+
+/// 30 bit Morton code of p normalized inside bounds.
+#[inline]
+pub fn morton3_from_point(p: Vec3, bounds: &Aabb) -> u32 {
+    morton3_encode(quantize(p, bounds, 10))
+}
+
+/// 63 bit Morton code of p normalized inside bounds.
+#[inline]
+pub fn morton3_from_point_64(p: Vec3, bounds: &Aabb) -> u64 {
+    morton3_encode_64(quantize(p, bounds, 21))
+}
+
+/// Index of p along the Hilbert curve filling a 2^order x 2^order grid,
+/// order at most 16. Consecutive indices are adjacent cells.
+pub fn hilbert2_encode(order: u32, p: Vec2u) -> u32 {
+    debug_assert!(order <= 16);
+    let n = (1u64 << order) as u32;
+    let (mut x, mut y) = (p.x & n.wrapping_sub(1), p.y & n.wrapping_sub(1));
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s != 0) as u32;
+        let ry = (y & s != 0) as u32;
+        d += s * s * ((3 * rx) ^ ry);
+        hilbert_rotate(n, &mut x, &mut y, rx, ry);
+        s /= 2;
+    }
+    d
+}
+
+/// Inverse of hilbert2_encode.
+pub fn hilbert2_decode(order: u32, d: u32) -> Vec2u {
+    debug_assert!(order <= 16);
+    let n = 1u64 << order;
+    let (mut x, mut y) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+    while (s as u64) < n {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        hilbert_rotate(s, &mut x, &mut y, rx, ry);
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    Vec2u::new(x, y)
+}
+
+#[inline]
+fn hilbert_rotate(n: u32, x: &mut u32, y: &mut u32, rx: u32, ry: u32) {
+    if ry == 0 {
+        if rx == 1 {
+            *x = n.wrapping_sub(1).wrapping_sub(*x);
+            *y = n.wrapping_sub(1).wrapping_sub(*y);
+        }
+        core::mem::swap(x, y);
+    }
+}
+
+/// Tiles of a width x height grid in Hilbert order, grids that are not a
+/// square power of two follow the curve of the enclosing one.
+pub fn hilbert_tile_order(width: u32, height: u32) -> Vec<Vec2u> {
+    let size = width.max(height).max(1).next_power_of_two();
+    let order = size.trailing_zeros();
+
+    let mut tiles: Vec<Vec2u> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| Vec2u::new(x, y)))
+        .collect();
+    radix_sort_by_key(&mut tiles, |t| hilbert2_encode(order, *t));
+    tiles
+}
+
+/// Unsigned integer keys for radix sorting.
+pub trait RadixKey: Copy {
+    const BYTES: usize;
+
+    fn byte(self, i: usize) -> u8;
+}
+
+impl RadixKey for u32 {
+    const BYTES: usize = 4;
+
+    #[inline]
+    fn byte(self, i: usize) -> u8 {
+        (self >> (i * 8)) as u8
+    }
+}
+
+impl RadixKey for u64 {
+    const BYTES: usize = 8;
+
+    #[inline]
+    fn byte(self, i: usize) -> u8 {
+        (self >> (i * 8)) as u8
+    }
+}
+
+/// Stable LSD radix sort with 8 bit digits, passes where every key has the
+/// same digit are skipped, so keys using few bits only pay for those.
+pub fn radix_sort_by_key<T: Copy, K: RadixKey>(items: &mut [T], key: impl Fn(&T) -> K) {
+    if items.len() < 2 {
+        return;
+    }
+
+    let mut scratch = items.to_vec();
+    let mut in_scratch = false;
+
+    for pass in 0..K::BYTES {
+        let (src, dst): (&[T], &mut [T]) = if in_scratch {
+            (&scratch, &mut *items)
+        } else {
+            (&*items, &mut scratch)
+        };
+
+        let mut offsets = [0usize; 256];
+        for it in src {
+            offsets[key(it).byte(pass) as usize] += 1;
+        }
+        if offsets.contains(&src.len()) {
+            continue;
+        }
+
+        let mut sum = 0;
+        for o in offsets.iter_mut() {
+            let c = *o;
+            *o = sum;
+            sum += c;
+        }
+
+        for it in src {
+            let b = key(it).byte(pass) as usize;
+            dst[offsets[b]] = *it;
+            offsets[b] += 1;
+        }
+        in_scratch = !in_scratch;
+    }
+
+    if in_scratch {
+        items.copy_from_slice(&scratch);
+    }
+}
+
+#[inline]
+pub fn radix_sort<K: RadixKey>(keys: &mut [K]) {
+    radix_sort_by_key(keys, |k| *k);
+}
+
+/// Indices that sort keys, ties keep their original order.
+pub fn radix_argsort<K: RadixKey>(keys: &[K]) -> Vec<u32> {
+    let mut indices: Vec<u32> = (0..keys.len() as u32).collect();
+    radix_sort_by_key(&mut indices, |i| keys[*i as usize]);
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Rng;
+
+    #[test]
+    fn morton_round_trip() {
+        assert_eq!(morton2_encode(Vec2u::new(1, 0)), 1);
+        assert_eq!(morton2_encode(Vec2u::new(0, 1)), 2);
+        assert_eq!(morton2_encode(Vec2u::new(0xffff, 0xffff)), u32::MAX);
+        assert_eq!(morton3_encode(Vec3u::new(1, 0, 0)), 1);
+        assert_eq!(morton3_encode(Vec3u::new(0, 1, 0)), 2);
+        assert_eq!(morton3_encode(Vec3u::new(0, 0, 1)), 4);
+        assert_eq!(morton3_encode(Vec3u::new(1023, 1023, 1023)), (1 << 30) - 1);
+        assert_eq!(morton3_encode_64(Vec3u::new(0, 0, 1 << 20)), 1 << 62);
+        assert_eq!(morton3_encode_64(Vec3u::from_scalar((1 << 21) - 1)), (1 << 63) - 1);
+
+        let mut rng = Rng(5);
+        for _ in 0..10000 {
+            let r = rng.next_u64();
+            let p = Vec2u::new(r as u32 & 0xffff, (r >> 16) as u32 & 0xffff);
+            assert_eq!(morton2_decode(morton2_encode(p)).to_slice(), p.to_slice());
+
+            let p = Vec3u::new(r as u32 & 0x3ff, (r >> 10) as u32 & 0x3ff, (r >> 20) as u32 & 0x3ff);
+            assert_eq!(morton3_decode(morton3_encode(p)).to_slice(), p.to_slice());
+
+            let m = (1 << 21) - 1;
+            let p = Vec3u::new(r as u32 & m, (r >> 21) as u32 & m, (r >> 42) as u32 & m);
+            assert_eq!(morton3_decode_64(morton3_encode_64(p)).to_slice(), p.to_slice());
+
+            let c = r as u32 & ((1 << 30) - 1);
+            assert_eq!(morton3_encode(morton3_decode(c)), c);
+            let c = r & ((1 << 63) - 1);
+            assert_eq!(morton3_encode_64(morton3_decode_64(c)), c);
+        }
+    }
+
+    #[test]
+    fn morton_from_point() {
+        let bounds = Aabb::new(Vec3::new(-1.0, 0.0, 2.0), Vec3::new(1.0, 4.0, 2.0));
+        assert_eq!(morton3_from_point(bounds.min, &bounds), 0);
+        assert_eq!(morton3_from_point(bounds.max, &bounds), 0b011011011011011011011011011011);
+        assert_eq!(morton3_from_point(Vec3::from_scalar(-10.0), &bounds), 0);
+        assert_eq!(quantize(bounds.center(), &bounds, 10).to_slice(), [512, 512, 0]);
+        assert_eq!(quantize(bounds.max, &bounds, 21).to_slice(), [(1 << 21) - 1, (1 << 21) - 1, 0]);
+        assert_eq!(morton3_from_point_64(Vec3::new(0.0, 4.0, 2.0), &bounds),
+                   morton3_encode_64(Vec3u::new(1 << 20, (1 << 21) - 1, 0)));
+    }
+
+    #[test]
+    fn morton_locality() {
+        // Cells in the same aligned 2^k block share the top bits of the code.
+        let mut rng = Rng(9);
+        for _ in 0..10000 {
+            let (a, b) = (rng.next_u64(), rng.next_u64());
+            let k = (a >> 60) as u32 % 10;
+            let pa = Vec3u::new(a as u32 & 0x3ff, (a >> 10) as u32 & 0x3ff, (a >> 20) as u32 & 0x3ff);
+            let pb = if b & 1 == 0 {
+                Vec3u::new(b as u32 & 0x3ff, (b >> 10) as u32 & 0x3ff, (b >> 20) as u32 & 0x3ff)
+            } else {
+                let mask = (1 << k) - 1;
+                Vec3u::new((pa.x & !mask) | (b as u32 & mask), (pa.y & !mask) | ((b >> 10) as u32 & mask),
+                           (pa.z & !mask) | ((b >> 20) as u32 & mask))
+            };
+            let same_block = (pa >> k).to_slice() == (pb >> k).to_slice();
+            let same_prefix = morton3_encode(pa) >> (3 * k) == morton3_encode(pb) >> (3 * k);
+            assert_eq!(same_block, same_prefix);
+        }
+
+        // Sorting by code shortens the path through random points by far.
+        let bounds = Aabb::new(Vec3::from_scalar(0.0), Vec3::from_scalar(1.0));
+        let points: Vec<Vec3> = (0..4096)
+            .map(|_| Vec3::new(rng.range(0., 1.), rng.range(0., 1.), rng.range(0., 1.)))
+            .collect();
+        let path = |p: &[Vec3]| p.windows(2).map(|w| (w[1] - w[0]).length()).sum::<f32>();
+
+        let mut sorted = points.clone();
+        radix_sort_by_key(&mut sorted, |p| morton3_from_point_64(*p, &bounds));
+        assert!(path(&sorted) < 0.2 * path(&points), "{} {}", path(&sorted), path(&points));
+    }
+
+    #[test]
+    fn hilbert() {
+        for order in 0..7 {
+            let n = 1u32 << order;
+            let mut seen = vec![false; (n * n) as usize];
+            let mut prev = hilbert2_decode(order, 0);
+            assert_eq!(prev.to_slice(), [0, 0]);
+            for d in 0..n * n {
+                let p = hilbert2_decode(order, d);
+                assert!(p.x < n && p.y < n);
+                assert_eq!(hilbert2_encode(order, p), d);
+                assert!(!seen[(p.y * n + p.x) as usize]);
+                seen[(p.y * n + p.x) as usize] = true;
+
+                let step = (p.x as i32 - prev.x as i32).abs() + (p.y as i32 - prev.y as i32).abs();
+                assert!(d == 0 || step == 1, "order {} index {}", order, d);
+                prev = p;
+            }
+        }
+
+        let p = Vec2u::new(54321, 12345);
+        assert_eq!(hilbert2_decode(16, hilbert2_encode(16, p)).to_slice(), p.to_slice());
+
+        let tiles = hilbert_tile_order(5, 3);
+        assert_eq!(tiles.len(), 15);
+        let mut seen = [false; 15];
+        for t in &tiles {
+            assert!(t.x < 5 && t.y < 3);
+            seen[(t.y * 5 + t.x) as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+        assert!(hilbert_tile_order(0, 4).is_empty());
+    }
+
+    #[test]
+    fn radix() {
+        let mut rng = Rng(3);
+        for &(n, bits) in &[(0, 32), (1, 32), (1000, 32), (1000, 10), (5000, 30)] {
+            let mut keys: Vec<u32> = (0..n).map(|_| (rng.next_u64() >> (64 - bits)) as u32).collect();
+            let mut expected = keys.clone();
+            expected.sort_unstable();
+            radix_sort(&mut keys);
+            assert_eq!(keys, expected);
+        }
+
+        let keys: Vec<u64> = (0..3000).map(|_| rng.next_u64() >> 1).collect();
+        let indices = radix_argsort(&keys);
+        assert!(indices.windows(2).all(|w| keys[w[0] as usize] <= keys[w[1] as usize]));
+
+        // Stable for equal keys
+        let mut items: Vec<(u32, u32)> = (0..2000).map(|i| ((rng.next_u64() % 16) as u32, i)).collect();
+        let mut expected = items.clone();
+        expected.sort_by_key(|i| i.0);
+        radix_sort_by_key(&mut items, |i| i.0);
+        assert_eq!(items, expected);
+    }
+}
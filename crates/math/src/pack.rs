@@ -0,0 +1,494 @@
+//! Compact encodings for vertex attributes and textures: IEEE half floats,
+//! snorm/unorm integers, octahedral normals and the D3D RGB9E5 and
+//! R11G11B10_FLOAT formats. Conversions round to nearest like the GPU.
+
+use crate::vec::{Vec2, Vec3, Vec4};
+
+/// Rounds the magnitude of x to a float with 5 exponent bits (bias 15) and
+/// m mantissa bits, returned as exponent << m | mantissa. Rounds to nearest
+/// even, overflows to infinity and keeps NaN.
+fn f32_to_float5(x: f32, m: u32) -> u32 {
+    let bits = x.to_bits() & 0x7fffffff;
+    if bits >= 0x7f800000 {
+        let nan = if bits > 0x7f800000 { 1 << (m - 1) } else { 0 };
+        return (31 << m) | nan;
+    }
+
+    let e = (bits >> 23) as i32 - 127 + 15;
+    if e >= 31 {
+        return 31 << m;
+    }
+
+    let (v, shift) = if e <= 0 {
+        // Denormal, 2^(e - 1) with the implicit bit in units of 2^(-14 - m)
+        let shift = 24 - m as i32 - e;
+        if shift > 24 {
+            return 0;
+        }
+        ((bits & 0x7fffff) | 0x800000, shift as u32)
+    } else {
+        (((e as u32) << 23) | (bits & 0x7fffff), 23 - m)
+    };
+
+    // The carry of the rounding moves to the exponent, up to infinity.
+    let rem = v & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+    let r = v >> shift;
+    if rem > half || (rem == half && r & 1 == 1) { r + 1 } else { r }
+}
+
+fn float5_to_f32(v: u32, m: u32) -> f32 {
+    let e = v >> m;
+    let mantissa = v & ((1 << m) - 1);
+    match e {
+        0 => mantissa as f32 * f32::from_bits((127 - 14 - m) << 23),
+        31 => f32::from_bits(0x7f800000 | (mantissa << (23 - m))),
+        _ => f32::from_bits(((e + 127 - 15) << 23) | (mantissa << (23 - m))),
+    }
+}
+
+/// IEEE 754 binary16, like f32tof16 in HLSL.
+#[inline]
+pub fn f32_to_f16(x: f32) -> u16 {
+    let sign = (x.to_bits() >> 16) & 0x8000;
+    (sign | f32_to_float5(x, 10)) as u16
+}
+
+#[inline]
+pub fn f16_to_f32(h: u16) -> f32 {
+    let x = float5_to_f32(h as u32 & 0x7fff, 10);
+    if h & 0x8000 != 0 { -x } else { x }
+}
+
+/// x in the low 16 bits and y in the high ones.
+#[inline]
+pub fn pack_half2(v: Vec2) -> u32 {
+    f32_to_f16(v.x) as u32 | (f32_to_f16(v.y) as u32) << 16
+}
+
+#[inline]
+pub fn unpack_half2(p: u32) -> Vec2 {
+    Vec2::new(f16_to_f32(p as u16), f16_to_f32((p >> 16) as u16))
+}
+
+#[inline]
+pub fn pack_half4(v: Vec4) -> [u16; 4] {
+    [f32_to_f16(v.x), f32_to_f16(v.y), f32_to_f16(v.z), f32_to_f16(v.w)]
+}
+
+#[inline]
+pub fn unpack_half4(h: [u16; 4]) -> Vec4 {
+    Vec4::new(f16_to_f32(h[0]), f16_to_f32(h[1]), f16_to_f32(h[2]), f16_to_f32(h[3]))
+}
+
+/// [0, 1] to [0, 255], NaN maps to 0.
+#[inline]
+pub fn pack_unorm8(x: f32) -> u8 {
+    (x.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[inline]
+pub fn unpack_unorm8(x: u8) -> f32 {
+    x as f32 / 255.0
+}
+
+#[inline]
+pub fn pack_unorm16(x: f32) -> u16 {
+    (x.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+#[inline]
+pub fn unpack_unorm16(x: u16) -> f32 {
+    x as f32 / 65535.0
+}
+
+/// [-1, 1] to [-127, 127], -128 is never produced and decodes to -1.
+#[inline]
+pub fn pack_snorm8(x: f32) -> i8 {
+    (x.clamp(-1.0, 1.0) * 127.0).round() as i8
+}
+
+#[inline]
+pub fn unpack_snorm8(x: i8) -> f32 {
+    (x as f32 / 127.0).max(-1.0)
+}
+
+#[inline]
+pub fn pack_snorm16(x: f32) -> i16 {
+    (x.clamp(-1.0, 1.0) * 32767.0).round() as i16
+}
+
+#[inline]
+pub fn unpack_snorm16(x: i16) -> f32 {
+    (x as f32 / 32767.0).max(-1.0)
+}
+
+/// Same layout as R8G8B8A8_UNORM, x in the low byte.
+#[inline]
+pub fn pack_unorm4x8(v: Vec4) -> u32 {
+    u32::from_le_bytes([pack_unorm8(v.x), pack_unorm8(v.y), pack_unorm8(v.z), pack_unorm8(v.w)])
+}
+
+#[inline]
+pub fn unpack_unorm4x8(p: u32) -> Vec4 {
+    let b = p.to_le_bytes();
+    Vec4::new(unpack_unorm8(b[0]), unpack_unorm8(b[1]), unpack_unorm8(b[2]), unpack_unorm8(b[3]))
+}
+
+#[inline]
+pub fn pack_snorm4x8(v: Vec4) -> u32 {
+    u32::from_le_bytes([pack_snorm8(v.x) as u8, pack_snorm8(v.y) as u8,
+                        pack_snorm8(v.z) as u8, pack_snorm8(v.w) as u8])
+}
+
+#[inline]
+pub fn unpack_snorm4x8(p: u32) -> Vec4 {
+    let b = p.to_le_bytes();
+    Vec4::new(unpack_snorm8(b[0] as i8), unpack_snorm8(b[1] as i8),
+              unpack_snorm8(b[2] as i8), unpack_snorm8(b[3] as i8))
+}
+
+#[inline]
+pub fn pack_unorm2x16(v: Vec2) -> u32 {
+    pack_unorm16(v.x) as u32 | (pack_unorm16(v.y) as u32) << 16
+}
+
+#[inline]
+pub fn unpack_unorm2x16(p: u32) -> Vec2 {
+    Vec2::new(unpack_unorm16(p as u16), unpack_unorm16((p >> 16) as u16))
+}
+
+#[inline]
+pub fn pack_snorm2x16(v: Vec2) -> u32 {
+    pack_snorm16(v.x) as u16 as u32 | (pack_snorm16(v.y) as u16 as u32) << 16
+}
+
+#[inline]
+pub fn unpack_snorm2x16(p: u32) -> Vec2 {
+    Vec2::new(unpack_snorm16(p as u16 as i16), unpack_snorm16((p >> 16) as u16 as i16))
+}
+
+#[inline]
+fn sign_not_zero(x: f32) -> f32 {
+    if x >= 0.0 { 1.0 } else { -1.0 }
+}
+
+/// Octahedral mapping of a unit vector to [-1, 1]^2, from "A Survey of
+/// Efficient Representations for Independent Unit Vectors" (Cigolle et al.)
+#[inline]
+pub fn oct_encode(n: Vec3) -> Vec2 {
+    let l1 = n.x.abs() + n.y.abs() + n.z.abs();
+    let (x, y) = (n.x / l1, n.y / l1);
+    if n.z < 0.0 {
+        Vec2::new((1.0 - y.abs()) * sign_not_zero(x), (1.0 - x.abs()) * sign_not_zero(y))
+    } else {
+        Vec2::new(x, y)
+    }
+}
+
+#[inline]
+pub fn oct_decode(e: Vec2) -> Vec3 {
+    let z = 1.0 - e.x.abs() - e.y.abs();
+    let t = (-z).max(0.0);
+    let x = e.x - t * sign_not_zero(e.x);
+    let y = e.y - t * sign_not_zero(e.y);
+    Vec3::new(x, y, z).normalized()
+}
+
+/// Octahedral normal in two snorm8, x in the low byte.
+#[inline]
+pub fn pack_oct16(n: Vec3) -> u16 {
+    let e = oct_encode(n);
+    u16::from_le_bytes([pack_snorm8(e.x) as u8, pack_snorm8(e.y) as u8])
+}
+
+#[inline]
+pub fn unpack_oct16(p: u16) -> Vec3 {
+    let b = p.to_le_bytes();
+    oct_decode(Vec2::new(unpack_snorm8(b[0] as i8), unpack_snorm8(b[1] as i8)))
+}
+
+/// Octahedral normal in two snorm16, x in the low bits.
+#[inline]
+pub fn pack_oct32(n: Vec3) -> u32 {
+    pack_snorm2x16(oct_encode(n))
+}
+
+#[inline]
+pub fn unpack_oct32(p: u32) -> Vec3 {
+    oct_decode(unpack_snorm2x16(p))
+}
+
+const RGB9E5_MANTISSA_BITS: i32 = 9;
+const RGB9E5_EXP_BIAS: i32 = 15;
+
+/// Largest value of RGB9E5, 511 / 512 * 2^16
+pub const RGB9E5_MAX: f32 = 65408.0;
+
+/// Shared exponent format with 9 bit mantissas, same as
+/// DXGI_FORMAT_R9G9B9E5_SHAREDEXP: r in the low bits and the exponent in
+/// the top 5. Negative values and NaN clamp to 0, large ones to RGB9E5_MAX.
+pub fn pack_rgb9e5(c: Vec3) -> u32 {
+    let clamp = |x: f32| if x > 0.0 { x.min(RGB9E5_MAX) } else { 0.0 };
+    let (r, g, b) = (clamp(c.x), clamp(c.y), clamp(c.z));
+    let max = r.max(g).max(b);
+
+    // floor(log2(max)) from the exponent bits, denormals are below the
+    // smallest exponent anyway.
+    let log2 = if max < f32::from_bits(((127 - RGB9E5_EXP_BIAS) as u32) << 23) {
+        -RGB9E5_EXP_BIAS - 1
+    } else {
+        (max.to_bits() >> 23) as i32 - 127
+    };
+
+    let mut exp = log2 + 1 + RGB9E5_EXP_BIAS;
+    let scale = |exp: i32| f32::from_bits(((127 - exp + RGB9E5_EXP_BIAS + RGB9E5_MANTISSA_BITS) as u32) << 23);
+    if (max * scale(exp) + 0.5).floor() as u32 == 1 << RGB9E5_MANTISSA_BITS {
+        exp += 1;
+    }
+
+    let s = scale(exp);
+    let q = |x: f32| (x * s + 0.5).floor() as u32;
+    q(r) | q(g) << 9 | q(b) << 18 | (exp as u32) << 27
+}
+
+pub fn unpack_rgb9e5(p: u32) -> Vec3 {
+    let exp = (p >> 27) as i32;
+    let s = f32::from_bits(((127 + exp - RGB9E5_EXP_BIAS - RGB9E5_MANTISSA_BITS) as u32) << 23);
+    Vec3::new((p & 0x1ff) as f32 * s, ((p >> 9) & 0x1ff) as f32 * s, ((p >> 18) & 0x1ff) as f32 * s)
+}
+
+/// Unsigned float with m mantissa bits, like DirectXMath: negative values
+/// and -inf are 0, +inf and NaN are kept and finite values clamp to the
+/// largest finite one.
+fn f32_to_packed_float(x: f32, m: u32) -> u32 {
+    if x.is_nan() {
+        return f32_to_float5(x, m);
+    }
+    if x <= 0.0 {
+        return 0;
+    }
+    let v = f32_to_float5(x, m);
+    if v == 31 << m && x.is_finite() { (31 << m) - 1 } else { v }
+}
+
+/// Same layout as DXGI_FORMAT_R11G11B10_FLOAT, r in the low bits.
+#[inline]
+pub fn pack_r11g11b10f(c: Vec3) -> u32 {
+    f32_to_packed_float(c.x, 6) | f32_to_packed_float(c.y, 6) << 11 | f32_to_packed_float(c.z, 5) << 22
+}
+
+#[inline]
+pub fn unpack_r11g11b10f(p: u32) -> Vec3 {
+    Vec3::new(float5_to_f32(p & 0x7ff, 6), float5_to_f32((p >> 11) & 0x7ff, 6),
+              float5_to_f32(p >> 22, 5))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Rng;
+
+    /// Checks that x rounds to the nearest of all the decoded values of a
+    /// format with non negative finite codes 0..count, ties to even.
+    fn check_round_to_nearest(encode: impl Fn(f32) -> u32, decode: impl Fn(u32) -> f32, count: u32) {
+        for c in 0..count - 1 {
+            let (a, b) = (decode(c) as f64, decode(c + 1) as f64);
+            let mid = ((a + b) / 2.0) as f32;
+            assert_eq!(mid as f64, (a + b) / 2.0);
+
+            assert_eq!(encode(decode(c)), c);
+            assert_eq!(encode(f32::from_bits(mid.to_bits() - 1)), c);
+            assert_eq!(encode(f32::from_bits(mid.to_bits() + 1)), c + 1);
+            assert_eq!(encode(mid), if c & 1 == 0 { c } else { c + 1 }, "{}", c);
+        }
+    }
+
+    #[test]
+    fn half() {
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(65519.0), 0x7bff);
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xfc00);
+        assert_eq!(f32_to_f16(1e-8), 0);
+        assert_eq!(f32_to_f16(2.0f32.powi(-24)), 1);
+        assert_eq!(f32_to_f16(2.0f32.powi(-25)), 0);
+        assert_eq!(f32_to_f16(1.5 * 2.0f32.powi(-25)), 1);
+        assert_eq!(f32_to_f16(2.0f32.powi(-14)), 0x0400);
+        assert_eq!(f16_to_f32(0x3555), 1365.0 / 4096.0);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+
+        // Every half round trips, including denormals, infinities and NaN
+        for h in 0..=u16::MAX {
+            let x = f16_to_f32(h);
+            if x.is_nan() {
+                assert!(f16_to_f32(f32_to_f16(x)).is_nan());
+                assert_eq!(h & 0x7c00, 0x7c00);
+            } else {
+                assert_eq!(f32_to_f16(x), h, "{:x}", h);
+            }
+        }
+
+        check_round_to_nearest(|x| f32_to_f16(x) as u32, |h| f16_to_f32(h as u16), 0x7c00);
+        check_round_to_nearest(|x| f32_to_f16(-x) as u32 & 0x7fff, |h| f16_to_f32(h as u16), 0x7c00);
+
+        let v = Vec4::new(0.5, -1.0, 1024.0, 0.1);
+        let u = unpack_half4(pack_half4(v));
+        assert_eq!(u.to_slice()[..3], v.to_slice()[..3]);
+        assert!((u.w - 0.1).abs() < 0.1 * 2.0f32.powi(-11));
+        assert_eq!(unpack_half2(pack_half2(Vec2::new(3.0, -0.25))).to_slice(), [3.0, -0.25]);
+        assert_eq!(pack_half2(Vec2::new(1.0, -2.0)), 0xc000_3c00);
+    }
+
+    #[test]
+    fn norm() {
+        for c in 0..=u8::MAX {
+            assert_eq!(pack_unorm8(unpack_unorm8(c)), c);
+        }
+        for c in 0..=u16::MAX {
+            assert_eq!(pack_unorm16(unpack_unorm16(c)), c);
+        }
+        for c in i8::MIN..=i8::MAX {
+            assert_eq!(pack_snorm8(unpack_snorm8(c)), c.max(-127));
+        }
+        for c in i16::MIN..=i16::MAX {
+            assert_eq!(pack_snorm16(unpack_snorm16(c)), c.max(-32767));
+        }
+        assert_eq!(unpack_snorm8(-128), -1.0);
+        assert_eq!(pack_snorm8(0.0), 0);
+        assert_eq!(pack_unorm8(f32::NAN), 0);
+        assert_eq!(pack_unorm16(2.0), u16::MAX);
+        assert_eq!(pack_snorm16(-2.0), -32767);
+
+        // Error is at most half a step
+        let mut rng = Rng(31);
+        for _ in 0..100000 {
+            let x = rng.range(0.0, 1.0);
+            assert!((unpack_unorm8(pack_unorm8(x)) - x).abs() <= 0.5 / 255.0 + 1e-7);
+            assert!((unpack_unorm16(pack_unorm16(x)) - x).abs() <= 0.5 / 65535.0 + 1e-7);
+            let x = rng.range(-1.0, 1.0);
+            assert!((unpack_snorm8(pack_snorm8(x)) - x).abs() <= 0.5 / 127.0 + 1e-7);
+            assert!((unpack_snorm16(pack_snorm16(x)) - x).abs() <= 0.5 / 32767.0 + 1e-7);
+        }
+
+        let v = Vec4::new(0.0, 1.0, 0.5, 0.25);
+        assert_eq!(pack_unorm4x8(v), 0x40_80_ff_00);
+        assert_eq!(pack_unorm4x8(unpack_unorm4x8(0x12345678)), 0x12345678);
+        assert_eq!(pack_snorm4x8(unpack_snorm4x8(0x7f0181c0)), 0x7f0181c0);
+        assert_eq!(pack_unorm2x16(unpack_unorm2x16(0xdeadbeef)), 0xdeadbeef);
+        assert_eq!(pack_snorm2x16(unpack_snorm2x16(0x7fff8001)), 0x7fff8001);
+        assert_eq!(unpack_snorm2x16(pack_snorm2x16(Vec2::new(-1.0, 1.0))).to_slice(), [-1.0, 1.0]);
+    }
+
+    #[test]
+    fn octahedral() {
+        let axes = [Vec3::new(1., 0., 0.), Vec3::new(-1., 0., 0.), Vec3::new(0., 1., 0.),
+                    Vec3::new(0., -1., 0.), Vec3::new(0., 0., 1.), Vec3::new(0., 0., -1.)];
+        for n in axes {
+            assert_eq!(oct_decode(oct_encode(n)).to_slice(), n.to_slice());
+            assert_eq!(unpack_oct16(pack_oct16(n)).to_slice(), n.to_slice());
+            assert_eq!(unpack_oct32(pack_oct32(n)).to_slice(), n.to_slice());
+        }
+
+        // Max angular error over random directions, 8 bit snorm cells are
+        // 2/254 wide so the error is about 1 degree.
+        let mut rng = Rng(32);
+        let (mut max16, mut max32, mut max_f) = (0.0f64, 0.0f64, 0.0f64);
+        for _ in 0..200000 {
+            let n = Vec3::new(rng.range(-1., 1.), rng.range(-1., 1.), rng.range(-1., 1.));
+            if n.norm() < 0.1 {
+                continue;
+            }
+            let n = n.normalized();
+            let e = oct_encode(n);
+            assert!(e.x.abs() <= 1.0 && e.y.abs() <= 1.0);
+
+            // atan2 of the cross and dot products is accurate for small angles
+            let angle = |m: Vec3| (m.cross(n).norm() as f64).atan2(m.dot(n) as f64).to_degrees();
+            max_f = max_f.max(angle(oct_decode(e)));
+            max16 = max16.max(angle(unpack_oct16(pack_oct16(n))));
+            max32 = max32.max(angle(unpack_oct32(pack_oct32(n))));
+        }
+        assert!(max_f < 1e-3, "{}", max_f);
+        assert!(max16 < 1.0, "{}", max16);
+        assert!(max32 < 0.005, "{}", max32);
+    }
+
+    #[test]
+    fn rgb9e5() {
+        assert_eq!(unpack_rgb9e5(pack_rgb9e5(Vec3::new(1.0, 0.5, 0.0))).to_slice(), [1.0, 0.5, 0.0]);
+        assert_eq!(unpack_rgb9e5(pack_rgb9e5(Vec3::from_scalar(1e9))).to_slice(), [RGB9E5_MAX; 3]);
+        assert_eq!(unpack_rgb9e5(pack_rgb9e5(Vec3::new(-1.0, f32::NAN, 0.0))).to_slice(), [0.0; 3]);
+        assert_eq!(pack_rgb9e5(Vec3::from_scalar(0.0)), 0);
+        // 511.6 / 512 rounds up to the next exponent
+        let p = pack_rgb9e5(Vec3::new(0.9993, 0.0, 0.0));
+        assert_eq!(p >> 27, 16);
+        assert_eq!(unpack_rgb9e5(p).x, 1.0);
+
+        // Decoded values are exactly representable
+        for exp in 0..32 {
+            for r in 0..512 {
+                let p = r | (511 - r) << 9 | (r / 3) << 18 | exp << 27;
+                let c = unpack_rgb9e5(p);
+                assert_eq!(unpack_rgb9e5(pack_rgb9e5(c)).to_slice(), c.to_slice(), "{:x}", p);
+            }
+        }
+
+        // Error of each channel is at most half a step of the shared exponent
+        let mut rng = Rng(33);
+        for _ in 0..100000 {
+            let scale = 2.0f32.powf(rng.range(-20.0, 16.0));
+            let c = Vec3::new(rng.range(0., 1.), rng.range(0., 1.), rng.range(0., 1.)) * scale;
+            let p = pack_rgb9e5(c);
+            let step = 2.0f32.powi((p >> 27) as i32 - 15 - 9);
+            let d = unpack_rgb9e5(p);
+            for (a, b) in c.to_slice().iter().zip(d.to_slice().iter()) {
+                assert!((a - b).abs() <= 0.5 * step, "{:?} {:?}", c, d);
+                assert!((a - b).abs() <= c.max_element() / 512.0 + 2.0f32.powi(-25));
+            }
+        }
+    }
+
+    #[test]
+    fn r11g11b10f() {
+        for m in [6, 5] {
+            let count = 31 << m;
+            check_round_to_nearest(|x| f32_to_packed_float(x, m), |v| float5_to_f32(v, m), count);
+            for v in 0..1 << (m + 5) {
+                let x = float5_to_f32(v, m);
+                if x.is_nan() {
+                    assert!(float5_to_f32(f32_to_packed_float(x, m), m).is_nan());
+                } else {
+                    assert_eq!(f32_to_packed_float(x, m), v);
+                }
+            }
+
+            assert_eq!(float5_to_f32(count - 1, m), if m == 6 { 65024.0 } else { 64512.0 });
+            assert_eq!(f32_to_packed_float(1e9, m), count - 1);
+            assert_eq!(f32_to_packed_float(f32::INFINITY, m), count);
+            assert_eq!(f32_to_packed_float(f32::NEG_INFINITY, m), 0);
+            assert_eq!(f32_to_packed_float(-1.0, m), 0);
+        }
+
+        let c = Vec3::new(1.0, 0.5, 2.0);
+        assert_eq!(unpack_r11g11b10f(pack_r11g11b10f(c)).to_slice(), c.to_slice());
+        assert_eq!(pack_r11g11b10f(Vec3::new(1.0, 0.0, 0.0)), 0x3c0);
+        assert_eq!(pack_r11g11b10f(Vec3::new(0.0, 1.0, 0.0)), 0x3c0 << 11);
+        assert_eq!(pack_r11g11b10f(Vec3::new(0.0, 0.0, 1.0)), 0x1e0 << 22);
+
+        // Relative error bounded by half an ulp of the mantissa, denormals
+        // have an absolute error of half the smallest denormal.
+        let mut rng = Rng(34);
+        for _ in 0..100000 {
+            let c = Vec3::new(rng.range(0., 1.), rng.range(0., 1.), rng.range(0., 1.)) *
+                2.0f32.powf(rng.range(-10.0, 15.0));
+            let d = unpack_r11g11b10f(pack_r11g11b10f(c));
+            assert!((d.x - c.x).abs() <= (c.x * 2.0f32.powi(-7)).max(2.0f32.powi(-21)));
+            assert!((d.y - c.y).abs() <= (c.y * 2.0f32.powi(-7)).max(2.0f32.powi(-21)));
+            assert!((d.z - c.z).abs() <= (c.z * 2.0f32.powi(-6)).max(2.0f32.powi(-20)));
+        }
+    }
+}
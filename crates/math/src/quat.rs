@@ -6,10 +6,23 @@ use crate::mat::{Mat3, Mat3d, Mat4, Mat4d};
 use bytemuck::{Pod, Zeroable};
 
 // TODO:
-// [ ] from_euler, from_mat3, from_mat4
-// [ ] slerp / nlerp
+// [x] from_euler, from_mat3, from_mat4
+// [x] slerp / nlerp
 //
-// [ ] transform.rs
+// [x] transform.rs
+
+/// Order in which euler angle rotations are applied. Rotations are around
+/// fixed world axes (extrinsic), e.g. XYZ rotates around X first, then Y and
+/// then Z, which is the same as intrinsic rotations in ZYX order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EulerOrder {
+    XYZ,
+    XZY,
+    YXZ,
+    YZX,
+    ZXY,
+    ZYX,
+}
 
 macro_rules! quat_impl {
     ($name: ident, $t: ident, $v3: ident, $v: ident, $m: ident, $m4: ident) => {
@@ -29,6 +42,11 @@ macro_rules! quat_impl {
                 Self { x, y, z, w }
             }
 
+            #[inline]
+            pub fn identity() -> Self {
+                Self { x: 0., y: 0., z: 0., w: 1. }
+            }
+
             #[inline]
             pub fn re(&self) -> $t {
                 self.w
@@ -69,7 +87,12 @@ macro_rules! quat_impl {
 
             #[inline]
             pub fn norm2(self) -> $t {
-                self.x * self.x + self.y * self.y + self.z * self.z * self.w * self.w
+                self.dot(self)
+            }
+
+            #[inline]
+            pub fn dot(self, b: Self) -> $t {
+                self.x * b.x + self.y * b.y + self.z * b.z + self.w * b.w
             }
 
             #[inline]
@@ -88,6 +111,18 @@ macro_rules! quat_impl {
                 }
             }
 
+            #[inline]
+            pub fn inverse(self) -> Self {
+                let i = 1.0 / self.norm2();
+                let c = self.conj();
+                Self {
+                    x: c.x * i,
+                    y: c.y * i,
+                    z: c.z * i,
+                    w: c.w * i,
+                }
+            }
+
             #[inline]
             pub fn rotate(axis: $v3, angle: $t) -> Self {
                 let s = (angle * 0.5).sin();
@@ -99,6 +134,127 @@ macro_rules! quat_impl {
                 }
             }
 
+            /// Returns a unit axis and an angle in [0, pi], the axis is
+            /// arbitrary (+X) if the rotation is the identity.
+            pub fn to_axis_angle(self) -> ($v3, $t) {
+                let q = if self.w < 0. { -1.0 * self } else { self };
+
+                let im = q.im();
+                let s = im.length();
+                if s == 0. {
+                    return ($v3::new(1., 0., 0.), 0.);
+                }
+
+                (im * (1.0 / s), 2. * $t::atan2(s, q.w))
+            }
+
+            /// Angles are given in the same order as the axes in order.
+            pub fn from_euler(order: EulerOrder, a: $t, b: $t, c: $t) -> Self {
+                let x = $v3::new(1., 0., 0.);
+                let y = $v3::new(0., 1., 0.);
+                let z = $v3::new(0., 0., 1.);
+
+                let (first, second, third) = match order {
+                    EulerOrder::XYZ => (x, y, z),
+                    EulerOrder::XZY => (x, z, y),
+                    EulerOrder::YXZ => (y, x, z),
+                    EulerOrder::YZX => (y, z, x),
+                    EulerOrder::ZXY => (z, x, y),
+                    EulerOrder::ZYX => (z, y, x),
+                };
+
+                Self::rotate(third, c) * Self::rotate(second, b) *
+                    Self::rotate(first, a)
+            }
+
+            /// Shepperd's method, picks the largest of the diagonal and the
+            /// trace to avoid dividing by a small number. The matrix must be
+            /// a pure rotation.
+            pub fn from_rotation_matrix(m: $m) -> Self {
+                // m(row, col) = e[col][row]
+                let m00 = m.e[0][0];
+                let m11 = m.e[1][1];
+                let m22 = m.e[2][2];
+                let m01 = m.e[1][0];
+                let m10 = m.e[0][1];
+                let m02 = m.e[2][0];
+                let m20 = m.e[0][2];
+                let m12 = m.e[2][1];
+                let m21 = m.e[1][2];
+
+                let trace = m00 + m11 + m22;
+
+                if trace >= m00 && trace >= m11 && trace >= m22 {
+                    let w = 0.5 * (1. + trace).sqrt();
+                    let s = 0.25 / w;
+                    Self { x: (m21 - m12) * s, y: (m02 - m20) * s, z: (m10 - m01) * s, w }
+                } else if m00 >= m11 && m00 >= m22 {
+                    let x = 0.5 * (1. + m00 - m11 - m22).sqrt();
+                    let s = 0.25 / x;
+                    Self { x, y: (m01 + m10) * s, z: (m02 + m20) * s, w: (m21 - m12) * s }
+                } else if m11 >= m22 {
+                    let y = 0.5 * (1. - m00 + m11 - m22).sqrt();
+                    let s = 0.25 / y;
+                    Self { x: (m01 + m10) * s, y, z: (m12 + m21) * s, w: (m02 - m20) * s }
+                } else {
+                    let z = 0.5 * (1. - m00 - m11 + m22).sqrt();
+                    let s = 0.25 / z;
+                    Self { x: (m02 + m20) * s, y: (m12 + m21) * s, z, w: (m10 - m01) * s }
+                }
+            }
+
+            #[inline]
+            pub fn from_mat3(m: $m) -> Self {
+                Self::from_rotation_matrix(m)
+            }
+
+            /// Uses the upper 3x3 block, which must be a pure rotation.
+            #[inline]
+            pub fn from_mat4(m: $m4) -> Self {
+                Self::from_rotation_matrix(m.to_mat3())
+            }
+
+            /// Normalized linear interpolation along the shortest path,
+            /// returns self for t = 0 and b for t = 1.
+            pub fn nlerp(self, b: Self, t: $t) -> Self {
+                let b = if self.dot(b) < 0. { -1.0 * b } else { b };
+                Self {
+                    x: self.x + (b.x - self.x) * t,
+                    y: self.y + (b.y - self.y) * t,
+                    z: self.z + (b.z - self.z) * t,
+                    w: self.w + (b.w - self.w) * t,
+                }.normalized()
+            }
+
+            /// Spherical linear interpolation along the shortest path,
+            /// returns self for t = 0 and b for t = 1.
+            pub fn slerp(self, b: Self, t: $t) -> Self {
+                let mut cos_theta = self.dot(b);
+                let b = if cos_theta < 0. {
+                    cos_theta = -cos_theta;
+                    -1.0 * b
+                } else {
+                    b
+                };
+
+                // Fall back to nlerp when sin(theta) gets too small
+                if cos_theta > 0.9995 {
+                    return self.nlerp(b, t);
+                }
+
+                let theta = cos_theta.acos();
+                let sin_theta = theta.sin();
+                let wa = ((1. - t) * theta).sin() / sin_theta;
+                let wb = (t * theta).sin() / sin_theta;
+
+                Self {
+                    x: self.x * wa + b.x * wb,
+                    y: self.y * wa + b.y * wb,
+                    z: self.z * wa + b.z * wb,
+                    w: self.w * wa + b.w * wb,
+                }
+            }
+
             #[inline]
             pub fn to_mat3(self) -> $m {
                 let x = self.x;
@@ -203,7 +359,7 @@ macro_rules! quat_impl {
                     x: v.x,
                     y: v.y,
                     z: v.z,
-                    w: w
+                    w,
                 }
             }
         }
@@ -216,7 +372,7 @@ macro_rules! quat_impl {
                 let s = self.re();
                 let v = self.im();
 
-                2. * v.dot(rhs) * v + (s * s - 1.) * rhs - 2. * s * v.cross(rhs)
+                2. * v.dot(rhs) * v + (s * s - v.dot(v)) * rhs + 2. * s * v.cross(rhs)
             }
         }
 
@@ -229,8 +385,20 @@ macro_rules! quat_impl {
             }
         }
 
+        /// [x, y, z, w] with w the real part.
+        impl From<[$t; 4]> for $name {
+            #[inline]
+            fn from(a: [$t; 4]) -> $name {
+                $name::new(a[0], a[1], a[2], a[3])
+            }
+        }
 
-
+        impl From<$name> for [$t; 4] {
+            #[inline]
+            fn from(q: $name) -> [$t; 4] {
+                [q.x, q.y, q.z, q.w]
+            }
+        }
     }
 }
 
@@ -239,3 +407,152 @@ quat_impl!(Quatd, f64, Vec3d, Vec4d, Mat3d, Mat4d);
 
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Rng;
+    use core::f32::consts::PI;
+
+    fn random_quat(rng: &mut Rng) -> Quat {
+        let axis = Vec3::new(rng.range(-1., 1.), rng.range(-1., 1.),
+                             rng.range(-1., 1.)).normalized();
+        Quat::rotate(axis, rng.range(-PI, PI))
+    }
+
+    fn assert_same_rotation(a: Quat, b: Quat, eps: f32) {
+        // q and -q represent the same rotation
+        assert!(1.0 - a.dot(b).abs() < eps, "{} {}", a, b);
+    }
+
+    fn assert_vec_eq(a: Vec3, b: Vec3, eps: f32) {
+        assert!((a - b).length() < eps, "{} {}", a, b);
+    }
+
+    #[test]
+    fn rotate_vector_matches_matrix() {
+        let mut rng = Rng(42);
+        for _ in 0..1000 {
+            let q = random_quat(&mut rng);
+            let v = Vec3::new(rng.range(-10., 10.), rng.range(-10., 10.),
+                              rng.range(-10., 10.));
+            assert_vec_eq(q * v, q.to_mat3() * v, 1e-4);
+
+            let m = q.to_mat4() * Vec4::new(v.x, v.y, v.z, 1.);
+            assert_vec_eq(q * v, Vec3::new(m.x, m.y, m.z), 1e-4);
+        }
+
+        let q = Quat::rotate(Vec3::new(0., 0., 1.), PI * 0.5);
+        assert_vec_eq(q * Vec3::new(1., 0., 0.), Vec3::new(0., 1., 0.), 1e-6);
+    }
+
+    /// Rotations used to go clockwise and scale the vector, q * v must be a
+    /// counterclockwise rotation about the axis that keeps lengths.
+    #[test]
+    fn rotate_vector_direction_and_length() {
+        for angle in [0.3, PI * 0.5, 2.0, PI, -1.0] {
+            let q = Quat::rotate(Vec3::new(0., 0., 1.), angle);
+            assert_vec_eq(q * Vec3::new(2., 0., 0.), Vec3::new(2. * angle.cos(), 2. * angle.sin(), 0.), 1e-5);
+            assert_vec_eq(q * Vec3::new(0., 0., 3.), Vec3::new(0., 0., 3.), 1e-5);
+        }
+        let q = Quat::rotate(Vec3::new(1., 0., 0.), PI * 0.5);
+        assert_vec_eq(q * Vec3::new(0., 1., 0.), Vec3::new(0., 0., 1.), 1e-6);
+    }
+
+    #[test]
+    fn matrix_round_trip() {
+        let mut rng = Rng(7);
+        for _ in 0..1000 {
+            let q = random_quat(&mut rng);
+            assert_same_rotation(Quat::from_mat3(q.to_mat3()), q, 1e-5);
+            assert_same_rotation(Quat::from_mat4(q.to_mat4()), q, 1e-5);
+        }
+
+        // Exercise every branch of Shepperd's method
+        for axis in [Vec3::new(1., 0., 0.), Vec3::new(0., 1., 0.), Vec3::new(0., 0., 1.)] {
+            for angle in [0., 0.5, PI * 0.5, PI * 0.99, PI] {
+                let q = Quat::rotate(axis, angle);
+                assert_same_rotation(Quat::from_rotation_matrix(q.to_mat3()), q, 1e-6);
+            }
+        }
+
+        let q = Quatd::rotate(Vec3d::new(0.6, 0.0, 0.8), 2.5);
+        let r = Quatd::from_mat4(q.to_mat4());
+        assert!(1.0 - q.dot(r).abs() < 1e-12);
+    }
+
+    #[test]
+    fn axis_angle_round_trip() {
+        let mut rng = Rng(99);
+        for _ in 0..1000 {
+            let axis = Vec3::new(rng.range(-1., 1.), rng.range(-1., 1.),
+                                 rng.range(-1., 1.)).normalized();
+            let angle = rng.range(0.01, PI - 0.01);
+            let (a, t) = Quat::rotate(axis, angle).to_axis_angle();
+            assert_vec_eq(a, axis, 1e-3);
+            assert!((t - angle).abs() < 1e-4);
+
+            // Negated quaternion is the same rotation
+            let (a, t) = (-1.0 * Quat::rotate(axis, angle)).to_axis_angle();
+            assert_vec_eq(a, axis, 1e-3);
+            assert!((t - angle).abs() < 1e-4);
+        }
+
+        assert_eq!(Quat::identity().to_axis_angle().1, 0.);
+    }
+
+    #[test]
+    fn euler() {
+        let x = Vec3::new(1., 0., 0.);
+        let y = Vec3::new(0., 1., 0.);
+        let z = Vec3::new(0., 0., 1.);
+        let (a, b, c) = (0.3, -1.2, 2.0);
+
+        let m = |axis, angle| Mat3::rotation(axis, angle);
+        let cases = [
+            (EulerOrder::XYZ, m(z, c) * m(y, b) * m(x, a)),
+            (EulerOrder::XZY, m(y, c) * m(z, b) * m(x, a)),
+            (EulerOrder::YXZ, m(z, c) * m(x, b) * m(y, a)),
+            (EulerOrder::YZX, m(x, c) * m(z, b) * m(y, a)),
+            (EulerOrder::ZXY, m(y, c) * m(x, b) * m(z, a)),
+            (EulerOrder::ZYX, m(x, c) * m(y, b) * m(z, a)),
+        ];
+
+        for (order, expected) in cases {
+            let q = Quat::from_euler(order, a, b, c);
+            let v = Vec3::new(0.3, 0.5, -0.7);
+            assert_vec_eq(q * v, expected * v, 1e-5);
+            assert_same_rotation(Quat::from_mat3(expected), q, 1e-5);
+        }
+    }
+
+    #[test]
+    fn interpolation() {
+        let z = Vec3::new(0., 0., 1.);
+        let a = Quat::rotate(z, 0.2);
+        let b = Quat::rotate(z, 1.4);
+
+        assert_same_rotation(a.slerp(b, 0.), a, 1e-6);
+        assert_same_rotation(a.slerp(b, 1.), b, 1e-6);
+        assert_same_rotation(a.slerp(b, 0.25), Quat::rotate(z, 0.5), 1e-6);
+        assert_same_rotation(a.nlerp(b, 0.5), Quat::rotate(z, 0.8), 1e-6);
+
+        // Shortest path with a negated endpoint
+        assert_same_rotation(a.slerp(-1.0 * b, 0.5), Quat::rotate(z, 0.8), 1e-6);
+
+        // Nearly identical endpoints
+        let c = Quat::rotate(z, 0.2001);
+        assert!((a.slerp(c, 0.5).norm() - 1.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn inverse() {
+        let mut rng = Rng(5);
+        for _ in 0..100 {
+            let q = 2.0 * random_quat(&mut rng);
+            let i = q * q.inverse();
+            assert_same_rotation(i, Quat::identity(), 1e-5);
+            assert!((i.norm() - 1.).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,427 @@
+//! Port of shaders/random.hlsl, functions keep the same names in snake case
+//! and the same conventions so CPU tools and tests match the GPU code. The
+//! integer generator is bit-for-bit identical, float math follows IEEE 754.
+
+pub mod sequences;
+
+use crate::vec::{Vec2, Vec3, Vec4};
+
+use core::f32::consts::PI;
+
+/// PCG4D hash from "Hash Functions for GPU Rendering" (Jarzynski, Olano),
+/// code by Moroz Mykhailo (https://www.shadertoy.com/view/wltcRS)
+#[inline]
+pub fn pcg4d(v: &mut [u32; 4]) {
+    for x in v.iter_mut() {
+        *x = x.wrapping_mul(1664525).wrapping_add(1013904223);
+    }
+
+    v[0] = v[0].wrapping_add(v[1].wrapping_mul(v[3]));
+    v[1] = v[1].wrapping_add(v[2].wrapping_mul(v[0]));
+    v[2] = v[2].wrapping_add(v[0].wrapping_mul(v[1]));
+    v[3] = v[3].wrapping_add(v[1].wrapping_mul(v[2]));
+
+    for x in v.iter_mut() {
+        *x ^= *x >> 16;
+    }
+
+    v[0] = v[0].wrapping_add(v[1].wrapping_mul(v[3]));
+    v[1] = v[1].wrapping_add(v[2].wrapping_mul(v[0]));
+    v[2] = v[2].wrapping_add(v[0].wrapping_mul(v[1]));
+    v[3] = v[3].wrapping_add(v[1].wrapping_mul(v[2]));
+}
+
+#[inline]
+fn to_float(x: u32) -> f32 {
+    x as f32 / 0xffffffffu32 as f32
+}
+
+/// Random number generator state, same as the uvec4 seed in the shaders.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Pcg4d {
+    pub state: [u32; 4],
+}
+
+impl Pcg4d {
+    #[inline]
+    pub fn new(seed: [u32; 4]) -> Pcg4d {
+        Pcg4d { state: seed }
+    }
+
+    #[inline]
+    pub fn next_uvec4(&mut self) -> [u32; 4] {
+        pcg4d(&mut self.state);
+        self.state
+    }
+
+    /// Uniform in [0, 1], like all the float generators.
+    #[inline]
+    pub fn rand(&mut self) -> f32 {
+        let s = self.next_uvec4();
+        to_float(s[0])
+    }
+
+    #[inline]
+    pub fn rand2(&mut self) -> Vec2 {
+        let s = self.next_uvec4();
+        Vec2::new(to_float(s[0]), to_float(s[1]))
+    }
+
+    #[inline]
+    pub fn rand3(&mut self) -> Vec3 {
+        let s = self.next_uvec4();
+        Vec3::new(to_float(s[0]), to_float(s[1]), to_float(s[2]))
+    }
+
+    #[inline]
+    pub fn rand4(&mut self) -> Vec4 {
+        let s = self.next_uvec4();
+        Vec4::new(to_float(s[0]), to_float(s[1]), to_float(s[2]),
+                  to_float(s[3]))
+    }
+}
+
+pub fn sample_uniform_sphere(u: Vec2) -> Vec3 {
+    let z = 2.0 * u.x - 1.0;
+    let r = (1.0 - z * z).sqrt();
+    let phi = (2.0 * PI) * u.y;
+    let x = r * phi.cos();
+    let y = r * phi.sin();
+    Vec3::new(x, y, z)
+}
+
+pub fn pdf_uniform_sphere() -> f32 {
+    1.0 / (4.0 * PI)
+}
+
+pub fn sample_uniform_disk(u: Vec2) -> Vec2 {
+    let r = u.x.sqrt();
+    let theta = (2.0 * PI) * u.y;
+    Vec2::new(r * theta.cos(), r * theta.sin())
+}
+
+/// Not in the shaders, pdf with respect to area of sample_uniform_disk.
+pub fn pdf_uniform_disk() -> f32 {
+    1.0 / PI
+}
+
+pub fn sample_uniform_hemisphere(u: Vec2) -> Vec3 {
+    let mut d = sample_uniform_sphere(u);
+    if d.z < 0.0 {
+        d.z = -d.z;
+    }
+    d
+}
+
+pub fn pdf_uniform_hemisphere() -> f32 {
+    1.0 / (2.0 * PI)
+}
+
+pub fn sample_cosine_weighted_hemisphere(u: Vec2) -> Vec3 {
+    let p = sample_uniform_disk(u);
+    let z = (1.0 - p.x * p.x - p.y * p.y).sqrt();
+    Vec3::new(p.x, p.y, z)
+}
+
+pub fn pdf_cosine_weighted_hemisphere(v: Vec3) -> f32 {
+    v.z * (1.0 / PI)
+}
+
+/// Uniform on the hemisphere around n, pdf_uniform_hemisphere.
+pub fn sample_uniform_hemisphere_n(u: Vec2, n: Vec3) -> Vec3 {
+    let d = sample_uniform_sphere(u);
+    if d.dot(n) < 0.0 {
+        -d
+    } else {
+        d
+    }
+}
+
+/// GGX / Trowbridge-Reitz normal distribution.
+pub fn eval_gtr2(cos_theta: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let d = 1.0 + (alpha2 - 1.0) * cos_theta * cos_theta;
+    alpha2 / (PI * d * d)
+}
+
+/// Samples a microfacet normal proportionally to D(m) * cos(theta_m).
+pub fn sample_gtr2(u: Vec2, alpha: f32) -> Vec3 {
+    let alpha2 = alpha * alpha;
+    let cos_theta2 = (1.0 - u.x) / (1.0 + (alpha2 - 1.0) * u.x);
+
+    let cos_theta = cos_theta2.sqrt();
+    let sin_theta = (1.0 - cos_theta2).sqrt();
+
+    let phi = 2.0 * PI * u.y;
+
+    let x = sin_theta * phi.cos();
+    let y = sin_theta * phi.sin();
+    let z = cos_theta;
+    Vec3::new(x, y, z)
+}
+
+pub fn pdf_gtr2(m: Vec3, alpha: f32) -> f32 {
+    eval_gtr2(m.z, alpha) * m.z
+}
+
+/// Returns the barycentrics of the first two vertices, uniform over the
+/// triangle area.
+pub fn sample_triangle(u: Vec2) -> Vec2 {
+    let su1 = u.x.sqrt();
+    Vec2::new(1.0 - su1, u.y * su1)
+}
+
+/// Not in the shaders, pdf of sample_triangle with respect to the
+/// barycentric domain, divide by twice the triangle area for the pdf with
+/// respect to area.
+pub fn pdf_triangle() -> f32 {
+    2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: usize = 200_000;
+
+    /// Wilson-Hilferty approximation of the chi-square quantile at 99.9%.
+    fn chi2_critical(dof: usize) -> f64 {
+        let k = dof as f64;
+        let z = 3.090232;
+        k * (1.0 - 2.0 / (9.0 * k) + z * (2.0 / (9.0 * k)).sqrt()).powi(3)
+    }
+
+    /// Pearson's chi-square test, bins with a low expected count are merged
+    /// together as recommended for the test to be valid.
+    fn chi2_test(observed: &[f64], expected: &[f64]) {
+        let mut chi2 = 0.0;
+        let mut dof = 0;
+        let (mut pooled_o, mut pooled_e) = (0.0, 0.0);
+
+        for (o, e) in observed.iter().zip(expected.iter()) {
+            if *e < 5.0 {
+                pooled_o += o;
+                pooled_e += e;
+            } else {
+                chi2 += (o - e) * (o - e) / e;
+                dof += 1;
+            }
+        }
+
+        if pooled_e > 0.0 {
+            chi2 += (pooled_o - pooled_e) * (pooled_o - pooled_e) / pooled_e;
+            dof += 1;
+        } else {
+            assert_eq!(pooled_o, 0.0, "samples outside of the pdf support");
+        }
+
+        let critical = chi2_critical(dof - 1);
+        assert!(chi2 < critical, "chi2 {} > critical {} ({} dof)",
+                chi2, critical, dof - 1);
+    }
+
+    const THETA_BINS: usize = 16;
+    const PHI_BINS: usize = 32;
+
+    /// Integral of the pdf over every (theta, phi) bin of the sphere.
+    fn integrate_sphere_bins(pdf: &dyn Fn(Vec3) -> f32) -> Vec<f64> {
+        // Fine theta subdivisions for peaked distributions like GTR2 with
+        // low roughness.
+        const THETA_SUB: usize = 64;
+        const PHI_SUB: usize = 4;
+        let dtheta = core::f64::consts::PI / (THETA_BINS * THETA_SUB) as f64;
+        let dphi = 2.0 * core::f64::consts::PI / (PHI_BINS * PHI_SUB) as f64;
+
+        let mut bins = vec![0.0; THETA_BINS * PHI_BINS];
+        for t in 0..THETA_BINS * THETA_SUB {
+            for p in 0..PHI_BINS * PHI_SUB {
+                let theta = (t as f64 + 0.5) * dtheta;
+                let phi = (p as f64 + 0.5) * dphi;
+                let d = Vec3::spherical_to_direction(theta as f32, phi as f32);
+                let v = pdf(d) as f64 * theta.sin() * dtheta * dphi;
+                bins[(t / THETA_SUB) * PHI_BINS + p / PHI_SUB] += v;
+            }
+        }
+        bins
+    }
+
+    fn sphere_bin(d: Vec3) -> usize {
+        let (theta, phi) = Vec3::direction_to_spherical(d);
+        let t = ((theta / PI * THETA_BINS as f32) as usize).min(THETA_BINS - 1);
+        let p = ((phi / (2.0 * PI) * PHI_BINS as f32) as usize).min(PHI_BINS - 1);
+        t * PHI_BINS + p
+    }
+
+    fn test_sphere_warp(sample: &dyn Fn(Vec2) -> Vec3,
+                        pdf: &dyn Fn(Vec3) -> f32, seed: u32) {
+        let expected = integrate_sphere_bins(pdf);
+
+        // The pdf integrates to one
+        let total: f64 = expected.iter().sum();
+        assert!((total - 1.0).abs() < 2e-3, "pdf integrates to {}", total);
+
+        let mut rng = Pcg4d::new([seed, 1, 2, 3]);
+        let mut observed = vec![0.0; expected.len()];
+        for _ in 0..SAMPLES {
+            let d = sample(rng.rand2());
+            assert!((d.length() - 1.0).abs() < 1e-4);
+            observed[sphere_bin(d.normalized())] += 1.0;
+        }
+
+        let expected: Vec<f64> = expected.iter().map(|e| e * SAMPLES as f64).collect();
+        chi2_test(&observed, &expected);
+    }
+
+    fn upper(d: Vec3) -> bool {
+        d.z >= 0.0
+    }
+
+    #[test]
+    fn pcg4d_reference() {
+        // Outputs computed with the reference C code of pcg4d from "Hash
+        // Functions for GPU Rendering" (Jarzynski and Olano 2020), the paper
+        // doesn't list any
+        let mut v = [0u32; 4];
+        pcg4d(&mut v);
+        assert_eq!(v, [251852841, 760645481, 850445371, 3542436074]);
+        let mut v = [1, 2, 3, 4];
+        pcg4d(&mut v);
+        assert_eq!(v, [908250390, 4044648920, 3775961919, 45698095]);
+
+        let mut rng = Pcg4d::new([0; 4]);
+        assert_eq!(rng.next_uvec4(), [251852841, 760645481, 850445371, 3542436074]);
+        assert_eq!(rng.next_uvec4(), [629284576, 580868700, 841404694, 3713504041]);
+
+        let mut rng = Pcg4d::new([7, 11, 13, 17]);
+        for _ in 0..1000 {
+            let u = rng.rand4();
+            for x in u.to_slice() {
+                assert!((0.0..=1.0).contains(&x));
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_random_numbers() {
+        let mut rng = Pcg4d::new([3, 5, 7, 11]);
+        let mut observed = vec![0.0; 64];
+        for _ in 0..SAMPLES / 4 {
+            for x in rng.rand4().to_slice() {
+                observed[((x * 64.0) as usize).min(63)] += 1.0;
+            }
+        }
+        let expected = vec![SAMPLES as f64 / 64.0; 64];
+        chi2_test(&observed, &expected);
+    }
+
+    #[test]
+    fn uniform_sphere() {
+        test_sphere_warp(&sample_uniform_sphere, &|_| pdf_uniform_sphere(), 1);
+    }
+
+    #[test]
+    fn uniform_hemisphere() {
+        test_sphere_warp(&sample_uniform_hemisphere,
+                         &|d| if upper(d) { pdf_uniform_hemisphere() } else { 0.0 }, 2);
+
+        let n = Vec3::new(0.0, 0.0, -1.0);
+        test_sphere_warp(&|u| sample_uniform_hemisphere_n(u, n),
+                         &|d| if !upper(d) { pdf_uniform_hemisphere() } else { 0.0 }, 3);
+    }
+
+    #[test]
+    fn cosine_weighted_hemisphere() {
+        test_sphere_warp(&sample_cosine_weighted_hemisphere,
+                         &|d| if upper(d) { pdf_cosine_weighted_hemisphere(d) } else { 0.0 }, 4);
+    }
+
+    #[test]
+    fn gtr2() {
+        for (i, alpha) in [0.1, 0.3, 0.7, 1.0].iter().enumerate() {
+            test_sphere_warp(&|u| sample_gtr2(u, *alpha),
+                             &|d| if upper(d) { pdf_gtr2(d, *alpha) } else { 0.0 },
+                             5 + i as u32);
+        }
+    }
+
+    #[test]
+    fn uniform_disk() {
+        const R_BINS: usize = 16;
+        const PHI_BINS: usize = 32;
+
+        // Bins equally spaced in r and phi, the area of a ring sector is
+        // (r1^2 - r0^2) / 2 * dphi.
+        let mut expected = vec![0.0; R_BINS * PHI_BINS];
+        let dphi = 2.0 * core::f64::consts::PI / PHI_BINS as f64;
+        for r in 0..R_BINS {
+            let r0 = r as f64 / R_BINS as f64;
+            let r1 = (r + 1) as f64 / R_BINS as f64;
+            let area = (r1 * r1 - r0 * r0) * 0.5 * dphi;
+            for p in 0..PHI_BINS {
+                expected[r * PHI_BINS + p] = pdf_uniform_disk() as f64 * area;
+            }
+        }
+
+        let total: f64 = expected.iter().sum();
+        assert!((total - 1.0).abs() < 1e-6);
+
+        let mut rng = Pcg4d::new([13, 0, 0, 0]);
+        let mut observed = vec![0.0; expected.len()];
+        for _ in 0..SAMPLES {
+            let p = sample_uniform_disk(rng.rand2());
+            let r = p.length();
+            assert!(r <= 1.0 + 1e-6);
+            let mut phi = p.y.atan2(p.x);
+            if phi < 0.0 {
+                phi += 2.0 * PI;
+            }
+            let rb = ((r * R_BINS as f32) as usize).min(R_BINS - 1);
+            let pb = ((phi / (2.0 * PI) * PHI_BINS as f32) as usize).min(PHI_BINS - 1);
+            observed[rb * PHI_BINS + pb] += 1.0;
+        }
+
+        let expected: Vec<f64> = expected.iter().map(|e| e * SAMPLES as f64).collect();
+        chi2_test(&observed, &expected);
+    }
+
+    #[test]
+    fn triangle() {
+        const BINS: usize = 24;
+        const SUB: usize = 16;
+
+        // Integral of the pdf over square bins of the barycentric domain.
+        // The diagonal goes through the corners of the sub cells, so cells
+        // on it are exactly half covered.
+        const N: usize = BINS * SUB;
+        let mut expected = vec![0.0; BINS * BINS];
+        let d = 1.0 / N as f64;
+        for i in 0..N {
+            for j in 0..N {
+                let coverage = if i + j + 2 <= N {
+                    1.0
+                } else if i + j + 1 == N {
+                    0.5
+                } else {
+                    0.0
+                };
+                expected[(i / SUB) * BINS + j / SUB] += coverage * pdf_triangle() as f64 * d * d;
+            }
+        }
+
+        let total: f64 = expected.iter().sum();
+        assert!((total - 1.0).abs() < 1e-9);
+
+        let mut rng = Pcg4d::new([17, 0, 0, 0]);
+        let mut observed = vec![0.0; expected.len()];
+        for _ in 0..SAMPLES {
+            let b = sample_triangle(rng.rand2());
+            assert!(b.x >= 0.0 && b.y >= 0.0 && b.x + b.y <= 1.0 + 1e-6);
+            let i = ((b.x * BINS as f32) as usize).min(BINS - 1);
+            let j = ((b.y * BINS as f32) as usize).min(BINS - 1);
+            observed[i * BINS + j] += 1.0;
+        }
+
+        let expected: Vec<f64> = expected.iter().map(|e| e * SAMPLES as f64).collect();
+        chi2_test(&observed, &expected);
+    }
+}
@@ -0,0 +1,815 @@
+//! Low discrepancy sequences for quasi Monte Carlo integration.
+//!
+//! All generators are stateless functions of (pixel, sample index, dimension)
+//! so the same values can be computed on the GPU from the tables exported
+//! here. Tables are plain u32 arrays, upload them with bytemuck::cast_slice
+//! or bytemuck::bytes_of.
+
+use super::Pcg4d;
+use crate::vec::Vec2;
+
+use bytemuck::{Pod, Zeroable};
+
+/// Largest f32 smaller than 1.
+pub const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Maps the 24 high bits of x to [0, 1), exact and never equal to 1.
+#[inline]
+pub fn to_unit_float(x: u32) -> f32 {
+    (x >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+}
+
+/// Integer hash from "Hash Functions for GPU Rendering" (Jarzynski, Olano).
+#[inline]
+pub fn pcg_hash(x: u32) -> u32 {
+    let state = x.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+#[inline]
+fn hash_combine(seed: u32, x: u32) -> u32 {
+    pcg_hash(seed ^ x.wrapping_add(0x9e3779b9).wrapping_add(seed << 6)
+             .wrapping_add(seed >> 2))
+}
+
+#[inline]
+fn pixel_seed(pixel: [u32; 2], seed: u32) -> u32 {
+    hash_combine(hash_combine(seed, pixel[0]), pixel[1])
+}
+
+//
+// Sobol
+//
+
+pub const SOBOL_DIMENSIONS: usize = 32;
+
+/// Degree s, coefficients a and initial direction numbers m of the primitive
+/// polynomials for dimensions 1 to 31, from new-joe-kuo-6.21201 ("Constructing
+/// Sobol sequences with better two-dimensional projections", Joe, Kuo 2008).
+/// Dimension 0 is the van der Corput sequence.
+const SOBOL_PARAMETERS: [(u32, u32, [u32; 7]); SOBOL_DIMENSIONS - 1] = [
+    (1, 0, [1, 0, 0, 0, 0, 0, 0]),
+    (2, 1, [1, 3, 0, 0, 0, 0, 0]),
+    (3, 1, [1, 3, 1, 0, 0, 0, 0]),
+    (3, 2, [1, 1, 1, 0, 0, 0, 0]),
+    (4, 1, [1, 1, 3, 3, 0, 0, 0]),
+    (4, 4, [1, 3, 5, 13, 0, 0, 0]),
+    (5, 2, [1, 1, 5, 5, 17, 0, 0]),
+    (5, 4, [1, 1, 5, 5, 5, 0, 0]),
+    (5, 7, [1, 1, 7, 11, 19, 0, 0]),
+    (5, 11, [1, 1, 5, 1, 1, 0, 0]),
+    (5, 13, [1, 1, 1, 3, 11, 0, 0]),
+    (5, 14, [1, 3, 5, 5, 31, 0, 0]),
+    (6, 1, [1, 3, 3, 9, 7, 49, 0]),
+    (6, 13, [1, 1, 1, 15, 21, 21, 0]),
+    (6, 16, [1, 3, 1, 13, 27, 49, 0]),
+    (6, 19, [1, 1, 1, 15, 7, 5, 0]),
+    (6, 22, [1, 3, 1, 15, 13, 25, 0]),
+    (6, 25, [1, 1, 5, 5, 19, 61, 0]),
+    (7, 1, [1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, [1, 3, 7, 13, 13, 15, 69]),
+    (7, 7, [1, 1, 3, 13, 7, 35, 63]),
+    (7, 8, [1, 3, 5, 9, 1, 25, 53]),
+    (7, 14, [1, 3, 1, 13, 9, 35, 107]),
+    (7, 19, [1, 3, 1, 5, 27, 61, 31]),
+    (7, 21, [1, 1, 5, 11, 19, 41, 61]),
+    (7, 28, [1, 3, 5, 3, 3, 13, 69]),
+    (7, 31, [1, 1, 7, 13, 1, 19, 1]),
+    (7, 32, [1, 3, 7, 5, 13, 19, 59]),
+    (7, 37, [1, 1, 3, 9, 25, 29, 41]),
+    (7, 41, [1, 3, 5, 13, 23, 1, 55]),
+    (7, 42, [1, 3, 7, 3, 13, 59, 17]),
+];
+
+const fn sobol_directions() -> [[u32; 32]; SOBOL_DIMENSIONS] {
+    let mut v = [[0u32; 32]; SOBOL_DIMENSIONS];
+
+    let mut k = 0;
+    while k < 32 {
+        v[0][k] = 1 << (31 - k);
+        k += 1;
+    }
+
+    let mut d = 1;
+    while d < SOBOL_DIMENSIONS {
+        let (s, a, m) = SOBOL_PARAMETERS[d - 1];
+        let s = s as usize;
+
+        let mut k = 0;
+        while k < 32 {
+            if k < s {
+                v[d][k] = m[k] << (31 - k);
+            } else {
+                let mut x = v[d][k - s] ^ (v[d][k - s] >> s);
+                let mut j = 1;
+                while j < s {
+                    if (a >> (s - 1 - j)) & 1 != 0 {
+                        x ^= v[d][k - j];
+                    }
+                    j += 1;
+                }
+                v[d][k] = x;
+            }
+            k += 1;
+        }
+        d += 1;
+    }
+
+    v
+}
+
+/// Direction numbers of every dimension, bit k of the index contributes
+/// directions[dimension][k].
+#[derive(Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+pub struct SobolTable {
+    pub directions: [[u32; 32]; SOBOL_DIMENSIONS],
+}
+
+pub static SOBOL_TABLE: SobolTable = SobolTable {
+    directions: sobol_directions(),
+};
+
+/// Sobol sample as a 0.32 fixed point number, dimension must be smaller than
+/// SOBOL_DIMENSIONS.
+#[inline]
+pub fn sobol(mut index: u32, dimension: usize) -> u32 {
+    let v = &SOBOL_TABLE.directions[dimension];
+    let mut x = 0;
+    let mut k = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            x ^= v[k];
+        }
+        index >>= 1;
+        k += 1;
+    }
+    x
+}
+
+/// Hash based permutation where every bit only depends on the bits below it,
+/// from "Stratified sampling for stochastic transparency" (Laine, Karras).
+#[inline]
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+/// Owen scrambling of a 0.32 fixed point number, from "Practical Hash-based
+/// Owen Scrambling" (Burley 2020).
+#[inline]
+pub fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// Owen scrambled Sobol sample, the index is shuffled with the same seed for
+/// all dimensions so the first 2^m samples are still a (t, m, s)-net.
+#[inline]
+pub fn sobol_owen(index: u32, dimension: usize, seed: u32) -> u32 {
+    let index = nested_uniform_scramble(index, pcg_hash(seed));
+    let x = sobol(index, dimension);
+    nested_uniform_scramble(x, hash_combine(seed, dimension as u32))
+}
+
+//
+// Halton
+//
+
+pub const HALTON_DIMENSIONS: usize = 32;
+
+pub const PRIMES: [u32; HALTON_DIMENSIONS] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Radical inverse of index in the given base, van der Corput for base 2.
+pub fn radical_inverse(base: u32, index: u32) -> f32 {
+    radical_inverse_permuted(base, index, None)
+}
+
+fn radical_inverse_permuted(base: u32, mut index: u32, perm: Option<&[u32]>) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0;
+    let mut reversed = 0u64;
+    while index != 0 {
+        let next = index / base;
+        let digit = index - next * base;
+        let digit = perm.map_or(digit, |p| p[digit as usize]);
+        reversed = reversed * base as u64 + digit as u64;
+        inv_base_n *= inv_base;
+        index = next;
+    }
+
+    ((reversed as f64 * inv_base_n) as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// Random digit permutations for the scrambled Halton sequence, one per
+/// dimension. Zero is always mapped to zero so that the trailing zeros of
+/// the index stay zero and the radical inverse remains a finite sum.
+#[derive(Debug, Clone)]
+pub struct HaltonPermutations {
+    /// Start of the permutation of each dimension in permutations, the
+    /// permutation of dimension i has PRIMES[i] entries.
+    pub offsets: [u32; HALTON_DIMENSIONS],
+    pub permutations: Vec<u32>,
+}
+
+impl HaltonPermutations {
+    pub fn new(seed: u32) -> HaltonPermutations {
+        let mut offsets = [0; HALTON_DIMENSIONS];
+        let mut permutations = Vec::with_capacity(PRIMES.iter().sum::<u32>() as usize);
+
+        for (i, &base) in PRIMES.iter().enumerate() {
+            offsets[i] = permutations.len() as u32;
+            let start = permutations.len();
+            permutations.extend(0..base);
+
+            // Fisher-Yates shuffle of the non zero digits
+            let mut rng = Pcg4d::new([seed, i as u32, 0, 0]);
+            let p = &mut permutations[start + 1..];
+            for j in (1..p.len()).rev() {
+                let r = rng.next_uvec4()[0];
+                let k = ((r as u64 * (j as u64 + 1)) >> 32) as usize;
+                p.swap(j, k);
+            }
+        }
+
+        HaltonPermutations { offsets, permutations }
+    }
+
+    #[inline]
+    pub fn permutation(&self, dimension: usize) -> &[u32] {
+        let start = self.offsets[dimension] as usize;
+        &self.permutations[start..start + PRIMES[dimension] as usize]
+    }
+
+    /// Scrambled Halton sample, dimension must be smaller than
+    /// HALTON_DIMENSIONS.
+    #[inline]
+    pub fn sample(&self, index: u32, dimension: usize) -> f32 {
+        radical_inverse_permuted(PRIMES[dimension], index,
+                                 Some(self.permutation(dimension)))
+    }
+}
+
+//
+// Kronecker
+//
+
+/// Additive recurrence x_n = frac(1/2 + n * alpha) with the generalized golden
+/// ratio alphas of the R_d sequence ("The Unreasonable Effectiveness of
+/// Quasirandom Sequences", Roberts 2018). Alphas are 0.32 fixed point numbers
+/// so the recurrence is exact and matches integer math on the GPU.
+#[derive(Debug, Clone)]
+pub struct Kronecker {
+    pub alphas: Vec<u32>,
+}
+
+impl Kronecker {
+    pub fn new(dimensions: usize) -> Kronecker {
+        assert!(dimensions > 0, "Kronecker sequence must have at least 1 dimension");
+
+        // phi_d is the positive root of x^(d + 1) = x + 1
+        let exponent = 1.0 / (dimensions as f64 + 1.0);
+        let mut phi = 2.0f64;
+        for _ in 0..64 {
+            phi = (1.0 + phi).powf(exponent);
+        }
+
+        let alphas = (1..=dimensions as i32).map(|j| {
+            let a = phi.powi(-j).fract();
+            (a * (1u64 << 32) as f64) as u32
+        }).collect();
+
+        Kronecker { alphas }
+    }
+
+    /// The R2 sequence.
+    pub fn r2() -> Kronecker {
+        Kronecker::new(2)
+    }
+
+    #[inline]
+    pub fn dimensions(&self) -> usize {
+        self.alphas.len()
+    }
+
+    /// Sample as a 0.32 fixed point number.
+    #[inline]
+    pub fn sample(&self, index: u32, dimension: usize) -> u32 {
+        index.wrapping_mul(self.alphas[dimension]).wrapping_add(0x8000_0000)
+    }
+}
+
+//
+// Blue noise
+//
+
+/// Blue noise dither matrix, every pixel of a size x size tile has a distinct
+/// rank and the pixels with rank smaller than any threshold are well spread,
+/// generated with void and cluster ("The void-and-cluster method for dither
+/// array generation", Ulichney 1993).
+#[derive(Debug, Clone)]
+pub struct BlueNoiseRanking {
+    pub size: u32,
+    /// Row major ranks in [0, size * size).
+    pub ranks: Vec<u32>,
+}
+
+impl BlueNoiseRanking {
+    pub fn new(size: u32, seed: u32) -> BlueNoiseRanking {
+        let s = size as usize;
+        let n = s * s;
+        assert!(n > 1, "Blue noise tile must have at least 2 pixels");
+
+        // Gaussian energy kernel indexed by the toroidal offset between pixels
+        let sigma = 1.5f32;
+        let mut kernel = vec![0.0f32; n];
+        for dy in 0..s {
+            for dx in 0..s {
+                let x = dx.min(s - dx) as f32;
+                let y = dy.min(s - dy) as f32;
+                kernel[dy * s + dx] = (-(x * x + y * y) / (2.0 * sigma * sigma)).exp();
+            }
+        }
+
+        let splat = |energy: &mut [f32], p: usize, sign: f32| {
+            let (px, py) = (p % s, p / s);
+            for qy in 0..s {
+                let dy = (qy + s - py) % s;
+                for qx in 0..s {
+                    let dx = (qx + s - px) % s;
+                    energy[qy * s + qx] += sign * kernel[dy * s + dx];
+                }
+            }
+        };
+
+        // Pixel with the highest energy among the set ones (tightest cluster)
+        // or the lowest among the unset ones (largest void).
+        let find = |energy: &[f32], on: &[bool], value: bool| -> usize {
+            let mut best = usize::MAX;
+            for i in 0..n {
+                if on[i] != value {
+                    continue;
+                }
+                if best == usize::MAX ||
+                    (value && energy[i] > energy[best]) ||
+                    (!value && energy[i] < energy[best]) {
+                    best = i;
+                }
+            }
+            best
+        };
+
+        // Random initial pattern with about 10% of the pixels set
+        let mut energy = vec![0.0f32; n];
+        let mut on = vec![false; n];
+        let mut rng = Pcg4d::new([seed, size, 0, 0]);
+        let mut ones = 0;
+        while ones < (n / 10).max(1) {
+            let p = ((rng.next_uvec4()[0] as u64 * n as u64) >> 32) as usize;
+            if !on[p] {
+                on[p] = true;
+                splat(&mut energy, p, 1.0);
+                ones += 1;
+            }
+        }
+
+        // Move points from clusters to voids until the pattern is stable
+        for _ in 0..n {
+            let cluster = find(&energy, &on, true);
+            on[cluster] = false;
+            splat(&mut energy, cluster, -1.0);
+
+            let void = find(&energy, &on, false);
+            on[void] = true;
+            splat(&mut energy, void, 1.0);
+
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut ranks = vec![0u32; n];
+
+        // Ranks below the initial pattern, removing the tightest clusters
+        {
+            let mut energy = energy.clone();
+            let mut on = on.clone();
+            for rank in (0..ones).rev() {
+                let cluster = find(&energy, &on, true);
+                on[cluster] = false;
+                splat(&mut energy, cluster, -1.0);
+                ranks[cluster] = rank as u32;
+            }
+        }
+
+        // Ranks above, filling the largest voids
+        for rank in ones..n {
+            let void = find(&energy, &on, false);
+            on[void] = true;
+            splat(&mut energy, void, 1.0);
+            ranks[void] = rank as u32;
+        }
+
+        BlueNoiseRanking { size, ranks }
+    }
+
+    /// Rank of a pixel, the tile repeats in both directions.
+    #[inline]
+    pub fn rank(&self, x: u32, y: u32) -> u32 {
+        self.ranks[((y % self.size) * self.size + x % self.size) as usize]
+    }
+
+    /// Rank of a pixel as a 0.32 fixed point number at the center of its
+    /// stratum.
+    #[inline]
+    pub fn value(&self, x: u32, y: u32) -> u32 {
+        let n = self.size as u64 * self.size as u64;
+        (((2 * self.rank(x, y) as u64 + 1) << 31) / n) as u32
+    }
+}
+
+//
+// Samplers
+//
+
+/// Source of samples in [0, 1) for a given pixel, sample index and
+/// dimension. Samples are pure functions of their arguments so dimensions
+/// can be consumed in any order, like on the GPU.
+pub trait Sampler {
+    fn sample_1d(&self, pixel: [u32; 2], index: u32, dimension: u32) -> f32;
+
+    /// Uses dimensions dimension and dimension + 1.
+    fn sample_2d(&self, pixel: [u32; 2], index: u32, dimension: u32) -> Vec2 {
+        Vec2::new(self.sample_1d(pixel, index, dimension),
+                  self.sample_1d(pixel, index, dimension + 1))
+    }
+}
+
+/// Independent uniform samples from pcg4d, what the shaders currently use.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RandomSampler {
+    pub seed: u32,
+}
+
+impl Sampler for RandomSampler {
+    fn sample_1d(&self, pixel: [u32; 2], index: u32, dimension: u32) -> f32 {
+        let mut rng = Pcg4d::new([pixel[0], pixel[1], index,
+                                  hash_combine(self.seed, dimension)]);
+        to_unit_float(rng.next_uvec4()[0])
+    }
+}
+
+/// Owen scrambled Sobol with an independent scramble per pixel. Dimensions
+/// past SOBOL_DIMENSIONS reuse the table with a different scramble.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SobolSampler {
+    pub seed: u32,
+}
+
+impl Sampler for SobolSampler {
+    fn sample_1d(&self, pixel: [u32; 2], index: u32, dimension: u32) -> f32 {
+        let d = dimension as usize;
+        let seed = hash_combine(pixel_seed(pixel, self.seed),
+                                (d / SOBOL_DIMENSIONS) as u32);
+        to_unit_float(sobol_owen(index, d % SOBOL_DIMENSIONS, seed))
+    }
+}
+
+/// Cranley-Patterson rotation by a per pixel and per dimension random shift.
+#[inline]
+fn rotate(x: f32, pixel: [u32; 2], seed: u32, dimension: u32) -> f32 {
+    let shift = to_unit_float(hash_combine(pixel_seed(pixel, seed), dimension));
+    let x = x + shift;
+    (if x >= 1.0 { x - 1.0 } else { x }).min(ONE_MINUS_EPSILON)
+}
+
+/// Scrambled Halton randomized per pixel with a Cranley-Patterson rotation.
+/// Dimensions past HALTON_DIMENSIONS reuse the bases with a different
+/// rotation.
+#[derive(Debug, Clone)]
+pub struct HaltonSampler {
+    pub permutations: HaltonPermutations,
+    pub seed: u32,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u32) -> HaltonSampler {
+        HaltonSampler { permutations: HaltonPermutations::new(seed), seed }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn sample_1d(&self, pixel: [u32; 2], index: u32, dimension: u32) -> f32 {
+        let x = self.permutations.sample(index, dimension as usize % HALTON_DIMENSIONS);
+        rotate(x, pixel, self.seed, dimension)
+    }
+}
+
+/// Kronecker sequence randomized per pixel with a Cranley-Patterson rotation.
+/// Dimensions past the sequence dimensions reuse the alphas with a different
+/// rotation.
+#[derive(Debug, Clone)]
+pub struct KroneckerSampler {
+    pub kronecker: Kronecker,
+    pub seed: u32,
+}
+
+impl KroneckerSampler {
+    pub fn new(dimensions: usize, seed: u32) -> KroneckerSampler {
+        KroneckerSampler { kronecker: Kronecker::new(dimensions), seed }
+    }
+}
+
+impl Sampler for KroneckerSampler {
+    fn sample_1d(&self, pixel: [u32; 2], index: u32, dimension: u32) -> f32 {
+        let d = dimension as usize % self.kronecker.dimensions();
+        let shift = hash_combine(pixel_seed(pixel, self.seed), dimension);
+        to_unit_float(self.kronecker.sample(index, d).wrapping_add(shift))
+    }
+}
+
+/// Distributes the error of a sampler as blue noise in screen space ("Blue
+/// noise dithered sampling", Georgiev, Fajardo 2016). All pixels share the
+/// same sequence, toroidally shifted by the blue noise rank of the pixel.
+/// Every dimension reads the tile at a different offset to decorrelate them.
+#[derive(Debug, Clone)]
+pub struct BlueNoiseSampler<S> {
+    pub sampler: S,
+    pub ranking: BlueNoiseRanking,
+}
+
+impl<S: Sampler> Sampler for BlueNoiseSampler<S> {
+    fn sample_1d(&self, pixel: [u32; 2], index: u32, dimension: u32) -> f32 {
+        let x = self.sampler.sample_1d([0, 0], index, dimension);
+        let offset = pcg_hash(dimension);
+        let shift = self.ranking.value(pixel[0].wrapping_add(offset & 0xffff),
+                                       pixel[1].wrapping_add(offset >> 16));
+        let x = x + to_unit_float(shift);
+        (if x >= 1.0 { x - 1.0 } else { x }).min(ONE_MINUS_EPSILON)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that the points of every elementary interval of area 1 / n have
+    /// exactly one point, n must be a power of two.
+    fn assert_net(points: &[Vec2]) {
+        let n = points.len();
+        let m = n.trailing_zeros();
+        for a in 0..=m {
+            let (nx, ny) = (1usize << a, 1usize << (m - a));
+            let mut count = vec![0; n];
+            for p in points {
+                let x = (p.x * nx as f32) as usize;
+                let y = (p.y * ny as f32) as usize;
+                count[y * nx + x] += 1;
+            }
+            assert!(count.iter().all(|&c| c == 1), "not a net for {}x{}", nx, ny);
+        }
+    }
+
+    /// Checks that every interval of length 1 / n contains exactly one sample.
+    /// Points are nudged by a fraction of a stratum to be robust to the
+    /// rounding of stratum boundaries that are not exact in f32.
+    fn assert_stratified(points: &[f32], nudge: f64) {
+        let n = points.len();
+        let mut count = vec![0; n];
+        for &p in points {
+            assert!((0.0..1.0).contains(&p));
+            count[((p as f64 * n as f64 + nudge) as usize).min(n - 1)] += 1;
+        }
+        assert!(count.iter().all(|&c| c == 1));
+    }
+
+    /// L2 star discrepancy with Warnock's formula.
+    fn l2_star_discrepancy(points: &[Vec2]) -> f64 {
+        let n = points.len() as f64;
+        let mut a = 0.0;
+        let mut b = 0.0;
+        for p in points {
+            let (x, y) = (p.x as f64, p.y as f64);
+            a += (1.0 - x * x) * (1.0 - y * y);
+            for q in points {
+                b += (1.0 - x.max(q.x as f64)) * (1.0 - y.max(q.y as f64));
+            }
+        }
+        (1.0 / 9.0 - a / (2.0 * n) + b / (n * n)).sqrt()
+    }
+
+    /// Expected L2 star discrepancy of n independent uniform 2D points.
+    fn random_discrepancy(n: usize) -> f64 {
+        ((1.0 / 4.0 - 1.0 / 9.0) / n as f64).sqrt()
+    }
+
+    fn points(sampler: &dyn Sampler, pixel: [u32; 2], n: u32, dimension: u32) -> Vec<Vec2> {
+        (0..n).map(|i| sampler.sample_2d(pixel, i, dimension)).collect()
+    }
+
+    #[test]
+    fn sobol_primitive_polynomials() {
+        for &(s, a, m) in SOBOL_PARAMETERS.iter() {
+            // Order of x modulo p(x) = x^s + a_1 x^(s-1) + ... + 1
+            let p = (1u32 << s) | (a << 1) | 1;
+            let mut x = 1u32;
+            let mut order = 0;
+            loop {
+                x <<= 1;
+                if x & (1 << s) != 0 {
+                    x ^= p;
+                }
+                order += 1;
+                if x == 1 {
+                    break;
+                }
+            }
+            assert_eq!(order, (1 << s) - 1, "polynomial {:b} is not primitive", p);
+
+            for (k, &m) in m.iter().enumerate().take(s as usize) {
+                assert!(m % 2 == 1 && m < 1 << (k + 1));
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_reference() {
+        assert_eq!(sobol(0, 0), 0);
+        for i in 0..1024u32 {
+            assert_eq!(sobol(i, 0), i.reverse_bits());
+        }
+
+        let y: Vec<f32> = (0..8).map(|i| to_unit_float(sobol(i, 1))).collect();
+        assert_eq!(y, [0.0, 0.5, 0.75, 0.25, 0.625, 0.125, 0.375, 0.875]);
+    }
+
+    #[test]
+    fn sobol_stratification() {
+        let n = 1 << 10;
+        for d in 0..SOBOL_DIMENSIONS {
+            let x: Vec<f32> = (0..n).map(|i| to_unit_float(sobol(i, d))).collect();
+            assert_stratified(&x, 0.0);
+            let x: Vec<f32> = (0..n).map(|i| to_unit_float(sobol_owen(i, d, 7))).collect();
+            assert_stratified(&x, 0.0);
+        }
+
+        // The first two dimensions are a (0, m, 2)-net for every power of two,
+        // Owen scrambling preserves it.
+        for m in 0..=10 {
+            let p: Vec<Vec2> = (0..1 << m).map(|i| {
+                Vec2::new(to_unit_float(sobol(i, 0)), to_unit_float(sobol(i, 1)))
+            }).collect();
+            assert_net(&p);
+
+            let sampler = SobolSampler { seed: 3 };
+            assert_net(&points(&sampler, [5, 9], 1 << m, 0));
+        }
+    }
+
+    #[test]
+    fn halton_stratification() {
+        let permutations = HaltonPermutations::new(1);
+        for (d, &base) in PRIMES.iter().enumerate() {
+            let p = permutations.permutation(d);
+            let mut sorted = p.to_vec();
+            sorted.sort_unstable();
+            assert!(sorted.iter().copied().eq(0..base));
+            assert_eq!(p[0], 0);
+
+            let mut n = base;
+            while n * base <= 4096 {
+                n *= base;
+            }
+
+            // Samples are exactly at the start of their stratum
+            let x: Vec<f32> = (0..n).map(|i| radical_inverse(base, i)).collect();
+            assert_stratified(&x, 1e-3);
+            let x: Vec<f32> = (0..n).map(|i| permutations.sample(i, d)).collect();
+            assert_stratified(&x, 1e-3);
+        }
+
+        assert_eq!(radical_inverse(3, 5), 7.0 / 9.0);
+    }
+
+    #[test]
+    fn kronecker() {
+        // phi_2 is the plastic number
+        let plastic = 1.324717957244746f64;
+        let r2 = Kronecker::r2();
+        assert_eq!(r2.alphas[0], ((1.0 / plastic) * (1u64 << 32) as f64) as u32);
+        assert_eq!(r2.alphas[1], ((1.0 / (plastic * plastic)) * (1u64 << 32) as f64) as u32);
+
+        // phi_1 is the golden ratio
+        let golden = (1.0 + 5.0f64.sqrt()) / 2.0;
+        let r1 = Kronecker::new(1);
+        assert_eq!(r1.alphas[0], ((golden - 1.0) * (1u64 << 32) as f64) as u32);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 1 dimension")]
+    fn kronecker_without_dimensions() {
+        KroneckerSampler::new(0, 0);
+    }
+
+    #[test]
+    fn discrepancy() {
+        let n = 1024;
+        let random = random_discrepancy(n as usize);
+
+        let samplers: Vec<(&str, Box<dyn Sampler>)> = vec![
+            ("sobol", Box::new(SobolSampler { seed: 1 })),
+            ("halton", Box::new(HaltonSampler::new(2))),
+            ("r2", Box::new(KroneckerSampler::new(2, 3))),
+            ("r8", Box::new(KroneckerSampler::new(8, 4))),
+            ("blue noise", Box::new(BlueNoiseSampler {
+                sampler: SobolSampler { seed: 5 },
+                ranking: BlueNoiseRanking::new(16, 5),
+            })),
+        ];
+
+        for (name, sampler) in samplers.iter() {
+            for &dimension in [0, 2, 6].iter() {
+                for &pixel in [[0, 0], [13, 7]].iter() {
+                    let p = points(sampler.as_ref(), pixel, n, dimension);
+                    let d = l2_star_discrepancy(&p);
+                    assert!(d < 0.3 * random, "{} dimension {}: {} vs random {}",
+                            name, dimension, d, random);
+                }
+            }
+        }
+
+        // Sanity check of the discrepancy with the random baseline
+        let p = points(&RandomSampler { seed: 0 }, [1, 2], n, 0);
+        let d = l2_star_discrepancy(&p);
+        assert!(d > 0.5 * random && d < 2.0 * random);
+    }
+
+    #[test]
+    fn blue_noise_ranking() {
+        let size = 32;
+        let ranking = BlueNoiseRanking::new(size, 0);
+
+        let mut sorted = ranking.ranks.clone();
+        sorted.sort_unstable();
+        assert!(sorted.iter().copied().eq(0..size * size));
+
+        // Pixels under a threshold are spread out, sparse sets have no
+        // adjacent pixels.
+        for &fraction in [16, 8, 4, 2].iter() {
+            let count = size * size / fraction;
+            let p: Vec<(i32, i32)> = (0..size * size)
+                .filter(|&i| ranking.ranks[i as usize] < count)
+                .map(|i| ((i % size) as i32, (i / size) as i32))
+                .collect();
+
+            // Clark-Evans ratio, mean distance to the nearest neighbor over
+            // its expected value for white noise of the same density.
+            let mut mean = 0.0;
+            let mut adjacent = 0;
+            for (i, a) in p.iter().enumerate() {
+                let mut min_dist2 = i32::MAX;
+                for (j, b) in p.iter().enumerate() {
+                    let dx = (a.0 - b.0).abs();
+                    let dy = (a.1 - b.1).abs();
+                    let dx = dx.min(size as i32 - dx);
+                    let dy = dy.min(size as i32 - dy);
+                    if i != j {
+                        min_dist2 = min_dist2.min(dx * dx + dy * dy);
+                    }
+                }
+                mean += (min_dist2 as f64).sqrt() / p.len() as f64;
+                if min_dist2 == 1 {
+                    adjacent += 1;
+                }
+            }
+            let ratio = mean / (0.5 * (fraction as f64).sqrt());
+            assert!(ratio > 1.3, "1/{} of the pixels: {}", fraction, ratio);
+            if fraction >= 8 {
+                assert_eq!(adjacent, 0);
+            }
+        }
+
+        let v: Vec<f32> = (0..size * size)
+            .map(|i| to_unit_float(ranking.value(i % size, i / size))).collect();
+        assert_stratified(&v, 0.0);
+    }
+
+    #[test]
+    fn gpu_tables() {
+        let bytes = bytemuck::bytes_of(&SOBOL_TABLE);
+        assert_eq!(bytes.len(), SOBOL_DIMENSIONS * 32 * 4);
+        assert_eq!(bytemuck::cast_slice::<u8, u32>(bytes)[32], 1 << 31);
+
+        let permutations = HaltonPermutations::new(0);
+        let last = HALTON_DIMENSIONS - 1;
+        assert_eq!(permutations.permutations.len(),
+                   (permutations.offsets[last] + PRIMES[last]) as usize);
+    }
+}
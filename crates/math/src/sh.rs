@@ -0,0 +1,453 @@
+//! Real spherical harmonics up to band 3 (16 coefficients), for environment
+//! lighting and irradiance probes. Basis functions follow "Stupid Spherical
+//! Harmonics (SH) Tricks" (Sloan 2008), including the Condon-Shortley phase.
+//! Directions use the z up convention of Vec3::spherical_to_direction.
+
+use core::ops;
+use crate::vec::Vec3;
+use crate::mat::Mat3;
+use crate::quat::Quat;
+
+use bytemuck::{Pod, Zeroable};
+
+use core::f64::consts::PI;
+
+pub const SH_BANDS: usize = 4;
+pub const SH_COEFFICIENTS: usize = SH_BANDS * SH_BANDS;
+
+fn basis_f64(x: f64, y: f64, z: f64) -> [f64; SH_COEFFICIENTS] {
+    let (x2, y2, z2) = (x * x, y * y, z * z);
+    [
+        0.282094791773878,
+
+        -0.488602511902920 * y,
+        0.488602511902920 * z,
+        -0.488602511902920 * x,
+
+        1.092548430592079 * x * y,
+        -1.092548430592079 * y * z,
+        0.315391565252520 * (3.0 * z2 - 1.0),
+        -1.092548430592079 * x * z,
+        0.546274215296040 * (x2 - y2),
+
+        -0.590043589926644 * y * (3.0 * x2 - y2),
+        2.890611442640554 * x * y * z,
+        -0.457045799464466 * y * (5.0 * z2 - 1.0),
+        0.373176332590115 * z * (5.0 * z2 - 3.0),
+        -0.457045799464466 * x * (5.0 * z2 - 1.0),
+        1.445305721320277 * z * (x2 - y2),
+        -0.590043589926644 * x * (x2 - 3.0 * y2),
+    ]
+}
+
+/// Values of the 16 basis functions in direction d, d must be normalized.
+/// Coefficient l * (l + 1) + m is band l and order m.
+pub fn sh_basis(d: Vec3) -> [f32; SH_COEFFICIENTS] {
+    let b = basis_f64(d.x as f64, d.y as f64, d.z as f64);
+    let mut r = [0.0; SH_COEFFICIENTS];
+    for (r, b) in r.iter_mut().zip(b.iter()) {
+        *r = *b as f32;
+    }
+    r
+}
+
+/// Nodes and weights of the Gauss-Legendre quadrature of order n on [-1, 1].
+fn gauss_legendre(n: usize) -> Vec<(f64, f64)> {
+    (0..n).map(|i| {
+        // Newton iterations from the Chebyshev approximation of the root
+        let mut x = (PI * (i as f64 + 0.75) / (n as f64 + 0.5)).cos();
+        let mut dp = 1.0;
+        for _ in 0..100 {
+            // Legendre recurrence for P_n(x) and its derivative
+            let (mut p0, mut p1) = (1.0, x);
+            for k in 2..=n {
+                let p2 = ((2 * k - 1) as f64 * x * p1 - (k - 1) as f64 * p0) / k as f64;
+                p0 = p1;
+                p1 = p2;
+            }
+            dp = n as f64 * (x * p1 - p0) / (x * x - 1.0);
+            let dx = p1 / dp;
+            x -= dx;
+            if dx.abs() < 1e-15 {
+                break;
+            }
+        }
+        (x, 2.0 / ((1.0 - x * x) * dp * dp))
+    }).collect()
+}
+
+/// Convolution weights of the clamped cosine lobe, pi, 2 pi / 3, pi / 4 and
+/// 0 for bands 0 to 3 ("An Efficient Representation for Irradiance
+/// Environment Maps", Ramamoorthi, Hanrahan 2001).
+pub const IRRADIANCE_WEIGHTS: [f32; SH_BANDS] = [
+    core::f32::consts::PI,
+    2.0 * core::f32::consts::PI / 3.0,
+    core::f32::consts::PI / 4.0,
+    0.0,
+];
+
+const ROTATION_FIT_DIRECTIONS: usize = 32;
+
+/// Rotation of SH coefficients, a block diagonal matrix with one block per
+/// band. Each block is fit on a set of directions to the rotated basis, which
+/// is exact since every band is closed under rotation.
+#[derive(Debug, Copy, Clone)]
+pub struct ShRotation {
+    /// Row major, zero outside of the diagonal blocks.
+    pub m: [[f32; SH_COEFFICIENTS]; SH_COEFFICIENTS],
+}
+
+impl ShRotation {
+    pub fn from_mat3(r: Mat3) -> ShRotation {
+        let mut m = [[0.0; SH_COEFFICIENTS]; SH_COEFFICIENTS];
+        m[0][0] = 1.0;
+
+        let rows = r.to_rows();
+        let inverse = |d: [f64; 3]| -> [f64; 3] {
+            // R^T d
+            let mut v = [0.0; 3];
+            for (i, row) in rows.iter().enumerate() {
+                let row = [row.x as f64, row.y as f64, row.z as f64];
+                for j in 0..3 {
+                    v[j] += row[j] * d[i];
+                }
+            }
+            v
+        };
+
+        for l in 1..SH_BANDS {
+            let n = 2 * l + 1;
+            let offset = l * l;
+
+            // Least squares fit of Y(d) M = Y(R^T d) on a Fibonacci sphere,
+            // solved with the normal equations A M = B.
+            let mut a = [[0.0f64; 7]; 7];
+            let mut b = [[0.0f64; 7]; 7];
+            for i in 0..ROTATION_FIT_DIRECTIONS {
+                let z = 1.0 - 2.0 * (i as f64 + 0.5) / ROTATION_FIT_DIRECTIONS as f64;
+                let r = (1.0 - z * z).sqrt();
+                let phi = i as f64 * 2.399963229728653;
+                let d = [r * phi.cos(), r * phi.sin(), z];
+                let rd = inverse(d);
+
+                let y = basis_f64(d[0], d[1], d[2]);
+                let yr = basis_f64(rd[0], rd[1], rd[2]);
+                for j in 0..n {
+                    for k in 0..n {
+                        a[j][k] += y[offset + j] * y[offset + k];
+                        b[j][k] += y[offset + j] * yr[offset + k];
+                    }
+                }
+            }
+
+            // Gauss-Jordan elimination with partial pivoting
+            for c in 0..n {
+                let p = (c..n).max_by(|&i, &j| {
+                    a[i][c].abs().partial_cmp(&a[j][c].abs()).unwrap()
+                }).unwrap();
+                a.swap(c, p);
+                b.swap(c, p);
+
+                let inv = 1.0 / a[c][c];
+                for i in 0..n {
+                    if i == c {
+                        continue;
+                    }
+                    let f = a[i][c] * inv;
+                    for j in 0..n {
+                        a[i][j] -= f * a[c][j];
+                        b[i][j] -= f * b[c][j];
+                    }
+                }
+            }
+
+            for i in 0..n {
+                for j in 0..n {
+                    m[offset + i][offset + j] = (b[i][j] / a[i][i]) as f32;
+                }
+            }
+        }
+
+        ShRotation { m }
+    }
+
+    pub fn from_quat(q: Quat) -> ShRotation {
+        ShRotation::from_mat3(q.to_mat4().to_mat3())
+    }
+}
+
+macro_rules! sh_impl {
+    ($name: ident, $t: ident, $zero: expr) => {
+
+        /// Coefficients of bands 0 to 3.
+        #[derive(Debug, Copy, Clone, Pod, Zeroable)]
+        #[repr(C)]
+        pub struct $name {
+            pub coeffs: [$t; SH_COEFFICIENTS],
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                $name { coeffs: [$zero; SH_COEFFICIENTS] }
+            }
+        }
+
+        impl $name {
+            #[inline]
+            pub fn new() -> $name {
+                $name::default()
+            }
+
+            pub fn eval(&self, d: Vec3) -> $t {
+                let b = sh_basis(d);
+                let mut v = $zero;
+                for (c, b) in self.coeffs.iter().zip(b.iter()) {
+                    v += *c * *b;
+                }
+                v
+            }
+
+            /// Adds value * weight to the projection in direction d, weight is
+            /// usually the solid angle of the sample or 1 / pdf.
+            #[inline]
+            pub fn add_sample(&mut self, d: Vec3, value: $t, weight: f32) {
+                let b = sh_basis(d);
+                for (c, b) in self.coeffs.iter_mut().zip(b.iter()) {
+                    *c += value * (b * weight);
+                }
+            }
+
+            /// Projects f with a product quadrature, Gauss-Legendre in z with
+            /// resolution nodes and uniform in phi with 2 resolution nodes.
+            /// Gauss-Legendre with n nodes is exact up to degree 2n - 1, so
+            /// the projection is exact when f times the basis is a polynomial
+            /// of degree up to 2 resolution - 1, and accurate for smooth
+            /// functions.
+            pub fn project(f: impl Fn(Vec3) -> $t, resolution: usize) -> $name {
+                let m = 2 * resolution;
+
+                let mut sh = $name::new();
+                for (z, w) in gauss_legendre(resolution) {
+                    let r = (1.0 - z * z).sqrt();
+                    let weight = (w * 2.0 * PI / m as f64) as f32;
+                    for j in 0..m {
+                        let phi = 2.0 * PI * (j as f64 + 0.5) / m as f64;
+                        let d = Vec3::new((r * phi.cos()) as f32,
+                                          (r * phi.sin()) as f32, z as f32);
+                        sh.add_sample(d, f(d), weight);
+                    }
+                }
+                sh
+            }
+
+            /// Projects a row major equirectangular image, rows go from
+            /// theta = 0 (+z) to pi and columns from phi = 0 to 2 pi like
+            /// Vec3::spherical_to_direction.
+            pub fn project_equirect(width: usize, height: usize, pixels: &[$t]) -> $name {
+                assert_eq!(pixels.len(), width * height);
+
+                let mut sh = $name::new();
+                for y in 0..height {
+                    let theta0 = PI * y as f64 / height as f64;
+                    let theta1 = PI * (y + 1) as f64 / height as f64;
+                    let theta = PI * (y as f64 + 0.5) / height as f64;
+                    let weight = ((theta0.cos() - theta1.cos()) * 2.0 * PI /
+                                  width as f64) as f32;
+
+                    for x in 0..width {
+                        let phi = 2.0 * PI * (x as f64 + 0.5) / width as f64;
+                        let d = Vec3::spherical_to_direction(theta as f32, phi as f32);
+                        sh.add_sample(d, pixels[y * width + x], weight);
+                    }
+                }
+                sh
+            }
+
+            /// Coefficients of the function rotated by r, that is
+            /// rotated.eval(R d) == self.eval(d).
+            pub fn rotate(&self, r: &ShRotation) -> $name {
+                let mut sh = $name::new();
+                for (i, row) in r.m.iter().enumerate() {
+                    let l = (i as f64).sqrt() as usize;
+                    for j in l * l..(l + 1) * (l + 1) {
+                        sh.coeffs[i] += self.coeffs[j] * row[j];
+                    }
+                }
+                sh
+            }
+
+            /// Convolution with a kernel symmetric around z, given by the
+            /// scale of each band.
+            pub fn convolve(&self, band_weights: [f32; SH_BANDS]) -> $name {
+                let mut sh = *self;
+                for (l, w) in band_weights.iter().enumerate() {
+                    for c in sh.coeffs[l * l..(l + 1) * (l + 1)].iter_mut() {
+                        *c *= *w;
+                    }
+                }
+                sh
+            }
+
+            /// Turns radiance into irradiance, eval(n) of the result is the
+            /// cosine weighted integral of the radiance around n. Divide by
+            /// pi for the radiance reflected by a white lambertian surface.
+            pub fn convolve_irradiance(&self) -> $name {
+                self.convolve(IRRADIANCE_WEIGHTS)
+            }
+        }
+
+        impl ops::Add<$name> for $name {
+            type Output = $name;
+
+            fn add(self, rhs: $name) -> $name {
+                let mut sh = self;
+                for (a, b) in sh.coeffs.iter_mut().zip(rhs.coeffs.iter()) {
+                    *a += *b;
+                }
+                sh
+            }
+        }
+
+        impl ops::Mul<f32> for $name {
+            type Output = $name;
+
+            fn mul(self, rhs: f32) -> $name {
+                let mut sh = self;
+                for c in sh.coeffs.iter_mut() {
+                    *c *= rhs;
+                }
+                sh
+            }
+        }
+    }
+}
+
+sh_impl!(Sh, f32, 0.0);
+sh_impl!(ShRgb, Vec3, Vec3::from_scalar(0.0));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Rng;
+
+    /// Polynomial of degree 3, exactly represented by bands 0 to 3.
+    fn cubic(d: Vec3) -> f32 {
+        1.0 + 2.0 * d.x - d.z * d.z + 3.0 * d.x * d.y * d.z - 0.5 * d.y * d.y * d.y
+    }
+
+    fn random_direction(rng: &mut Rng) -> Vec3 {
+        crate::sampling::sample_uniform_sphere(
+            crate::vec::Vec2::new(rng.range(0., 1.), rng.range(0., 1.)))
+    }
+
+    #[test]
+    fn orthonormal() {
+        for i in 0..SH_COEFFICIENTS {
+            let sh = Sh::project(|d| sh_basis(d)[i], 64);
+            for (j, c) in sh.coeffs.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((c - expected).abs() < 1e-3, "<Y{}, Y{}> = {}", i, j, c);
+            }
+        }
+    }
+
+    #[test]
+    fn reconstruction() {
+        let sh = Sh::project(cubic, 64);
+
+        let mut rng = Rng(1);
+        for _ in 0..100 {
+            let d = random_direction(&mut rng);
+            assert!((sh.eval(d) - cubic(d)).abs() < 2e-3);
+        }
+
+        // Equirect image of the same function
+        let (w, h) = (256, 128);
+        let mut pixels = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                let theta = core::f32::consts::PI * (y as f32 + 0.5) / h as f32;
+                let phi = 2.0 * core::f32::consts::PI * (x as f32 + 0.5) / w as f32;
+                let c = cubic(Vec3::spherical_to_direction(theta, phi));
+                pixels.push(Vec3::new(c, 2.0 * c, -c));
+            }
+        }
+        let rgb = ShRgb::project_equirect(w, h, &pixels);
+        for (a, b) in rgb.coeffs.iter().zip(sh.coeffs.iter()) {
+            assert!((*a - Vec3::new(*b, 2.0 * b, -b)).length() < 2e-3, "{:?} {}", a, b);
+        }
+
+        // Smooth function outside of the span, the error is the truncation of
+        // the bands above 3.
+        let f = |d: Vec3| d.z.exp();
+        let sh = Sh::project(f, 64);
+        for _ in 0..100 {
+            let d = random_direction(&mut rng);
+            assert!((sh.eval(d) - f(d)).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn rotation() {
+        let mut rng = Rng(2);
+        let sh = Sh::project(cubic, 64);
+
+        for _ in 0..20 {
+            let axis = random_direction(&mut rng);
+            let q = Quat::rotate(axis, rng.range(-core::f32::consts::PI, core::f32::consts::PI));
+            let r = q.to_mat4().to_mat3();
+
+            let rotation = ShRotation::from_mat3(r);
+            let rotated = sh.rotate(&rotation);
+            let rotated_q = sh.rotate(&ShRotation::from_quat(q));
+
+            for _ in 0..20 {
+                let d = random_direction(&mut rng);
+                let rd = r * d;
+                assert!((rotated.eval(rd) - sh.eval(d)).abs() < 1e-3);
+                assert!((rotated_q.eval(rd) - sh.eval(d)).abs() < 1e-3);
+            }
+
+            // Rotating the function before projecting is the same
+            let projected = Sh::project(|d| cubic(r.transpose() * d), 64);
+            for (a, b) in projected.coeffs.iter().zip(rotated.coeffs.iter()) {
+                assert!((a - b).abs() < 2e-3);
+            }
+
+            // Rotations preserve the energy of every band
+            for l in 0..SH_BANDS {
+                let e = |s: &Sh| -> f32 {
+                    s.coeffs[l * l..(l + 1) * (l + 1)].iter().map(|c| c * c).sum()
+                };
+                assert!((e(&rotated) - e(&sh)).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn irradiance() {
+        use core::f32::consts::PI;
+
+        // Constant radiance of 1 gives pi everywhere
+        let sh = Sh::project(|_| 1.0, 16).convolve_irradiance();
+        assert!((sh.eval(Vec3::new(0., 0., 1.)) - PI).abs() < 1e-4);
+
+        // Linear radiance is exact, E(n) = 2 pi / 3 n.z
+        let sh = Sh::project(|d| d.z, 32).convolve_irradiance();
+        let n = Vec3::new(0.6, 0., 0.8);
+        assert!((sh.eval(n) - 2.0 * PI / 3.0 * n.z).abs() < 1e-3);
+
+        // Sky radiance, compared with the cosine weighted integral. Bands 0
+        // to 2 approximate irradiance with an error of a few percent.
+        let sky = |d: Vec3| d.z.max(0.0);
+        let sh = Sh::project(sky, 64).convolve_irradiance();
+        let mut rng = Rng(3);
+        for _ in 0..10 {
+            let n = random_direction(&mut rng);
+            let reference = Sh::project(|d| sky(d) * n.dot(d).max(0.0), 128).coeffs[0] *
+                (4.0 * PI).sqrt();
+            assert!((sh.eval(n) - reference).abs() < 0.03 * PI, "{} {}",
+                    sh.eval(n), reference);
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! SSE implementations of the hottest Vec4, Vec3 and Mat4 operations, enabled
+//! with the simd feature. SSE is part of the x86_64 baseline so no runtime
+//! detection is needed. Types keep their scalar #[repr(C)] layout and are
+//! loaded and stored unaligned, so the public API and Pod layout are the
+//! same as the scalar path.
+
+use core::arch::x86_64::*;
+use core::ops;
+
+use crate::vec::{Vec3, Vec4};
+use crate::mat::Mat4;
+
+#[inline(always)]
+fn load(v: Vec4) -> __m128 {
+    unsafe { _mm_loadu_ps(&v as *const Vec4 as *const f32) }
+}
+
+#[inline(always)]
+fn store(v: __m128) -> Vec4 {
+    let mut r = Vec4::default();
+    unsafe { _mm_storeu_ps(&mut r as *mut Vec4 as *mut f32, v) };
+    r
+}
+
+#[inline(always)]
+fn load3(v: Vec3) -> __m128 {
+    unsafe { _mm_set_ps(0.0, v.z, v.y, v.x) }
+}
+
+#[inline(always)]
+fn store3(v: __m128) -> Vec3 {
+    store(v).xyz()
+}
+
+#[inline(always)]
+fn load_columns(m: &Mat4) -> [__m128; 4] {
+    let p = m.e.as_ptr() as *const f32;
+    unsafe {
+        [
+            _mm_loadu_ps(p),
+            _mm_loadu_ps(p.add(4)),
+            _mm_loadu_ps(p.add(8)),
+            _mm_loadu_ps(p.add(12)),
+        ]
+    }
+}
+
+/// c[0] * v.x + c[1] * v.y + c[2] * v.z + c[3] * v.w
+#[inline(always)]
+fn linear_combination(c: &[__m128; 4], v: __m128) -> __m128 {
+    unsafe {
+        let x = _mm_mul_ps(c[0], _mm_shuffle_ps::<0x00>(v, v));
+        let y = _mm_mul_ps(c[1], _mm_shuffle_ps::<0x55>(v, v));
+        let z = _mm_mul_ps(c[2], _mm_shuffle_ps::<0xAA>(v, v));
+        let w = _mm_mul_ps(c[3], _mm_shuffle_ps::<0xFF>(v, v));
+        _mm_add_ps(_mm_add_ps(x, y), _mm_add_ps(z, w))
+    }
+}
+
+impl Vec4 {
+    #[inline]
+    pub fn dot(self, b: Vec4) -> f32 {
+        unsafe {
+            let m = _mm_mul_ps(load(self), load(b));
+            // (x + y, x + y, z + w, z + w)
+            let s = _mm_add_ps(m, _mm_shuffle_ps::<0xB1>(m, m));
+            let s = _mm_add_ss(s, _mm_movehl_ps(s, s));
+            _mm_cvtss_f32(s)
+        }
+    }
+}
+
+impl Vec3 {
+    #[inline]
+    pub fn cross(self, b: Vec3) -> Vec3 {
+        unsafe {
+            let a = load3(self);
+            let b = load3(b);
+            // a * b.yzx - a.yzx * b, then rotate back to xyz
+            let a_yzx = _mm_shuffle_ps::<0xC9>(a, a);
+            let b_yzx = _mm_shuffle_ps::<0xC9>(b, b);
+            let c = _mm_sub_ps(_mm_mul_ps(a, b_yzx), _mm_mul_ps(a_yzx, b));
+            store3(_mm_shuffle_ps::<0xC9>(c, c))
+        }
+    }
+}
+
+impl ops::Mul<Mat4> for Mat4 {
+    type Output = Mat4;
+
+    #[inline]
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let a = load_columns(&self);
+        let b = load_columns(&rhs);
+
+        let mut m = Mat4::new();
+        let p = m.e.as_mut_ptr() as *mut f32;
+        for (j, c) in b.iter().enumerate() {
+            unsafe { _mm_storeu_ps(p.add(j * 4), linear_combination(&a, *c)) };
+        }
+        m
+    }
+}
+
+impl ops::Mul<Vec4> for Mat4 {
+    type Output = Vec4;
+
+    #[inline]
+    fn mul(self, rhs: Vec4) -> Vec4 {
+        store(linear_combination(&load_columns(&self), load(rhs)))
+    }
+}
+
+impl Mat4 {
+    /// Same as transform_point on every element, in place.
+    pub fn transform_points(&self, points: &mut [Vec3]) {
+        let c = load_columns(self);
+        for p in points.iter_mut() {
+            unsafe {
+                let x = _mm_mul_ps(c[0], _mm_set1_ps(p.x));
+                let y = _mm_mul_ps(c[1], _mm_set1_ps(p.y));
+                let z = _mm_mul_ps(c[2], _mm_set1_ps(p.z));
+                *p = store3(_mm_add_ps(_mm_add_ps(x, y), _mm_add_ps(z, c[3])));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec::{Vec3d, Vec4d};
+    use crate::mat::Mat4d;
+    use crate::tests::Rng;
+
+    // The f64 types always use the scalar path, so they act as reference.
+
+    fn random_vec4(rng: &mut Rng) -> Vec4 {
+        Vec4::new(rng.range(-10., 10.), rng.range(-10., 10.),
+                  rng.range(-10., 10.), rng.range(-10., 10.))
+    }
+
+    fn random_mat4(rng: &mut Rng) -> Mat4 {
+        Mat4::from_columns(&[random_vec4(rng), random_vec4(rng),
+                             random_vec4(rng), random_vec4(rng)])
+    }
+
+    fn to_d(v: Vec4) -> Vec4d {
+        Vec4d::new(v.x as f64, v.y as f64, v.z as f64, v.w as f64)
+    }
+
+    fn to_md(m: Mat4) -> Mat4d {
+        let mut r = Mat4d::new();
+        for j in 0..4 {
+            for i in 0..4 {
+                r.e[j][i] = m.e[j][i] as f64;
+            }
+        }
+        r
+    }
+
+    fn close(a: f32, b: f64) -> bool {
+        (a as f64 - b).abs() <= 1e-5 * (1.0 + b.abs())
+    }
+
+    #[test]
+    fn dot_and_cross() {
+        let mut rng = Rng(21);
+        for _ in 0..10000 {
+            let a = random_vec4(&mut rng);
+            let b = random_vec4(&mut rng);
+            assert!(close(a.dot(b), to_d(a).dot(to_d(b))));
+
+            let a3 = Vec3::new(a.x, a.y, a.z);
+            let b3 = Vec3::new(b.x, b.y, b.z);
+            let c = a3.cross(b3);
+            let cd = Vec3d::new(a.x as f64, a.y as f64, a.z as f64)
+                .cross(Vec3d::new(b.x as f64, b.y as f64, b.z as f64));
+            assert!(close(c.x, cd.x) && close(c.y, cd.y) && close(c.z, cd.z));
+        }
+
+        assert_eq!(Vec4::new(1., 2., 3., 4.).dot(Vec4::new(5., 6., 7., 8.)), 70.);
+        assert_eq!(Vec3::new(1., 0., 0.).cross(Vec3::new(0., 1., 0.)).to_slice(),
+                   [0., 0., 1.]);
+    }
+
+    #[test]
+    fn mat4_mul() {
+        let mut rng = Rng(22);
+        for _ in 0..10000 {
+            let a = random_mat4(&mut rng);
+            let b = random_mat4(&mut rng);
+            let v = random_vec4(&mut rng);
+
+            let m = a * b;
+            let md = to_md(a) * to_md(b);
+            for j in 0..4 {
+                for i in 0..4 {
+                    assert!(close(m.e[j][i], md.e[j][i]));
+                }
+            }
+
+            let r = a * v;
+            let rd = to_md(a) * to_d(v);
+            assert!(close(r.x, rd.x) && close(r.y, rd.y) &&
+                    close(r.z, rd.z) && close(r.w, rd.w));
+        }
+    }
+
+    #[test]
+    fn transform_points() {
+        let mut rng = Rng(23);
+        let m = random_mat4(&mut rng);
+        let mut points: Vec<Vec3> = (0..1000).map(|_| {
+            let v = random_vec4(&mut rng);
+            Vec3::new(v.x, v.y, v.z)
+        }).collect();
+        let original = points.clone();
+
+        m.transform_points(&mut points);
+        for (p, o) in points.iter().zip(original.iter()) {
+            let r = to_md(m) * Vec4d::new(o.x as f64, o.y as f64, o.z as f64, 1.0);
+            assert!(close(p.x, r.x) && close(p.y, r.y) && close(p.z, r.z));
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use core::ops;
+use core::fmt;
+use crate::vec::{Vec3, Vec3d, Vec4, Vec4d};
+use crate::mat::{Mat4, Mat4d};
+use crate::quat::{Quat, Quatd};
+
+use bytemuck::{Pod, Zeroable};
+
+macro_rules! transform_impl {
+    ($name: ident, $t: ident, $v3: ident, $v4: ident, $q: ident, $m4: ident) => {
+
+        /// Translation, rotation and scale, applied in reverse order
+        /// (scale first, translation last) like T * R * S.
+        #[derive(Debug, Copy, Clone, Pod, Zeroable)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        #[repr(C)]
+        pub struct $name {
+            pub translation: $v3,
+            pub rotation: $q,
+            pub scale: $v3,
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::identity()
+            }
+        }
+
+        impl $name {
+            #[inline]
+            pub fn new(translation: $v3, rotation: $q, scale: $v3) -> Self {
+                Self { translation, rotation, scale }
+            }
+
+            #[inline]
+            pub fn identity() -> Self {
+                Self {
+                    translation: $v3::from_scalar(0.),
+                    rotation: $q::identity(),
+                    scale: $v3::from_scalar(1.),
+                }
+            }
+
+            pub fn to_mat4(&self) -> $m4 {
+                let mut m = self.rotation.to_mat4();
+                let s = self.scale.to_slice();
+                for (i, s) in s.iter().enumerate() {
+                    for j in 0..3 {
+                        m.e[i][j] *= s;
+                    }
+                }
+                m.e[3][0..3].copy_from_slice(&self.translation.to_slice());
+
+                m
+            }
+
+            #[inline]
+            pub fn transform_point(&self, p: $v3) -> $v3 {
+                self.rotation * (p * self.scale) + self.translation
+            }
+
+            #[inline]
+            pub fn transform_vector(&self, v: $v3) -> $v3 {
+                self.rotation * (v * self.scale)
+            }
+
+            /// Exact only if the scale is uniform, otherwise the inverse of a
+            /// TRS transform is not a TRS transform and the shear is lost.
+            pub fn inverse(&self) -> Self {
+                let rotation = self.rotation.inverse();
+                let scale = 1.0 / self.scale;
+                let translation = scale * (rotation * -self.translation);
+
+                Self { translation, rotation, scale }
+            }
+
+            /// Linear interpolation of translation and scale and spherical
+            /// interpolation of rotation, returns self for t = 0 and b for
+            /// t = 1.
+            pub fn lerp(&self, b: &Self, t: $t) -> Self {
+                Self {
+                    translation: self.translation + (b.translation - self.translation) * t,
+                    rotation: self.rotation.slerp(b.rotation, t),
+                    scale: self.scale + (b.scale - self.scale) * t,
+                }
+            }
+        }
+
+        /// Composition, the result applies rhs first and then self. Exact
+        /// only if the scale of self is uniform, see inverse.
+        impl ops::Mul<$name> for $name {
+            type Output = $name;
+
+            #[inline]
+            fn mul(self, rhs: $name) -> $name {
+                $name {
+                    translation: self.transform_point(rhs.translation),
+                    rotation: self.rotation * rhs.rotation,
+                    scale: self.scale * rhs.scale,
+                }
+            }
+        }
+
+        impl From<$name> for $m4 {
+            fn from(t: $name) -> $m4 {
+                t.to_mat4()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let prec = f.precision().unwrap_or(3);
+                write!(f, "{}(t: {:.prec$}, r: {:.prec$}, s: {:.prec$})",
+                       stringify!($name), self.translation, self.rotation,
+                       self.scale, prec = prec)
+            }
+        }
+
+        impl $m4 {
+            /// Decomposes an affine matrix into translation, rotation and
+            /// scale. A negative determinant is represented as a negative
+            /// scale on the x axis. Returns None if the matrix is projective,
+            /// singular or has shear.
+            pub fn decompose(&self) -> Option<$name> {
+                if self.e[0][3] != 0. || self.e[1][3] != 0. ||
+                    self.e[2][3] != 0. || self.e[3][3] != 1. {
+                    return None;
+                }
+
+                let c = self.to_columns();
+                let mut x = $v3::new(c[0].x, c[0].y, c[0].z);
+                let y = $v3::new(c[1].x, c[1].y, c[1].z);
+                let z = $v3::new(c[2].x, c[2].y, c[2].z);
+
+                let mut scale = $v3::new(x.length(), y.length(), z.length());
+                let max = scale.x.max(scale.y).max(scale.z);
+                if max.is_nan() || scale.x.min(scale.y).min(scale.z) <= max * $t::EPSILON {
+                    return None;
+                }
+
+                if x.cross(y).dot(z) < 0. {
+                    scale.x = -scale.x;
+                    x = -x;
+                }
+
+                let x = x * (1.0 / scale.x.abs());
+                let y = y * (1.0 / scale.y);
+                let z = z * (1.0 / scale.z);
+
+                let tolerance = $t::EPSILON.sqrt();
+                if x.dot(y).abs() > tolerance || x.dot(z).abs() > tolerance ||
+                    y.dot(z).abs() > tolerance {
+                    return None;
+                }
+
+                let mut r = $m4::identity();
+                r.e[0][0..3].copy_from_slice(&x.to_slice());
+                r.e[1][0..3].copy_from_slice(&y.to_slice());
+                r.e[2][0..3].copy_from_slice(&z.to_slice());
+
+                Some($name {
+                    translation: $v3::new(c[3].x, c[3].y, c[3].z),
+                    rotation: $q::from_mat4(r).normalized(),
+                    scale,
+                })
+            }
+        }
+
+        impl ops::Mul<$v4> for $name {
+            type Output = $v4;
+
+            /// Points (w = 1) are translated, vectors (w = 0) are not.
+            #[inline]
+            fn mul(self, rhs: $v4) -> $v4 {
+                let v = self.transform_vector($v3::new(rhs.x, rhs.y, rhs.z)) +
+                    self.translation * rhs.w;
+                $v4::new(v.x, v.y, v.z, rhs.w)
+            }
+        }
+    }
+}
+
+transform_impl!(Transform, f32, Vec3, Vec4, Quat, Mat4);
+transform_impl!(Transformd, f64, Vec3d, Vec4d, Quatd, Mat4d);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::Rng;
+    use core::f32::consts::PI;
+
+    fn random_transform(rng: &mut Rng, uniform: bool) -> Transform {
+        let axis = Vec3::new(rng.range(-1., 1.), rng.range(-1., 1.),
+                             rng.range(-1., 1.)).normalized();
+        let scale = if uniform {
+            Vec3::from_scalar(rng.range(0.1, 10.))
+        } else {
+            Vec3::new(rng.range(0.1, 10.), rng.range(0.1, 10.),
+                      rng.range(0.1, 10.))
+        };
+
+        Transform::new(
+            Vec3::new(rng.range(-50., 50.), rng.range(-50., 50.),
+                      rng.range(-50., 50.)),
+            Quat::rotate(axis, rng.range(-PI, PI)),
+            scale,
+        )
+    }
+
+    fn assert_mat_eq(a: Mat4, b: Mat4, eps: f32) {
+        for j in 0..4 {
+            for i in 0..4 {
+                assert!((a.e[j][i] - b.e[j][i]).abs() < eps, "{:?}\n{:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn to_mat4_matches_trs() {
+        let mut rng = Rng(1);
+        for _ in 0..100 {
+            let t = random_transform(&mut rng, false);
+            let m = Mat4::translation(t.translation) * t.rotation.to_mat4() *
+                Mat4::scale3(t.scale);
+            assert_mat_eq(t.to_mat4(), m, 1e-4);
+
+            let p = Vec3::new(1., -2., 3.);
+            let a = t.transform_point(p);
+            let b = m * Vec4::new(p.x, p.y, p.z, 1.);
+            assert!((a - Vec3::new(b.x, b.y, b.z)).length() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn decompose_round_trip() {
+        let mut rng = Rng(2);
+        for i in 0..1000 {
+            let mut t = random_transform(&mut rng, false);
+            if i % 2 == 0 {
+                t.scale.x = -t.scale.x;
+            }
+
+            let m = t.to_mat4();
+            let d = m.decompose().expect("Failed to decompose");
+            assert_mat_eq(d.to_mat4(), m, 1e-3);
+
+            if i % 2 == 0 {
+                assert!(d.scale.x < 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn decompose_negative_scale() {
+        // Mirroring on y is represented as a mirror on x plus a rotation
+        let m = Mat4::scale3(Vec3::new(1., -2., 3.));
+        let d = m.decompose().unwrap();
+        assert_eq!(d.scale.x, -1.);
+        assert_mat_eq(d.to_mat4(), m, 1e-6);
+    }
+
+    #[test]
+    fn decompose_fails() {
+        assert!(Mat4::scale3(Vec3::new(1., 0., 1.)).decompose().is_none());
+
+        let mut shear = Mat4::identity();
+        shear.e[1][0] = 0.5;
+        assert!(shear.decompose().is_none());
+
+        let p = crate::mat::lh::zo::perspective(1., 0.1, 100., 1.);
+        assert!(p.decompose().is_none());
+    }
+
+    #[test]
+    fn composition_and_inverse() {
+        let mut rng = Rng(3);
+        for _ in 0..100 {
+            let a = random_transform(&mut rng, true);
+            let b = random_transform(&mut rng, false);
+
+            assert_mat_eq((a * b).to_mat4(), a.to_mat4() * b.to_mat4(), 1e-2);
+            assert_mat_eq((a * a.inverse()).to_mat4(), Mat4::identity(), 1e-4);
+            assert_mat_eq(a.inverse().to_mat4(), a.to_mat4().inverse(), 1e-4);
+        }
+    }
+
+    #[test]
+    fn interpolation() {
+        let z = Vec3::new(0., 0., 1.);
+        let a = Transform::new(Vec3::new(0., 0., 0.), Quat::identity(),
+                               Vec3::from_scalar(1.));
+        let b = Transform::new(Vec3::new(2., 4., 6.), Quat::rotate(z, 1.),
+                               Vec3::from_scalar(3.));
+
+        let m = a.lerp(&b, 0.5);
+        assert!((m.translation - Vec3::new(1., 2., 3.)).length() < 1e-6);
+        assert!((m.scale - Vec3::from_scalar(2.)).length() < 1e-6);
+        assert!(1. - m.rotation.dot(Quat::rotate(z, 0.5)).abs() < 1e-6);
+
+        assert_mat_eq(a.lerp(&b, 1.).to_mat4(), b.to_mat4(), 1e-6);
+    }
+}
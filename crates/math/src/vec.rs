@@ -62,7 +62,7 @@ macro_rules! scalar_assign_op_impl {
     }
 }
 
-macro_rules! vec_float_utils_impl {
+macro_rules! vec_dot_impl {
     ($v: ident, $t: ident, $($e: ident),*) => {
         impl $v {
             #[inline]
@@ -77,7 +77,13 @@ macro_rules! vec_float_utils_impl {
                 // ( 0.0 +  0.0) =  0.0
                 $( self.$e * b.$e + )* (-0.0)
             }
+        }
+    }
+}
 
+macro_rules! vec_float_utils_impl {
+    ($v: ident, $t: ident, $($e: ident),*) => {
+        impl $v {
             #[inline]
             pub fn length2(self) -> $t {
                 $v::dot(self, self)
@@ -107,6 +113,32 @@ macro_rules! vec_float_utils_impl {
             pub fn bounce(self, n: $v) -> $v {
                 self - 2.0 * $v::dot(self, n) * n
             }
+
+            #[inline]
+            pub fn abs(self) -> $v {
+                $v { $( $e: self.$e.abs(), )* }
+            }
+
+            #[inline]
+            pub fn floor(self) -> $v {
+                $v { $( $e: self.$e.floor(), )* }
+            }
+
+            #[inline]
+            pub fn ceil(self) -> $v {
+                $v { $( $e: self.$e.ceil(), )* }
+            }
+
+            /// Rounds half way cases away from zero.
+            #[inline]
+            pub fn round(self) -> $v {
+                $v { $( $e: self.$e.round(), )* }
+            }
+
+            #[inline]
+            pub fn recip(self) -> $v {
+                $v { $( $e: self.$e.recip(), )* }
+            }
         }
 
         impl std::ops::Neg for $v {
@@ -119,8 +151,119 @@ macro_rules! vec_float_utils_impl {
     }
 }
 
-macro_rules! vec3_utils_impl {
-    ($v: ident, $t: ident, $pi: expr) => {
+macro_rules! vec_int_impl {
+    ($v: ident, $t: ident, $($e: ident),*) => {
+        vec_op_impl!(Rem, rem, $v, $($e),*);
+        vec_op_impl!(BitAnd, bitand, $v, $($e),*);
+        vec_op_impl!(BitOr, bitor, $v, $($e),*);
+        vec_op_impl!(BitXor, bitxor, $v, $($e),*);
+        vec_op_impl!(Shl, shl, $v, $($e),*);
+        vec_op_impl!(Shr, shr, $v, $($e),*);
+
+        vec_assign_op_impl!(RemAssign, rem_assign, $v, $($e),*);
+        vec_assign_op_impl!(BitAndAssign, bitand_assign, $v, $($e),*);
+        vec_assign_op_impl!(BitOrAssign, bitor_assign, $v, $($e),*);
+        vec_assign_op_impl!(BitXorAssign, bitxor_assign, $v, $($e),*);
+        vec_assign_op_impl!(ShlAssign, shl_assign, $v, $($e),*);
+        vec_assign_op_impl!(ShrAssign, shr_assign, $v, $($e),*);
+
+        scalar_op_impl!(Rem, rem, $v, $t, $($e),*);
+        scalar_op_impl!(BitAnd, bitand, $v, $t, $($e),*);
+        scalar_op_impl!(BitOr, bitor, $v, $t, $($e),*);
+        scalar_op_impl!(BitXor, bitxor, $v, $t, $($e),*);
+        scalar_op_impl!(Shl, shl, $v, $t, $($e),*);
+        scalar_op_impl!(Shr, shr, $v, $t, $($e),*);
+
+        scalar_assign_op_impl!(RemAssign, rem_assign, $v, $t, $($e),*);
+        scalar_assign_op_impl!(BitAndAssign, bitand_assign, $v, $t, $($e),*);
+        scalar_assign_op_impl!(BitOrAssign, bitor_assign, $v, $t, $($e),*);
+        scalar_assign_op_impl!(BitXorAssign, bitxor_assign, $v, $t, $($e),*);
+        scalar_assign_op_impl!(ShlAssign, shl_assign, $v, $t, $($e),*);
+        scalar_assign_op_impl!(ShrAssign, shr_assign, $v, $t, $($e),*);
+
+        impl ops::Not for $v {
+            type Output = $v;
+
+            #[inline]
+            fn not(self) -> $v {
+                $v { $( $e: !self.$e, )* }
+            }
+        }
+    }
+}
+
+macro_rules! vec_signed_int_impl {
+    ($v: ident, $($e: ident),*) => {
+        impl $v {
+            #[inline]
+            pub fn abs(self) -> $v {
+                $v { $( $e: self.$e.abs(), )* }
+            }
+        }
+
+        impl std::ops::Neg for $v {
+            type Output = $v;
+
+            fn neg(self) -> $v {
+                $v { $( $e: self.$e.neg(), )* }
+            }
+        }
+    }
+}
+
+macro_rules! vec_swizzle_impl {
+    ($v2: ident, $v3: ident, $v4: ident, $t: ident) => {
+        impl $v2 {
+            #[inline]
+            pub fn extend(self, z: $t) -> $v3 {
+                $v3 { x: self.x, y: self.y, z }
+            }
+        }
+
+        impl $v3 {
+            #[inline]
+            pub fn xy(self) -> $v2 {
+                $v2 { x: self.x, y: self.y }
+            }
+
+            #[inline]
+            pub fn extend(self, w: $t) -> $v4 {
+                $v4 { x: self.x, y: self.y, z: self.z, w }
+            }
+        }
+
+        impl $v4 {
+            #[inline]
+            pub fn xy(self) -> $v2 {
+                $v2 { x: self.x, y: self.y }
+            }
+
+            #[inline]
+            pub fn xyz(self) -> $v3 {
+                $v3 { x: self.x, y: self.y, z: self.z }
+            }
+        }
+    }
+}
+
+/// Conversions between element types use as casts: floats to integers
+/// truncate toward zero and saturate, f64 to f32 rounds to nearest.
+macro_rules! vec_convert_impl {
+    ($from: ident, [$($to: ident: $t: ident),*], $e: tt) => {
+        $( vec_convert_impl!(@one $from, $to, $t, $e); )*
+    };
+    (@one $from: ident, $to: ident, $t: ident, ($($e: ident),*)) => {
+        impl From<$from> for $to {
+            #[inline]
+            fn from(v: $from) -> $to {
+                $to { $( $e: v.$e as $t, )* }
+            }
+        }
+    };
+}
+
+macro_rules! vec3_cross_impl {
+    ($v: ident) => {
         impl $v {
             #[inline]
             pub fn cross(self, b:$v) -> $v {
@@ -130,7 +273,13 @@ macro_rules! vec3_utils_impl {
                     z: self.x * b.y - self.y * b.x,
                 }
             }
+        }
+    }
+}
 
+macro_rules! vec3_utils_impl {
+    ($v: ident, $t: ident, $pi: expr) => {
+        impl $v {
             pub fn spherical_to_direction(theta: $t, phi: $t) -> $v {
                 let (sin_theta, cos_theta) = theta.sin_cos();
                 let (sin_phi, cos_phi) = phi.sin_cos();
@@ -152,6 +301,8 @@ macro_rules! vec3_utils_impl {
                 (theta, phi)
             }
 
+            /// Luminance of a linear Rec.709 color, see color::ColorSpace
+            /// for other working spaces.
             pub fn luminance(self) -> $t {
                 self.dot($v::new(0.212671, 0.715160, 0.072169))
             }
@@ -208,6 +359,71 @@ macro_rules! vec_impl {
                 }
             }
 
+            #[inline]
+            pub fn min_element(self) -> $t {
+                let a = self.to_slice();
+                a.iter().fold(a[0], |m, &x| if x < m { x } else { m })
+            }
+
+            #[inline]
+            pub fn max_element(self) -> $t {
+                let a = self.to_slice();
+                a.iter().fold(a[0], |m, &x| if x > m { x } else { m })
+            }
+
+            /// Component-wise mask[i] ? a[i] : b[i], like select in HLSL.
+            #[inline]
+            pub fn select(mask: [bool; $n], a: $v, b: $v) -> $v {
+                let (a, b) = (a.to_slice(), b.to_slice());
+                let mut r = b;
+                for i in 0..$n {
+                    if mask[i] {
+                        r[i] = a[i];
+                    }
+                }
+                $v::from_slice(&r)
+            }
+
+            #[inline]
+            pub fn cmp_eq(self, b: $v) -> [bool; $n] {
+                [$( self.$e == b.$e, )*]
+            }
+
+            #[inline]
+            pub fn cmp_lt(self, b: $v) -> [bool; $n] {
+                [$( self.$e < b.$e, )*]
+            }
+
+            #[inline]
+            pub fn cmp_le(self, b: $v) -> [bool; $n] {
+                [$( self.$e <= b.$e, )*]
+            }
+
+            #[inline]
+            pub fn cmp_gt(self, b: $v) -> [bool; $n] {
+                [$( self.$e > b.$e, )*]
+            }
+
+            #[inline]
+            pub fn cmp_ge(self, b: $v) -> [bool; $n] {
+                [$( self.$e >= b.$e, )*]
+            }
+        }
+
+        impl ops::Index<usize> for $v {
+            type Output = $t;
+
+            #[inline]
+            fn index(&self, i: usize) -> &$t {
+                &bytemuck::cast_ref::<$v, [$t; $n]>(self)[i]
+            }
+        }
+
+        impl ops::IndexMut<usize> for $v {
+            #[inline]
+            fn index_mut(&mut self, i: usize) -> &mut $t {
+                &mut bytemuck::cast_mut::<$v, [$t; $n]>(self)[i]
+            }
         }
 
         impl fmt::Display for $v {
@@ -239,6 +455,20 @@ macro_rules! vec_impl {
         scalar_assign_op_impl!(SubAssign, sub_assign, $v, $t, $($e),*);
         scalar_assign_op_impl!(MulAssign, mul_assign, $v, $t, $($e),*);
         scalar_assign_op_impl!(DivAssign, div_assign, $v, $t, $($e),*);
+
+        impl From<[$t; $n]> for $v {
+            #[inline]
+            fn from(a: [$t; $n]) -> $v {
+                $v::from_slice(&a)
+            }
+        }
+
+        impl From<$v> for [$t; $n] {
+            #[inline]
+            fn from(v: $v) -> [$t; $n] {
+                v.to_slice()
+            }
+        }
     }
 }
 
@@ -249,7 +479,7 @@ vec_impl!(Vec4i, i32, 4, x, y, z, w);
 
 vec_impl!(Vec2u, u32, 2, x, y);
 vec_impl!(Vec3u, u32, 3, x, y, z);
-vec_impl!(Vec4u, u64, 4, x, y, z, w);
+vec_impl!(Vec4u, u32, 4, x, y, z, w);
 
 vec_impl!(Vec2, f32, 2, x, y);
 vec_impl!(Vec3, f32, 3, x, y, z);
@@ -259,6 +489,19 @@ vec_impl!(Vec2d, f64, 2, x, y);
 vec_impl!(Vec3d, f64, 3, x, y, z);
 vec_impl!(Vec4d, f64, 4, x, y, z, w);
 
+// With the simd feature Vec4::dot and Vec3::cross are implemented in simd.rs
+vec_dot_impl!(Vec2, f32, x, y);
+vec_dot_impl!(Vec3, f32, x, y, z);
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+vec_dot_impl!(Vec4, f32, x, y, z, w);
+vec_dot_impl!(Vec2d, f64, x, y);
+vec_dot_impl!(Vec3d, f64, x, y, z);
+vec_dot_impl!(Vec4d, f64, x, y, z, w);
+
+#[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+vec3_cross_impl!(Vec3);
+vec3_cross_impl!(Vec3d);
+
 vec_float_utils_impl!(Vec2, f32, x, y);
 vec_float_utils_impl!(Vec3, f32, x, y, z);
 vec_float_utils_impl!(Vec4, f32, x, y, z, w);
@@ -269,4 +512,106 @@ vec_float_utils_impl!(Vec4d, f64, x, y, z, w);
 vec3_utils_impl!(Vec3, f32, core::f32::consts::PI);
 vec3_utils_impl!(Vec3d, f64, core::f64::consts::PI);
 
+vec_int_impl!(Vec2i, i32, x, y);
+vec_int_impl!(Vec3i, i32, x, y, z);
+vec_int_impl!(Vec4i, i32, x, y, z, w);
+vec_int_impl!(Vec2u, u32, x, y);
+vec_int_impl!(Vec3u, u32, x, y, z);
+vec_int_impl!(Vec4u, u32, x, y, z, w);
+
+vec_signed_int_impl!(Vec2i, x, y);
+vec_signed_int_impl!(Vec3i, x, y, z);
+vec_signed_int_impl!(Vec4i, x, y, z, w);
+
+vec_swizzle_impl!(Vec2i, Vec3i, Vec4i, i32);
+vec_swizzle_impl!(Vec2u, Vec3u, Vec4u, u32);
+vec_swizzle_impl!(Vec2, Vec3, Vec4, f32);
+vec_swizzle_impl!(Vec2d, Vec3d, Vec4d, f64);
+
+vec_convert_impl!(Vec2i, [Vec2u: u32, Vec2: f32, Vec2d: f64], (x, y));
+vec_convert_impl!(Vec2u, [Vec2i: i32, Vec2: f32, Vec2d: f64], (x, y));
+vec_convert_impl!(Vec2, [Vec2i: i32, Vec2u: u32, Vec2d: f64], (x, y));
+vec_convert_impl!(Vec2d, [Vec2i: i32, Vec2u: u32, Vec2: f32], (x, y));
+
+vec_convert_impl!(Vec3i, [Vec3u: u32, Vec3: f32, Vec3d: f64], (x, y, z));
+vec_convert_impl!(Vec3u, [Vec3i: i32, Vec3: f32, Vec3d: f64], (x, y, z));
+vec_convert_impl!(Vec3, [Vec3i: i32, Vec3u: u32, Vec3d: f64], (x, y, z));
+vec_convert_impl!(Vec3d, [Vec3i: i32, Vec3u: u32, Vec3: f32], (x, y, z));
+
+vec_convert_impl!(Vec4i, [Vec4u: u32, Vec4: f32, Vec4d: f64], (x, y, z, w));
+vec_convert_impl!(Vec4u, [Vec4i: i32, Vec4: f32, Vec4d: f64], (x, y, z, w));
+vec_convert_impl!(Vec4, [Vec4i: i32, Vec4u: u32, Vec4d: f64], (x, y, z, w));
+vec_convert_impl!(Vec4d, [Vec4i: i32, Vec4u: u32, Vec4: f32], (x, y, z, w));
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_and_swizzle() {
+        let mut v = Vec4::new(1., 2., 3., 4.);
+        assert_eq!(v[2], 3.);
+        v[3] = 5.;
+        assert_eq!(v.w, 5.);
+
+        let p = Vec3::new(1., 2., 3.);
+        assert_eq!(p.extend(1.).to_slice(), [1., 2., 3., 1.]);
+        assert_eq!(p.extend(1.).xyz().to_slice(), p.to_slice());
+        assert_eq!(v.xy().extend(7.).to_slice(), [1., 2., 7.]);
+        assert_eq!(p.xy().to_slice(), [1., 2.]);
+    }
+
+    #[test]
+    fn conversions() {
+        let v = Vec3::new(-1.5, 2.7, 1e10);
+        assert_eq!(Vec3i::from(v).to_slice(), [-1, 2, i32::MAX]);
+        assert_eq!(Vec3u::from(v).to_slice(), [0, 2, u32::MAX]);
+        assert_eq!(Vec3d::from(v).to_slice(), [-1.5f32 as f64, 2.7f32 as f64, 1e10]);
+        assert_eq!(Vec3::from(Vec3d::new(0.1, 0., 0.)).x, 0.1f32);
+        assert_eq!(Vec4::from(Vec4i::new(1, -2, 3, 4)).to_slice(), [1., -2., 3., 4.]);
+        assert_eq!(Vec2u::from(Vec2i::new(-1, 3)).to_slice(), [u32::MAX, 3]);
+    }
+
+    #[test]
+    fn integer_ops() {
+        let a = Vec4u::new(0b1100, 7, 16, u32::MAX);
+        let b = Vec4u::new(0b1010, 3, 5, 1);
+        assert_eq!(std::mem::size_of::<Vec4u>(), 16);
+        assert_eq!((a & b).to_slice(), [0b1000, 3, 0, 1]);
+        assert_eq!((a | b).to_slice(), [0b1110, 7, 21, u32::MAX]);
+        assert_eq!((a ^ b).to_slice(), [0b0110, 4, 21, u32::MAX - 1]);
+        assert_eq!((a % b).to_slice(), [2, 1, 1, 0]);
+        assert_eq!((a >> 2).to_slice(), [3, 1, 4, u32::MAX >> 2]);
+        assert_eq!((b << b).to_slice(), [0b1010 << 0b1010, 24, 160, 2]);
+        assert_eq!((!b).to_slice(), [!0b1010, !3, !5, !1]);
+
+        let mut c = Vec3i::new(-7, 7, 9);
+        assert_eq!((c % 4).to_slice(), [-3, 3, 1]);
+        assert_eq!((-c).abs().to_slice(), [7, 7, 9]);
+        c <<= 1;
+        c &= Vec3i::from_scalar(!3);
+        assert_eq!(c.to_slice(), [-16, 12, 16]);
+        assert_eq!(c.min_element(), -16);
+        assert_eq!(c.max_element(), 16);
+    }
 
+    #[test]
+    fn component_wise() {
+        let v = Vec4::new(-1.5, 0.5, 2.25, -0.);
+        assert_eq!(v.abs().to_slice(), [1.5, 0.5, 2.25, 0.]);
+        assert_eq!(v.floor().to_slice(), [-2., 0., 2., -0.]);
+        assert_eq!(v.ceil().to_slice(), [-1., 1., 3., -0.]);
+        assert_eq!(v.round().to_slice(), [-2., 1., 2., -0.]);
+        assert_eq!(Vec2::new(2., -4.).recip().to_slice(), [0.5, -0.25]);
+        assert_eq!(v.min_element(), -1.5);
+        assert_eq!(v.max_element(), 2.25);
+
+        let zero = Vec4::from_scalar(0.);
+        let mask = v.cmp_lt(zero);
+        assert_eq!(mask, [true, false, false, false]);
+        assert_eq!(Vec4::select(mask, -v, v).to_slice(), v.abs().to_slice());
+        assert_eq!(v.cmp_ge(zero), [false, true, true, true]);
+        assert_eq!(v.cmp_eq(v), [true; 4]);
+    }
+}
@@ -1,9 +1,11 @@
 
 use math::{
-    vec::{Vec2, Vec3},
+    vec::{Vec2, Vec3, Vec4},
     mat::{self, Mat4},
 };
 
+use crate::{with_offset, Deserialize, DeserializeError, Serialize};
+
 use core::f32::consts::PI;
 
 #[derive(Clone, Copy, PartialEq)]
@@ -36,6 +38,7 @@ pub struct Camera {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(position: Vec3, target: Vec3, world_up: Vec3,
             fov: f32, aspect_ratio: f32, near: f32, far: f32,
             move_speed: f32, rotate_speed: f32) -> Camera {
@@ -64,10 +67,14 @@ impl Camera {
         )
     }
 
+    /// Depth goes from 0 at near to 1 at far, or at infinity when far is
+    /// f32::INFINITY.
     pub fn projection(&self) -> Mat4 {
-        mat::lh::zo::perspective(
-            self.fov, self.near, self.far, self.aspect_ratio,
-        )
+        if self.far.is_infinite() {
+            mat::lh::zo::perspective_infinite(self.fov, self.near, self.aspect_ratio)
+        } else {
+            mat::lh::zo::perspective(self.fov, self.near, self.far, self.aspect_ratio)
+        }
     }
 
     pub fn move_in_direction(&mut self, dir: Direction, dt: f32) {
@@ -87,6 +94,16 @@ impl Camera {
         self.up = self.forward.cross(self.right).normalized();
     }
 
+    /// Distance from the pinhole to the film of width 1 used by
+    /// GenerateCameraRay in ray.lib.hlsl, fov is horizontal.
+    pub fn film_distance(&self) -> f32 {
+        0.5 / (self.fov * 0.5).tan()
+    }
+
+    pub fn set_film_distance(&mut self, film_distance: f32) {
+        self.fov = 2. * (0.5 / film_distance).atan();
+    }
+
     pub fn drag(&mut self, offset: Vec2) {
         let (mut theta, mut phi) = Vec3::direction_to_spherical(self.forward);
         theta += f32::clamp(offset.y * self.rotate_speed, -PI * 0.99, PI * 0.99);
@@ -95,4 +112,260 @@ impl Camera {
         self.forward = Vec3::spherical_to_direction(theta, phi);
         self.update_up_right();
     }
-}
\ No newline at end of file
+}
+
+/// Projections of glTF cameras, angles in radians.
+#[derive(Debug, Clone, Copy)]
+pub enum Projection {
+    Perspective {
+        yfov: f32,
+        /// Width over height, None to follow the viewport.
+        aspect_ratio: Option<f32>,
+        near: f32,
+        /// f32::INFINITY for an infinite projection.
+        far: f32,
+    },
+    /// Half of the width and height of the view volume.
+    Orthographic {
+        xmag: f32,
+        ymag: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ThinLens {
+    pub aperture_radius: f32,
+    pub focus_distance: f32,
+}
+
+/// Camera authored in a scene, placed in the world by the nodes referencing
+/// it in Node::camera. In the frame of the node the camera looks along -Z
+/// with +Y up like glTF.
+#[derive(Debug, Clone)]
+pub struct CameraDesc {
+    pub name: String,
+    pub projection: Projection,
+    /// None for a pinhole camera.
+    pub lens: Option<ThinLens>,
+}
+
+impl CameraDesc {
+    /// Perspective cameras only, transform is the world transform of the
+    /// node and only its position and -Z axis are used, so scale and shear
+    /// are ignored. Cameras looking along world_up, like top down views, use
+    /// the +Y axis of the node as up instead. aspect_ratio is the width over
+    /// the height of the viewport, used when the camera doesn't have one. The
+    /// speeds are 1.
+    pub fn to_camera(&self, transform: &Mat4, world_up: Vec3, aspect_ratio: f32) -> Option<Camera> {
+        let Projection::Perspective { yfov, aspect_ratio: a, near, far } = self.projection else {
+            return None;
+        };
+        let aspect_ratio = a.unwrap_or(aspect_ratio);
+        let position = transform.transform_point(Vec3::from_scalar(0.));
+        let forward = transform.transform_vector(Vec3::new(0., 0., -1.)).normalized();
+        let orthogonal = |up: Vec3| up.normalized().cross(forward).length() > 1e-4;
+        let world_up = if orthogonal(world_up) {
+            world_up
+        } else {
+            transform.transform_vector(Vec3::new(0., 1., 0.))
+        };
+        if !forward.x.is_finite() || !orthogonal(world_up) {
+            return None;
+        }
+        // Camera::fov is horizontal and its aspect ratio is height over width
+        let fov = 2. * ((yfov * 0.5).tan() * aspect_ratio).atan();
+        Some(Camera::new(position, position + forward, world_up,
+                         fov, 1. / aspect_ratio, near, far, 1., 1.))
+    }
+
+    /// Pinhole camera with the same view and projection, and the world
+    /// transform of a node placing it.
+    pub fn from_camera(name: &str, camera: &Camera) -> (CameraDesc, Mat4) {
+        let column = |v: Vec3, w: f32| Vec4::new(v.x, v.y, v.z, w);
+        let x = camera.forward.cross(camera.up).normalized();
+        let m = Mat4::from_columns(&[
+            column(x, 0.),
+            column(camera.up, 0.),
+            column(-camera.forward, 0.),
+            column(camera.position, 1.),
+        ]);
+        let desc = CameraDesc {
+            name: name.to_string(),
+            projection: Projection::Perspective {
+                yfov: 2. * ((camera.fov * 0.5).tan() * camera.aspect_ratio).atan(),
+                aspect_ratio: Some(1. / camera.aspect_ratio),
+                near: camera.near,
+                far: camera.far,
+            },
+            lens: None,
+        };
+        (desc, m)
+    }
+}
+
+/// Projections are a u32 kind followed by 4 floats, a missing aspect ratio
+/// is 0 and a pinhole camera has a lens with an aperture of 0.
+impl Serialize for CameraDesc {
+    fn serialize_buf(&self, buf: &mut Vec<u8>) {
+        self.name.serialize_buf(buf);
+        let (kind, values) = match self.projection {
+            Projection::Perspective { yfov, aspect_ratio, near, far } =>
+                (0u32, [yfov, aspect_ratio.unwrap_or(0.), near, far]),
+            Projection::Orthographic { xmag, ymag, near, far } =>
+                (1u32, [xmag, ymag, near, far]),
+        };
+        (&kind).serialize_buf(buf);
+        (&values).serialize_buf(buf);
+        let lens = self.lens.map_or([0.; 2], |l| [l.aperture_radius, l.focus_distance]);
+        (&lens).serialize_buf(buf);
+    }
+}
+
+impl Deserialize for CameraDesc {
+    type Item = CameraDesc;
+    type AllocatorItem = CameraDesc;
+
+    fn deserialize(buf: &mut &[u8]) -> Result<CameraDesc, DeserializeError> {
+        with_offset(buf, |buf| {
+            let name = String::deserialize(buf)?;
+            let kind = <&u32>::deserialize(buf)?;
+            let [a, b, near, far] = <&[f32; 4]>::deserialize(buf)?;
+            let projection = match kind {
+                0 => Projection::Perspective { yfov: a, aspect_ratio: (b != 0.).then_some(b), near, far },
+                1 => Projection::Orthographic { xmag: a, ymag: b, near, far },
+                value => return Err(DeserializeError::UnknownProjection { value }),
+            };
+            let [aperture_radius, focus_distance] = <&[f32; 2]>::deserialize(buf)?;
+            Ok(CameraDesc {
+                name,
+                projection,
+                lens: (aperture_radius != 0.).then_some(ThinLens { aperture_radius, focus_distance }),
+            })
+        })
+    }
+
+    fn deserialize_in(buf: &mut &[u8], _a: std::alloc::Global) -> Result<CameraDesc, DeserializeError> {
+        Self::deserialize(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn film_distance() {
+        let mut camera = Camera::new(Vec3::new(0., 0., 0.), Vec3::new(1., 0., 0.), Vec3::new(0., 0., 1.),
+                                     0., 0.5, 0.1, 100., 1., 1.);
+        camera.set_film_distance(0.5);
+        math::assert_approx_eq!(camera.fov, PI / 2.);
+        math::assert_approx_eq!(camera.film_distance(), 0.5);
+    }
+
+    #[test]
+    fn conversion() {
+        let camera = Camera::new(Vec3::new(1., 2., 3.), Vec3::new(2., 3., 3.), Vec3::new(0., 0., 1.),
+                                 1.2, 0.5, 0.1, 100., 1., 1.);
+        let (desc, transform) = CameraDesc::from_camera("view", &camera);
+        let Projection::Perspective { yfov, aspect_ratio, .. } = desc.projection else { panic!() };
+        math::assert_approx_eq!((yfov * 0.5).tan(), (0.6f32).tan() * 0.5);
+        assert_eq!(aspect_ratio, Some(2.));
+
+        // The viewport aspect ratio is ignored when the camera has one
+        let c = desc.to_camera(&transform, Vec3::new(0., 0., 1.), 1.).unwrap();
+        math::assert_approx_eq!(c.position, camera.position);
+        math::assert_approx_eq!(c.forward, camera.forward, epsilon = 1e-5);
+        math::assert_approx_eq!(c.up, camera.up, epsilon = 1e-5);
+        math::assert_approx_eq!(c.fov, camera.fov, epsilon = 1e-5);
+        math::assert_approx_eq!(c.aspect_ratio, camera.aspect_ratio);
+        math::assert_approx_eq!(c.view(), camera.view(), epsilon = 1e-5);
+        math::assert_approx_eq!(c.projection(), camera.projection(), epsilon = 1e-5);
+
+        // Scale and shear of the node that keep -Z don't change the view
+        let sheared = transform * Mat4::from_columns(&[
+            Vec4::new(2., 0., 0., 0.),
+            Vec4::new(1., 0.5, 0., 0.),
+            Vec4::new(0., 0., 3., 0.),
+            Vec4::new(0., 0., 0., 1.),
+        ]);
+        let c = desc.to_camera(&sheared, Vec3::new(0., 0., 1.), 1.).unwrap();
+        math::assert_approx_eq!(c.view(), camera.view(), epsilon = 1e-5);
+
+        let ortho = CameraDesc {
+            projection: Projection::Orthographic { xmag: 1., ymag: 1., near: 0., far: 1. },
+            ..desc.clone()
+        };
+        assert!(ortho.to_camera(&transform, Vec3::new(0., 0., 1.), 1.).is_none());
+
+        // Top down view, up is +Y of the node
+        let c = desc.to_camera(&Mat4::translation(Vec3::new(0., 0., 10.)), Vec3::new(0., 0., 1.), 1.).unwrap();
+        assert_eq!(c.forward.to_slice(), [0., 0., -1.]);
+        assert_eq!((c.up.to_slice(), c.world_up.to_slice()), ([0., 1., 0.], [0., 1., 0.]));
+        assert!(c.view().e.iter().flatten().all(|x| x.is_finite()));
+
+        // Node +Y along -Z too
+        let degenerate = Mat4::from_columns(&[
+            Vec4::new(1., 0., 0., 0.),
+            Vec4::new(0., 0., 1., 0.),
+            Vec4::new(0., 0., 1., 0.),
+            Vec4::new(0., 0., 0., 1.),
+        ]);
+        assert!(desc.to_camera(&degenerate, Vec3::new(0., 0., 1.), 1.).is_none());
+        assert!(desc.to_camera(&Mat4::new(), Vec3::new(0., 0., 1.), 1.).is_none());
+    }
+
+    #[test]
+    fn infinite_projection() {
+        let mut camera = Camera::new(Vec3::new(0., 0., 0.), Vec3::new(1., 0., 0.), Vec3::new(0., 0., 1.),
+                                     1.2, 0.5, 0.1, f32::INFINITY, 1., 1.);
+        let m = camera.projection();
+        assert!(m.e.iter().flatten().all(|x| x.is_finite()));
+
+        let depth = |z: f32| {
+            let p = m * Vec4::new(0., 0., z, 1.);
+            p.z / p.w
+        };
+        math::assert_approx_eq!(depth(0.1), 0.);
+        math::assert_approx_eq!(depth(1e6), 1., epsilon = 1e-6);
+
+        // Same as a far plane far away
+        camera.far = 1e9;
+        let finite = camera.projection();
+        math::assert_approx_eq!(m, finite, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn serialize() {
+        let cameras = [
+            CameraDesc {
+                name: "a".to_string(),
+                projection: Projection::Perspective { yfov: 0.8, aspect_ratio: None, near: 0.1, far: f32::INFINITY },
+                lens: Some(ThinLens { aperture_radius: 0.01, focus_distance: 5. }),
+            },
+            CameraDesc {
+                name: "b".to_string(),
+                projection: Projection::Orthographic { xmag: 2., ymag: 1., near: 0.5, far: 10. },
+                lens: None,
+            },
+        ];
+        let mut buf = Vec::new();
+        for c in &cameras {
+            c.serialize_buf(&mut buf);
+        }
+
+        let mut view = &buf[..];
+        for c in &cameras {
+            let d = CameraDesc::deserialize(&mut view).unwrap();
+            assert_eq!(format!("{d:?}"), format!("{c:?}"));
+        }
+        assert!(view.is_empty());
+
+        // Kind after the name
+        let mut b = cameras[1].serialize();
+        b[9..13].copy_from_slice(&2u32.to_le_bytes());
+        assert_eq!(CameraDesc::deserialize(&mut &b[..]).unwrap_err(),
+                   DeserializeError::UnknownProjection { value: 2 });
+    }
+}
@@ -0,0 +1,344 @@
+//! Scene asset container: a header with a magic number and the format
+//! version, a table of sections and the section payloads, each payload
+//! starting at a SECTION_ALIGNMENT aligned offset with a CRC32 in the table.
+//!
+//! Compatibility rules:
+//! - Any change to the layout of an existing section bumps VERSION. Files
+//!   outside MIN_VERSION..=VERSION are rejected with UnsupportedVersion and
+//!   must be rebuilt with asset_builder.
+//! - New section kinds can be added without a version bump, readers skip
+//!   the kinds they don't know.
+//! - Missing sections read as empty.
+//!
+//! Versions:
+//! 1. Initial version.
+//! 2. Vector data is aligned to SECTION_ALIGNMENT from the start of the
+//!    container.
+//! 3. Meshes are lists of primitives without transform, instanced by the
+//!    nodes section.
+//! 4. Metallic-roughness materials with explicit factors and textures.
+//! 5. Images have mip levels and array layers.
+//! 6. Cameras are placed by the nodes referencing them, without a
+//!    transform of their own.
+
+use bytemuck::{bytes_of, Pod, Zeroable, pod_read_unaligned};
+
+use crate::DeserializeError;
+
+pub const MAGIC: [u8; 8] = *b"GRAYSCN\0";
+pub const VERSION: u32 = 6;
+pub const MIN_VERSION: u32 = 6;
+pub const SECTION_ALIGNMENT: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum SectionKind {
+    Meshes    = 0,
+    Images    = 1,
+    Materials = 2,
+    Lights    = 3,
+    Cameras   = 4,
+    Nodes     = 5,
+}
+
+impl TryFrom<u32> for SectionKind {
+    type Error = &'static str;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(SectionKind::Meshes),
+            1 => Ok(SectionKind::Images),
+            2 => Ok(SectionKind::Materials),
+            3 => Ok(SectionKind::Lights),
+            4 => Ok(SectionKind::Cameras),
+            5 => Ok(SectionKind::Nodes),
+            _ => Err("Unknown section kind"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct Header {
+    pub magic: [u8; 8],
+    pub version: u32,
+    pub section_count: u32,
+}
+
+impl Header {
+    /// Checks the magic and the version at the start of a container.
+    pub fn parse(data: &[u8]) -> Result<Header, ContainerError> {
+        if data.len() < MAGIC.len() || data[..MAGIC.len()] != MAGIC {
+            return Err(ContainerError::BadMagic);
+        }
+        if data.len() < core::mem::size_of::<Header>() {
+            return Err(ContainerError::Truncated);
+        }
+
+        let header: Header = pod_read_unaligned(&data[..core::mem::size_of::<Header>()]);
+        if header.version < MIN_VERSION || header.version > VERSION {
+            return Err(ContainerError::UnsupportedVersion { version: header.version });
+        }
+        Ok(header)
+    }
+
+    /// Size of the section table following the header.
+    pub fn table_size(&self) -> Option<usize> {
+        (self.section_count as usize).checked_mul(core::mem::size_of::<SectionHeader>())
+    }
+}
+
+/// Offset is from the start of the container.
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct SectionHeader {
+    pub kind: u32,
+    pub checksum: u32,
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl SectionHeader {
+    pub fn check(&self, payload: &[u8]) -> Result<(), ContainerError> {
+        if crc32fast::hash(payload) != self.checksum {
+            return Err(ContainerError::ChecksumMismatch { kind: self.kind });
+        }
+        Ok(())
+    }
+}
+
+/// Reads a section table, the sections themselves are checked by the caller.
+pub fn parse_table(table: &[u8]) -> Result<Vec<SectionHeader>, ContainerError> {
+    let sections: Vec<SectionHeader> = table
+        .chunks_exact(core::mem::size_of::<SectionHeader>())
+        .map(pod_read_unaligned)
+        .collect();
+    for (i, s) in sections.iter().enumerate() {
+        if sections[..i].iter().any(|p| p.kind == s.kind) {
+            return Err(ContainerError::DuplicateSection { kind: s.kind });
+        }
+    }
+    Ok(sections)
+}
+
+/// Error of a section at offset in the container, the offset of
+/// UnexpectedEof is made relative to the start of the container.
+pub fn section_error(kind: SectionKind, offset: u64, error: DeserializeError) -> ContainerError {
+    let error = match error {
+        DeserializeError::UnexpectedEof { offset: o } =>
+            DeserializeError::UnexpectedEof { offset: o + offset as usize },
+        e => e,
+    };
+    ContainerError::Section { kind, error }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerError {
+    BadMagic,
+    UnsupportedVersion { version: u32 },
+    /// The header, the section table or a section is past the end of the data.
+    Truncated,
+    DuplicateSection { kind: u32 },
+    /// Section starting before the end of the previous one, only rejected
+    /// by readers that stream the container.
+    Overlap { kind: u32 },
+    ChecksumMismatch { kind: u32 },
+    /// The payload of a section is malformed.
+    Section { kind: SectionKind, error: DeserializeError },
+}
+
+impl std::fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContainerError::BadMagic =>
+                write!(f, "not a scene asset file, or written by an asset_builder older than the container format"),
+            ContainerError::UnsupportedVersion { version } if *version < MIN_VERSION =>
+                write!(f, "asset file version {version} is no longer supported (oldest supported is {MIN_VERSION}), rebuild it with asset_builder"),
+            ContainerError::UnsupportedVersion { version } =>
+                write!(f, "asset file version {version} is newer than the latest supported version {VERSION}"),
+            ContainerError::Truncated =>
+                write!(f, "asset file is truncated"),
+            ContainerError::DuplicateSection { kind } =>
+                write!(f, "section {kind} appears more than once"),
+            ContainerError::Overlap { kind } =>
+                write!(f, "section {kind} overlaps the previous section"),
+            ContainerError::ChecksumMismatch { kind } =>
+                write!(f, "checksum mismatch in section {kind}"),
+            ContainerError::Section { kind, error } =>
+                write!(f, "invalid {kind:?} section: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ContainerError::Section { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Appends the payload of a section to the buffer.
+pub type SectionWriter<'a> = &'a dyn Fn(&mut Vec<u8>);
+
+/// Appends a container to buf.
+pub fn write_container(buf: &mut Vec<u8>, sections: &[(SectionKind, SectionWriter)]) {
+    let start = buf.len();
+    // Vectors are aligned from the start of buf
+    assert!(start.is_multiple_of(SECTION_ALIGNMENT));
+    let header = Header {
+        magic: MAGIC,
+        version: VERSION,
+        section_count: sections.len() as u32,
+    };
+    buf.extend_from_slice(bytes_of(&header));
+
+    let table = buf.len();
+    buf.resize(table + sections.len() * core::mem::size_of::<SectionHeader>(), 0);
+
+    for (i, (kind, write)) in sections.iter().enumerate() {
+        let padding = (SECTION_ALIGNMENT - (buf.len() - start) % SECTION_ALIGNMENT) % SECTION_ALIGNMENT;
+        buf.resize(buf.len() + padding, 0);
+
+        let offset = buf.len();
+        write(buf);
+
+        let section = SectionHeader {
+            kind: *kind as u32,
+            checksum: crc32fast::hash(&buf[offset..]),
+            offset: (offset - start) as u64,
+            size: (buf.len() - offset) as u64,
+        };
+        let at = table + i * core::mem::size_of::<SectionHeader>();
+        buf[at..at + core::mem::size_of::<SectionHeader>()].copy_from_slice(bytes_of(&section));
+    }
+}
+
+/// Validated view of a container, every section is in bounds and matches
+/// its checksum.
+#[derive(Debug)]
+pub struct Container<'a> {
+    pub version: u32,
+    pub sections: Vec<SectionHeader>,
+    data: &'a [u8],
+}
+
+impl<'a> Container<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Container<'a>, ContainerError> {
+        let header_size = core::mem::size_of::<Header>();
+        let header = Header::parse(data)?;
+        let table_end = header.table_size()
+            .and_then(|s| s.checked_add(header_size))
+            .filter(|end| *end <= data.len())
+            .ok_or(ContainerError::Truncated)?;
+
+        let sections = parse_table(&data[header_size..table_end])?;
+        for s in &sections {
+            let end = s.offset.checked_add(s.size).ok_or(ContainerError::Truncated)?;
+            if end > data.len() as u64 {
+                return Err(ContainerError::Truncated);
+            }
+            s.check(&data[s.offset as usize..end as usize])?;
+        }
+
+        Ok(Container {
+            version: header.version,
+            sections,
+            data,
+        })
+    }
+
+    /// Payload of a section, None if it is not in the file.
+    pub fn section(&self, kind: SectionKind) -> Option<&'a [u8]> {
+        self.sections.iter()
+            .find(|s| s.kind == kind as u32)
+            .map(|s| &self.data[s.offset as usize..(s.offset + s.size) as usize])
+    }
+
+    /// Reads a section with f, offsets in the errors of f are made relative
+    /// to the start of the container.
+    pub fn read_section<T>(&self, kind: SectionKind,
+        f: impl FnOnce(Option<&'a [u8]>) -> Result<T, DeserializeError>) -> Result<T, ContainerError> {
+        f(self.section(kind)).map_err(|error| {
+            let start = self.sections.iter().find(|s| s.kind == kind as u32).map_or(0, |s| s.offset);
+            section_error(kind, start, error)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_test_container() -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_container(&mut buf, &[
+            (SectionKind::Meshes, &|b: &mut Vec<u8>| b.extend_from_slice(b"meshes")),
+            (SectionKind::Images, &|_: &mut Vec<u8>| {}),
+            (SectionKind::Lights, &|b: &mut Vec<u8>| b.extend_from_slice(&[7; 33])),
+        ]);
+        buf
+    }
+
+    #[test]
+    fn round_trip() {
+        let buf = write_test_container();
+        let c = Container::parse(&buf).unwrap();
+        assert_eq!(c.version, VERSION);
+        assert_eq!(c.section(SectionKind::Meshes), Some(&b"meshes"[..]));
+        assert_eq!(c.section(SectionKind::Images), Some(&[][..]));
+        assert_eq!(c.section(SectionKind::Lights), Some(&[7; 33][..]));
+        assert_eq!(c.section(SectionKind::Cameras), None);
+        assert!(c.sections.iter().all(|s| (s.offset as usize).is_multiple_of(SECTION_ALIGNMENT)));
+    }
+
+    #[test]
+    fn errors() {
+        let buf = write_test_container();
+
+        assert_eq!(Container::parse(b"").unwrap_err(), ContainerError::BadMagic);
+        // Files written before the container have no magic
+        assert_eq!(Container::parse(&[1, 0, 0, 0, 0, 0, 0, 0, 5, 0]).unwrap_err(),
+                   ContainerError::BadMagic);
+
+        for version in [0, VERSION + 1] {
+            let mut b = buf.clone();
+            b[8..12].copy_from_slice(&version.to_le_bytes());
+            let err = Container::parse(&b).unwrap_err();
+            assert_eq!(err, ContainerError::UnsupportedVersion { version });
+            assert!(err.to_string().contains(&version.to_string()));
+        }
+
+        for len in [12, 20, buf.len() - 1] {
+            assert_eq!(Container::parse(&buf[..len]).unwrap_err(), ContainerError::Truncated);
+        }
+
+        let mut b = buf.clone();
+        *b.last_mut().unwrap() ^= 1;
+        assert_eq!(Container::parse(&b).unwrap_err(),
+                   ContainerError::ChecksumMismatch { kind: SectionKind::Lights as u32 });
+
+        // Second section claims to be meshes too
+        let mut b = buf.clone();
+        let at = core::mem::size_of::<Header>() + core::mem::size_of::<SectionHeader>();
+        b[at..at + 4].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(Container::parse(&b).unwrap_err(), ContainerError::DuplicateSection { kind: 0 });
+
+        // Huge section count
+        let mut b = buf;
+        b[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(Container::parse(&b).unwrap_err(), ContainerError::Truncated);
+    }
+
+    #[test]
+    fn unknown_sections_are_skipped() {
+        let mut buf = write_test_container();
+        let at = core::mem::size_of::<Header>() + 2 * core::mem::size_of::<SectionHeader>();
+        buf[at..at + 4].copy_from_slice(&1000u32.to_le_bytes());
+
+        let c = Container::parse(&buf).unwrap();
+        assert_eq!(c.section(SectionKind::Lights), None);
+        assert_eq!(c.section(SectionKind::Meshes), Some(&b"meshes"[..]));
+    }
+}
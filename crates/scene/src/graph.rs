@@ -0,0 +1,356 @@
+//! Scene graph. Nodes are stored with parents before their children so world
+//! transforms can be computed in a single pass, Scene::add_node keeps this
+//! order and from_container rejects files that don't follow it.
+
+use std::alloc::Allocator;
+
+use math::{mat::Mat4, transform::Transform};
+
+use crate::{
+    deserialize_index, serialize_index, with_offset, Deserialize, DeserializeError, Scene, Serialize,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Node {
+    pub name: String,
+    pub parent: Option<u32>,
+    pub children: Vec<u32>,
+    /// Relative to the parent.
+    pub transform: Transform,
+    /// Index in Scene::meshes
+    pub mesh: Option<u32>,
+    /// Index in Scene::lights
+    pub light: Option<u32>,
+    /// Index in Scene::cameras
+    pub camera: Option<u32>,
+}
+
+/// Mesh placed in the world by a node.
+#[derive(Debug, Clone, Copy)]
+pub struct MeshInstance {
+    pub node: u32,
+    pub mesh: u32,
+    pub transform: Mat4,
+}
+
+/// Light placed in the world by a node.
+#[derive(Debug, Clone, Copy)]
+pub struct LightInstance {
+    pub node: u32,
+    pub light: u32,
+    pub transform: Mat4,
+}
+
+/// Camera placed in the world by a node.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraInstance {
+    pub node: u32,
+    pub camera: u32,
+    pub transform: Mat4,
+}
+
+/// World transform of each node.
+pub fn world_transforms(nodes: &[Node]) -> Vec<Mat4> {
+    let mut world: Vec<Mat4> = Vec::with_capacity(nodes.len());
+    for n in nodes {
+        let local = n.transform.to_mat4();
+        world.push(match n.parent {
+            Some(p) => world[p as usize] * local,
+            None => local,
+        });
+    }
+    world
+}
+
+/// Instances of the meshes in the world, in node order.
+pub fn flatten(nodes: &[Node]) -> Vec<MeshInstance> {
+    world_transforms(nodes).into_iter().zip(nodes).enumerate()
+        .filter_map(|(i, (transform, n))| n.mesh.map(|mesh| MeshInstance {
+            node: i as u32,
+            mesh,
+            transform,
+        }))
+        .collect()
+}
+
+/// Instances of the lights in the world, in node order.
+pub fn light_instances(nodes: &[Node]) -> Vec<LightInstance> {
+    world_transforms(nodes).into_iter().zip(nodes).enumerate()
+        .filter_map(|(i, (transform, n))| n.light.map(|light| LightInstance {
+            node: i as u32,
+            light,
+            transform,
+        }))
+        .collect()
+}
+
+/// Instances of the cameras in the world, in node order.
+pub fn camera_instances(nodes: &[Node]) -> Vec<CameraInstance> {
+    world_transforms(nodes).into_iter().zip(nodes).enumerate()
+        .filter_map(|(i, (transform, n))| n.camera.map(|camera| CameraInstance {
+            node: i as u32,
+            camera,
+            transform,
+        }))
+        .collect()
+}
+
+/// Inserts root as the first node and parent of the previous roots, the
+/// other nodes move by one.
+pub fn insert_root<A: Allocator>(nodes: &mut Vec<Node, A>, mut root: Node) {
+    root.parent = None;
+    root.children = Vec::new();
+    for (i, n) in nodes.iter_mut().enumerate() {
+        n.children.iter_mut().for_each(|c| *c += 1);
+        n.parent = match n.parent {
+            Some(p) => Some(p + 1),
+            None => {
+                root.children.push(i as u32 + 1);
+                Some(0)
+            },
+        };
+    }
+    nodes.insert(0, root);
+}
+
+/// Checks the order of the nodes, the links between parents and children
+/// and the mesh, light and camera indices.
+pub(crate) fn validate(
+    nodes: &[Node], mesh_count: usize, light_count: usize, camera_count: usize,
+) -> Result<(), DeserializeError> {
+    for (i, n) in nodes.iter().enumerate() {
+        let error = DeserializeError::InvalidNode { index: i as u32 };
+        if let Some(p) = n.parent {
+            if p as usize >= i || !nodes[p as usize].children.contains(&(i as u32)) {
+                return Err(error);
+            }
+        }
+        for &c in &n.children {
+            if c as usize <= i || nodes.get(c as usize).map(|c| c.parent) != Some(Some(i as u32)) {
+                return Err(error);
+            }
+        }
+        if n.mesh.is_some_and(|m| m as usize >= mesh_count)
+            || n.light.is_some_and(|l| l as usize >= light_count)
+            || n.camera.is_some_and(|c| c as usize >= camera_count) {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+impl<A: Allocator + Copy> Scene<A> {
+    /// Appends a node and links it to its parent, which must already be in
+    /// the scene.
+    pub fn add_node(&mut self, node: Node) -> u32 {
+        let index = self.nodes.len() as u32;
+        if let Some(p) = node.parent {
+            self.nodes[p as usize].children.push(index);
+        }
+        self.nodes.push(node);
+        index
+    }
+
+    /// See insert_root.
+    pub fn insert_root(&mut self, root: Node) {
+        insert_root(&mut self.nodes, root)
+    }
+
+    pub fn world_transforms(&self) -> Vec<Mat4> {
+        world_transforms(&self.nodes)
+    }
+
+    pub fn flatten(&self) -> Vec<MeshInstance> {
+        flatten(&self.nodes)
+    }
+
+    pub fn light_instances(&self) -> Vec<LightInstance> {
+        light_instances(&self.nodes)
+    }
+
+    pub fn camera_instances(&self) -> Vec<CameraInstance> {
+        camera_instances(&self.nodes)
+    }
+}
+
+impl Serialize for Node {
+    fn serialize_buf(&self, buf: &mut Vec<u8>) {
+        self.name.serialize_buf(buf);
+        serialize_index(self.parent, buf);
+        self.children.serialize_buf(buf);
+        (&self.transform).serialize_buf(buf);
+        serialize_index(self.mesh, buf);
+        serialize_index(self.light, buf);
+        serialize_index(self.camera, buf);
+    }
+}
+
+impl Deserialize for Node {
+    type Item = Node;
+    type AllocatorItem = Node;
+
+    fn deserialize(buf: &mut &[u8]) -> Result<Node, DeserializeError> {
+        with_offset(buf, |buf| Ok(Node {
+            name: String::deserialize(buf)?,
+            parent: deserialize_index(buf)?,
+            children: Vec::<u32>::deserialize(buf)?,
+            transform: <&Transform>::deserialize(buf)?,
+            mesh: deserialize_index(buf)?,
+            light: deserialize_index(buf)?,
+            camera: deserialize_index(buf)?,
+        }))
+    }
+
+    fn deserialize_in(buf: &mut &[u8], _a: std::alloc::Global) -> Result<Node, DeserializeError> {
+        Self::deserialize(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::{vec::{Vec3, Vec4}, quat::Quat};
+
+    fn translation(x: f32, y: f32, z: f32) -> Transform {
+        Transform::new(Vec3::new(x, y, z), Quat::identity(), Vec3::from_scalar(1.0))
+    }
+
+    /// Root with two children sharing mesh 0, the second one with a child
+    /// using mesh 1.
+    fn test_scene() -> Scene {
+        let mut scene = Scene::new();
+        let root = scene.add_node(Node {
+            name: "root".to_string(),
+            transform: Transform::new(Vec3::from_scalar(0.0),
+                                      Quat::rotate(Vec3::new(0., 0., 1.), std::f32::consts::FRAC_PI_2),
+                                      Vec3::from_scalar(2.0)),
+            ..Default::default()
+        });
+        scene.add_node(Node {
+            name: "a".to_string(),
+            parent: Some(root),
+            transform: translation(1., 0., 0.),
+            mesh: Some(0),
+            light: Some(0),
+            ..Default::default()
+        });
+        let b = scene.add_node(Node {
+            name: "b".to_string(),
+            parent: Some(root),
+            transform: translation(0., 1., 0.),
+            mesh: Some(0),
+            ..Default::default()
+        });
+        scene.add_node(Node {
+            name: "c".to_string(),
+            parent: Some(b),
+            transform: translation(0., 0., 1.),
+            mesh: Some(1),
+            camera: Some(0),
+            ..Default::default()
+        });
+        scene.meshes.push(crate::Mesh { primitives: Vec::new() });
+        scene.meshes.push(crate::Mesh { primitives: Vec::new() });
+        scene.lights.push(crate::Light::Directional {
+            color: Vec3::from_scalar(1.0),
+            intensity: 1.0,
+            angular_radius: 0.0,
+        });
+        scene
+    }
+
+    #[test]
+    fn world_transforms() {
+        let scene = test_scene();
+        assert_eq!(scene.nodes[0].children, [1, 2]);
+        assert_eq!(scene.nodes[2].children, [3]);
+
+        let world = scene.world_transforms();
+        let origin = |i: usize| world[i].transform_point(Vec3::from_scalar(0.0));
+        math::assert_approx_eq!(origin(1), Vec3::new(0., 2., 0.), epsilon = 1e-5);
+        math::assert_approx_eq!(origin(2), Vec3::new(-2., 0., 0.), epsilon = 1e-5);
+        math::assert_approx_eq!(origin(3), Vec3::new(-2., 0., 2.), epsilon = 1e-5);
+
+        let instances = scene.flatten();
+        assert_eq!(instances.iter().map(|i| (i.node, i.mesh)).collect::<Vec<_>>(),
+                   [(1, 0), (2, 0), (3, 1)]);
+        math::assert_approx_eq!(instances[2].transform, world[3]);
+
+        let lights = scene.light_instances();
+        assert_eq!(lights.iter().map(|i| (i.node, i.light)).collect::<Vec<_>>(), [(1, 0)]);
+        math::assert_approx_eq!(lights[0].transform, world[1]);
+    }
+
+    #[test]
+    fn insert_root() {
+        let mut scene = test_scene();
+        let world = scene.world_transforms();
+        let to_z_up = Mat4::from_columns(&[
+            Vec4::new(1., 0., 0., 0.),
+            Vec4::new(0., 0., 1., 0.),
+            Vec4::new(0., 1., 0., 0.),
+            Vec4::new(0., 0., 0., 1.),
+        ]);
+        scene.add_node(Node { transform: Transform::new(Vec3::from_scalar(0.0), Quat::identity(), Vec3::from_scalar(0.0)),
+                              ..Default::default() });
+        scene.insert_root(Node { transform: to_z_up.decompose().unwrap(), ..Default::default() });
+
+        assert_eq!(super::validate(&scene.nodes, 2, 1, 1), Ok(()));
+        assert_eq!(scene.nodes[0].children, [1, 5]);
+        assert_eq!(scene.nodes[1].children, [2, 3]);
+        let moved = scene.world_transforms();
+        for (i, w) in world.iter().enumerate() {
+            math::assert_approx_eq!(moved[i + 1], to_z_up * *w, epsilon = 1e-5);
+        }
+        assert_eq!(scene.flatten().iter().map(|i| i.node).collect::<Vec<_>>(), [2, 3, 4]);
+    }
+
+    #[test]
+    fn validate() {
+        let scene = test_scene();
+        assert_eq!(super::validate(&scene.nodes, 2, 1, 1), Ok(()));
+        assert_eq!(super::validate(&scene.nodes, 1, 1, 1), Err(DeserializeError::InvalidNode { index: 3 }));
+        assert_eq!(super::validate(&scene.nodes, 2, 0, 1), Err(DeserializeError::InvalidNode { index: 1 }));
+        assert_eq!(super::validate(&scene.nodes, 2, 1, 0), Err(DeserializeError::InvalidNode { index: 3 }));
+
+        // Child before its parent
+        let mut nodes = scene.nodes.clone();
+        nodes.swap(2, 3);
+        assert!(super::validate(&nodes, 2, 1, 1).is_err());
+
+        // Parent not listing the child
+        let mut nodes = scene.nodes.clone();
+        nodes[0].children.pop();
+        assert_eq!(super::validate(&nodes, 2, 1, 1), Err(DeserializeError::InvalidNode { index: 2 }));
+
+        // Cycle
+        let mut nodes = scene.nodes.clone();
+        nodes[0].parent = Some(3);
+        nodes[3].children.push(0);
+        assert!(super::validate(&nodes, 2, 1, 1).is_err());
+    }
+
+    #[test]
+    fn serialize() {
+        let scene = test_scene();
+        let mut buf = Vec::new();
+        for n in &scene.nodes {
+            n.serialize_buf(&mut buf);
+        }
+
+        let mut view = &buf[..];
+        for n in &scene.nodes {
+            let d = Node::deserialize(&mut view).unwrap();
+            assert_eq!(d.name, n.name);
+            assert_eq!(d.parent, n.parent);
+            assert_eq!(d.children, n.children);
+            assert_eq!((d.mesh, d.light, d.camera), (n.mesh, n.light, n.camera));
+            math::assert_approx_eq!(d.transform, n.transform);
+        }
+        assert!(view.is_empty());
+
+        let mut b = buf.clone();
+        b[8] = 0xff;
+        assert_eq!(Node::deserialize(&mut &b[..]).unwrap_err(), DeserializeError::InvalidUtf8);
+    }
+}
@@ -0,0 +1,359 @@
+//! Images with mip chains and array layers. The data holds the
+//! subresources tightly packed in D3D12 order: the levels of layer 0 from
+//! the largest, then the levels of layer 1... Rows of block compressed
+//! formats are rows of 4x4 blocks.
+
+use std::alloc::{Allocator, Global};
+use std::ops::Range;
+
+use crate::{with_offset, Deserialize, DeserializeError, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub enum Format {
+    RGBA8,
+    SRGBA8,
+    R8,
+    RG8,
+    RGBA16F,
+    RGBA32F,
+    /// RGB with 1 bit alpha.
+    BC1,
+    BC1SRGB,
+    /// RGB with explicit 4 bit alpha.
+    BC2,
+    BC2SRGB,
+    /// RGB with interpolated alpha.
+    BC3,
+    BC3SRGB,
+    /// Single channel.
+    BC4,
+    /// Two channels, for normal maps.
+    BC5,
+    /// Unsigned half float RGB.
+    BC6H,
+    BC7,
+    BC7SRGB,
+}
+
+impl Format {
+    pub const ALL: [Format; 17] = [
+        Format::RGBA8, Format::SRGBA8, Format::R8, Format::RG8, Format::RGBA16F, Format::RGBA32F,
+        Format::BC1, Format::BC1SRGB, Format::BC2, Format::BC2SRGB, Format::BC3, Format::BC3SRGB,
+        Format::BC4, Format::BC5, Format::BC6H, Format::BC7, Format::BC7SRGB,
+    ];
+
+    /// Width and height of a block in pixels, 1 for uncompressed formats.
+    pub fn block_size(self) -> u32 {
+        if self.is_compressed() { 4 } else { 1 }
+    }
+
+    /// Bytes per block, or per pixel for uncompressed formats.
+    pub fn block_bytes(self) -> u32 {
+        match self {
+            Format::R8 => 1,
+            Format::RG8 => 2,
+            Format::RGBA8 | Format::SRGBA8 => 4,
+            Format::RGBA16F => 8,
+            Format::RGBA32F => 16,
+            Format::BC1 | Format::BC1SRGB | Format::BC4 => 8,
+            Format::BC2 | Format::BC2SRGB | Format::BC3 | Format::BC3SRGB
+                | Format::BC5 | Format::BC6H | Format::BC7 | Format::BC7SRGB => 16,
+        }
+    }
+
+    pub fn is_compressed(self) -> bool {
+        !matches!(self, Format::RGBA8 | Format::SRGBA8 | Format::R8 | Format::RG8
+                      | Format::RGBA16F | Format::RGBA32F)
+    }
+
+    /// Color channels are sRGB encoded, alpha is always linear.
+    pub fn is_srgb(self) -> bool {
+        matches!(self, Format::SRGBA8 | Format::BC1SRGB | Format::BC2SRGB | Format::BC3SRGB
+                     | Format::BC7SRGB)
+    }
+
+    /// Bytes of a tightly packed row of pixels or blocks.
+    pub fn row_size(self, width: u32) -> usize {
+        width.div_ceil(self.block_size()) as usize * self.block_bytes() as usize
+    }
+
+    /// Rows of pixels or blocks.
+    pub fn row_count(self, height: u32) -> u32 {
+        height.div_ceil(self.block_size())
+    }
+
+    /// Bytes of a tightly packed level.
+    pub fn level_size(self, width: u32, height: u32) -> usize {
+        self.row_size(width) * self.row_count(height) as usize
+    }
+
+    /// Bytes of all the subresources, None on overflow.
+    pub fn image_size(self, width: u32, height: u32, mip_levels: u32, array_layers: u32) -> Option<usize> {
+        let mut layer_size = 0usize;
+        for level in 0..mip_levels {
+            let (w, h) = mip_extent(width, height, level);
+            let size = self.row_size(w).checked_mul(self.row_count(h) as usize)?;
+            layer_size = layer_size.checked_add(size)?;
+        }
+        layer_size.checked_mul(array_layers as usize)
+    }
+}
+
+impl From<Format> for u32 {
+    fn from(f: Format) -> u32 {
+        match f {
+            Format::RGBA8   => 0,
+            Format::SRGBA8  => 1,
+            Format::R8      => 2,
+            Format::RG8     => 3,
+            Format::RGBA16F => 4,
+            Format::RGBA32F => 5,
+            Format::BC1     => 6,
+            Format::BC1SRGB => 7,
+            Format::BC2     => 8,
+            Format::BC2SRGB => 9,
+            Format::BC3     => 10,
+            Format::BC3SRGB => 11,
+            Format::BC4     => 12,
+            Format::BC5     => 13,
+            Format::BC6H    => 14,
+            Format::BC7     => 15,
+            Format::BC7SRGB => 16,
+        }
+    }
+}
+
+impl TryFrom<u32> for Format {
+    type Error = &'static str;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0  => Ok(Format::RGBA8),
+            1  => Ok(Format::SRGBA8),
+            2  => Ok(Format::R8),
+            3  => Ok(Format::RG8),
+            4  => Ok(Format::RGBA16F),
+            5  => Ok(Format::RGBA32F),
+            6  => Ok(Format::BC1),
+            7  => Ok(Format::BC1SRGB),
+            8  => Ok(Format::BC2),
+            9  => Ok(Format::BC2SRGB),
+            10 => Ok(Format::BC3),
+            11 => Ok(Format::BC3SRGB),
+            12 => Ok(Format::BC4),
+            13 => Ok(Format::BC5),
+            14 => Ok(Format::BC6H),
+            15 => Ok(Format::BC7),
+            16 => Ok(Format::BC7SRGB),
+            _ => Err("Unknown format"),
+        }
+    }
+}
+
+/// Size of a level, halved down to 1 pixel.
+pub fn mip_extent(width: u32, height: u32, level: u32) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+/// Levels of a full mip chain.
+pub fn max_mip_levels(width: u32, height: u32) -> u32 {
+    32 - (width | height).leading_zeros()
+}
+
+#[derive(Debug)]
+pub struct Image<A: Allocator + Copy=Global> {
+    pub width: u32,
+    pub height: u32,
+    pub format: Format,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    /// Subresources in D3D12 order, see image.rs.
+    pub data: Vec<u8, A>,
+}
+
+impl<A: Allocator + Copy> Image<A> {
+    pub fn mip_extent(&self, level: u32) -> (u32, u32) {
+        mip_extent(self.width, self.height, level)
+    }
+
+    /// Byte range of a level of a layer in data.
+    pub fn subresource_range(&self, layer: u32, level: u32) -> Range<usize> {
+        subresource_range(self.format, self.width, self.height, self.mip_levels, layer, level)
+    }
+
+    pub fn subresource(&self, layer: u32, level: u32) -> &[u8] {
+        &self.data[self.subresource_range(layer, level)]
+    }
+}
+
+pub(crate) fn subresource_range(format: Format, width: u32, height: u32, mip_levels: u32,
+    layer: u32, level: u32) -> Range<usize> {
+    assert!(level < mip_levels);
+    let level_size = |l| {
+        let (w, h) = mip_extent(width, height, l);
+        format.level_size(w, h)
+    };
+    let layer_size: usize = (0..mip_levels).map(level_size).sum();
+    let start = layer as usize * layer_size + (0..level).map(level_size).sum::<usize>();
+    start..start + level_size(level)
+}
+
+/// Image fields before the data.
+pub(crate) struct ImageHeader {
+    pub width: u32,
+    pub height: u32,
+    pub format: Format,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+}
+
+impl ImageHeader {
+    /// Reads and validates the header, size is the expected data size.
+    pub fn deserialize(buf: &mut &[u8]) -> Result<(ImageHeader, usize), DeserializeError> {
+        with_offset(buf, |buf| {
+            let width = <&u32>::deserialize(buf)?;
+            let height = <&u32>::deserialize(buf)?;
+            let format = <&u32>::deserialize(buf)?;
+            let format = format.try_into()
+                .map_err(|_| DeserializeError::UnknownFormat { value: format })?;
+            let mip_levels = <&u32>::deserialize(buf)?;
+            let array_layers = <&u32>::deserialize(buf)?;
+
+            let valid = width > 0 && height > 0 && array_layers > 0
+                && mip_levels > 0 && mip_levels <= max_mip_levels(width, height);
+            let size = valid.then(|| Format::image_size(format, width, height, mip_levels, array_layers))
+                .flatten()
+                .ok_or(DeserializeError::InvalidImage)?;
+            Ok((ImageHeader { width, height, format, mip_levels, array_layers }, size))
+        })
+    }
+}
+
+impl Serialize for Format {
+    fn serialize_buf(&self, buf: &mut Vec<u8>) {
+        let v: u32 = (*self).into();
+        (&v).serialize_buf(buf);
+    }
+}
+
+impl<A: Allocator + Copy> Serialize for Image<A> {
+    fn serialize_buf(&self, buf: &mut Vec<u8>) {
+        (&self.width).serialize_buf(buf);
+        (&self.height).serialize_buf(buf);
+        self.format.serialize_buf(buf);
+        (&self.mip_levels).serialize_buf(buf);
+        (&self.array_layers).serialize_buf(buf);
+        self.data.serialize_buf(buf);
+    }
+}
+
+impl<A: Allocator + Copy> Deserialize<A> for Image<A> {
+    type Item = Image;
+    type AllocatorItem = Image<A>;
+
+    fn deserialize(buf: &mut &[u8]) -> Result<Image, DeserializeError> {
+        Image::<Global>::deserialize_in(buf, Global)
+    }
+
+    fn deserialize_in(buf: &mut &[u8], a: A) -> Result<Image<A>, DeserializeError> {
+        with_offset(buf, |buf| {
+            let (h, size) = ImageHeader::deserialize(buf)?;
+            let data = Vec::<u8, A>::deserialize_in(buf, a)?;
+            if data.len() != size {
+                return Err(DeserializeError::InvalidImage);
+            }
+            Ok(Image {
+                width: h.width,
+                height: h.height,
+                format: h.format,
+                mip_levels: h.mip_levels,
+                array_layers: h.array_layers,
+                data,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image(format: Format, width: u32, height: u32, mip_levels: u32, array_layers: u32) -> Image {
+        let size = format.image_size(width, height, mip_levels, array_layers).unwrap();
+        Image {
+            width,
+            height,
+            format,
+            mip_levels,
+            array_layers,
+            data: (0..size).map(|i| (i * 31 % 251) as u8).collect(),
+        }
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(Format::RGBA8.level_size(3, 5), 60);
+        assert_eq!(Format::RG8.row_size(3), 6);
+        assert_eq!(Format::RGBA32F.level_size(2, 2), 64);
+        // Partial blocks are padded
+        assert_eq!(Format::BC1.level_size(5, 4), 16);
+        assert_eq!(Format::BC7.level_size(1, 1), 16);
+        assert_eq!(Format::BC4.row_count(9), 3);
+
+        assert_eq!(max_mip_levels(1, 1), 1);
+        assert_eq!(max_mip_levels(256, 3), 9);
+        assert_eq!(mip_extent(256, 3, 8), (1, 1));
+        assert_eq!(mip_extent(5, 3, 1), (2, 1));
+
+        // 8x8, 4x4, 2x2 and 1x1 blocks of 16 bytes
+        assert_eq!(Format::BC3.image_size(8, 8, 4, 2), Some(2 * 16 * (4 + 1 + 1 + 1)));
+        assert_eq!(Format::RGBA32F.image_size(u32::MAX, u32::MAX, 1, 1), None);
+
+        let image = test_image(Format::RGBA8, 4, 2, 3, 2);
+        // Layer of 32 + 8 + 4 bytes
+        assert_eq!(image.subresource_range(0, 2), 40..44);
+        assert_eq!(image.subresource_range(1, 0), 44..76);
+        assert_eq!(image.subresource_range(1, 2), 84..88);
+        assert_eq!(image.subresource(1, 2).len(), 4);
+    }
+
+    #[test]
+    fn round_trip() {
+        for format in Format::ALL {
+            assert_eq!(Format::try_from(u32::from(format)), Ok(format));
+
+            for (width, height, mip_levels, array_layers) in [(1, 1, 1, 1), (13, 7, 4, 3)] {
+                let image = test_image(format, width, height, mip_levels, array_layers);
+                let buf = image.serialize();
+                let mut view = &buf[..];
+                let d = Image::<Global>::deserialize(&mut view).unwrap();
+                assert!(view.is_empty());
+                assert_eq!(d.format, format);
+                assert_eq!((d.width, d.height, d.mip_levels, d.array_layers),
+                           (width, height, mip_levels, array_layers));
+                assert_eq!(d.data, image.data);
+                assert_eq!(d.subresource(array_layers - 1, mip_levels - 1).len(),
+                           format.block_bytes() as usize);
+            }
+        }
+        assert!(Format::try_from(Format::ALL.len() as u32).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        let image = test_image(Format::BC1, 8, 8, 2, 1);
+        let buf = image.serialize();
+
+        let with_field = |at: usize, value: u32| {
+            let mut b = buf.clone();
+            b[at..at + 4].copy_from_slice(&value.to_le_bytes());
+            Image::<Global>::deserialize(&mut &b[..]).unwrap_err()
+        };
+        assert_eq!(with_field(8, 17), DeserializeError::UnknownFormat { value: 17 });
+        // Sizes not matching the data
+        for (at, value) in [(0, 0), (0, 9), (4, 0), (8, 0), (12, 0), (12, 1), (12, 5), (16, 0), (16, 2)] {
+            assert_eq!(with_field(at, value), DeserializeError::InvalidImage, "{at} {value}");
+        }
+    }
+}
@@ -2,32 +2,73 @@
 
 use std::alloc::{Global, Allocator};
 
-use math::{
-    vec::{Vec2, Vec3, Vec4},
-    mat::Mat4,
-};
+use math::vec::{Vec2, Vec3};
 
 pub mod camera;
+pub mod container;
+pub mod graph;
+pub mod image;
+pub mod light;
+pub mod light_sampling;
+pub mod material;
+pub mod view;
 
 pub use camera::*;
-use bytemuck::{bytes_of, cast_slice, Pod, pod_read_unaligned};
+pub use container::{ContainerError, SectionKind};
+pub use graph::{Node, MeshInstance, LightInstance, CameraInstance};
+pub use image::{Format, Image};
+pub use light::Light;
+pub use light_sampling::{LightSamplingTables, EmissiveTriangle};
+pub use material::{Material, AlphaMode, OrmChannels, Clearcoat, Sheen};
+pub use view::{SceneView, MeshView, PrimitiveView, ImageView};
+use bytemuck::{bytes_of, cast_slice, cast_slice_mut, Pod, pod_read_unaligned};
 
 #[derive(Debug)]
-pub struct Mesh<A: Allocator + Copy=Global> {
+pub struct Primitive<A: Allocator + Copy=Global> {
     pub positions: Vec<Vec3, A>,
     pub normals: Vec<Vec3, A>,
     pub tangents: Vec<Vec3, A>,
     pub uvs: Vec<Vec2, A>,
     pub indices: Vec<u32, A>,
 
-    pub transform: Mat4,
-    pub material: Material,
+    /// Index in Scene::materials
+    pub material: u32,
+}
+
+/// Checks the materials and indices of the primitives, given for each mesh,
+/// and that their attributes have one element per position.
+pub(crate) fn validate_meshes<'a, M: IntoIterator<Item = PrimitiveView<'a>>>(
+    meshes: impl IntoIterator<Item = M>, material_count: usize,
+) -> Result<(), DeserializeError> {
+    let valid = |p: PrimitiveView| {
+        let n = p.positions.len();
+        (p.material as usize) < material_count
+            && p.normals.len() == n && p.tangents.len() == n && p.uvs.len() == n
+            && p.indices.iter().all(|&i| (i as usize) < n)
+    };
+    for (i, primitives) in meshes.into_iter().enumerate() {
+        if !primitives.into_iter().all(valid) {
+            return Err(DeserializeError::InvalidMesh { index: i as u32 });
+        }
+    }
+    Ok(())
+}
+
+/// Geometry in local space, shared by all the nodes referencing it.
+#[derive(Debug)]
+pub struct Mesh<A: Allocator + Copy=Global> {
+    pub primitives: Vec<Primitive<A>, A>,
 }
 
 #[derive(Debug)]
 pub struct Scene<A: Allocator + Copy=Global> {
     pub meshes: Vec<Mesh<A>, A>,
     pub images: Vec<Image<A>, A>,
+    pub materials: Vec<Material, A>,
+    pub lights: Vec<Light, A>,
+    pub cameras: Vec<CameraDesc, A>,
+    /// Parents come before their children, see graph.rs.
+    pub nodes: Vec<Node, A>,
 }
 
 impl Scene {
@@ -35,87 +76,33 @@ impl Scene {
         Self {
             meshes: Vec::new(),
             images: Vec::new(),
+            materials: Vec::new(),
+            lights: Vec::new(),
+            cameras: Vec::new(),
+            nodes: Vec::new(),
         }
     }
 }
 
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<A: Allocator + Copy> Scene<A> {
     pub fn new_in(a: A) -> Self {
         Self {
             meshes: Vec::<Mesh<A>,A>::new_in(a),
             images: Vec::<Image<A>,A>::new_in(a),
+            materials: Vec::<Material,A>::new_in(a),
+            lights: Vec::<Light,A>::new_in(a),
+            cameras: Vec::<CameraDesc,A>::new_in(a),
+            nodes: Vec::<Node,A>::new_in(a),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-#[repr(C)]
-pub enum Format {
-    RGBA8,
-    SRGBA8,
-}
-
-impl Into<u32> for Format {
-    fn into(self) -> u32 {
-        match self {
-            Format::RGBA8 => 0,
-            Format::SRGBA8 => 1,
-        }
-    }
-}
-
-impl TryFrom<u32> for Format {
-    type Error = &'static str;
-
-    fn try_from(value: u32) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(Format::RGBA8),
-            1 => Ok(Format::SRGBA8),
-            _ => Err("Unknown format"),
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct Image<A: Allocator + Copy=Global> {
-    pub width: u32,
-    pub height: u32,
-    pub format: Format,
-    pub data: Vec<u8, A>,
-}
-
-
-#[derive(Debug, Clone, Copy)]
-pub enum MaterialParameter {
-    None,
-    Texture(u32),
-    Vec2(Vec2),
-    Vec3(Vec3),
-    Vec4(Vec4),
-}
-
-impl Into<u32> for MaterialParameter {
-    fn into(self) -> u32 {
-        match self {
-            MaterialParameter::None => 0,
-            MaterialParameter::Texture(_) => 1,
-            MaterialParameter::Vec2(_)    => 2,
-            MaterialParameter::Vec3(_)    => 3,
-            MaterialParameter::Vec4(_)    => 4,
-        }
-    }
-}
-
-#[derive(Debug, Clone, Copy)]
-#[repr(C)]
-pub struct Material {
-    pub base_color: MaterialParameter,
-    pub normal:     MaterialParameter,
-    pub specular:   MaterialParameter,
-    pub emissive:   MaterialParameter,
-}
-
-
 pub trait Serialize {
     fn serialize_buf(&self, buf: &mut Vec<u8>);
     fn serialize(&self) -> Vec<u8> {
@@ -125,79 +112,74 @@ pub trait Serialize {
     }
 }
 
-impl<A: Allocator + Copy> Serialize for Image<A> {
+impl Serialize for Primitive {
     fn serialize_buf(&self, buf: &mut Vec<u8>) {
-        (&self.width).serialize_buf(buf);
-        (&self.height).serialize_buf(buf);
-        (&self.format).serialize_buf(buf);
-        self.data.serialize_buf(buf);
+        self.positions.serialize_buf(buf);
+        self.normals.serialize_buf(buf);
+        self.tangents.serialize_buf(buf);
+        self.uvs.serialize_buf(buf);
+        self.indices.serialize_buf(buf);
+        (&self.material).serialize_buf(buf);
     }
 }
 
-impl Serialize for Format {
+impl Serialize for Mesh {
     fn serialize_buf(&self, buf: &mut Vec<u8>) {
-        let v: u32 = (*self).into();
-        (&v).serialize_buf(buf);
+        serialize_items(&self.primitives, buf);
     }
 }
 
-impl Serialize for MaterialParameter {
+/// Length in bytes followed by the UTF-8 data.
+impl Serialize for String {
     fn serialize_buf(&self, buf: &mut Vec<u8>) {
-        let typ: u32 = (*self).into();
-        (&typ).serialize_buf(buf);
-
-        match *self {
-            MaterialParameter::None => {},
-            MaterialParameter::Texture(v) => (&v).serialize_buf(buf),
-            MaterialParameter::Vec2(v)    => (&v).serialize_buf(buf),
-            MaterialParameter::Vec3(v)    => (&v).serialize_buf(buf),
-            MaterialParameter::Vec4(v)    => (&v).serialize_buf(buf),
-        }
+        (&(self.len() as u64)).serialize_buf(buf);
+        buf.extend_from_slice(self.as_bytes());
     }
 }
 
-impl Serialize for Material {
-    fn serialize_buf(&self, buf: &mut Vec<u8>) {
-        self.base_color.serialize_buf(buf);
-        self.normal.serialize_buf(buf);
-        self.specular.serialize_buf(buf);
-        self.emissive.serialize_buf(buf);
-    }
+/// Optional index, u32::MAX for None.
+pub(crate) fn serialize_index(index: Option<u32>, buf: &mut Vec<u8>) {
+    (&index.unwrap_or(u32::MAX)).serialize_buf(buf);
 }
 
-impl Serialize for Mesh {
-    fn serialize_buf(&self, buf: &mut Vec<u8>) {
-        self.positions.serialize_buf(buf);
-        self.normals.serialize_buf(buf);
-        self.tangents.serialize_buf(buf);
-        self.uvs.serialize_buf(buf);
-        self.indices.serialize_buf(buf);
-        (&self.transform).serialize_buf(buf);
-        (&self.material).serialize_buf(buf);
+pub(crate) fn deserialize_index(buf: &mut &[u8]) -> Result<Option<u32>, DeserializeError> {
+    let index = <&u32>::deserialize(buf)?;
+    Ok((index != u32::MAX).then_some(index))
+}
+
+/// Count followed by the items.
+fn serialize_items<T: Serialize>(items: &[T], buf: &mut Vec<u8>) {
+    (&(items.len() as u64)).serialize_buf(buf);
+    for it in items {
+        it.serialize_buf(buf);
     }
 }
 
+/// Writes the scene in a container, see container.rs.
 impl Serialize for Scene {
     fn serialize_buf(&self, buf: &mut Vec<u8>) {
-        let meshes_count = self.meshes.len() as u64;
-        (&meshes_count).serialize_buf(buf);
-        for m in self.meshes.iter() {
-            m.serialize_buf(buf);
-        }
-
-        let images_count = self.images.len() as u64;
-        (&images_count).serialize_buf(buf);
-        for img in self.images.iter() {
-            img.serialize_buf(buf);
-        }
+        container::write_container(buf, &[
+            (SectionKind::Meshes,    &|b: &mut Vec<u8>| serialize_items(&self.meshes, b)),
+            (SectionKind::Images,    &|b: &mut Vec<u8>| serialize_items(&self.images, b)),
+            (SectionKind::Materials, &|b: &mut Vec<u8>| serialize_items(&self.materials, b)),
+            (SectionKind::Lights,    &|b: &mut Vec<u8>| serialize_items(&self.lights, b)),
+            (SectionKind::Cameras,   &|b: &mut Vec<u8>| serialize_items(&self.cameras, b)),
+            (SectionKind::Nodes,     &|b: &mut Vec<u8>| serialize_items(&self.nodes, b)),
+        ]);
     }
 }
 
+/// Size in bytes, padding and the data, aligned to SECTION_ALIGNMENT from
+/// the start of buf so it can be borrowed in place by SceneView.
 impl<T: Pod, A: Allocator> Serialize for Vec<T, A> {
     fn serialize_buf(&self, buf: &mut Vec<u8>) {
-        let size = self.len() * core::mem::size_of::<T>();
+        let size = (self.len() * core::mem::size_of::<T>()) as u64;
         buf.extend_from_slice(&size.to_le_bytes());
-        buf.extend_from_slice(cast_slice(&self));
+        let padding = (container::SECTION_ALIGNMENT - (buf.len() + 4) % container::SECTION_ALIGNMENT)
+            % container::SECTION_ALIGNMENT;
+        buf.extend_from_slice(&(padding as u32).to_le_bytes());
+        buf.resize(buf.len() + padding, 0);
+        buf.extend_from_slice(cast_slice(self));
     }
 }
 
@@ -210,37 +192,146 @@ impl<T: Pod> Serialize for &T {
 
 impl<T: Pod> Serialize for &[T] {
     fn serialize_buf(&self, buf: &mut Vec<u8>) {
-        buf.extend_from_slice(cast_slice(*self));
+        buf.extend_from_slice(cast_slice(self));
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeserializeError {
+    /// A read at offset went past the end of the data. The offset is from
+    /// the start of the buffer given to deserialize, or of the container for
+    /// Scene::from_container.
+    UnexpectedEof { offset: usize },
+    UnknownAlphaMode { value: u32 },
+    /// Texture channel other than 0 to 3, or missing where one is required.
+    InvalidChannel { channel: u8 },
+    UnknownFormat { value: u32 },
+    /// Bytes left in a section after its last item.
+    TrailingBytes { count: usize },
+    /// Size of a vector in bytes is not a multiple of its element size.
+    SizeMismatch { size: u64, element_size: usize },
+    /// Data borrowed by SceneView is not aligned for its type.
+    Misaligned,
+    InvalidUtf8,
+    /// Node with an out of range or cyclic reference, see graph.rs.
+    InvalidNode { index: u32 },
+    /// Image with empty or inconsistent sizes, see image.rs.
+    InvalidImage,
+    UnknownLight { tag: u32 },
+    UnknownProjection { value: u32 },
+    /// Environment light with an out of range image.
+    InvalidLight { index: u32 },
+    /// Primitive of the mesh with an out of range material or index, or
+    /// attributes of different lengths.
+    InvalidMesh { index: u32 },
+    /// Material with an out of range texture.
+    InvalidMaterial { index: u32 },
+}
+
+impl std::fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeserializeError::UnexpectedEof { offset } =>
+                write!(f, "unexpected end of data at offset {offset}"),
+            DeserializeError::UnknownAlphaMode { value } =>
+                write!(f, "unknown alpha mode {value}"),
+            DeserializeError::InvalidChannel { channel } =>
+                write!(f, "invalid texture channel {channel}"),
+            DeserializeError::UnknownFormat { value } =>
+                write!(f, "unknown image format {value}"),
+            DeserializeError::TrailingBytes { count } =>
+                write!(f, "{count} unexpected bytes after the last item"),
+            DeserializeError::SizeMismatch { size, element_size } =>
+                write!(f, "vector of {size} bytes with elements of {element_size} bytes"),
+            DeserializeError::Misaligned =>
+                write!(f, "misaligned data"),
+            DeserializeError::InvalidUtf8 =>
+                write!(f, "invalid UTF-8 string"),
+            DeserializeError::InvalidNode { index } =>
+                write!(f, "invalid references in node {index}"),
+            DeserializeError::InvalidImage =>
+                write!(f, "invalid image sizes"),
+            DeserializeError::UnknownLight { tag } =>
+                write!(f, "unknown light type {tag}"),
+            DeserializeError::UnknownProjection { value } =>
+                write!(f, "unknown camera projection {value}"),
+            DeserializeError::InvalidLight { index } =>
+                write!(f, "invalid image in light {index}"),
+            DeserializeError::InvalidMesh { index } =>
+                write!(f, "invalid primitive in mesh {index}"),
+            DeserializeError::InvalidMaterial { index } =>
+                write!(f, "invalid texture in material {index}"),
+        }
+    }
+}
+
+impl std::error::Error for DeserializeError {}
+
+/// On error buf is left at the read that failed.
 pub trait Deserialize<A: Allocator + Copy=Global> {
     type Item;
     type AllocatorItem;
 
-    fn deserialize(buf: &mut &[u8]) -> Self::Item;
-    fn deserialize_in(buf: &mut &[u8], a: A) -> Self::AllocatorItem;
+    fn deserialize(buf: &mut &[u8]) -> Result<Self::Item, DeserializeError>;
+    fn deserialize_in(buf: &mut &[u8], a: A) -> Result<Self::AllocatorItem, DeserializeError>;
+
+}
 
+/// Runs f on buf and makes the offset of UnexpectedEof relative to the
+/// current start of buf.
+fn with_offset<'a, T>(buf: &mut &'a [u8], f: impl FnOnce(&mut &'a [u8]) -> Result<T, DeserializeError>)
+    -> Result<T, DeserializeError> {
+    let len = buf.len();
+    f(buf).map_err(|e| match e {
+        DeserializeError::UnexpectedEof { .. } =>
+            DeserializeError::UnexpectedEof { offset: len - buf.len() },
+        e => e,
+    })
+}
+
+fn take<'a>(buf: &mut &'a [u8], size: usize) -> Result<&'a [u8], DeserializeError> {
+    if buf.len() < size {
+        return Err(DeserializeError::UnexpectedEof { offset: 0 });
+    }
+    let (v, rest) = buf.split_at(size);
+    *buf = rest;
+    Ok(v)
+}
+
+/// Bytes of a vector of T, buf is left unchanged on error.
+fn vec_bytes<'a, T>(buf: &mut &'a [u8]) -> Result<&'a [u8], DeserializeError> {
+    let mut b = *buf;
+    let size = <&u64>::deserialize(&mut b)?;
+    let padding = <&u32>::deserialize(&mut b)?;
+    let element_size = core::mem::size_of::<T>();
+    if element_size == 0 || size % element_size as u64 != 0 {
+        return Err(DeserializeError::SizeMismatch { size, element_size });
+    }
+    // Checked before allocating so corrupted sizes fail instead of aborting
+    if (padding as u64).saturating_add(size) > b.len() as u64 {
+        return Err(DeserializeError::UnexpectedEof { offset: 0 });
+    }
+    let data = &b[padding as usize..padding as usize + size as usize];
+    *buf = &b[padding as usize + size as usize..];
+    Ok(data)
 }
 
 impl<T: Pod, A: Allocator + Copy> Deserialize<A> for Vec<T, A> {
     type Item = Vec<T>;
     type AllocatorItem = Vec<T, A>;
 
-    fn deserialize(buf: &mut &[u8]) -> Self::Item {
-        let size = u64::from_le_bytes(buf[..8].try_into().unwrap()) as usize;
-        let mut v = Vec::new();
-        v.extend_from_slice(cast_slice(&buf[8..8 + size]));
-        *buf = &buf[8 + size as usize..];
-        v
+    fn deserialize(buf: &mut &[u8]) -> Result<Self::Item, DeserializeError> {
+        Vec::<T, Global>::deserialize_in(buf, Global)
     }
 
-    fn deserialize_in(buf: &mut &[u8], a: A) -> Self::AllocatorItem {
-        let size = u64::from_le_bytes(buf[..8].try_into().unwrap()) as usize;
-        let mut v = Vec::new_in(a);
-        v.extend_from_slice(cast_slice(&buf[8..8 + size]));
-        *buf = &buf[8 + size as usize..];
-        v
+    fn deserialize_in(buf: &mut &[u8], a: A) -> Result<Self::AllocatorItem, DeserializeError> {
+        let data = vec_bytes::<T>(buf)?;
+        // Copied as bytes since buf is not necessarily aligned for T
+        let len = data.len() / core::mem::size_of::<T>();
+        let mut v = Vec::with_capacity_in(len, a);
+        v.resize(len, T::zeroed());
+        cast_slice_mut(&mut v).copy_from_slice(data);
+        Ok(v)
     }
 }
 
@@ -249,151 +340,393 @@ impl<T: Pod> Deserialize for &T {
     type Item = T;
     type AllocatorItem = T;
 
-    fn deserialize(buf: &mut &[u8]) -> T {
-        let v = pod_read_unaligned(&buf[..core::mem::size_of::<T>()]);
-        *buf = &buf[core::mem::size_of::<T>() as usize..];
-        v
+    fn deserialize(buf: &mut &[u8]) -> Result<T, DeserializeError> {
+        take(buf, core::mem::size_of::<T>()).map(pod_read_unaligned)
     }
 
-    fn deserialize_in(buf: &mut &[u8], _a: Global) -> T {
+    fn deserialize_in(buf: &mut &[u8], _a: Global) -> Result<T, DeserializeError> {
         <&T>::deserialize(buf)
     }
 }
 
-impl Deserialize for MaterialParameter {
-    type Item = MaterialParameter;
-    type AllocatorItem = MaterialParameter;
+impl<A: Allocator + Copy> Deserialize<A> for Primitive<A> {
+    type Item = Primitive;
+    type AllocatorItem = Primitive<A>;
 
-    fn deserialize(buf: &mut &[u8]) -> Self::Item {
-        let typ = <&u32>::deserialize(buf);
-        match typ {
-            0 => MaterialParameter::None,
-            1 => MaterialParameter::Texture(<&u32>::deserialize(buf)),
-            2 => MaterialParameter::Vec2(<&Vec2>::deserialize(buf)),
-            3 => MaterialParameter::Vec3(<&Vec3>::deserialize(buf)),
-            4 => MaterialParameter::Vec4(<&Vec4>::deserialize(buf)),
-            _ => panic!(),
-        }
+    fn deserialize(buf: &mut &[u8]) -> Result<Primitive, DeserializeError> {
+        Primitive::<Global>::deserialize_in(buf, Global)
     }
 
-    fn deserialize_in(buf: &mut &[u8], _a: Global) -> Self::AllocatorItem {
-        Self::deserialize(buf)
+    fn deserialize_in(buf: &mut &[u8], a: A) -> Result<Primitive<A>, DeserializeError> {
+        with_offset(buf, |buf| Ok(Primitive {
+            positions: Vec::<Vec3, A>::deserialize_in(buf, a)?,
+            normals: Vec::<Vec3, A>::deserialize_in(buf, a)?,
+            tangents: Vec::<Vec3, A>::deserialize_in(buf, a)?,
+            uvs: Vec::<Vec2, A>::deserialize_in(buf, a)?,
+            indices: Vec::<u32, A>::deserialize_in(buf, a)?,
+
+            material: <&u32>::deserialize(buf)?,
+        }))
     }
 }
 
-impl Deserialize for Material {
-    type Item = Material;
-    type AllocatorItem = Material;
+impl<A: Allocator + Copy> Deserialize<A> for Mesh<A> {
+    type Item = Mesh;
+    type AllocatorItem = Mesh<A>;
 
-    fn deserialize(buf: &mut &[u8]) -> Self::Item {
-        Material {
-            base_color: MaterialParameter::deserialize(buf),
-            normal:     MaterialParameter::deserialize(buf),
-            specular:   MaterialParameter::deserialize(buf),
-            emissive:   MaterialParameter::deserialize(buf),
-        }
+    fn deserialize(buf: &mut &[u8]) -> Result<Mesh, DeserializeError> {
+        Mesh::<Global>::deserialize_in(buf, Global)
     }
 
-    fn deserialize_in(buf: &mut &[u8], _a: Global) -> Self::AllocatorItem {
-        Self::deserialize(buf)
+    fn deserialize_in(buf: &mut &[u8], a: A) -> Result<Mesh<A>, DeserializeError> {
+        Ok(Mesh {
+            primitives: deserialize_vec_in(buf, a, |b| Primitive::deserialize_in(b, a))?,
+        })
     }
 }
 
-impl<A: Allocator + Copy> Deserialize<A> for Mesh<A> {
-    type Item = Mesh;
-    type AllocatorItem = Mesh<A>;
-
-    fn deserialize(buf: &mut &[u8]) -> Mesh {
-        Mesh {
-            positions: Vec::<Vec3>::deserialize(buf),
-            normals: Vec::<Vec3>::deserialize(buf),
-            tangents: Vec::<Vec3>::deserialize(buf),
-            uvs: Vec::<Vec2>::deserialize(buf),
-            indices: Vec::<u32>::deserialize(buf),
+impl Deserialize for String {
+    type Item = String;
+    type AllocatorItem = String;
+
+    fn deserialize(buf: &mut &[u8]) -> Result<String, DeserializeError> {
+        with_offset(buf, |buf| {
+            let len = <&u64>::deserialize(buf)?;
+            if len > buf.len() as u64 {
+                return Err(DeserializeError::UnexpectedEof { offset: 0 });
+            }
+            let s = std::str::from_utf8(take(buf, len as usize)?)
+                .map_err(|_| DeserializeError::InvalidUtf8)?;
+            Ok(s.to_string())
+        })
+    }
 
-            transform: <&Mat4>::deserialize(buf),
-            material: Material::deserialize(buf),
-        }
+    fn deserialize_in(buf: &mut &[u8], _a: Global) -> Result<String, DeserializeError> {
+        Self::deserialize(buf)
     }
+}
 
-    fn deserialize_in(buf: &mut &[u8], a: A) -> Mesh<A> {
-        Mesh {
-            positions: Vec::<Vec3, A>::deserialize_in(buf, a),
-            normals: Vec::<Vec3, A>::deserialize_in(buf, a),
-            tangents: Vec::<Vec3, A>::deserialize_in(buf, a),
-            uvs: Vec::<Vec2, A>::deserialize_in(buf, a),
-            indices: Vec::<u32, A>::deserialize_in(buf, a),
 
-            transform: <&Mat4>::deserialize(buf),
-            material: Material::deserialize(buf),
+/// Reads the count written by serialize_items and then each item.
+fn deserialize_vec_in<'a, T, A: Allocator + Copy>(buf: &mut &'a [u8], a: A,
+    mut item: impl FnMut(&mut &'a [u8]) -> Result<T, DeserializeError>)
+    -> Result<Vec<T, A>, DeserializeError> {
+    with_offset(buf, |buf| {
+        let count = <&u64>::deserialize(buf)?;
+        // Every item takes at least one byte, don't trust the count for the
+        // allocation
+        let mut items = Vec::with_capacity_in(count.min(buf.len() as u64) as usize, a);
+        for _ in 0..count {
+            items.push(item(buf)?);
         }
+        Ok(items)
+    })
+}
+
+/// Items of a section, which must be entirely consumed.
+fn deserialize_items<'a, T, A: Allocator + Copy>(section: Option<&'a [u8]>, a: A,
+    item: impl FnMut(&mut &'a [u8]) -> Result<T, DeserializeError>)
+    -> Result<Vec<T, A>, DeserializeError> {
+    let mut buf = match section {
+        Some(s) => s,
+        None => return Ok(Vec::new_in(a)),
+    };
+
+    let items = deserialize_vec_in(&mut buf, a, item)?;
+    if !buf.is_empty() {
+        return Err(DeserializeError::TrailingBytes { count: buf.len() });
     }
+    Ok(items)
 }
 
+impl Scene {
+    /// Reads a scene written by Serialize, see container.rs.
+    pub fn from_container(data: &[u8]) -> Result<Scene, ContainerError> {
+        Scene::from_container_in(data, Global)
+    }
+}
 
-impl<A: Allocator + Copy> Deserialize<A> for Image<A> {
-    type Item = Image;
-    type AllocatorItem = Image<A>;
+impl<A: Allocator + Copy> Scene<A> {
+    pub fn from_container_in(data: &[u8], a: A) -> Result<Scene<A>, ContainerError> {
+        let c = container::Container::parse(data)?;
 
-    fn deserialize(buf: &mut &[u8]) -> Image {
-        Image {
-            width: <&u32>::deserialize(buf),
-            height: <&u32>::deserialize(buf),
-            format: <&u32>::deserialize(buf).try_into().unwrap(),
-            data: Vec::<u8>::deserialize(buf),
+        let mut scene = Scene::new_in(a);
+        for kind in [SectionKind::Meshes, SectionKind::Images, SectionKind::Materials,
+                     SectionKind::Lights, SectionKind::Cameras, SectionKind::Nodes] {
+            c.read_section(kind, |s| scene.read_section(kind, s))?;
         }
+        scene.validate()?;
+        Ok(scene)
     }
 
-    fn deserialize_in(buf: &mut &[u8], a: A) -> Image<A> {
-        Image {
-            width: <&u32>::deserialize(buf),
-            height: <&u32>::deserialize(buf),
-            format: <&u32>::deserialize(buf).try_into().unwrap(),
-            data: Vec::<u8, A>::deserialize_in(buf, a),
+    /// Replaces the items of a section, for readers getting the sections
+    /// one at a time. The references between sections are only checked by
+    /// validate once every section is read.
+    pub fn read_section(&mut self, kind: SectionKind, data: Option<&[u8]>) -> Result<(), DeserializeError> {
+        let a = *self.meshes.allocator();
+        match kind {
+            SectionKind::Meshes =>
+                self.meshes = deserialize_items(data, a, |b| Mesh::deserialize_in(b, a))?,
+            SectionKind::Images =>
+                self.images = deserialize_items(data, a, |b| Image::deserialize_in(b, a))?,
+            SectionKind::Materials =>
+                self.materials = deserialize_items(data, a, Material::deserialize)?,
+            SectionKind::Lights =>
+                self.lights = deserialize_items(data, a, Light::deserialize)?,
+            SectionKind::Cameras =>
+                self.cameras = deserialize_items(data, a, CameraDesc::deserialize)?,
+            SectionKind::Nodes =>
+                self.nodes = deserialize_items(data, a, Node::deserialize)?,
         }
+        Ok(())
+    }
+
+    /// Checks the references between sections.
+    pub fn validate(&self) -> Result<(), ContainerError> {
+        let error = |kind| move |error| ContainerError::Section { kind, error };
+        light::validate(&self.lights, self.images.len()).map_err(error(SectionKind::Lights))?;
+        validate_meshes(self.meshes.iter().map(|m| m.primitives.iter().map(Primitive::view)),
+                        self.materials.len()).map_err(error(SectionKind::Meshes))?;
+        material::validate(&self.materials, self.images.len()).map_err(error(SectionKind::Materials))?;
+        graph::validate(&self.nodes, self.meshes.len(), self.lights.len(), self.cameras.len())
+            .map_err(error(SectionKind::Nodes))
     }
 }
 
-impl<A: Allocator + Copy> Deserialize<A> for Scene<A> {
-    type Item = Scene;
-    type AllocatorItem = Scene<A>;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_scene() -> Scene {
+        let mut scene = Scene::new();
+        scene.materials.push(Material {
+            base_color_texture: Some(0),
+            roughness_factor: 0.5,
+            alpha_mode: AlphaMode::Blend,
+            ..Default::default()
+        });
+        scene.meshes.push(Mesh {
+            primitives: vec![Primitive {
+                positions: vec![Vec3::new(0., 0., 0.), Vec3::new(1., 0., 0.), Vec3::new(0., 1., 0.)],
+                normals: vec![Vec3::new(0., 0., 1.); 3],
+                tangents: vec![Vec3::new(1., 0., 0.); 3],
+                uvs: vec![Vec2::new(0., 0.), Vec2::new(1., 0.), Vec2::new(0., 1.)],
+                indices: vec![0, 1, 2],
+                material: 0,
+            }],
+        });
+        let root = scene.add_node(Node { name: "root".to_string(), ..Default::default() });
+        scene.add_node(Node {
+            name: "triangle".to_string(),
+            parent: Some(root),
+            mesh: Some(0),
+            ..Default::default()
+        });
+        scene.lights.push(Light::Environment { image: 0, intensity: 1.5 });
+        scene.add_node(Node { name: "sky".to_string(), light: Some(0), ..Default::default() });
+        scene.cameras.push(CameraDesc {
+            name: "view".to_string(),
+            projection: Projection::Perspective { yfov: 0.7, aspect_ratio: None, near: 0.1, far: 100. },
+            lens: None,
+        });
+        scene.add_node(Node { name: "view".to_string(), camera: Some(0), ..Default::default() });
+        scene.images.push(Image {
+            width: 2,
+            height: 1,
+            format: Format::SRGBA8,
+            mip_levels: 1,
+            array_layers: 1,
+            data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        });
+        scene
+    }
 
-    fn deserialize(buf: &mut &[u8]) -> Scene {
-        let meshes_count = <&u64>::deserialize(buf);
-        let mut meshes = Vec::with_capacity(meshes_count as usize);
-        for _ in 0..meshes_count {
-            meshes.push(Mesh::<Global>::deserialize(buf));
-        }
+    #[test]
+    fn scene_round_trip() {
+        let buf = test_scene().serialize();
+        let scene = Scene::from_container(&buf).unwrap();
+
+        assert_eq!(scene.meshes.len(), 1);
+        let p = &scene.meshes[0].primitives[0];
+        assert_eq!(p.indices, [0, 1, 2]);
+        assert_eq!(p.positions[1].to_slice(), [1., 0., 0.]);
+        assert_eq!(p.material, 0);
+        assert_eq!(scene.nodes[1].name, "triangle");
+        assert_eq!(scene.nodes[0].children, [1]);
+        assert_eq!(scene.flatten().len(), 1);
+        assert_eq!(scene.images[0].data, [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(matches!(scene.images[0].format, Format::SRGBA8));
+        assert_eq!(scene.materials[0].base_color_texture, Some(0));
+        assert_eq!(scene.materials[0].roughness_factor, 0.5);
+        assert_eq!(scene.materials[0].alpha_mode, AlphaMode::Blend);
+        assert!(matches!(scene.lights[..], [Light::Environment { image: 0, intensity }] if intensity == 1.5));
+        assert_eq!(scene.light_instances()[0].node, 2);
+        assert_eq!(scene.cameras[0].name, "view");
+        assert_eq!(scene.nodes[3].camera, Some(0));
+        assert_eq!(scene.camera_instances()[0].node, 3);
+    }
 
-        let images_count = <&u64>::deserialize(buf);
-        let mut images = Vec::with_capacity(images_count as usize);
-        for _ in 0..images_count {
-            images.push(Image::<Global>::deserialize(buf));
-        }
+    fn section(buf: &[u8], kind: SectionKind) -> std::ops::Range<usize> {
+        let c = container::Container::parse(buf).unwrap();
+        let s = c.sections.iter().find(|s| s.kind == kind as u32).unwrap();
+        s.offset as usize..(s.offset + s.size) as usize
+    }
 
-        Scene {
-            meshes,
-            images,
-        }
+    #[test]
+    fn errors() {
+        let mut buf = Vec::new();
+        test_scene().images[0].serialize_buf(&mut buf);
+        buf[8..12].copy_from_slice(&99u32.to_le_bytes());
+        assert_eq!(Image::<Global>::deserialize(&mut &buf[..]).unwrap_err(),
+                   DeserializeError::UnknownFormat { value: 99 });
+
+        let mut buf = Vec::new();
+        vec![1u32, 2, 3].serialize_buf(&mut buf);
+        buf[..8].copy_from_slice(&6u64.to_le_bytes());
+        assert_eq!(Vec::<u32>::deserialize(&mut &buf[..]).unwrap_err(),
+                   DeserializeError::SizeMismatch { size: 6, element_size: 4 });
+        buf[..8].copy_from_slice(&16u64.to_le_bytes());
+        assert_eq!(Vec::<u32>::deserialize(&mut &buf[..]).unwrap_err(),
+                   DeserializeError::UnexpectedEof { offset: 0 });
+
+        // Offsets are from the start of the buffer, here the material index
+        // at the end of the primitive
+        let mut buf = Vec::new();
+        test_scene().meshes[0].serialize_buf(&mut buf);
+        let mut view = &buf[..buf.len() - 1];
+        assert_eq!(Mesh::<Global>::deserialize(&mut view).unwrap_err(),
+                   DeserializeError::UnexpectedEof { offset: buf.len() - 4 });
+
+        let mut items = Vec::new();
+        serialize_items(&test_scene().materials, &mut items);
+        assert_eq!(deserialize_items(Some(&items[..items.len() - 2]), Global, Material::deserialize)
+                       .unwrap_err(),
+                   DeserializeError::UnexpectedEof { offset: items.len() - 4 });
+
+        // In a scene, offsets are from the start of the container. Claim a
+        // second material and fix the checksum.
+        let mut buf = test_scene().serialize();
+        let r = section(&buf, SectionKind::Materials);
+        buf[r.start..r.start + 8].copy_from_slice(&2u64.to_le_bytes());
+        fix_checksum(&mut buf, SectionKind::Materials);
+        assert_eq!(Scene::from_container(&buf).unwrap_err(), ContainerError::Section {
+            kind: SectionKind::Materials,
+            error: DeserializeError::UnexpectedEof { offset: r.end },
+        });
+
+        // Environment light without its image
+        let mut scene = test_scene();
+        scene.images.clear();
+        assert_eq!(Scene::from_container(&scene.serialize()).unwrap_err(), ContainerError::Section {
+            kind: SectionKind::Lights,
+            error: DeserializeError::InvalidLight { index: 0 },
+        });
+
+        // Material without its texture
+        let mut scene = test_scene();
+        scene.lights.clear();
+        scene.images.clear();
+        assert_eq!(Scene::from_container(&scene.serialize()).unwrap_err(), ContainerError::Section {
+            kind: SectionKind::Materials,
+            error: DeserializeError::InvalidMaterial { index: 0 },
+        });
+
+        // Primitive without its material
+        let mut scene = test_scene();
+        scene.materials.clear();
+        assert_eq!(Scene::from_container(&scene.serialize()).unwrap_err(), ContainerError::Section {
+            kind: SectionKind::Meshes,
+            error: DeserializeError::InvalidMesh { index: 0 },
+        });
+
+        // Index past the vertices and missing uvs
+        let mut scene = test_scene();
+        let p = &mut scene.meshes[0].primitives[0];
+        *p.indices.last_mut().unwrap() = p.positions.len() as u32;
+        assert_eq!(Scene::from_container(&scene.serialize()).unwrap_err(), ContainerError::Section {
+            kind: SectionKind::Meshes,
+            error: DeserializeError::InvalidMesh { index: 0 },
+        });
+        let mut scene = test_scene();
+        scene.meshes[0].primitives[0].uvs.pop();
+        assert_eq!(scene.validate().unwrap_err(), ContainerError::Section {
+            kind: SectionKind::Meshes,
+            error: DeserializeError::InvalidMesh { index: 0 },
+        });
+
+        let mut buf = items.clone();
+        buf.push(0);
+        assert_eq!(deserialize_items(Some(&buf[..]), Global, Material::deserialize).unwrap_err(),
+                   DeserializeError::TrailingBytes { count: 1 });
     }
 
+    fn fix_checksum(buf: &mut [u8], kind: SectionKind) {
+        let table = core::mem::size_of::<container::Header>();
+        let entry_size = core::mem::size_of::<container::SectionHeader>();
+        let count = u32::from_le_bytes(buf[12..16].try_into().unwrap()) as usize;
+        for at in (table..).step_by(entry_size).take(count) {
+            let s: container::SectionHeader = pod_read_unaligned(&buf[at..at + entry_size]);
+            if s.kind == kind as u32 {
+                let checksum = crc32fast::hash(&buf[s.offset as usize..(s.offset + s.size) as usize]);
+                buf[at + 4..at + 8].copy_from_slice(&checksum.to_le_bytes());
+            }
+        }
+    }
 
-    fn deserialize_in(buf: &mut &[u8], a: A) -> Scene<A> {
-        let meshes_count = <&u64>::deserialize(buf);
-        let mut meshes = Vec::with_capacity_in(meshes_count as usize, a);
-        for _ in 0..meshes_count {
-            meshes.push(Mesh::deserialize_in(buf, a));
+    #[test]
+    fn truncated_never_panics() {
+        let buf = test_scene().serialize();
+        for len in 0..buf.len() {
+            assert!(Scene::from_container(&buf[..len]).is_err());
         }
 
-        let images_count = <&u64>::deserialize(buf);
-        let mut images = Vec::with_capacity_in(images_count as usize, a);
-        for _ in 0..images_count {
-            images.push(Image::deserialize_in(buf, a));
+        for kind in [SectionKind::Meshes, SectionKind::Images, SectionKind::Materials, SectionKind::Lights,
+                     SectionKind::Cameras, SectionKind::Nodes] {
+            let r = section(&buf, kind);
+            for len in 0..r.len() {
+                let s = Some(&buf[r.start..r.start + len]);
+                let err = match kind {
+                    SectionKind::Meshes => deserialize_items(s, Global, Mesh::<Global>::deserialize).err(),
+                    SectionKind::Images => deserialize_items(s, Global, Image::<Global>::deserialize).err(),
+                    SectionKind::Lights => deserialize_items(s, Global, Light::deserialize).err(),
+                    SectionKind::Cameras => deserialize_items(s, Global, CameraDesc::deserialize).err(),
+                    SectionKind::Nodes => deserialize_items(s, Global, Node::deserialize).err(),
+                    _ => deserialize_items(s, Global, Material::deserialize).err(),
+                };
+                assert!(matches!(err, Some(DeserializeError::UnexpectedEof { offset }) if offset <= len),
+                        "{kind:?} {len} {err:?}");
+            }
         }
+    }
 
-        Scene {
-            meshes,
-            images,
+    #[test]
+    fn bit_flips_never_panic() {
+        let buf = test_scene().serialize();
+        for kind in [SectionKind::Meshes, SectionKind::Images, SectionKind::Materials, SectionKind::Lights,
+                     SectionKind::Cameras, SectionKind::Nodes] {
+            let r = section(&buf, kind);
+            for i in r.clone() {
+                for bit in [0, 3, 7] {
+                    let mut b = buf.clone();
+                    b[i] ^= 1 << bit;
+                    // Caught by the checksum
+                    assert!(matches!(Scene::from_container(&b),
+                                     Err(ContainerError::ChecksumMismatch { .. })));
+
+                    // Past the checksum, errors or a different scene
+                    fix_checksum(&mut b, kind);
+                    let _ = Scene::from_container(&b);
+                }
+            }
         }
     }
+
+    #[test]
+    fn pre_container_files_are_rejected() {
+        // Files written before the container start with the mesh count
+        let mut old = Vec::new();
+        serialize_items(&test_scene().meshes, &mut old);
+        serialize_items(&test_scene().images, &mut old);
+        assert_eq!(Scene::from_container(&old).unwrap_err(), ContainerError::BadMagic);
+    }
 }
@@ -0,0 +1,213 @@
+//! Lights, placed in the world by the nodes referencing them in Node::light.
+//! In the frame of the node lights point along -Z like glTF, and area lights
+//! lie in the XY plane. Intensities are in glTF units: candela for point and
+//! spot lights, lux for directional lights and nits for area and environment
+//! lights.
+
+use math::vec::Vec3;
+
+use crate::{with_offset, Deserialize, DeserializeError, Serialize};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Light {
+    Point {
+        color: Vec3,
+        intensity: f32,
+        /// Radius of the emitting sphere, 0 for a point.
+        radius: f32,
+        /// Distance where the light is cut off, f32::INFINITY for none.
+        range: f32,
+    },
+    Spot {
+        color: Vec3,
+        intensity: f32,
+        radius: f32,
+        range: f32,
+        /// Angles from -Z in radians, the intensity falls off between them.
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+    Directional {
+        color: Vec3,
+        intensity: f32,
+        /// Half of the angle covered by the source, about 0.0047 for the sun.
+        angular_radius: f32,
+    },
+    /// Centered on the node, emitting towards -Z.
+    Rect {
+        color: Vec3,
+        intensity: f32,
+        width: f32,
+        height: f32,
+        two_sided: bool,
+    },
+    Disk {
+        color: Vec3,
+        intensity: f32,
+        radius: f32,
+        two_sided: bool,
+    },
+    /// Equirectangular map in Scene::images, oriented by the node with +Z
+    /// up.
+    Environment {
+        image: u32,
+        intensity: f32,
+    },
+}
+
+impl Light {
+    fn tag(&self) -> u32 {
+        match self {
+            Light::Point { .. }       => 0,
+            Light::Spot { .. }        => 1,
+            Light::Directional { .. } => 2,
+            Light::Rect { .. }        => 3,
+            Light::Disk { .. }        => 4,
+            Light::Environment { .. } => 5,
+        }
+    }
+}
+
+/// Checks the images of the environment lights.
+pub(crate) fn validate(lights: &[Light], image_count: usize) -> Result<(), DeserializeError> {
+    for (i, l) in lights.iter().enumerate() {
+        if let Light::Environment { image, .. } = l {
+            if *image as usize >= image_count {
+                return Err(DeserializeError::InvalidLight { index: i as u32 });
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Serialize for Light {
+    fn serialize_buf(&self, buf: &mut Vec<u8>) {
+        (&self.tag()).serialize_buf(buf);
+        match *self {
+            Light::Point { color, intensity, radius, range } => {
+                (&color).serialize_buf(buf);
+                (&[intensity, radius, range]).serialize_buf(buf);
+            },
+            Light::Spot { color, intensity, radius, range, inner_cone_angle, outer_cone_angle } => {
+                (&color).serialize_buf(buf);
+                (&[intensity, radius, range, inner_cone_angle, outer_cone_angle]).serialize_buf(buf);
+            },
+            Light::Directional { color, intensity, angular_radius } => {
+                (&color).serialize_buf(buf);
+                (&[intensity, angular_radius]).serialize_buf(buf);
+            },
+            Light::Rect { color, intensity, width, height, two_sided } => {
+                (&color).serialize_buf(buf);
+                (&[intensity, width, height]).serialize_buf(buf);
+                (&(two_sided as u32)).serialize_buf(buf);
+            },
+            Light::Disk { color, intensity, radius, two_sided } => {
+                (&color).serialize_buf(buf);
+                (&[intensity, radius]).serialize_buf(buf);
+                (&(two_sided as u32)).serialize_buf(buf);
+            },
+            Light::Environment { image, intensity } => {
+                (&image).serialize_buf(buf);
+                (&intensity).serialize_buf(buf);
+            },
+        }
+    }
+}
+
+impl Deserialize for Light {
+    type Item = Light;
+    type AllocatorItem = Light;
+
+    fn deserialize(buf: &mut &[u8]) -> Result<Light, DeserializeError> {
+        with_offset(buf, |buf| {
+            let tag = <&u32>::deserialize(buf)?;
+            Ok(match tag {
+                0 => {
+                    let color = <&Vec3>::deserialize(buf)?;
+                    let [intensity, radius, range] = <&[f32; 3]>::deserialize(buf)?;
+                    Light::Point { color, intensity, radius, range }
+                },
+                1 => {
+                    let color = <&Vec3>::deserialize(buf)?;
+                    let [intensity, radius, range, inner_cone_angle, outer_cone_angle] =
+                        <&[f32; 5]>::deserialize(buf)?;
+                    Light::Spot { color, intensity, radius, range, inner_cone_angle, outer_cone_angle }
+                },
+                2 => {
+                    let color = <&Vec3>::deserialize(buf)?;
+                    let [intensity, angular_radius] = <&[f32; 2]>::deserialize(buf)?;
+                    Light::Directional { color, intensity, angular_radius }
+                },
+                3 => {
+                    let color = <&Vec3>::deserialize(buf)?;
+                    let [intensity, width, height] = <&[f32; 3]>::deserialize(buf)?;
+                    let two_sided = <&u32>::deserialize(buf)? != 0;
+                    Light::Rect { color, intensity, width, height, two_sided }
+                },
+                4 => {
+                    let color = <&Vec3>::deserialize(buf)?;
+                    let [intensity, radius] = <&[f32; 2]>::deserialize(buf)?;
+                    let two_sided = <&u32>::deserialize(buf)? != 0;
+                    Light::Disk { color, intensity, radius, two_sided }
+                },
+                5 => Light::Environment {
+                    image: <&u32>::deserialize(buf)?,
+                    intensity: <&f32>::deserialize(buf)?,
+                },
+                tag => return Err(DeserializeError::UnknownLight { tag }),
+            })
+        })
+    }
+
+    fn deserialize_in(buf: &mut &[u8], _a: std::alloc::Global) -> Result<Light, DeserializeError> {
+        Self::deserialize(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_lights() -> [Light; 6] {
+        let color = Vec3::new(1.0, 0.5, 0.25);
+        [
+            Light::Point { color, intensity: 10.0, radius: 0.0, range: f32::INFINITY },
+            Light::Spot { color, intensity: 5.0, radius: 0.1, range: 20.0,
+                          inner_cone_angle: 0.2, outer_cone_angle: 0.5 },
+            Light::Directional { color, intensity: 1e5, angular_radius: 0.0047 },
+            Light::Rect { color, intensity: 100.0, width: 2.0, height: 1.0, two_sided: true },
+            Light::Disk { color, intensity: 50.0, radius: 0.5, two_sided: false },
+            Light::Environment { image: 1, intensity: 2.0 },
+        ]
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buf = Vec::new();
+        for l in test_lights() {
+            l.serialize_buf(&mut buf);
+        }
+
+        let mut view = &buf[..];
+        for l in test_lights() {
+            assert_eq!(format!("{:?}", Light::deserialize(&mut view).unwrap()), format!("{l:?}"));
+        }
+        assert!(view.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let lights = test_lights();
+        assert_eq!(validate(&lights, 2), Ok(()));
+        assert_eq!(validate(&lights, 1), Err(DeserializeError::InvalidLight { index: 5 }));
+
+        let mut buf = lights[0].serialize();
+        buf[..4].copy_from_slice(&6u32.to_le_bytes());
+        assert_eq!(Light::deserialize(&mut &buf[..]).unwrap_err(), DeserializeError::UnknownLight { tag: 6 });
+
+        // Offset of the intensity
+        let buf = lights[5].serialize();
+        assert_eq!(Light::deserialize(&mut &buf[..10]).unwrap_err(),
+                   DeserializeError::UnexpectedEof { offset: 8 });
+    }
+}
@@ -0,0 +1,435 @@
+//! Emissive triangles of a scene and the tables used to sample them with a
+//! probability proportional to their power, by binary search in the CDF or
+//! with the alias method. Emissive textures are averaged over the UV
+//! footprint of each triangle for the tables, the shaders sample them at the
+//! light samples.
+
+use bytemuck::{Pod, Zeroable};
+use math::{color::srgb_eotf, pack::f16_to_f32, vec::{Vec2, Vec3}};
+
+use crate::{Format, ImageView, SceneView};
+
+/// Triangle in world space with its average emitted radiance, laid out like
+/// Light in types.hlsl.
+#[derive(Debug, Default, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct EmissiveTriangle {
+    pub p0: Vec3,
+    pub p1: Vec3,
+    pub p2: Vec3,
+    pub emission: Vec3,
+    /// Emission of the material, multiplied by the emissive texture at uvs.
+    pub factor: Vec3,
+    pub uvs: [Vec2; 3],
+    /// Index in Scene::images, u32::MAX without a texture.
+    pub texture: u32,
+}
+
+impl EmissiveTriangle {
+    pub fn area(&self) -> f32 {
+        (self.p1 - self.p0).cross(self.p2 - self.p0).norm() * 0.5
+    }
+
+    pub fn power(&self) -> f32 {
+        self.area() * self.emission.luminance()
+    }
+}
+
+/// Entry of the alias table, laid out like Alias in types.hlsl. Column i
+/// is picked with probability p, otherwise its alias a.
+#[derive(Debug, Default, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct Alias {
+    pub p: f32,
+    pub a: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct LightSamplingTables {
+    pub triangles: Vec<EmissiveTriangle>,
+    /// Probability of sampling each triangle.
+    pub pdf: Vec<f32>,
+    /// Running sum of pdf, ending at 1.
+    pub cdf: Vec<f32>,
+    pub alias_table: Vec<Alias>,
+    /// Inverse of the total power, the area density of a point on triangle i
+    /// is luminance(emission) * pdf_normalization.
+    pub pdf_normalization: f32,
+    /// Index of the first triangle of each primitive of each mesh instance,
+    /// in the order of SceneView::flatten, u32::MAX if it's not emissive or
+    /// the tables are empty.
+    pub first_triangles: Vec<u32>,
+}
+
+/// Alias method from:
+/// https://www.keithschwarz.com/interesting/code/?dir=alias-method
+pub fn create_alias_table(mut probabilities: Vec<f64>) -> Vec<Alias> {
+    assert!(probabilities.len() < u32::MAX as usize);
+
+    let n = probabilities.len() as f64;
+    let avg = 1.0 / n;
+
+    let mut small: Vec<u32> = Vec::new();
+    let mut large: Vec<u32> = Vec::new();
+
+    for (i, p) in probabilities.iter().enumerate() {
+        if *p < avg {
+            small.push(i as u32);
+        } else {
+            large.push(i as u32);
+        }
+    }
+
+    let mut table = vec![Alias { p: 0.0, a: u32::MAX }; probabilities.len()];
+
+    while !small.is_empty() && !large.is_empty() {
+        let s = small.pop().unwrap() as usize;
+        let l = large.pop().unwrap() as usize;
+
+        table[s].p = (probabilities[s] * n) as f32;
+        table[s].a = l as u32;
+
+        probabilities[l] = (probabilities[l] + probabilities[s]) - avg;
+
+        if probabilities[l] >= avg {
+            large.push(l as u32);
+        } else {
+            small.push(l as u32);
+        }
+    }
+
+    for s in small {
+        table[s as usize].p = 1.0;
+    }
+
+    for l in large {
+        table[l as usize].p = 1.0;
+    }
+
+    table
+}
+
+impl LightSamplingTables {
+    /// Triangles without power are never sampled, the tables are empty if
+    /// none has any. Negative or non-finite powers, from float textures,
+    /// count as 0 and the emission of their triangles is zeroed so the pdf
+    /// of the shaders matches.
+    pub fn from_triangles(mut triangles: Vec<EmissiveTriangle>) -> Self {
+        for t in triangles.iter_mut() {
+            let power = t.power();
+            if !power.is_finite() || power < 0.0 {
+                t.emission = Vec3::from_scalar(0.0);
+            }
+        }
+        let power: Vec<f64> = triangles.iter().map(|t| t.power().max(0.0) as f64).collect();
+        let sum: f64 = power.iter().sum();
+        if sum <= 0.0 {
+            return LightSamplingTables::default();
+        }
+
+        let mut cdf = Vec::with_capacity(power.len());
+        let mut running = 0.0;
+        for p in &power {
+            running += p;
+            cdf.push((running / sum) as f32);
+        }
+        let pdf: Vec<f64> = power.iter().map(|p| p / sum).collect();
+
+        LightSamplingTables {
+            triangles,
+            pdf: pdf.iter().map(|&p| p as f32).collect(),
+            cdf,
+            alias_table: create_alias_table(pdf),
+            pdf_normalization: (1.0 / sum) as f32,
+            first_triangles: Vec::new(),
+        }
+    }
+
+    /// Every emissive triangle of every mesh instance.
+    pub fn from_scene(scene: &SceneView) -> Self {
+        let mut triangles = Vec::new();
+        let mut first_triangles = Vec::new();
+        for instance in scene.flatten() {
+            for p in scene.meshes[instance.mesh as usize].primitives.iter() {
+                let material = &scene.materials[p.material as usize];
+                let factor = material.emission();
+                if factor.max_element() <= 0.0 {
+                    first_triangles.push(u32::MAX);
+                    continue;
+                }
+                first_triangles.push(triangles.len() as u32);
+                let image = material.emissive_texture.map(|t| &scene.images[t as usize]);
+
+                for t in p.indices.chunks_exact(3) {
+                    let [p0, p1, p2] = [0, 1, 2].map(|i| {
+                        (instance.transform * p.positions[t[i] as usize].extend(1.0)).xyz()
+                    });
+                    let uvs = [0, 1, 2].map(|i| p.uvs[t[i] as usize]);
+                    let emission = match image {
+                        Some(image) => factor * average_texel(image, uvs),
+                        None => factor,
+                    };
+                    let texture = material.emissive_texture.unwrap_or(u32::MAX);
+                    triangles.push(EmissiveTriangle { p0, p1, p2, emission, factor, uvs, texture });
+                }
+            }
+        }
+        let mut tables = Self::from_triangles(triangles);
+        if tables.triangles.is_empty() {
+            first_triangles.fill(u32::MAX);
+        }
+        tables.first_triangles = first_triangles;
+        tables
+    }
+
+    /// Index of the first triangle with cdf >= u, like sampleAreaLights.
+    /// None if the tables are empty.
+    pub fn sample_cdf(&self, u: f32) -> Option<usize> {
+        let last = self.cdf.len().checked_sub(1)?;
+        Some(self.cdf.partition_point(|&c| c < u).min(last))
+    }
+
+    /// Column from u and alias from v, both in [0, 1). None if the tables
+    /// are empty.
+    pub fn sample_alias(&self, u: f32, v: f32) -> Option<usize> {
+        let n = self.alias_table.len();
+        let i = ((u * n as f32) as usize).min(n.checked_sub(1)?);
+        Some(if v <= self.alias_table[i].p { i } else { self.alias_table[i].a as usize })
+    }
+}
+
+/// Texels visited per triangle, larger footprints use a coarser level.
+const MAX_FOOTPRINT_TEXELS: i64 = 4096;
+
+/// Average of the texels of the first layer with centers inside the UV
+/// triangle, with repeat addressing. Footprints smaller than a texel use the
+/// texel at the centroid. Block compressed images are not decoded and count
+/// as white.
+fn average_texel(image: &ImageView, uvs: [Vec2; 3]) -> Vec3 {
+    if image.format.is_compressed() {
+        return Vec3::from_scalar(1.0);
+    }
+
+    let min = Vec2::new(uvs.iter().map(|p| p.x).fold(f32::INFINITY, f32::min),
+                        uvs.iter().map(|p| p.y).fold(f32::INFINITY, f32::min));
+    let max = Vec2::new(uvs.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max),
+                        uvs.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max));
+    // Texel centers inside the bounding box, the loop visits
+    // (x1 - x0 + 1) * (y1 - y0 + 1) of them and at least one per row
+    let bounds = |level: u32| {
+        let (w, h) = image.mip_extent(level);
+        [(min.x * w as f32 - 0.5).ceil() as i64, (max.x * w as f32 - 0.5).floor() as i64,
+         (min.y * h as f32 - 0.5).ceil() as i64, (max.y * h as f32 - 0.5).floor() as i64]
+    };
+    let visited = |[x0, x1, y0, y1]: [i64; 4]| {
+        (x1 - x0 + 1).max(1).saturating_mul((y1 - y0 + 1).max(1))
+    };
+    let mut level = 0;
+    while level + 1 < image.mip_levels && visited(bounds(level)) > MAX_FOOTPRINT_TEXELS {
+        level += 1;
+    }
+    let (w, h) = image.mip_extent(level);
+    let centroid = (uvs[0] + uvs[1] + uvs[2]) * (1.0 / 3.0);
+    let at = |p: Vec2| texel(image, level, (p.x * w as f32).floor() as i64, (p.y * h as f32).floor() as i64);
+
+    let e1 = uvs[1] - uvs[0];
+    let e2 = uvs[2] - uvs[0];
+    let det = e1.x * e2.y - e1.y * e2.x;
+    let [x0, x1, y0, y1] = bounds(level);
+    if det == 0.0 || visited([x0, x1, y0, y1]) > MAX_FOOTPRINT_TEXELS {
+        return at(centroid);
+    }
+
+    let mut sum = Vec3::from_scalar(0.0);
+    let mut count = 0;
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            let v = Vec2::new((x as f32 + 0.5) / w as f32, (y as f32 + 0.5) / h as f32) - uvs[0];
+            let s = (v.x * e2.y - v.y * e2.x) / det;
+            let t = (e1.x * v.y - e1.y * v.x) / det;
+            if s >= 0.0 && t >= 0.0 && s + t <= 1.0 {
+                sum += texel(image, level, x, y);
+                count += 1;
+            }
+        }
+    }
+    if count == 0 {
+        return at(centroid);
+    }
+    sum * (1.0 / count as f32)
+}
+
+/// Linear RGB of a texel, missing channels are 0 like in the shaders.
+fn texel(image: &ImageView, level: u32, x: i64, y: i64) -> Vec3 {
+    let (w, h) = image.mip_extent(level);
+    let (x, y) = (x.rem_euclid(w as i64) as usize, y.rem_euclid(h as i64) as usize);
+    let size = image.format.block_bytes() as usize;
+    let start = (y * w as usize + x) * size;
+    let bytes = &image.subresource(0, level)[start..start + size];
+
+    let mut rgb = [0.0; 3];
+    match image.format {
+        Format::R8 | Format::RG8 | Format::RGBA8 | Format::SRGBA8 => {
+            for (c, &b) in rgb.iter_mut().zip(bytes) {
+                let v = b as f32 / 255.0;
+                *c = if image.format.is_srgb() { srgb_eotf(v) } else { v };
+            }
+        },
+        Format::RGBA16F => {
+            for (c, b) in rgb.iter_mut().zip(bytes.chunks_exact(2)) {
+                *c = f16_to_f32(u16::from_le_bytes([b[0], b[1]]));
+            }
+        },
+        Format::RGBA32F => {
+            for (c, b) in rgb.iter_mut().zip(bytes.chunks_exact(4)) {
+                *c = f32::from_le_bytes(b.try_into().unwrap());
+            }
+        },
+        _ => unreachable!("Block compressed texels are not decoded"),
+    }
+    Vec3::from_slice(&rgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Image, Material, Mesh, Node, Primitive, Scene};
+
+    fn triangle(size: f32, emission: f32) -> EmissiveTriangle {
+        EmissiveTriangle {
+            p0: Vec3::new(0., 0., 0.),
+            p1: Vec3::new(size, 0., 0.),
+            p2: Vec3::new(0., size, 0.),
+            emission: Vec3::from_scalar(emission),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn proportional_to_power() {
+        // Powers 0.5, 2, 0, 4.5 and 2
+        let tables = LightSamplingTables::from_triangles(vec![
+            triangle(1., 1.), triangle(2., 1.), triangle(1., 0.), triangle(3., 1.), triangle(1., 4.),
+        ]);
+        let expected = [0.5 / 9., 2. / 9., 0., 4.5 / 9., 2. / 9.];
+        for (p, e) in tables.pdf.iter().zip(expected) {
+            math::assert_approx_eq!(*p, e, epsilon = 1e-6);
+        }
+        math::assert_approx_eq!(tables.pdf_normalization, 1. / 9.);
+        assert_eq!(*tables.cdf.last().unwrap(), 1.0);
+
+        let n = 300;
+        let mut cdf_counts = [0usize; 5];
+        let mut alias_counts = [0usize; 5];
+        for i in 0..n {
+            let u = (i as f32 + 0.5) / n as f32;
+            cdf_counts[tables.sample_cdf(u).unwrap()] += 1;
+            for j in 0..n {
+                let v = (j as f32 + 0.5) / n as f32;
+                alias_counts[tables.sample_alias(u, v).unwrap()] += 1;
+            }
+        }
+        for i in 0..5 {
+            math::assert_approx_eq!(cdf_counts[i] as f32 / n as f32, expected[i], epsilon = 2. / n as f32);
+            math::assert_approx_eq!(alias_counts[i] as f32 / (n * n) as f32, expected[i], epsilon = 1e-2);
+        }
+        assert_eq!((cdf_counts[2], alias_counts[2]), (0, 0));
+
+        // Negative and NaN texels
+        let tables = LightSamplingTables::from_triangles(vec![
+            triangle(1., -1.5), triangle(1., 2.), triangle(1., f32::NAN), triangle(1., f32::INFINITY),
+        ]);
+        assert_eq!(tables.pdf, [0., 1., 0., 0.]);
+        assert_eq!(tables.cdf, [0., 1., 1., 1.]);
+        assert_eq!(tables.pdf_normalization, 1.);
+        assert_eq!(tables.triangles.iter().map(|t| t.emission.x).collect::<Vec<_>>(), [0., 2., 0., 0.]);
+        assert_eq!(tables.sample_alias(0.1, 0.9), Some(1));
+
+        let empty = LightSamplingTables::from_triangles(vec![triangle(1., 0.)]);
+        assert!(empty.triangles.is_empty() && empty.cdf.is_empty() && empty.alias_table.is_empty());
+        assert_eq!((empty.sample_cdf(0.5), empty.sample_alias(0.5, 0.5)), (None, None));
+    }
+
+    #[test]
+    fn from_scene() {
+        let mut scene = Scene::new();
+        // Left half black and right half white
+        scene.images.push(Image {
+            width: 4,
+            height: 1,
+            format: Format::SRGBA8,
+            mip_levels: 1,
+            array_layers: 1,
+            data: [[0, 0, 0, 255], [0, 0, 0, 255], [255; 4], [255; 4]].concat(),
+        });
+        scene.materials.push(Material::default());
+        scene.materials.push(Material {
+            emissive_factor: Vec3::from_scalar(1.),
+            emissive_strength: 2.,
+            emissive_texture: Some(0),
+            ..Default::default()
+        });
+        let quad = |material| Primitive {
+            positions: vec![Vec3::new(0., 0., 0.), Vec3::new(1., 0., 0.), Vec3::new(1., 1., 0.),
+                            Vec3::new(0., 1., 0.)],
+            normals: vec![Vec3::new(0., 0., 1.); 4],
+            tangents: vec![Vec3::new(1., 0., 0.); 4],
+            uvs: vec![Vec2::new(0., 0.), Vec2::new(0.5, 0.), Vec2::new(0.5, 1.), Vec2::new(0., 1.)],
+            indices: vec![0, 1, 2, 0, 2, 3],
+            material,
+        };
+        scene.meshes.push(Mesh { primitives: vec![quad(0), quad(1)] });
+        scene.add_node(Node { mesh: Some(0), ..Default::default() });
+        let mut shifted = quad(1);
+        shifted.uvs.iter_mut().for_each(|uv| uv.x += 0.5);
+        // Outside of [0, 1], wrapped
+        shifted.uvs.iter_mut().for_each(|uv| uv.y += 3.);
+        scene.meshes.push(Mesh { primitives: vec![shifted] });
+        scene.add_node(Node { mesh: Some(1), ..Default::default() });
+
+        let tables = LightSamplingTables::from_scene(&scene.view());
+        let emission: Vec<f32> = tables.triangles.iter().map(|t| t.emission.x).collect();
+        assert_eq!(emission, [0., 0., 2., 2.]);
+        assert_eq!(tables.pdf, [0., 0., 0.5, 0.5]);
+        assert!((2..4).contains(&tables.sample_cdf(0.01).unwrap()));
+        assert_eq!(tables.first_triangles, [u32::MAX, 0, 2]);
+        let t = tables.triangles[3];
+        assert_eq!((t.factor.x, t.texture), (2., 0));
+        assert_eq!(t.uvs.map(|uv| uv.to_slice()), [[0.5, 3.], [1., 4.], [0.5, 4.]]);
+
+        // Without power no triangle can be looked up
+        scene.images[0].data.fill(0);
+        let tables = LightSamplingTables::from_scene(&scene.view());
+        assert!(tables.triangles.is_empty());
+        assert_eq!(tables.first_triangles, [u32::MAX; 3]);
+    }
+
+    #[test]
+    fn texels() {
+        let image = Image {
+            width: 2,
+            height: 2,
+            format: Format::RGBA16F,
+            mip_levels: 2,
+            array_layers: 1,
+            // 1.0, 2.0, 0.5, 1.0 then -1.5
+            data: [[0x3c00u16, 0x4000, 0x3800, 0x3c00]; 4].concat().into_iter()
+                .chain([0xbe00, 0, 0, 0])
+                .flat_map(u16::to_le_bytes)
+                .collect(),
+        };
+        let image = image.view();
+        assert_eq!(texel(&image, 0, 3, -1).to_slice(), [1.0, 2.0, 0.5]);
+        assert_eq!(texel(&image, 1, 0, 0).to_slice(), [-1.5, 0.0, 0.0]);
+
+        // Tiny footprint, texel at the centroid
+        let uvs = [Vec2::new(0.7, 0.2), Vec2::new(0.71, 0.2), Vec2::new(0.7, 0.21)];
+        assert_eq!(average_texel(&image, uvs).to_slice(), [1.0, 2.0, 0.5]);
+        // Large footprint, last level
+        let uvs = [Vec2::new(0., 0.), Vec2::new(100., 0.), Vec2::new(0., 100.)];
+        assert_eq!(average_texel(&image, uvs).to_slice(), [-1.5, 0.0, 0.0]);
+        // Sliver with a small area but 6000 rows at level 0
+        let uvs = [Vec2::new(0.25, 0.), Vec2::new(0.2501, 0.), Vec2::new(0.25, 3000.)];
+        assert_eq!(average_texel(&image, uvs).to_slice(), [-1.5, 0.0, 0.0]);
+    }
+}
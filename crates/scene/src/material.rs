@@ -0,0 +1,401 @@
+//! glTF metallic-roughness materials. Textures are indices in Scene::images
+//! and are multiplied by their factor, a material without textures is
+//! described by the factors alone.
+
+use math::vec::{Vec3, Vec4};
+
+use crate::{
+    deserialize_index, serialize_index, with_offset, Deserialize, DeserializeError, Serialize,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    /// Alpha tested against Material::alpha_cutoff.
+    Mask,
+    Blend,
+}
+
+impl From<AlphaMode> for u32 {
+    fn from(m: AlphaMode) -> u32 {
+        match m {
+            AlphaMode::Opaque => 0,
+            AlphaMode::Mask   => 1,
+            AlphaMode::Blend  => 2,
+        }
+    }
+}
+
+impl TryFrom<u32> for AlphaMode {
+    type Error = &'static str;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AlphaMode::Opaque),
+            1 => Ok(AlphaMode::Mask),
+            2 => Ok(AlphaMode::Blend),
+            _ => Err("Unknown alpha mode"),
+        }
+    }
+}
+
+/// Channels of Material::orm_texture holding each parameter, 0 to 3 for R
+/// to A.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrmChannels {
+    /// None if the occlusion is not packed in the texture.
+    pub occlusion: Option<u8>,
+    pub roughness: u8,
+    pub metallic: u8,
+}
+
+impl OrmChannels {
+    /// glTF metallicRoughnessTexture, also used by the Bistro specular
+    /// textures.
+    pub const GLTF: OrmChannels = OrmChannels { occlusion: Some(0), roughness: 1, metallic: 2 };
+}
+
+impl Default for OrmChannels {
+    fn default() -> Self {
+        OrmChannels::GLTF
+    }
+}
+
+/// KHR_materials_clearcoat, disabled when factor is 0.
+#[derive(Debug, Clone, Copy)]
+pub struct Clearcoat {
+    pub factor: f32,
+    /// Factor in R.
+    pub texture: Option<u32>,
+    pub roughness_factor: f32,
+    /// Roughness in G.
+    pub roughness_texture: Option<u32>,
+    pub normal_texture: Option<u32>,
+}
+
+impl Default for Clearcoat {
+    fn default() -> Self {
+        Clearcoat {
+            factor: 0.0,
+            texture: None,
+            roughness_factor: 0.0,
+            roughness_texture: None,
+            normal_texture: None,
+        }
+    }
+}
+
+/// KHR_materials_sheen, disabled when the color is black.
+#[derive(Debug, Clone, Copy)]
+pub struct Sheen {
+    pub color_factor: Vec3,
+    /// sRGB color in RGB.
+    pub color_texture: Option<u32>,
+    pub roughness_factor: f32,
+    /// Roughness in A.
+    pub roughness_texture: Option<u32>,
+}
+
+impl Default for Sheen {
+    fn default() -> Self {
+        Sheen {
+            color_factor: Vec3::from_scalar(0.0),
+            color_texture: None,
+            roughness_factor: 0.0,
+            roughness_texture: None,
+        }
+    }
+}
+
+/// Defaults are the glTF ones.
+#[derive(Debug, Clone, Copy)]
+pub struct Material {
+    pub base_color_factor: Vec4,
+    /// sRGB color in RGB and alpha in A.
+    pub base_color_texture: Option<u32>,
+
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Occlusion, roughness and metallic in the channels given by
+    /// orm_channels.
+    pub orm_texture: Option<u32>,
+    pub orm_channels: OrmChannels,
+
+    /// Occlusion in R, when it is not packed in orm_texture.
+    pub occlusion_texture: Option<u32>,
+    pub occlusion_strength: f32,
+
+    /// Tangent space normal map.
+    pub normal_texture: Option<u32>,
+    pub normal_scale: f32,
+
+    pub emissive_factor: Vec3,
+    /// KHR_materials_emissive_strength, multiplies emissive_factor.
+    pub emissive_strength: f32,
+    /// sRGB color in RGB.
+    pub emissive_texture: Option<u32>,
+
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+
+    /// KHR_materials_ior
+    pub ior: f32,
+    /// KHR_materials_transmission, factor in R of the texture.
+    pub transmission_factor: f32,
+    pub transmission_texture: Option<u32>,
+    pub clearcoat: Clearcoat,
+    pub sheen: Sheen,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            base_color_factor: Vec4::from_scalar(1.0),
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            orm_texture: None,
+            orm_channels: OrmChannels::GLTF,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            normal_texture: None,
+            normal_scale: 1.0,
+            emissive_factor: Vec3::from_scalar(0.0),
+            emissive_strength: 1.0,
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+            ior: 1.5,
+            transmission_factor: 0.0,
+            transmission_texture: None,
+            clearcoat: Clearcoat::default(),
+            sheen: Sheen::default(),
+        }
+    }
+}
+
+impl Material {
+    /// Emitted radiance, to be multiplied by emissive_texture.
+    pub fn emission(&self) -> Vec3 {
+        self.emissive_factor * self.emissive_strength
+    }
+
+    /// Images referenced by the material.
+    pub fn textures(&self) -> impl Iterator<Item = u32> {
+        [
+            self.base_color_texture, self.orm_texture, self.occlusion_texture,
+            self.normal_texture, self.emissive_texture, self.transmission_texture,
+            self.clearcoat.texture, self.clearcoat.roughness_texture, self.clearcoat.normal_texture,
+            self.sheen.color_texture, self.sheen.roughness_texture,
+        ].into_iter().flatten()
+    }
+}
+
+/// Checks the textures of the materials.
+pub(crate) fn validate(materials: &[Material], image_count: usize) -> Result<(), DeserializeError> {
+    for (i, m) in materials.iter().enumerate() {
+        if m.textures().any(|t| t as usize >= image_count) {
+            return Err(DeserializeError::InvalidMaterial { index: i as u32 });
+        }
+    }
+    Ok(())
+}
+
+fn serialize_channel(channel: Option<u8>, buf: &mut Vec<u8>) {
+    (&channel.unwrap_or(u8::MAX)).serialize_buf(buf);
+}
+
+fn deserialize_channel(buf: &mut &[u8]) -> Result<Option<u8>, DeserializeError> {
+    match <&u8>::deserialize(buf)? {
+        u8::MAX => Ok(None),
+        c if c < 4 => Ok(Some(c)),
+        channel => Err(DeserializeError::InvalidChannel { channel }),
+    }
+}
+
+impl Serialize for OrmChannels {
+    fn serialize_buf(&self, buf: &mut Vec<u8>) {
+        serialize_channel(self.occlusion, buf);
+        serialize_channel(Some(self.roughness), buf);
+        serialize_channel(Some(self.metallic), buf);
+    }
+}
+
+impl Deserialize for OrmChannels {
+    type Item = OrmChannels;
+    type AllocatorItem = OrmChannels;
+
+    fn deserialize(buf: &mut &[u8]) -> Result<OrmChannels, DeserializeError> {
+        with_offset(buf, |buf| {
+            let occlusion = deserialize_channel(buf)?;
+            let mut required = || deserialize_channel(buf)?
+                .ok_or(DeserializeError::InvalidChannel { channel: u8::MAX });
+            Ok(OrmChannels { occlusion, roughness: required()?, metallic: required()? })
+        })
+    }
+
+    fn deserialize_in(buf: &mut &[u8], _a: std::alloc::Global) -> Result<OrmChannels, DeserializeError> {
+        Self::deserialize(buf)
+    }
+}
+
+impl Serialize for Material {
+    fn serialize_buf(&self, buf: &mut Vec<u8>) {
+        (&self.base_color_factor).serialize_buf(buf);
+        serialize_index(self.base_color_texture, buf);
+        (&self.metallic_factor).serialize_buf(buf);
+        (&self.roughness_factor).serialize_buf(buf);
+        serialize_index(self.orm_texture, buf);
+        self.orm_channels.serialize_buf(buf);
+        serialize_index(self.occlusion_texture, buf);
+        (&self.occlusion_strength).serialize_buf(buf);
+        serialize_index(self.normal_texture, buf);
+        (&self.normal_scale).serialize_buf(buf);
+        (&self.emissive_factor).serialize_buf(buf);
+        (&self.emissive_strength).serialize_buf(buf);
+        serialize_index(self.emissive_texture, buf);
+        (&u32::from(self.alpha_mode)).serialize_buf(buf);
+        (&self.alpha_cutoff).serialize_buf(buf);
+        (&(self.double_sided as u32)).serialize_buf(buf);
+        (&self.ior).serialize_buf(buf);
+        (&self.transmission_factor).serialize_buf(buf);
+        serialize_index(self.transmission_texture, buf);
+
+        let c = &self.clearcoat;
+        (&c.factor).serialize_buf(buf);
+        serialize_index(c.texture, buf);
+        (&c.roughness_factor).serialize_buf(buf);
+        serialize_index(c.roughness_texture, buf);
+        serialize_index(c.normal_texture, buf);
+
+        let s = &self.sheen;
+        (&s.color_factor).serialize_buf(buf);
+        serialize_index(s.color_texture, buf);
+        (&s.roughness_factor).serialize_buf(buf);
+        serialize_index(s.roughness_texture, buf);
+    }
+}
+
+impl Deserialize for Material {
+    type Item = Material;
+    type AllocatorItem = Material;
+
+    fn deserialize(buf: &mut &[u8]) -> Result<Material, DeserializeError> {
+        with_offset(buf, |buf| Ok(Material {
+            base_color_factor: <&Vec4>::deserialize(buf)?,
+            base_color_texture: deserialize_index(buf)?,
+            metallic_factor: <&f32>::deserialize(buf)?,
+            roughness_factor: <&f32>::deserialize(buf)?,
+            orm_texture: deserialize_index(buf)?,
+            orm_channels: OrmChannels::deserialize(buf)?,
+            occlusion_texture: deserialize_index(buf)?,
+            occlusion_strength: <&f32>::deserialize(buf)?,
+            normal_texture: deserialize_index(buf)?,
+            normal_scale: <&f32>::deserialize(buf)?,
+            emissive_factor: <&Vec3>::deserialize(buf)?,
+            emissive_strength: <&f32>::deserialize(buf)?,
+            emissive_texture: deserialize_index(buf)?,
+            alpha_mode: {
+                let value = <&u32>::deserialize(buf)?;
+                AlphaMode::try_from(value).map_err(|_| DeserializeError::UnknownAlphaMode { value })?
+            },
+            alpha_cutoff: <&f32>::deserialize(buf)?,
+            double_sided: <&u32>::deserialize(buf)? != 0,
+            ior: <&f32>::deserialize(buf)?,
+            transmission_factor: <&f32>::deserialize(buf)?,
+            transmission_texture: deserialize_index(buf)?,
+            clearcoat: Clearcoat {
+                factor: <&f32>::deserialize(buf)?,
+                texture: deserialize_index(buf)?,
+                roughness_factor: <&f32>::deserialize(buf)?,
+                roughness_texture: deserialize_index(buf)?,
+                normal_texture: deserialize_index(buf)?,
+            },
+            sheen: Sheen {
+                color_factor: <&Vec3>::deserialize(buf)?,
+                color_texture: deserialize_index(buf)?,
+                roughness_factor: <&f32>::deserialize(buf)?,
+                roughness_texture: deserialize_index(buf)?,
+            },
+        }))
+    }
+
+    fn deserialize_in(buf: &mut &[u8], _a: std::alloc::Global) -> Result<Material, DeserializeError> {
+        Self::deserialize(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_material() -> Material {
+        Material {
+            base_color_factor: Vec4::new(0.5, 0.25, 1.0, 0.75),
+            base_color_texture: Some(3),
+            metallic_factor: 0.0,
+            roughness_factor: 0.4,
+            orm_texture: Some(1),
+            orm_channels: OrmChannels { occlusion: None, roughness: 3, metallic: 0 },
+            occlusion_texture: Some(2),
+            occlusion_strength: 0.8,
+            normal_scale: -1.0,
+            emissive_factor: Vec3::new(1.0, 0.5, 0.0),
+            emissive_strength: 10.0,
+            emissive_texture: Some(0),
+            alpha_mode: AlphaMode::Mask,
+            alpha_cutoff: 0.3,
+            double_sided: true,
+            ior: 1.33,
+            transmission_factor: 0.9,
+            clearcoat: Clearcoat { factor: 1.0, roughness_factor: 0.1, normal_texture: Some(4), ..Default::default() },
+            sheen: Sheen { color_factor: Vec3::new(0.2, 0.2, 0.3), roughness_texture: Some(5), ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for m in [Material::default(), test_material()] {
+            let buf = m.serialize();
+            let mut view = &buf[..];
+            let d = Material::deserialize(&mut view).unwrap();
+            assert!(view.is_empty());
+            // Bitwise equality of every field
+            assert_eq!(format!("{d:?}"), format!("{m:?}"));
+        }
+
+        let m = test_material();
+        math::assert_approx_eq!(m.emission(), Vec3::new(10.0, 5.0, 0.0));
+        assert_eq!(m.textures().collect::<Vec<_>>(), [3, 1, 2, 0, 4, 5]);
+        assert_eq!(Material::default().textures().count(), 0);
+
+        assert_eq!(validate(&[Material::default(), m], 6), Ok(()));
+        assert_eq!(validate(&[Material::default(), m], 5), Err(DeserializeError::InvalidMaterial { index: 1 }));
+    }
+
+    #[test]
+    fn errors() {
+        let buf = test_material().serialize();
+
+        // Alpha mode after the emissive texture
+        let at = 16 + 4 + 4 + 4 + 4 + 3 + 4 + 4 + 4 + 4 + 12 + 4 + 4;
+        let mut b = buf.clone();
+        b[at..at + 4].copy_from_slice(&3u32.to_le_bytes());
+        assert_eq!(Material::deserialize(&mut &b[..]).unwrap_err(),
+                   DeserializeError::UnknownAlphaMode { value: 3 });
+
+        // Channels after the ORM texture
+        let at = 16 + 4 + 4 + 4 + 4;
+        let mut b = buf.clone();
+        b[at] = 4;
+        assert_eq!(Material::deserialize(&mut &b[..]).unwrap_err(),
+                   DeserializeError::InvalidChannel { channel: 4 });
+        let mut b = buf;
+        b[at + 1] = u8::MAX;
+        assert_eq!(Material::deserialize(&mut &b[..]).unwrap_err(),
+                   DeserializeError::InvalidChannel { channel: u8::MAX });
+    }
+}
@@ -0,0 +1,354 @@
+//! Zero-copy view of a scene container. Vectors are borrowed straight from
+//! the data, which must be aligned to SECTION_ALIGNMENT like a memory mapped
+//! file, so they can be uploaded without intermediate copies.
+
+use std::alloc::{Allocator, Global};
+
+use bytemuck::{try_cast_slice, Pod};
+use math::{
+    vec::{Vec2, Vec3},
+    mat::Mat4,
+};
+
+use crate::{
+    container::Container, deserialize_items, CameraDesc, CameraInstance, deserialize_vec_in, graph, image::{self, ImageHeader},
+    light, material, validate_meshes, vec_bytes, with_offset, ContainerError, Deserialize, DeserializeError, Format, Image, Light, LightInstance, Material,
+    MeshInstance, Node, Primitive, Scene, SectionKind,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct PrimitiveView<'a> {
+    pub positions: &'a [Vec3],
+    pub normals: &'a [Vec3],
+    pub tangents: &'a [Vec3],
+    pub uvs: &'a [Vec2],
+    pub indices: &'a [u32],
+
+    /// Index in SceneView::materials
+    pub material: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct MeshView<'a> {
+    pub primitives: Vec<PrimitiveView<'a>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImageView<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub format: Format,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub data: &'a [u8],
+}
+
+#[derive(Debug)]
+pub struct SceneView<'a> {
+    pub meshes: Vec<MeshView<'a>>,
+    pub images: Vec<ImageView<'a>>,
+    pub materials: Vec<Material>,
+    pub lights: Vec<Light>,
+    pub cameras: Vec<CameraDesc>,
+    pub nodes: Vec<Node>,
+}
+
+fn vec_view<'a, T: Pod>(buf: &mut &'a [u8]) -> Result<&'a [T], DeserializeError> {
+    try_cast_slice(vec_bytes::<T>(buf)?).map_err(|_| DeserializeError::Misaligned)
+}
+
+impl<'a> PrimitiveView<'a> {
+    fn deserialize(buf: &mut &'a [u8]) -> Result<PrimitiveView<'a>, DeserializeError> {
+        with_offset(buf, |buf| Ok(PrimitiveView {
+            positions: vec_view(buf)?,
+            normals: vec_view(buf)?,
+            tangents: vec_view(buf)?,
+            uvs: vec_view(buf)?,
+            indices: vec_view(buf)?,
+
+            material: <&u32>::deserialize(buf)?,
+        }))
+    }
+}
+
+impl<'a> MeshView<'a> {
+    fn deserialize(buf: &mut &'a [u8]) -> Result<MeshView<'a>, DeserializeError> {
+        Ok(MeshView {
+            primitives: deserialize_vec_in(buf, Global, PrimitiveView::deserialize)?,
+        })
+    }
+}
+
+impl<'a> ImageView<'a> {
+    fn deserialize(buf: &mut &'a [u8]) -> Result<ImageView<'a>, DeserializeError> {
+        with_offset(buf, |buf| {
+            let (h, size) = ImageHeader::deserialize(buf)?;
+            let data = vec_view(buf)?;
+            if data.len() != size {
+                return Err(DeserializeError::InvalidImage);
+            }
+            Ok(ImageView {
+                width: h.width,
+                height: h.height,
+                format: h.format,
+                mip_levels: h.mip_levels,
+                array_layers: h.array_layers,
+                data,
+            })
+        })
+    }
+
+    pub fn mip_extent(&self, level: u32) -> (u32, u32) {
+        image::mip_extent(self.width, self.height, level)
+    }
+
+    pub fn subresource(&self, layer: u32, level: u32) -> &'a [u8] {
+        &self.data[image::subresource_range(self.format, self.width, self.height, self.mip_levels,
+                                            layer, level)]
+    }
+}
+
+impl<A: Allocator + Copy> Primitive<A> {
+    pub fn view(&self) -> PrimitiveView<'_> {
+        PrimitiveView {
+            positions: &self.positions,
+            normals: &self.normals,
+            tangents: &self.tangents,
+            uvs: &self.uvs,
+            indices: &self.indices,
+            material: self.material,
+        }
+    }
+}
+
+impl<A: Allocator + Copy> Image<A> {
+    pub fn view(&self) -> ImageView<'_> {
+        ImageView {
+            width: self.width,
+            height: self.height,
+            format: self.format,
+            mip_levels: self.mip_levels,
+            array_layers: self.array_layers,
+            data: &self.data,
+        }
+    }
+}
+
+impl<A: Allocator + Copy> Scene<A> {
+    /// View borrowing the vectors of the scene, so loaded and mapped scenes
+    /// go through the same code.
+    pub fn view(&self) -> SceneView<'_> {
+        SceneView {
+            meshes: self.meshes.iter()
+                .map(|m| MeshView { primitives: m.primitives.iter().map(Primitive::view).collect() })
+                .collect(),
+            images: self.images.iter().map(Image::view).collect(),
+            materials: self.materials.to_vec(),
+            lights: self.lights.to_vec(),
+            cameras: self.cameras.to_vec(),
+            nodes: self.nodes.to_vec(),
+        }
+    }
+}
+
+impl<'a> SceneView<'a> {
+    /// Only the tables of the sections are allocated.
+    pub fn parse(data: &'a [u8]) -> Result<SceneView<'a>, ContainerError> {
+        let c = Container::parse(data)?;
+
+        let meshes: Vec<MeshView> = c.read_section(SectionKind::Meshes, |s| {
+            deserialize_items(s, Global, MeshView::deserialize)
+        })?;
+        let images: Vec<ImageView> = c.read_section(SectionKind::Images, |s| {
+            deserialize_items(s, Global, ImageView::deserialize)
+        })?;
+        let materials: Vec<Material> = c.read_section(SectionKind::Materials, |s| {
+            let materials = deserialize_items(s, Global, Material::deserialize)?;
+            material::validate(&materials, images.len())?;
+            Ok(materials)
+        })?;
+        validate_meshes(meshes.iter().map(|m| m.primitives.iter().copied()), materials.len())
+            .map_err(|error| ContainerError::Section { kind: SectionKind::Meshes, error })?;
+        let lights = c.read_section(SectionKind::Lights, |s| {
+            let lights: Vec<Light> = deserialize_items(s, Global, Light::deserialize)?;
+            light::validate(&lights, images.len())?;
+            Ok(lights)
+        })?;
+        let cameras: Vec<CameraDesc> = c.read_section(SectionKind::Cameras, |s| {
+            deserialize_items(s, Global, CameraDesc::deserialize)
+        })?;
+        let nodes = c.read_section(SectionKind::Nodes, |s| {
+            let nodes: Vec<Node> = deserialize_items(s, Global, Node::deserialize)?;
+            graph::validate(&nodes, meshes.len(), lights.len(), cameras.len())?;
+            Ok(nodes)
+        })?;
+
+        Ok(SceneView {
+            meshes,
+            images,
+            materials,
+            lights,
+            cameras,
+            nodes,
+        })
+    }
+
+    pub fn world_transforms(&self) -> Vec<Mat4> {
+        graph::world_transforms(&self.nodes)
+    }
+
+    pub fn flatten(&self) -> Vec<MeshInstance> {
+        graph::flatten(&self.nodes)
+    }
+
+    pub fn light_instances(&self) -> Vec<LightInstance> {
+        graph::light_instances(&self.nodes)
+    }
+
+    pub fn camera_instances(&self) -> Vec<CameraInstance> {
+        graph::camera_instances(&self.nodes)
+    }
+
+    /// See graph::insert_root.
+    pub fn insert_root(&mut self, root: Node) {
+        graph::insert_root(&mut self.nodes, root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Image, Light, Mesh, Primitive, Scene, Serialize};
+
+    /// Copy of data aligned to SECTION_ALIGNMENT, like a memory map.
+    fn aligned(data: &[u8]) -> Vec<u128> {
+        let mut v = vec![0u128; data.len().div_ceil(16)];
+        bytemuck::cast_slice_mut::<u128, u8>(&mut v)[..data.len()].copy_from_slice(data);
+        v
+    }
+
+    fn large_scene() -> Scene {
+        let n = 100_000;
+        let mut scene = Scene::new();
+        scene.materials.push(Material { base_color_texture: Some(0), ..Default::default() });
+        for m in 0..4 {
+            let primitive = Primitive {
+                positions: (0..n).map(|i| Vec3::new(i as f32, m as f32, 0.)).collect(),
+                normals: vec![Vec3::new(0., 0., 1.); n],
+                tangents: vec![Vec3::new(1., 0., 0.); n],
+                uvs: vec![Vec2::new(0.5, 0.5); n],
+                // Odd sizes, the next vectors are padded
+                indices: (0..3 * n as u32 - 1).map(|i| i % n as u32).collect(),
+                material: 0,
+            };
+            scene.meshes.push(Mesh { primitives: vec![primitive] });
+            scene.add_node(Node { mesh: Some(m), ..Default::default() });
+        }
+        scene.lights.push(Light::Spot {
+            color: Vec3::new(1., 1., 0.9),
+            intensity: 100.,
+            radius: 0.,
+            range: f32::INFINITY,
+            inner_cone_angle: 0.,
+            outer_cone_angle: 0.5,
+        });
+        scene.add_node(Node { light: Some(0), ..Default::default() });
+        scene.images.push(Image {
+            width: 513,
+            height: 511,
+            format: Format::SRGBA8,
+            mip_levels: 10,
+            array_layers: 1,
+            data: vec![7; Format::SRGBA8.image_size(513, 511, 10, 1).unwrap()],
+        });
+        scene
+    }
+
+    #[test]
+    fn view() {
+        let buf = large_scene().serialize();
+        let data = aligned(&buf);
+        let data = &bytemuck::cast_slice::<u128, u8>(&data)[..buf.len()];
+
+        // Allocations are checked in tests/peak_memory.rs
+        let view = SceneView::parse(data).unwrap();
+        let scene = Scene::from_container(data).unwrap();
+
+        assert_eq!(view.meshes.len(), 4);
+        assert_eq!(view.flatten().len(), 4);
+        for (v, m) in view.meshes.iter().zip(scene.meshes.iter()) {
+            let (v, m) = (&v.primitives[0], &m.primitives[0]);
+            assert_eq!(bytemuck::cast_slice::<Vec3, f32>(v.positions),
+                       bytemuck::cast_slice::<Vec3, f32>(&m.positions));
+            assert_eq!(v.uvs.len(), m.uvs.len());
+            assert_eq!(v.indices, &m.indices[..]);
+            assert_eq!(v.material, m.material);
+            // Borrowed from the data
+            assert!(data.as_ptr_range().contains(&(v.positions.as_ptr() as *const u8)));
+        }
+        assert_eq!(view.images[0].data, &scene.images[0].data[..]);
+        assert_eq!(view.images[0].width, 513);
+        assert_eq!(view.images[0].subresource(0, 9), scene.images[0].subresource(0, 9));
+        assert_eq!(view.materials[0].base_color_texture, Some(0));
+        assert_eq!(format!("{:?}", view.lights), format!("{:?}", scene.lights));
+        assert_eq!(view.light_instances()[0].node, 4);
+    }
+
+    #[test]
+    fn misaligned() {
+        let buf = large_scene().serialize();
+        let data = aligned(&[&[0][..], &buf].concat());
+        let data = &bytemuck::cast_slice::<u128, u8>(&data)[1..buf.len() + 1];
+
+        assert_eq!(SceneView::parse(data).unwrap_err(), ContainerError::Section {
+            kind: SectionKind::Meshes,
+            error: DeserializeError::Misaligned,
+        });
+        // Copies don't care about alignment
+        assert!(Scene::from_container(data).is_ok());
+    }
+
+    #[test]
+    fn owned_view() {
+        let scene = large_scene();
+        let view = scene.view();
+        assert_eq!(view.meshes.len(), 4);
+        assert_eq!(view.meshes[3].primitives[0].indices.as_ptr(), scene.meshes[3].primitives[0].indices.as_ptr());
+        assert_eq!(view.images[0].subresource(0, 9), scene.images[0].subresource(0, 9));
+        assert_eq!(view.images[0].mip_extent(1), scene.images[0].mip_extent(1));
+        assert_eq!(view.flatten().len(), scene.flatten().len());
+    }
+
+    #[test]
+    fn references() {
+        let mut scene = large_scene();
+        scene.meshes[2].primitives[0].material = 1;
+        let buf = scene.serialize();
+        let data = aligned(&buf);
+        assert_eq!(SceneView::parse(&bytemuck::cast_slice::<u128, u8>(&data)[..buf.len()]).unwrap_err(),
+                   ContainerError::Section {
+                       kind: SectionKind::Meshes,
+                       error: DeserializeError::InvalidMesh { index: 2 },
+                   });
+
+        let mut scene = large_scene();
+        scene.meshes[1].primitives[0].indices[0] = u32::MAX;
+        let buf = scene.serialize();
+        let data = aligned(&buf);
+        assert_eq!(SceneView::parse(&bytemuck::cast_slice::<u128, u8>(&data)[..buf.len()]).unwrap_err(),
+                   ContainerError::Section {
+                       kind: SectionKind::Meshes,
+                       error: DeserializeError::InvalidMesh { index: 1 },
+                   });
+
+        let mut scene = large_scene();
+        scene.images.clear();
+        let buf = scene.serialize();
+        let data = aligned(&buf);
+        assert_eq!(SceneView::parse(&bytemuck::cast_slice::<u128, u8>(&data)[..buf.len()]).unwrap_err(),
+                   ContainerError::Section {
+                       kind: SectionKind::Materials,
+                       error: DeserializeError::InvalidMaterial { index: 0 },
+                   });
+    }
+}
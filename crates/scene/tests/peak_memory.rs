@@ -0,0 +1,89 @@
+//! Memory used to parse a scene, in its own test binary since it replaces
+//! the global allocator.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use math::vec::{Vec2, Vec3};
+use scene::{Format, Image, Mesh, Node, Primitive, Scene, SceneView, Serialize};
+
+/// Counts the bytes allocated by the current thread, tests run in parallel.
+struct CountingAllocator;
+
+thread_local! {
+    static CURRENT: Cell<isize> = const { Cell::new(0) };
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+}
+
+fn track(delta: isize) {
+    let _ = CURRENT.try_with(|c| {
+        c.set(c.get() + delta);
+        let _ = PEAK.try_with(|p| p.set(p.get().max(c.get())));
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        track(layout.size() as isize);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        track(-(layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        track(new_size as isize - layout.size() as isize);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Peak of the bytes allocated by f and still alive at some point.
+fn peak_memory<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let start = CURRENT.with(|c| c.get());
+    PEAK.with(|p| p.set(start));
+    let r = f();
+    (r, (PEAK.with(|p| p.get()) - start) as usize)
+}
+
+#[test]
+fn view_borrows_the_data() {
+    let n = 100_000;
+    let mut scene = Scene::new();
+    scene.materials.push(Default::default());
+    for m in 0..4 {
+        scene.meshes.push(Mesh { primitives: vec![Primitive {
+            positions: vec![Vec3::new(m as f32, 0., 0.); n],
+            normals: vec![Vec3::new(0., 0., 1.); n],
+            tangents: vec![Vec3::new(1., 0., 0.); n],
+            uvs: vec![Vec2::new(0.5, 0.5); n],
+            indices: (0..3 * n as u32).map(|i| i % n as u32).collect(),
+            material: 0,
+        }] });
+        scene.add_node(Node { mesh: Some(m), ..Default::default() });
+    }
+    scene.images.push(Image {
+        width: 512,
+        height: 512,
+        format: Format::SRGBA8,
+        mip_levels: 1,
+        array_layers: 1,
+        data: vec![7; 512 * 512 * 4],
+    });
+    let buf = scene.serialize();
+
+    // Aligned to SECTION_ALIGNMENT, like a memory map
+    let mut aligned = vec![0u128; buf.len().div_ceil(16)];
+    bytemuck::cast_slice_mut::<u128, u8>(&mut aligned)[..buf.len()].copy_from_slice(&buf);
+    let data = &bytemuck::cast_slice::<u128, u8>(&aligned)[..buf.len()];
+
+    let (view, peak) = peak_memory(|| SceneView::parse(data).unwrap());
+    let (_, copy_peak) = peak_memory(|| Scene::from_container(data).unwrap());
+    assert!(peak < 4096, "{peak}");
+    assert!(copy_peak > buf.len() * 9 / 10, "{copy_peak} {}", buf.len());
+    assert_eq!(view.flatten().len(), 4);
+}
@@ -22,31 +22,25 @@ float4 main(PS_INPUT input) : SV_Target
     vec3 ka = 0.1;
     vec3 kd = max(dot(L, N), 0) * g_constants.light_radiance;
 
-    RasterMeshInstance instance = g_mesh_instances[g_draw_constants.index];
+    MeshMaterial material = g_mesh_instances[g_draw_constants.index].material;
     vec2 uv = input.uv;
-    vec3 albedo;
-    if(instance.albedo_index != 0xFFFFFFFF) {
-        albedo = textures[instance.albedo_index].Sample(linear_sampler, uv).rgb;
-    } else {
-        albedo = instance.albedo_value;
+    vec3 albedo = material.base_color_factor.rgb;
+    if(material.base_color_index != 0xFFFFFFFF) {
+        albedo *= textures[material.base_color_index].Sample(linear_sampler, uv).rgb;
     }
 
-    vec3 emissive = 0;
-    if(instance.emissive_index != 0xFFFFFFFF) {
-        emissive = textures[instance.emissive_index].SampleLevel(linear_sampler, uv, 0.0f).rgb;
-    } else {
-        emissive = instance.emissive_value.rgb;
+    vec3 emissive = material.emissive_factor.rgb;
+    if(material.emissive_index != 0xFFFFFFFF) {
+        emissive *= textures[material.emissive_index].Sample(linear_sampler, uv).rgb;
     }
 
-    vec2 specular = 0;
-    if(instance.specular_index != 0xFFFFFFFF) {
-        specular = textures[instance.specular_index].SampleLevel(linear_sampler, uv, 0.0f).gb;
-    } else {
-        specular = instance.specular_value.gb;
+    vec4 orm = 1.0;
+    if(material.orm_index != 0xFFFFFFFF) {
+        orm = textures[material.orm_index].Sample(linear_sampler, uv);
     }
 
-    float roughness = specular.r;
-    float metallic = specular.g;
+    float roughness = material.orm_factor.y * orm[material.roughness_channel];
+    float metallic = material.orm_factor.z * orm[material.metallic_channel];
 
     vec3 color = albedo / PI * (ka + kd);// + emissive;
 
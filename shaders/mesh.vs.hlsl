@@ -11,12 +11,13 @@ PS_INPUT main(VS_INPUT input)
 {
 #if 1
     mat4 model = g_mesh_instances[g_draw_constants.index].transform;
+    mat4 normal_matrix = g_mesh_instances[g_draw_constants.index].normal_matrix;
     vec4 world_pos = mul(model, float4(input.pos, 1.0));
 
     PS_INPUT output;
     output.pos = mul(g_constants.projection, mul(g_constants.view, world_pos));
     output.world_pos = world_pos.xyz;
-    output.normal = mul(model, float4(input.normal, 0.0)).xyz;
+    output.normal = mul(normal_matrix, float4(input.normal, 0.0)).xyz;
     output.uv = input.uv;
 
     return output;
@@ -22,6 +22,7 @@ GlobalRootSignature MyGlobalRootSignature =
     "SRV(t6)," // 2 - Lights buffer
     "SRV(t7)," // 3 - Lights cdf buffer
     "SRV(t8)," // 4 - Alias table buffer
+    "SRV(t9)," // 5 - First light of each instance
     "StaticSampler(s0, filter = FILTER_MIN_MAG_MIP_LINEAR),"
 };
 
@@ -33,7 +34,7 @@ TriangleHitGroup HitGroup =
 
 RaytracingShaderConfig  MyShaderConfig =
 {
-    64, // max payload size
+    68, // max payload size
     8   // max attribute size
 };
 
@@ -57,6 +58,7 @@ Texture2D<vec4> textures[]: register(t0, space1);
 StructuredBuffer<Light> lights_buffer: register(t6);
 StructuredBuffer<float> lights_cdf_buffer: register(t7);
 StructuredBuffer<Alias> alias_table: register(t8);
+StructuredBuffer<uint> first_lights_buffer: register(t9);
 
 SamplerState linear_sampler: register(s0);
 ConstantBuffer<Constants> g_constants: register(b0);
@@ -70,8 +72,26 @@ struct HitInfo
     uvec4 seed;
     u32 bounce;
     float brdf_pdf;
+    // Width of the ray cone at the origin of the ray
+    float cone_width;
 };
 
+// Angle covered by a pixel, reflected rays keep the spread of the camera
+// rays
+float pixelSpreadAngle()
+{
+    return 1.0 / (DispatchRaysDimensions().x * g_constants.film_dist);
+}
+
+// Mip level of t for a footprint lod computed without the texture size, see
+// the ray cones in ClosestHit
+float textureLod(Texture2D<vec4> t, float lod)
+{
+    uint w, h;
+    t.GetDimensions(w, h);
+    return lod + 0.5 * log2(w * h);
+}
+
 inline void GenerateCameraRay(uint2 index, float2 jitter, out float3 origin, out float3 direction)
 {
     float2 xy = index + 0.5;
@@ -128,6 +148,7 @@ void RayGeneration()
         seed,
         0,
         0.0,
+        0.0,
     };
 
     uint max_bounces = g_constants.bounces;
@@ -217,7 +238,15 @@ void sampleAreaLights(inout LightSample s, vec3 position, vec4 u) {
     vec3 e2 = light.p2 - light.p0;
     vec3 p = e1 * tri_uv.x + e2 * tri_uv.y + light.p0;
     vec3 n = normalize(cross(e2, e1));
-    vec3 radiance = light.emissive * g_constants.emissive_multiplier;
+
+    // The pdf follows the average of the texture, the radiance is the texel
+    // at the sample like for BRDF samples hitting the light
+    vec3 emissive = light.factor;
+    if(light.texture != 0xFFFFFFFF) {
+        vec2 uv = (light.uv1 - light.uv0) * tri_uv.x + (light.uv2 - light.uv0) * tri_uv.y + light.uv0;
+        emissive *= textures[light.texture].SampleLevel(linear_sampler, uv, 0).rgb;
+    }
+    vec3 radiance = emissive * g_constants.emissive_multiplier;
 
     vec3 v = p - position;
     float dist2 = dot(v, v);
@@ -258,38 +287,47 @@ void ClosestHit(inout HitInfo payload, in BuiltInTriangleIntersectionAttributes
         normals_buffer[indices.x + vertex_offset] * (1 - barycentrics.x - barycentrics.y) +
         normals_buffer[indices.y + vertex_offset] * barycentrics.x +
         normals_buffer[indices.z + vertex_offset] * barycentrics.y;
-    vec3 N = normalize(mul((float3x3)ObjectToWorld(), normal));
+    // Normals transform by the inverse-transpose of the object to world matrix
+    vec3 world_normal = mul(normal, (float3x3)WorldToObject());
+    vec3 N = normalize(world_normal);
 
     vec2 uv =
         uvs_buffer[indices.x + vertex_offset] * (1 - barycentrics.x - barycentrics.y) +
         uvs_buffer[indices.y + vertex_offset] * barycentrics.x +
         uvs_buffer[indices.z + vertex_offset] * barycentrics.y;
 
+    // Texture LOD from a ray cone, Akenine-Moller et al. 2021, "Improved
+    // Shader and Texture Level of Detail Using Ray Cones". The texel density
+    // is the average of the primitive, scaled by the area change of the
+    // object to world transform.
+    float cone_width = payload.cone_width + pixelSpreadAngle() * distance;
+    float area_scale = abs(determinant((float3x3)ObjectToWorld())) * length(world_normal) / length(normal);
+    float lod = 0.5 * log2(instance.uv_area_ratio / area_scale) + log2(cone_width)
+        - log2(max(abs(dot(N, direction)), 1.0e-4));
 
     // Material info
-    vec3 albedo = 1.0;
-    if(instance.albedo_index != 0xFFFFFFFF) {
-       albedo = textures[instance.albedo_index].SampleLevel(linear_sampler, uv, 0.0f).rgb;
-    } else {
-        albedo = instance.albedo_value.rgb;
+    MeshMaterial material = instance.material;
+    vec3 albedo = material.base_color_factor.rgb;
+    if(material.base_color_index != 0xFFFFFFFF) {
+       Texture2D<vec4> t = textures[material.base_color_index];
+       albedo *= t.SampleLevel(linear_sampler, uv, textureLod(t, lod)).rgb;
     }
 
-    vec3 emissive = 0;
-    if(instance.emissive_index != 0xFFFFFFFF) {
-        // emissive = textures[instance.emissive_index].SampleLevel(linear_sampler, uv, 0.0f).rgb;
-    } else {
-        emissive = instance.emissive_value.rgb;
+    vec3 emissive = material.emissive_factor.rgb;
+    if(material.emissive_index != 0xFFFFFFFF) {
+        Texture2D<vec4> t = textures[material.emissive_index];
+        emissive *= t.SampleLevel(linear_sampler, uv, textureLod(t, lod)).rgb;
     }
 
-    vec2 specular = 0;
-    if(instance.specular_index != 0xFFFFFFFF) {
-        specular = textures[instance.specular_index].SampleLevel(linear_sampler, uv, 0.0f).gb;
-    } else {
-        specular = instance.specular_value.gb;
+    vec4 orm = 1.0;
+    if(material.orm_index != 0xFFFFFFFF) {
+        Texture2D<vec4> t = textures[material.orm_index];
+        orm = t.SampleLevel(linear_sampler, uv, textureLod(t, lod));
     }
 
 
-    const float SUN_P = clamp(g_constants.light_radiance * 10.0 /
+    // Only the sun is sampled without emissive triangles
+    const float SUN_P = g_constants.num_lights == 0 ? 1.0 : clamp(g_constants.light_radiance * 10.0 /
     (g_constants.light_radiance * 10.0 + g_constants.emissive_multiplier), 0.05, 0.95);
 
     // Light hit
@@ -299,10 +337,18 @@ void ClosestHit(inout HitInfo payload, in BuiltInTriangleIntersectionAttributes
         } else if(g_constants.sampling_mode == MIS) {
             float brdf_pdf = payload.brdf_pdf;
 
-            float area_pdf = (1.0 - SUN_P) * luminance(emissive) * g_constants.lights_pdf_normalization;
+            // Density of sampleAreaLights, from the average emission of the
+            // triangle in the tables. Triangles it can't sample get the full
+            // weight.
+            float area_pdf = 0.0;
+            uint first_light = first_lights_buffer[InstanceIndex()];
+            if(first_light != 0xFFFFFFFF) {
+                Light light = lights_buffer[first_light + triangle_index];
+                area_pdf = (1.0 - SUN_P) * luminance(light.emissive) * g_constants.lights_pdf_normalization;
+            }
             float light_pdf = (area_pdf * square(distance)) / dot(-direction, N);
 
-            if(light_pdf > 0.0) {
+            if(light_pdf >= 0.0) {
                 float mis_weight = brdf_pdf * balance_heuristic(brdf_pdf, light_pdf);
                 payload.color += mis_weight * payload.throughput * emissive * g_constants.emissive_multiplier;
             }
@@ -316,24 +362,24 @@ void ClosestHit(inout HitInfo payload, in BuiltInTriangleIntersectionAttributes
             tangents_buffer[indices.x + vertex_offset] * (1 - barycentrics.x - barycentrics.y) +
             tangents_buffer[indices.y + vertex_offset] * barycentrics.x +
             tangents_buffer[indices.z + vertex_offset] * barycentrics.y;
-        vec3 T = normalize(mul((float3x3)ObjectToWorld(), tangent));
-        vec3 B = normalize(cross(N, T));
-        if (any(isnan(B)) || g_constants.debug == 5) {
+        vec3 T = mul((float3x3)ObjectToWorld(), tangent);
+        if (g_constants.debug == 5) {
             frame = frameFromNormal(N);
         }
         else {
-            frame = makeFrame(T, B, N);
-            if(instance.normal_index != 0xFFFFFFFF) {
-                vec2 n = textures[instance.normal_index].SampleLevel(linear_sampler, uv, 0.0f).rg * 2.0 - 1.0;
+            frame = frameFromTBN(T, cross(N, T), N);
+            if(material.normal_index != 0xFFFFFFFF) {
+                Texture2D<vec4> t = textures[material.normal_index];
+                vec2 n = (t.SampleLevel(linear_sampler, uv, textureLod(t, lod)).rg * 2.0 - 1.0) *
+                    material.orm_factor.w;
                 // Lerp towards local +Z when viewing at grazing angle
                 float weight = max(dot(N, -direction), 0.0);
                 n = lerp(0.0, n, weight);
 
-                float z = sqrt(1.0 - n.x * n.x - n.y * n.y);
+                // Scaled normals can leave the unit disk
+                float z = sqrt(max(1.0 - n.x * n.x - n.y * n.y, 0.0));
                 N = toWorld(frame, vec3(n, z));
-                B = normalize(cross(N, T));
-                T = normalize(cross(B, N));
-                frame = makeFrame(T, B, N);
+                frame = frameFromTBN(T, cross(N, T), N);
             }
         }
     } else {
@@ -341,8 +387,8 @@ void ClosestHit(inout HitInfo payload, in BuiltInTriangleIntersectionAttributes
     }
 
     // BRDF
-    float roughness = max(specular.r, 0.05);
-    float metallic = specular.g;
+    float roughness = max(material.orm_factor.y * orm[material.roughness_channel], 0.05);
+    float metallic = material.orm_factor.z * orm[material.metallic_channel];
     float alpha = square(roughness);
     vec3 wo = toLocal(frame, -direction);
 
@@ -402,6 +448,7 @@ void ClosestHit(inout HitInfo payload, in BuiltInTriangleIntersectionAttributes
         uvec4(0, 0, 0, 0),
         0,
         0.0,
+        0.0,
     };
 
     // Shadowing
@@ -433,6 +480,7 @@ void ClosestHit(inout HitInfo payload, in BuiltInTriangleIntersectionAttributes
     payload.throughput *= pdf > 0.0 ? f / pdf : 0.0;
     payload.brdf_pdf = pdf;
     payload.distance = distance;
+    payload.cone_width = cone_width;
 
     // Debug
     switch(g_constants.debug) {
@@ -31,38 +31,51 @@ struct Constants {
     u32 use_alias_table;
 };
 
+// Textures are 0xFFFFFFFF when missing and multiply their factor
+struct MeshMaterial {
+    u32 base_color_index;
+    u32 normal_index;
+    u32 orm_index;
+    u32 emissive_index;
+
+    vec4 base_color_factor;
+    // Occlusion strength, roughness, metallic and normal scale
+    vec4 orm_factor;
+    vec4 emissive_factor;
+
+    // Channels of the ORM texture
+    u32 roughness_channel;
+    u32 metallic_channel;
+};
+
 struct RayMeshInstance {
     u32 vertex_offset;
     u32 index_offset;
+    // UV area per object space area, for the texture LOD
+    float uv_area_ratio;
 
-    u32 albedo_index;
-    u32 normal_index;
-    u32 specular_index;
-    u32 emissive_index;
-
-    vec4 albedo_value;
-    vec4 specular_value;
-    vec4 emissive_value;
+    MeshMaterial material;
 };
 
 struct RasterMeshInstance {
     mat4 transform;
+    mat4 normal_matrix;
 
-    u32 albedo_index;
-    u32 normal_index;
-    u32 specular_index;
-    u32 emissive_index;
-
-    vec4 albedo_value;
-    vec4 specular_value;
-    vec4 emissive_value;
+    MeshMaterial material;
 };
 
+// Emissive triangle, emissive is the average over the triangle used by the
+// sampling tables and factor multiplies the texture at the sample
 struct Light {
     vec3 p0;
     vec3 p1;
     vec3 p2;
     vec3 emissive;
+    vec3 factor;
+    vec2 uv0;
+    vec2 uv1;
+    vec2 uv2;
+    u32 texture;
 };
 
 struct Alias {
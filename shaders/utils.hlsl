@@ -38,27 +38,37 @@ vec3 toLocal(Frame f, vec3 world_v) {
     return mul(local_from_world, world_v);
 }
 
-Frame frameFromNormal(vec3 a) {
-    vec3 b, c;
-    if (abs(a.x) > abs(a.y)) {
-        float invLen = 1.0f / sqrt(a.x * a.x + a.z * a.z);
-        c = vec3(a.z * invLen, 0.0f, -a.x * invLen);
-    } else {
-        float invLen = 1.0f / sqrt(a.y * a.y + a.z * a.z);
-        c = vec3(0.0f, a.z * invLen, -a.y * invLen);
-    }
-    b = cross(c, a);
-    Frame frame;
-    frame.world_from_local = transpose(mat3(b, c, a));
-    return frame;
-}
-
 Frame makeFrame(vec3 t, vec3 b, vec3 n) {
     Frame frame;
     frame.world_from_local = transpose(mat3(t, b, n));
     return frame;
 }
 
+// Branchless orthonormal basis from "Building an Orthonormal Basis, Revisited"
+// (Duff et al. 2017), same as Frame::from_normal in the math crate.
+Frame frameFromNormal(vec3 n) {
+    float s = n.z >= 0.0f ? 1.0f : -1.0f;
+    float a = -1.0f / (s + n.z);
+    float b = n.x * n.y * a;
+    vec3 t = vec3(1.0f + s * n.x * n.x * a, s * b, -s * n.x);
+    vec3 bt = vec3(b, s + n.y * n.y * a, -n.y);
+    return makeFrame(t, bt, n);
+}
+
+// Gram-Schmidt orthonormalization, the bitangent only gives the handedness.
+// Falls back to frameFromNormal if the tangent is parallel to the normal.
+Frame frameFromTBN(vec3 t, vec3 b, vec3 n) {
+    n = normalize(n);
+    vec3 t_perp = t - n * dot(n, t);
+    float len = length(t_perp);
+    if (isnan(len) || len <= 1e-6f * length(t)) {
+        return frameFromNormal(n);
+    }
+    t_perp /= len;
+    vec3 b_perp = cross(n, t_perp);
+    return makeFrame(t_perp, dot(b_perp, b) < 0.0f ? -b_perp : b_perp, n);
+}
+
 
 float safeSqrt(float x) {
     return sqrt(max(x, 0.0));
@@ -486,7 +486,8 @@ impl Context {
                         D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING,
                     Anonymous: D3D12_SHADER_RESOURCE_VIEW_DESC_0 {
                         Texture2D: D3D12_TEX2D_SRV {
-                            MipLevels: 1,
+                            // All the levels
+                            MipLevels: u32::MAX,
                             MostDetailedMip: 0,
                             ..Default::default()
                         }
@@ -619,63 +620,90 @@ impl Context {
     pub fn upload_tex2d_sync(&self, data: &[u8], width: u32, height: u32,
                           format: DXGI_FORMAT, state: D3D12_RESOURCE_STATES)
     -> Option<ID3D12Resource> {
+        let subresource = SubresourceData {
+            data,
+            width,
+            height,
+            row_size: width * 4,
+        };
+        self.upload_tex2d_array_sync(&[subresource], width, height, 1,
+                                     format, state)
+    }
 
-        let upload_pitch = (width * 4 + D3D12_TEXTURE_DATA_PITCH_ALIGNMENT - 1)
-            & !(D3D12_TEXTURE_DATA_PITCH_ALIGNMENT - 1);
-        let upload_size = height as usize * upload_pitch as usize;
+    /// Subresources are in D3D12 order, the mips of the first layer then
+    /// the mips of the next one.
+    pub fn upload_tex2d_array_sync(&self, subresources: &[SubresourceData],
+                                   width: u32, height: u32, array_layers: u32,
+                                   format: DXGI_FORMAT,
+                                   state: D3D12_RESOURCE_STATES)
+    -> Option<ID3D12Resource> {
+        assert!(subresources.len() % array_layers as usize == 0);
+        let mip_levels = subresources.len() as u32 / array_layers;
+
+        // Offset and row pitch of each subresource in the upload buffer
+        let mut placements = Vec::with_capacity(subresources.len());
+        let mut upload_size = 0;
+        for s in subresources {
+            let pitch = (s.row_size + D3D12_TEXTURE_DATA_PITCH_ALIGNMENT - 1)
+                & !(D3D12_TEXTURE_DATA_PITCH_ALIGNMENT - 1);
+            let offset = (upload_size + D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as usize - 1)
+                & !(D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as usize - 1);
+            placements.push((offset, pitch));
+            upload_size = offset + s.rows() as usize * pitch as usize;
+        }
 
         let upload_resource =
             self.create_mappable_resource(upload_size, D3D12_HEAP_TYPE_UPLOAD)?;
 
         upload_resource.write_with(|map| {
-            for y in 0..height as usize {
-                let map_start = y * upload_pitch as usize;
-                let map_end = map_start + width as usize * 4;
-
-                let data_start = y * width as usize * 4;
-                let data_end = data_start + width as usize * 4;
-
-                map[map_start..map_end]
-                    .copy_from_slice(&data[data_start..data_end]);
+            for (s, &(offset, pitch)) in subresources.iter().zip(&placements) {
+                for (y, row) in s.data.chunks_exact(s.row_size as usize).enumerate() {
+                    let map_start = offset + y * pitch as usize;
+                    map[map_start..map_start + row.len()].copy_from_slice(row);
+                }
             }
         });
 
         let dest_resource =
-            self.create_resource(&ResourceDesc::tex2d(format, width,
-                                                      height,
-                                                      D3D12_RESOURCE_FLAG_NONE),
+            self.create_resource(&ResourceDesc::tex2d_array(format, width, height,
+                                                            array_layers, mip_levels,
+                                                            D3D12_RESOURCE_FLAG_NONE),
                                  D3D12_RESOURCE_STATE_COPY_DEST,
                                  D3D12_HEAP_TYPE_DEFAULT)?;
 
-        let upload_loc = D3D12_TEXTURE_COPY_LOCATION {
-            pResource: Some(upload_resource.res),
-            Type: D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
-            Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
-                PlacedFootprint: D3D12_PLACED_SUBRESOURCE_FOOTPRINT {
-                    Footprint: D3D12_SUBRESOURCE_FOOTPRINT {
-                        Format: DXGI_FORMAT_R8G8B8A8_UNORM,
-                        Width: width,
-                        Height: height,
-                        Depth: 1,
-                        RowPitch: upload_pitch,
-                    },
-                    ..Default::default()
+        for (i, (s, &(offset, pitch))) in subresources.iter().zip(&placements).enumerate() {
+            let upload_loc = D3D12_TEXTURE_COPY_LOCATION {
+                pResource: Some(upload_resource.res.clone()),
+                Type: D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
+                Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
+                    PlacedFootprint: D3D12_PLACED_SUBRESOURCE_FOOTPRINT {
+                        Offset: offset as u64,
+                        Footprint: D3D12_SUBRESOURCE_FOOTPRINT {
+                            Format: format,
+                            Width: s.width,
+                            Height: s.height,
+                            Depth: 1,
+                            RowPitch: pitch,
+                        },
+                    }
                 }
-            }
-        };
+            };
 
+            let dest_loc = D3D12_TEXTURE_COPY_LOCATION {
+                pResource: Some(dest_resource.clone()),
+                Type: D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+                Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
+                    SubresourceIndex: i as u32,
+                }
+            };
 
-        let dest_loc = D3D12_TEXTURE_COPY_LOCATION {
-            pResource: Some(dest_resource.clone()),
-            Type: D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
-            Anonymous: D3D12_TEXTURE_COPY_LOCATION_0 {
-                SubresourceIndex: 0,
+            unsafe {
+                self.sync_command_list.CopyTextureRegion(&dest_loc, 0, 0, 0,
+                                                         &upload_loc, null());
             }
-        };
+        }
 
         unsafe {
-            self.sync_command_list.CopyTextureRegion(&dest_loc, 0, 0, 0,
-                                                     &upload_loc, null());
             let barriers = [
                 ResourceBarrier::transition(&dest_resource,
                                             D3D12_RESOURCE_STATE_COPY_DEST,
@@ -693,24 +721,39 @@ impl Context {
 
     pub fn upload_buffer_sync(&self, data: &[u8], state: D3D12_RESOURCE_STATES)
         -> Option<ID3D12Resource> {
+        self.upload_buffer_parts_sync(&[data], state)
+    }
+
+    /// Uploads the concatenation of parts, they are copied straight to the
+    /// upload heap so borrowed scene data doesn't go through a temporary
+    /// buffer.
+    pub fn upload_buffer_parts_sync(&self, parts: &[&[u8]], state: D3D12_RESOURCE_STATES)
+        -> Option<ID3D12Resource> {
 
-        if data.len() == 0 {
+        let size: usize = parts.iter().map(|p| p.len()).sum();
+        if size == 0 {
             return None;
         }
 
 
-        let upload_buffer = self.create_mappable_resource(data.len(),
+        let upload_buffer = self.create_mappable_resource(size,
                                                           D3D12_HEAP_TYPE_UPLOAD)?;
-        upload_buffer.write_with(|map| map.copy_from_slice(data));
+        upload_buffer.write_with(|mut map| {
+            for p in parts {
+                let (dst, rest) = map.split_at_mut(p.len());
+                dst.copy_from_slice(p);
+                map = rest;
+            }
+        });
 
         let dest_buffer =
-            self.create_resource(&ResourceDesc::buffer(data.len()),
+            self.create_resource(&ResourceDesc::buffer(size),
                                 D3D12_RESOURCE_STATE_COMMON,
                                 D3D12_HEAP_TYPE_DEFAULT)?;
         unsafe {
             self.sync_command_list.CopyBufferRegion(&dest_buffer, 0,
                                                     &upload_buffer.res, 0,
-                                                    data.len() as u64);
+                                                    size as u64);
             let barriers = [
                 ResourceBarrier::transition(&dest_buffer,
                                             D3D12_RESOURCE_STATE_COPY_DEST,
@@ -1074,19 +1117,40 @@ impl PerFrameConstantBuffer {
     }
 }
 
+/// Tightly packed subresource of a texture. For block compressed formats
+/// the size is rounded up to whole blocks and rows are rows of blocks.
+pub struct SubresourceData<'a> {
+    pub data: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    pub row_size: u32,
+}
+
+impl SubresourceData<'_> {
+    pub fn rows(&self) -> u32 {
+        (self.data.len() / self.row_size as usize) as u32
+    }
+}
+
 pub struct ResourceDesc(D3D12_RESOURCE_DESC);
 impl ResourceDesc {
     pub fn tex2d(format: DXGI_FORMAT, width: u32, height: u32,
                  flags: D3D12_RESOURCE_FLAGS) -> Self {
+        Self::tex2d_array(format, width, height, 1, 1, flags)
+    }
+
+    pub fn tex2d_array(format: DXGI_FORMAT, width: u32, height: u32,
+                       array_layers: u32, mip_levels: u32,
+                       flags: D3D12_RESOURCE_FLAGS) -> Self {
         Self(D3D12_RESOURCE_DESC {
             Dimension: D3D12_RESOURCE_DIMENSION_TEXTURE2D,
             Alignment: 0,
             Width: width.into(),
             Height: height,
-            DepthOrArraySize: 1,
+            DepthOrArraySize: array_layers as u16,
             Format: format,
             Flags: flags,
-            MipLevels: 1,
+            MipLevels: mip_levels as u16,
             SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
             Layout: D3D12_TEXTURE_LAYOUT_UNKNOWN,
         })
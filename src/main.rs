@@ -11,6 +11,7 @@ use bytemuck::cast_slice;
 use math::{
     vec::{Vec2, Vec3, Vec4},
     mat::Mat4,
+    color::ColorSpace,
 };
 use scene::{Camera, Direction};
 use render::{Raster, Ray, Pipeline, SceneConstants};
@@ -72,20 +73,49 @@ fn main() {
     let path = std::env::args().nth(1).unwrap_or(
         String::from("res/bistro.lz4"));
 
-    let mut scene = asset::load_scene_from_asset_file(&Path::new(&path))
-        .expect("Failed to open asset file");
+    // Uncompressed files are mapped and their vectors uploaded straight from
+    // the mapping, compressed ones are loaded in memory first
+    let mapped;
+    let loaded;
+    let scene = match asset::MappedScene::open(Path::new(&path)) {
+        Ok(m) => {
+            mapped = m;
+            mapped.view()
+        },
+        Err(asset::AssetError::Compressed) => match asset::load_scene_from_asset_file(Path::new(&path)) {
+            Ok(s) => {
+                loaded = s;
+                Ok(loaded.view())
+            },
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    let mut scene = scene.unwrap_or_else(|e| panic!("Failed to open asset file {path}: {e}"));
 
-    // Transform to z up;
+    // Transform to z up, with a root so the transforms of the scene are kept
+    // as they are
     let to_z_up = Mat4::from_columns(&[
         Vec4::new(1., 0., 0., 0.),
         Vec4::new(0., 0., 1., 0.),
         Vec4::new(0., 1., 0., 0.),
         Vec4::new(0., 0., 0., 1.),
     ]).transpose();
+    scene.insert_root(scene::Node {
+        name: String::from("z up"),
+        transform: to_z_up.decompose().expect("Swapping y and z is decomposable"),
+        ..Default::default()
+    });
 
-    for m in scene.meshes.iter_mut() {
-        m.transform = to_z_up * m.transform;
-    }
+    // The first directional light of the scene is the sun, it's off by
+    // default in scenes without one
+    let sun = scene.light_instances().into_iter().find_map(|i| match scene.lights[i.light as usize] {
+        scene::Light::Directional { color, intensity, .. } => Some((
+            i.transform.transform_vector(Vec3::new(0., 0., -1.)).normalized(),
+            intensity * ColorSpace::Rec709.luminance(color),
+        )),
+        _ => None,
+    });
 
 
     let mut window = win32::create_window("Rust window", 1280, 720)
@@ -112,10 +142,38 @@ fn main() {
     for img in &scene.images {
         let descriptor = d3d12.alloc_csu_descriptor().unwrap();
         let format = match img.format {
-            scene::Format::RGBA8 => d3d12::DXGI_FORMAT_R8G8B8A8_UNORM,
-            scene::Format::SRGBA8 => d3d12::DXGI_FORMAT_R8G8B8A8_UNORM_SRGB,
+            scene::Format::RGBA8   => d3d12::DXGI_FORMAT_R8G8B8A8_UNORM,
+            scene::Format::SRGBA8  => d3d12::DXGI_FORMAT_R8G8B8A8_UNORM_SRGB,
+            scene::Format::R8      => d3d12::DXGI_FORMAT_R8_UNORM,
+            scene::Format::RG8     => d3d12::DXGI_FORMAT_R8G8_UNORM,
+            scene::Format::RGBA16F => d3d12::DXGI_FORMAT_R16G16B16A16_FLOAT,
+            scene::Format::RGBA32F => d3d12::DXGI_FORMAT_R32G32B32A32_FLOAT,
+            scene::Format::BC1     => d3d12::DXGI_FORMAT_BC1_UNORM,
+            scene::Format::BC1SRGB => d3d12::DXGI_FORMAT_BC1_UNORM_SRGB,
+            scene::Format::BC2     => d3d12::DXGI_FORMAT_BC2_UNORM,
+            scene::Format::BC2SRGB => d3d12::DXGI_FORMAT_BC2_UNORM_SRGB,
+            scene::Format::BC3     => d3d12::DXGI_FORMAT_BC3_UNORM,
+            scene::Format::BC3SRGB => d3d12::DXGI_FORMAT_BC3_UNORM_SRGB,
+            scene::Format::BC4     => d3d12::DXGI_FORMAT_BC4_UNORM,
+            scene::Format::BC5     => d3d12::DXGI_FORMAT_BC5_UNORM,
+            scene::Format::BC6H    => d3d12::DXGI_FORMAT_BC6H_UF16,
+            scene::Format::BC7     => d3d12::DXGI_FORMAT_BC7_UNORM,
+            scene::Format::BC7SRGB => d3d12::DXGI_FORMAT_BC7_UNORM_SRGB,
         };
-        let texture = d3d12.upload_tex2d_sync(&img.data, img.width, img.height,
+        // Materials sample Texture2D
+        assert!(img.array_layers == 1, "Texture arrays are not supported by the renderer");
+
+        let block_size = img.format.block_size();
+        let subresources: Vec<d3d12::SubresourceData> = (0..img.mip_levels).map(|level| {
+            let (width, height) = img.mip_extent(level);
+            d3d12::SubresourceData {
+                data: img.subresource(0, level),
+                width: width.next_multiple_of(block_size),
+                height: height.next_multiple_of(block_size),
+                row_size: img.format.row_size(width) as u32,
+            }
+        }).collect();
+        let texture = d3d12.upload_tex2d_array_sync(&subresources, img.width, img.height, 1,
             format, d3d12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE)
             .expect("Failed to upload texture");
         d3d12.create_shader_resource_view_tex2d(&texture, format, descriptor);
@@ -124,27 +182,36 @@ fn main() {
     d3d12.wait_sync_commands();
 
 
+    // Start from the first perspective camera of the scene
+    let mut camera = scene.camera_instances().iter()
+        .find_map(|i| scene.cameras[i.camera as usize].to_camera(
+            &i.transform, Vec3::new(0., 0., 1.), window.width() as f32 / window.height() as f32))
+        .unwrap_or_else(|| {
+            let mut c = Camera::new(
+                Vec3::new(-21., -1.0, 5.5),
+                Vec3::new(0., 0., 0.),
+                Vec3::new(0., 0., 1.),
+                0., 0., 0., 0., 0., 0.
+            );
+            c.set_film_distance(0.7);
+            c
+        });
+    camera.move_speed = 20.;
+    camera.rotate_speed = 1.;
+
     let mut ray_scene:  Box<dyn Pipeline> = ray;
     let mut raster_scene: Box<dyn Pipeline> = raster;
 
     let mut scene: &mut Box<dyn Pipeline> = &mut ray_scene;
 
-    let camera_pos = Vec3::new(-21., -1.0, 5.5);
-    let mut camera = Camera::new(
-        camera_pos,
-        Vec3::new(0., 0., 0.),
-        Vec3::new(0., 0., 1.),
-        0., 0., 0., 0., 20., 1.
-    );
-
 
     let mut constants = SceneConstants {
-        camera_position: camera_pos,
+        camera_position: camera.position,
         camera_direction: camera.forward,
-        light_direction: Vec3::new(-0.496, 0.694, -0.522).normalized(),
-        light_radiance: 0.0,
+        light_direction: sun.map_or(Vec3::new(-0.496, 0.694, -0.522).normalized(), |s| s.0),
+        light_radiance: sun.map_or(0.0, |s| s.1),
         diffuse_color: Vec3::new(0., 1., 0.),
-        film_dist: 0.7,
+        film_dist: camera.film_distance(),
         emissive_multiplier: 100.0,
         bounces: 8,
         sampling_mode: 3,
@@ -310,7 +377,7 @@ fn main() {
                 window.height() as f32 / window.width() as f32;
             camera.near = 0.1;
             camera.far = 1000.0;
-            camera.fov = 2. * (1. / (constants.film_dist * 2.)).atan();
+            camera.set_film_distance(constants.film_dist);
 
 
             constants.camera_position = camera.position;
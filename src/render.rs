@@ -1,11 +1,9 @@
 use core::ptr::{null, null_mut};
 use core::mem::{size_of, size_of_val};
-use std::alloc::Allocator;
-
 use bytemuck::cast_slice;
 use math::vec::{Vec2, Vec3, Vec4};
 
-use scene::{Mesh, Scene};
+use scene::{LightSamplingTables, Mesh, MeshInstance, PrimitiveView, SceneView};
 
 use crate::d3d12::{self, ResourceDesc, ResourceBarrier};
 use crate::shaders::{self, RayMeshInstance, RasterMeshInstance, Light};
@@ -14,6 +12,74 @@ use crate::win32;
 
 pub const MAX_SAMPLES: u32 = 256;
 
+/// GPU material, missing textures are u32::MAX.
+fn mesh_material(m: &scene::Material) -> shaders::MeshMaterial {
+    let texture = |t: Option<u32>| t.unwrap_or(u32::MAX);
+    shaders::MeshMaterial {
+        base_color_index: texture(m.base_color_texture),
+        normal_index: texture(m.normal_texture),
+        orm_index: texture(m.orm_texture),
+        emissive_index: texture(m.emissive_texture),
+        base_color_factor: m.base_color_factor,
+        orm_factor: Vec4::new(m.occlusion_strength, m.roughness_factor, m.metallic_factor, m.normal_scale),
+        emissive_factor: m.emission().extend(0.0),
+        roughness_channel: m.orm_channels.roughness as u32,
+        metallic_channel: m.orm_channels.metallic as u32,
+    }
+}
+
+/// UV area per object space area of a primitive, the texel density of the
+/// texture LOD in ray.lib.hlsl.
+fn uv_area_ratio(positions: &[Vec3], uvs: &[Vec2], indices: &[u32]) -> f32 {
+    let (mut uv_area, mut area) = (0.0, 0.0);
+    for t in indices.chunks_exact(3) {
+        let [a, b, c] = [t[0], t[1], t[2]].map(|i| i as usize);
+        area += (positions[b] - positions[a]).cross(positions[c] - positions[a]).length();
+        if !uvs.is_empty() {
+            let (e1, e2) = (uvs[b] - uvs[a], uvs[c] - uvs[a]);
+            uv_area += (e1.x * e2.y - e1.y * e2.x).abs();
+        }
+    }
+    if area > 0.0 { uv_area / area } else { 0.0 }
+}
+
+/// Uploads a buffer bound as a root SRV, with one zeroed element if data is
+/// empty since upload_buffer_sync can't create empty buffers.
+fn upload_root_buffer<T: bytemuck::Pod>(d3d12: &d3d12::Context, data: &[T], name: &str)
+    -> d3d12::ID3D12Resource {
+    let zeroed = [T::zeroed()];
+    let data = if data.is_empty() { &zeroed[..] } else { data };
+    d3d12.upload_buffer_sync(cast_slice(data), d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
+        .unwrap_or_else(|| panic!("Failed to upload {name} buffer"))
+}
+
+/// Elements of T in the parts given to upload_buffer_parts_sync.
+fn parts_count<T>(parts: &[&[u8]]) -> u32 {
+    (parts.iter().map(|p| p.len()).sum::<usize>() / size_of::<T>()) as u32
+}
+
+/// Primitives of all the meshes and the index of the first primitive of
+/// each mesh.
+fn scene_primitives<'a>(scene: &'a SceneView) -> (Vec<&'a PrimitiveView<'a>>, Vec<usize>) {
+    let mut primitives = Vec::new();
+    let mut first_primitive = Vec::with_capacity(scene.meshes.len());
+    for m in scene.meshes.iter() {
+        first_primitive.push(primitives.len());
+        primitives.extend(m.primitives.iter());
+    }
+    (primitives, first_primitive)
+}
+
+/// Every primitive of every mesh instance, with the index of the primitive.
+fn primitive_instances<'a>(scene: &'a SceneView, first_primitive: &'a [usize])
+    -> impl Iterator<Item = (MeshInstance, usize)> + 'a {
+    scene.flatten().into_iter().flat_map(move |instance| {
+        let first = first_primitive[instance.mesh as usize];
+        let count = scene.meshes[instance.mesh as usize].primitives.len();
+        (first..first + count).map(move |p| (instance, p))
+    })
+}
+
 pub trait Pipeline {
     fn resize(&mut self, d3d12: &d3d12::Context, width: u32, height:u32);
 
@@ -66,8 +132,7 @@ struct DrawArgs {
 }
 
 impl Raster {
-    pub fn init<A: Allocator + Copy>(window: &win32::Window, d3d12: &d3d12::Context,
-            scene: &Scene<A>) -> Self {
+    pub fn init(window: &win32::Window, d3d12: &d3d12::Context, scene: &SceneView) -> Self {
 
         let rs =
             d3d12.create_root_signature_from_shader(&shaders::MESH_VS)
@@ -94,48 +159,45 @@ impl Raster {
                                                 as u32, &argument_descs)
             .expect("Failed to create command signature");
 
+        // Slices of the scene, uploaded without concatenating them first
         let mut commands_buf: Vec<DrawArgs> = Vec::new();
-        let mut positions_buf: Vec<Vec3> = Vec::new();
-        let mut normals_buf: Vec<Vec3> = Vec::new();
-        let mut uvs_buf: Vec<Vec2> = Vec::new();
-        let mut indices_buf: Vec<u32> = Vec::new();
+        let mut positions_parts: Vec<&[u8]> = Vec::new();
+        let mut normals_parts: Vec<&[u8]> = Vec::new();
+        let mut uvs_parts: Vec<&[u8]> = Vec::new();
+        let mut indices_parts: Vec<&[u8]> = Vec::new();
         let mut mesh_constants_buf: Vec<RasterMeshInstance> = Vec::new();
 
         let mut current_vertex: u32 = 0;
         let mut current_index:  u32 = 0;
 
-        for (i, m) in scene.meshes.iter().enumerate() {
-            positions_buf.extend_from_slice(&m.positions);
-            normals_buf  .extend_from_slice(&m.normals);
-            uvs_buf      .extend_from_slice(&m.uvs);
-            indices_buf  .extend_from_slice(&m.indices);
+        // Geometry is uploaded once per primitive and drawn for every
+        // instance of its mesh
+        let (primitives, first_primitive) = scene_primitives(scene);
+        let mut offsets: Vec<(u32, u32)> = Vec::with_capacity(primitives.len());
+        for m in &primitives {
+            positions_parts.push(cast_slice(m.positions));
+            normals_parts  .push(cast_slice(m.normals));
+            uvs_parts      .push(cast_slice(m.uvs));
+            indices_parts  .push(cast_slice(m.indices));
 
-            let mut mesh_instance = RasterMeshInstance {
-                transform: m.transform,
-                ..Default::default()
-            };
+            offsets.push((current_vertex, current_index));
 
-            macro_rules! material {
-                ($m: ident, $index: ident, $value: ident, $default: expr) => {
-                    match m.material.$m {
-                        scene::MaterialParameter::Texture(v) => {
-                            mesh_instance.$index = v;
-                        },
-                        scene::MaterialParameter::Vec4(v) => {
-                            mesh_instance.$index  = u32::MAX;
-                            mesh_instance.$value = v;
-                        }
-                        _ => {
-                            mesh_instance.$index  = u32::MAX;
-                            mesh_instance.$value = $default;
-                        }
-                    };
-                };
-            }
+            current_vertex = current_vertex.checked_add(m.positions.len() as u32)
+                .expect("Overflow");
+            current_index = current_index.checked_add(m.indices.len() as u32)
+                .expect("Overflow");
+        }
+
+        for (i, (instance, p)) in primitive_instances(scene, &first_primitive).enumerate() {
+            let m = primitives[p];
+            let (vertex_offset, index_offset) = offsets[p];
+
+            let mesh_instance = RasterMeshInstance {
+                transform: instance.transform,
+                normal_matrix: instance.transform.to_normal_matrix(),
+                material: mesh_material(&scene.materials[m.material as usize]),
+            };
 
-            material!(base_color, albedo_index,   albedo_value,   Vec4::new(1.0, 0.0, 1.0, 1.0));
-            material!(specular,   specular_index, specular_value, Vec4::new(0.0, 1.0, 0.0, 0.0));
-            material!(emissive,   emissive_index, emissive_value, Vec4::new(0.0, 0.0, 0.0, 0.0));
 
             mesh_constants_buf.push(mesh_instance);
 
@@ -144,43 +206,30 @@ impl Raster {
                 args: d3d12::D3D12_DRAW_INDEXED_ARGUMENTS {
                     IndexCountPerInstance: m.indices.len() as u32,
                     InstanceCount: 1,
-                    StartIndexLocation: current_index,
-                    BaseVertexLocation: current_vertex as i32,
+                    StartIndexLocation: index_offset,
+                    BaseVertexLocation: vertex_offset as i32,
                     StartInstanceLocation: 0,
                 },
             });
-
-            current_vertex = current_vertex.checked_add(m.positions.len() as u32)
-                .expect("Overflow");
-            current_index = current_index.checked_add(m.indices.len() as u32)
-                .expect("Overflow");
         }
 
-        assert!(current_vertex == positions_buf.len() as u32);
-        assert!(current_index == indices_buf.len() as u32);
+        assert!(current_vertex == parts_count::<Vec3>(&positions_parts));
+        assert!(current_index == parts_count::<u32>(&indices_parts));
 
-        let positions = d3d12.upload_buffer_sync( unsafe {
-            core::slice::from_raw_parts(positions_buf.as_ptr() as *const u8,
-                                        positions_buf.len() * size_of::<Vec3>())
-            }, d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
+        let positions = d3d12.upload_buffer_parts_sync(&positions_parts,
+            d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
             .expect("Failed to upload positions");
 
-        let normals = d3d12.upload_buffer_sync( unsafe {
-            core::slice::from_raw_parts(normals_buf.as_ptr() as *const u8,
-                                        normals_buf.len() * size_of::<Vec3>())
-            }, d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
+        let normals = d3d12.upload_buffer_parts_sync(&normals_parts,
+            d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
             .expect("Failed to upload normals");
 
-        let uvs = d3d12.upload_buffer_sync( unsafe {
-            core::slice::from_raw_parts(uvs_buf.as_ptr() as *const u8,
-                                        uvs_buf.len() * size_of::<Vec2>())
-            }, d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
-            .expect("Failed to upload normals");
+        let uvs = d3d12.upload_buffer_parts_sync(&uvs_parts,
+            d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
+            .expect("Failed to upload uvs");
 
-        let indices = d3d12.upload_buffer_sync( unsafe {
-            core::slice::from_raw_parts(indices_buf.as_ptr() as *const u8,
-                                        indices_buf.len() * size_of::<u32>())
-            }, d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
+        let indices = d3d12.upload_buffer_parts_sync(&indices_parts,
+            d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
             .expect("Failed to upload indices");
 
         let mesh_constants = d3d12.upload_buffer_sync( unsafe {
@@ -287,9 +336,9 @@ impl Raster {
             normals,
             indices,
             uvs,
-            vertices_count: positions_buf.len(),
-            indices_count: indices_buf.len(),
-            meshes_count: scene.meshes.len(),
+            vertices_count: current_vertex as usize,
+            indices_count: current_index as usize,
+            meshes_count: commands_buf.len(),
             mesh_constants,
             constant_buffer,
             pso,
@@ -442,10 +491,9 @@ pub struct Ray {
     mesh_instances_desc_handle:     d3d12::D3D12_CPU_DESCRIPTOR_HANDLE,
     lights_buffer:                  d3d12::ID3D12Resource,
     lights_cdf_buffer:              d3d12::ID3D12Resource,
-    lights:                         Vec<Light>,
-    lights_cdf:                     Vec<f32>,
-    lights_pdf_normalization:       f32,
+    light_tables:                   LightSamplingTables,
     alias_table_buffer:           d3d12::ID3D12Resource,
+    first_lights_buffer:            d3d12::ID3D12Resource,
 
     postprocess_rs:                 d3d12::ID3D12RootSignature,
     postprocess_pso:                d3d12::ID3D12PipelineState,
@@ -457,58 +505,8 @@ pub struct Ray {
     max_samples: u32,
 }
 
-/// Alias method from:
-/// https://www.keithschwarz.com/interesting/code/?dir=alias-method
-fn create_alias_table(mut probabilities: Vec::<f64>) -> Vec<shaders::Alias> {
-    assert!(probabilities.len() < u32::MAX as usize);
-
-    let n = probabilities.len() as f64;
-    let avg =  1.0 / n;
-
-    let mut small: Vec<u32> = Vec::new();
-    let mut large: Vec<u32> = Vec::new();
-
-    for (i, p) in probabilities.iter().enumerate() {
-        if *p < avg {
-            small.push(i as u32);
-        } else {
-            large.push(i as u32);
-        }
-    }
-
-    let mut table = vec![shaders::Alias { p: 0.0, a: u32::MAX};
-                         probabilities.len()];
-
-    while !small.is_empty() && !large.is_empty() {
-        let s = small.pop().unwrap() as usize;
-        let l = large.pop().unwrap() as usize;
-
-        table[s].p = (probabilities[s] * n) as f32;
-        table[s].a = l as u32;
-
-        probabilities[l] = (probabilities[l] + probabilities[s]) - avg;
-
-        if probabilities[l] >= avg {
-            large.push(l as u32);
-        } else {
-            small.push(l as u32);
-        }
-    }
-
-    for s in small {
-        table[s as usize].p = 1.0;
-    }
-
-    for l in large {
-        table[l as usize].p = 1.0;
-    }
-
-    table
-}
-
 impl Ray {
-    pub fn init<A: Allocator + Copy>(window: &win32::Window, d3d12: &d3d12::Context,
-            scene: &Scene<A>) -> Self {
+    pub fn init(window: &win32::Window, d3d12: &d3d12::Context, scene: &SceneView) -> Self {
         let rs = d3d12.create_root_signature_from_shader(&shaders::RAY_LIB)
             .expect("Failed to create root signature");
 
@@ -572,68 +570,48 @@ impl Ray {
             StrideInBytes: d3d12::D3D12_SHADER_IDENTIFIER_SIZE_IN_BYTES as u64,
         };
 
-        // Acceleration structures
-        let mut positions_buf: Vec<Vec3> = Vec::new();
-        let mut normals_buf: Vec<Vec3> = Vec::new();
-        let mut tangents_buf: Vec<Vec3> = Vec::new();
-        let mut uvs_buf: Vec<Vec2> = Vec::new();
-        let mut indices_buf: Vec<u32> = Vec::new();
+        // Acceleration structures, the geometry is uploaded from the slices
+        // of the scene
+        let mut positions_parts: Vec<&[u8]> = Vec::new();
+        let mut normals_parts: Vec<&[u8]> = Vec::new();
+        let mut tangents_parts: Vec<&[u8]> = Vec::new();
+        let mut uvs_parts: Vec<&[u8]> = Vec::new();
+        let mut indices_parts: Vec<&[u8]> = Vec::new();
         let mut mesh_instances_buf: Vec<RayMeshInstance> = Vec::new();
 
         let mut scratch_size: u64 = 0;
         let mut blas_size:    u64 = 0;
 
+        // One BLAS per primitive, instanced in the TLAS for every instance
+        // of its mesh
+        let (primitives, first_primitive) = scene_primitives(scene);
+
         let mut geom_descs =
-            vec![d3d12::D3D12_RAYTRACING_GEOMETRY_DESC::default(); scene.meshes.len()];
+            vec![d3d12::D3D12_RAYTRACING_GEOMETRY_DESC::default(); primitives.len()];
 
         let mut inputs =
-            vec![d3d12::D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS::default(); scene.meshes.len()];
+            vec![d3d12::D3D12_BUILD_RAYTRACING_ACCELERATION_STRUCTURE_INPUTS::default(); primitives.len()];
 
-        let mut info_sizes = vec![(0u64, 0u64); scene.meshes.len()];
+        let mut info_sizes = vec![(0u64, 0u64); primitives.len()];
 
 
         let mut current_vertex: u32 = 0;
         let mut current_index:  u32 = 0;
 
-        for (i, m) in scene.meshes.iter().enumerate() {
-            positions_buf.extend_from_slice(&m.positions);
-            normals_buf  .extend_from_slice(&m.normals  );
-            tangents_buf .extend_from_slice(&m.tangents );
-            uvs_buf      .extend_from_slice(&m.uvs      );
-            indices_buf  .extend_from_slice(&m.indices  );
+        for (i, m) in primitives.iter().enumerate() {
+            positions_parts.push(cast_slice(m.positions));
+            normals_parts  .push(cast_slice(m.normals  ));
+            tangents_parts .push(cast_slice(m.tangents ));
+            uvs_parts      .push(cast_slice(m.uvs      ));
+            indices_parts  .push(cast_slice(m.indices  ));
 
-            let mut mesh_instance = RayMeshInstance {
+            let mesh_instance = RayMeshInstance {
                 vertex_offset: current_vertex,
                 index_offset: current_index,
-                ..Default::default()
+                uv_area_ratio: uv_area_ratio(m.positions, m.uvs, m.indices),
+                material: mesh_material(&scene.materials[m.material as usize]),
             };
 
-            macro_rules! material {
-                ($m: ident, $index: ident, $value: ident, $default: expr) => {
-                    match m.material.$m {
-                        scene::MaterialParameter::Texture(v) => {
-                            mesh_instance.$index = v;
-                        },
-                        scene::MaterialParameter::Vec4(v) => {
-                            mesh_instance.$index  = u32::MAX;
-                            mesh_instance.$value = v;
-                        }
-                        _ => {
-                            mesh_instance.$index  = u32::MAX;
-                            mesh_instance.$value = $default;
-                        }
-                    };
-                };
-            }
-
-            material!(base_color, albedo_index,   albedo_value,   Vec4::new(1.0, 0.0, 1.0, 1.0));
-            material!(specular,   specular_index, specular_value, Vec4::new(0.0, 1.0, 0.0, 0.0));
-            material!(emissive,   emissive_index, emissive_value, Vec4::new(0.0, 0.0, 0.0, 0.0));
-            if let scene::MaterialParameter::Texture(v) = m.material.normal {
-                mesh_instance.normal_index = v;
-            } else {
-                mesh_instance.normal_index = u32::MAX;
-            }
 
             mesh_instances_buf.push(mesh_instance);
 
@@ -684,26 +662,26 @@ impl Ray {
                              info.ResultDataMaxSizeInBytes);
         }
 
-        assert!(current_vertex == positions_buf.len() as u32);
-        assert!(current_index == indices_buf.len() as u32);
+        assert!(current_vertex == parts_count::<Vec3>(&positions_parts));
+        assert!(current_index == parts_count::<u32>(&indices_parts));
 
-        let positions = d3d12.upload_buffer_sync(cast_slice(&positions_buf),
+        let positions = d3d12.upload_buffer_parts_sync(&positions_parts,
             d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
             .expect("Failed to upload positions");
 
-        let normals = d3d12.upload_buffer_sync(cast_slice(&normals_buf),
+        let normals = d3d12.upload_buffer_parts_sync(&normals_parts,
             d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
             .expect("Failed to upload normals");
 
-        let tangents = d3d12.upload_buffer_sync(cast_slice(&tangents_buf),
+        let tangents = d3d12.upload_buffer_parts_sync(&tangents_parts,
             d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
             .expect("Failed to upload tangents");
 
-        let uvs = d3d12.upload_buffer_sync(cast_slice(&uvs_buf),
+        let uvs = d3d12.upload_buffer_parts_sync(&uvs_parts,
             d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
             .expect("Failed to upload uvs");
 
-        let indices = d3d12.upload_buffer_sync(cast_slice(&indices_buf),
+        let indices = d3d12.upload_buffer_parts_sync(&indices_parts,
             d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
             .expect("Failed to upload indices");
 
@@ -737,7 +715,9 @@ impl Ray {
             indices.GetGPUVirtualAddress()
         };
 
-        for (i, m) in scene.meshes.iter().enumerate() {
+        let mut blas_offsets = Vec::with_capacity(primitives.len());
+        for (i, m) in primitives.iter().enumerate() {
+            blas_offsets.push(blas_pointer);
             unsafe {
                 let tris = &mut geom_descs[i].Anonymous.Triangles;
                 tris.VertexBuffer.StartAddress = vertex_gpu_pointer;
@@ -777,9 +757,11 @@ impl Ray {
             Type: d3d12::D3D12_RAYTRACING_ACCELERATION_STRUCTURE_TYPE_TOP_LEVEL,
             DescsLayout: d3d12::D3D12_ELEMENTS_LAYOUT_ARRAY,
             Flags: d3d12::D3D12_RAYTRACING_ACCELERATION_STRUCTURE_BUILD_FLAG_NONE,
-            NumDescs: scene.meshes.len() as u32,
+            NumDescs: 0,
             ..Default::default()
         };
+        let instances_list: Vec<(MeshInstance, usize)> = primitive_instances(scene, &first_primitive).collect();
+        inputs.NumDescs = instances_list.len() as u32;
 
         let mut info = Default::default();
 
@@ -803,7 +785,7 @@ impl Ray {
         let instances = d3d12.create_resource(
             &d3d12::ResourceDesc::buffer(
                 size_of::<d3d12::D3D12_RAYTRACING_INSTANCE_DESC>() *
-                scene.meshes.len()),
+                instances_list.len()),
             d3d12::D3D12_RESOURCE_STATE_GENERIC_READ,
             d3d12::D3D12_HEAP_TYPE_UPLOAD).expect("Failed to alloc instances");
 
@@ -812,31 +794,24 @@ impl Ray {
             instances.Map(0, null(), &mut ptr)
                 .expect("Failed to map instance descriptors");
             core::slice::from_raw_parts_mut(ptr as *mut d3d12::D3D12_RAYTRACING_INSTANCE_DESC,
-                                            scene.meshes.len())
+                                            instances_list.len())
         };
 
-        let mut blas_pointer = unsafe { blas.GetGPUVirtualAddress() };
-        let blas_end = blas_pointer + blas_size;
-
-        for (i, m) in scene.meshes.iter().enumerate() {
-            let t = m.transform;
+        // The instance id is the index of the primitive in mesh_instances
+        for (i, (instance, p)) in instances_list.iter().enumerate() {
+            let t = instance.transform;
             instances_descs[i] = d3d12::D3D12_RAYTRACING_INSTANCE_DESC {
                 Transform: [
                     t.e[0][0], t.e[1][0], t.e[2][0], t.e[3][0],
                     t.e[0][1], t.e[1][1], t.e[2][1], t.e[3][1],
                     t.e[0][2], t.e[1][2], t.e[2][2], t.e[3][2],
                 ],
-                _bitfield1: i as u32 | (0xFF << 24),
+                _bitfield1: *p as u32 | (0xFF << 24),
                 _bitfield2: 0 | (d3d12::D3D12_RAYTRACING_INSTANCE_FLAG_NONE.0 << 24),
-                AccelerationStructure: blas_pointer,
+                AccelerationStructure: blas_offsets[*p],
             };
-
-            blas_pointer += (info_sizes[i].1 + 0xFF) & !0xFF;
-            assert!(blas_pointer <= blas_end);
         }
 
-        assert!(blas_pointer == blas_end);
-
         // Unmap instance descs
         core::mem::drop(instances_descs);
         unsafe { instances.Unmap(0, null()) };
@@ -871,7 +846,7 @@ impl Ray {
 
         d3d12.create_shader_resource_view_buffer(&indices,
                                                  d3d12::DXGI_FORMAT_R32_UINT,
-                                                 0, indices_buf.len() as u32,
+                                                 0, current_index,
                                                  indices_desc_handle);
 
         let normals_desc_handle = d3d12.alloc_csu_descriptor()
@@ -879,7 +854,7 @@ impl Ray {
 
         d3d12.create_shader_resource_view_buffer(&normals,
                                                  d3d12::DXGI_FORMAT_R32G32B32_FLOAT,
-                                                 0, normals_buf.len() as u32,
+                                                 0, parts_count::<Vec3>(&normals_parts),
                                                  normals_desc_handle);
 
         let tangents_desc_handle = d3d12.alloc_csu_descriptor()
@@ -887,7 +862,7 @@ impl Ray {
 
         d3d12.create_shader_resource_view_buffer(&tangents,
                                                  d3d12::DXGI_FORMAT_R32G32B32_FLOAT,
-                                                 0, tangents_buf.len() as u32,
+                                                 0, parts_count::<Vec3>(&tangents_parts),
                                                  tangents_desc_handle);
 
         let uvs_desc_handle = d3d12.alloc_csu_descriptor()
@@ -895,7 +870,7 @@ impl Ray {
 
         d3d12.create_shader_resource_view_buffer(&uvs,
                                                  d3d12::DXGI_FORMAT_R32G32_FLOAT,
-                                                 0, uvs_buf.len() as u32,
+                                                 0, parts_count::<Vec2>(&uvs_parts),
                                                  uvs_desc_handle);
 
         let mesh_instances_desc_handle = d3d12.alloc_csu_descriptor()
@@ -943,81 +918,32 @@ impl Ray {
 
 
 
-        // let mut emissive_textures: u64 = 0;
-        // let mut emissive_materials: u64 = 0;
-        // let mut emissive_triangles: u64 = 0;
-
-
-        let mut lights_pdf: Vec<f32> = Vec::new();
-        let mut lights: Vec<Light> = Vec::new();
-
-        for m in &scene.meshes {
-            match m.material.emissive  {
-                scene::MaterialParameter::None => {},
-                scene::MaterialParameter::Texture(_) => {}, // emissive_textures += 1,
-                scene::MaterialParameter::Vec4(e) => if e.x != 0. || e.y != 0. || e.z != 0. {
-                    // emissive_materials += 1;
-                    // emissive_triangles += m.indices.len() as u64 / 3;
-
-                    for i in 0..m.indices.len() / 3 {
-                        let p0 = m.positions[m.indices[i * 3 + 0] as usize];
-                        let p1 = m.positions[m.indices[i * 3 + 1] as usize];
-                        let p2 = m.positions[m.indices[i * 3 + 2] as usize];
-
-                        let p0 = m.transform * Vec4::new(p0.x, p0.y, p0.z, 1.0);
-                        let p1 = m.transform * Vec4::new(p1.x, p1.y, p1.z, 1.0);
-                        let p2 = m.transform * Vec4::new(p2.x, p2.y, p2.z, 1.0);
-
-                        let p0 = Vec3::new(p0.x, p0.y, p0.z);
-                        let p1 = Vec3::new(p1.x, p1.y, p1.z);
-                        let p2 = Vec3::new(p2.x, p2.y, p2.z);
-
-                        let e = Vec3::new(e.x, e.y, e.z);
-                        let area = (p1 - p0).cross(p2 - p0).norm() * e.luminance();
-                        lights_pdf.push(area);
-                        lights.push(Light { p0, p1, p2, emissive: Vec3::new(e.x, e.y, e.z) });
-                    }
-                },
-                _ => panic!("Unexpected emissive material parametr"),
-            }
-        }
-
-        // Compute CDF
-        let mut lights_cdf: Vec<f32> = Vec::with_capacity(lights_pdf.len());
-        let mut sum: f64 = 0.0;
-        for v in lights_pdf.iter() {
-            sum += *v as f64;
-            lights_cdf.push(sum as f32);
-        }
-        lights_cdf.iter_mut().for_each(|x| *x = (*x as f64 / sum) as f32);
-
-        // Multiply by 0.5 because we used double area instead of area in the previous step.
-        // We want this value to be the sum over all triangles of Area * Emissive
-        let lights_pdf_normalization = (1.0 / (sum * 0.5)) as f32;
-
-        // Normalize pdf
-        let lights_pdf: Vec<f64> = lights_pdf.iter_mut().map(|x| *x as f64 / sum).collect();
-        let alias_table = create_alias_table(lights_pdf);
-
-
-        let lights_buffer = d3d12.upload_buffer_sync(
-            cast_slice(&lights),
-            d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
-            .expect("Failed to upload lights buffer");
-
-        let lights_cdf_buffer = d3d12.upload_buffer_sync(
-            cast_slice(&lights_cdf),
-            d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
-            .expect("Failed to upload lights cdf buffer");
-
-        let alias_table_buffer = d3d12.upload_buffer_sync(
-            cast_slice(&alias_table),
-            d3d12::D3D12_RESOURCE_STATE_GENERIC_READ)
-            .expect("Failed to upload alias table p buffer");
-
-
-        // println!("Emissive objects: {} + {} -> {} triangles", emissive_textures, emissive_materials, emissive_triangles);
-
+        let light_tables = LightSamplingTables::from_scene(scene);
+        let lights: Vec<Light> = light_tables.triangles.iter()
+            .map(|t| Light {
+                p0: t.p0,
+                p1: t.p1,
+                p2: t.p2,
+                emissive: t.emission,
+                factor: t.factor,
+                uv0: t.uvs[0],
+                uv1: t.uvs[1],
+                uv2: t.uvs[2],
+                texture: t.texture,
+            })
+            .collect();
+        let alias_table: Vec<shaders::Alias> = light_tables.alias_table.iter()
+            .map(|a| shaders::Alias { p: a.p, a: a.a })
+            .collect();
+
+        // The tables are empty in scenes without emissive triangles, the
+        // shaders don't sample area lights when num_lights is 0
+        let lights_buffer = upload_root_buffer(d3d12, &lights, "lights");
+        let lights_cdf_buffer = upload_root_buffer(d3d12, &light_tables.cdf, "lights cdf");
+        let alias_table_buffer = upload_root_buffer(d3d12, &alias_table, "alias table");
+
+        // Indexed by the TLAS instances, in the same order
+        let first_lights_buffer = upload_root_buffer(d3d12, &light_tables.first_triangles, "first lights");
 
         Self {
             width: window.width(),
@@ -1047,12 +973,11 @@ impl Ray {
             constant_buffer,
             mesh_instances,
             mesh_instances_desc_handle,
-            lights,
-            lights_cdf,
+            light_tables,
             lights_buffer,
             lights_cdf_buffer,
-            lights_pdf_normalization,
             alias_table_buffer,
+            first_lights_buffer,
 
             postprocess_rs,
             postprocess_pso,
@@ -1112,8 +1037,8 @@ impl Pipeline for Ray {
             constants.samples = self.samples;
             dispatch = true;
         }
-        constants.num_lights = self.lights.len() as u32;
-        constants.lights_pdf_normalization = self.lights_pdf_normalization;
+        constants.num_lights = self.light_tables.triangles.len() as u32;
+        constants.lights_pdf_normalization = self.light_tables.pdf_normalization;
 
         let command_list = d3d12.create_graphics_command_list(frame)
             .expect("Failed to create command list");
@@ -1154,6 +1079,8 @@ impl Pipeline for Ray {
                     self.lights_cdf_buffer.GetGPUVirtualAddress());
                 command_list.SetComputeRootShaderResourceView(4,
                     self.alias_table_buffer.GetGPUVirtualAddress());
+                command_list.SetComputeRootShaderResourceView(5,
+                    self.first_lights_buffer.GetGPUVirtualAddress());
 
                 command_list.SetPipelineState1(&self.state_object);
                 command_list.DispatchRays(&ray_desc);
@@ -1309,7 +1236,7 @@ impl Pipeline for Ray {
 
         Some((image_name, data.iter().map(|c| {
             if c.w > 0.0 {
-                Vec3::new(c.x, c.y, c.z) * 1.0 / c.w
+                c.xyz() * 1.0 / c.w
             } else {
                 Vec3::new(0., 0., 0.)
             }
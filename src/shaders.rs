@@ -38,19 +38,29 @@ pub struct Constants {
     pub use_alias_table: u32,
 }
 
+#[allow(dead_code)]
+#[derive(Default, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct MeshMaterial {
+    pub base_color_index: u32,
+    pub normal_index: u32,
+    pub orm_index: u32,
+    pub emissive_index: u32,
+    pub base_color_factor: Vec4,
+    pub orm_factor: Vec4,
+    pub emissive_factor: Vec4,
+    pub roughness_channel: u32,
+    pub metallic_channel: u32,
+}
+
 #[allow(dead_code)]
 #[derive(Default, Clone, Copy, Pod, Zeroable)]
 #[repr(C)]
 pub struct RayMeshInstance {
     pub vertex_offset: u32,
     pub index_offset: u32,
-    pub albedo_index: u32,
-    pub normal_index: u32,
-    pub specular_index: u32,
-    pub emissive_index: u32,
-    pub albedo_value: Vec4,
-    pub specular_value: Vec4,
-    pub emissive_value: Vec4,
+    pub uv_area_ratio: f32,
+    pub material: MeshMaterial,
 }
 
 #[allow(dead_code)]
@@ -58,13 +68,8 @@ pub struct RayMeshInstance {
 #[repr(C)]
 pub struct RasterMeshInstance {
     pub transform: Mat4,
-    pub albedo_index: u32,
-    pub normal_index: u32,
-    pub specular_index: u32,
-    pub emissive_index: u32,
-    pub albedo_value: Vec4,
-    pub specular_value: Vec4,
-    pub emissive_value: Vec4,
+    pub normal_matrix: Mat4,
+    pub material: MeshMaterial,
 }
 
 #[allow(dead_code)]
@@ -75,6 +80,11 @@ pub struct Light {
     pub p1: Vec3,
     pub p2: Vec3,
     pub emissive: Vec3,
+    pub factor: Vec3,
+    pub uv0: Vec2,
+    pub uv1: Vec2,
+    pub uv2: Vec2,
+    pub texture: u32,
 }
 
 #[allow(dead_code)]